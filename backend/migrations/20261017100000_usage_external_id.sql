@@ -0,0 +1,6 @@
+-- Client-supplied idempotency key for usage events
+ALTER TABLE api_usage ADD COLUMN external_id VARCHAR(255);
+
+CREATE UNIQUE INDEX idx_api_usage_user_external_id
+    ON api_usage(user_id, external_id)
+    WHERE external_id IS NOT NULL;
@@ -0,0 +1,22 @@
+-- Separate ingest time from event time
+ALTER TABLE api_usage ADD COLUMN received_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+UPDATE api_usage SET received_at = timestamp;
+
+-- Windows whose rollups must be recomputed, e.g. after late-arriving data
+CREATE TABLE usage_recompute_windows (
+    id BIGSERIAL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    window_start TIMESTAMPTZ NOT NULL,
+    window_end TIMESTAMPTZ NOT NULL,
+    reason VARCHAR(50) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    processed_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX idx_usage_recompute_windows_pending
+    ON usage_recompute_windows(user_id, api_key_id, window_start, reason)
+    WHERE processed_at IS NULL;
+
+-- Predictions built from data that has since changed
+ALTER TABLE predictions ADD COLUMN is_stale BOOLEAN NOT NULL DEFAULT false;
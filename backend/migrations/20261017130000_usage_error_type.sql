@@ -0,0 +1,17 @@
+-- Error category for failed calls
+ALTER TABLE api_usage ADD COLUMN error_type VARCHAR(32);
+
+-- Derive errors for existing rows from their status codes
+UPDATE api_usage
+SET errors = 1,
+    error_type = CASE
+        WHEN status_code = 429 THEN 'rate_limited'
+        WHEN status_code IN (408, 504, 524) THEN 'timeout'
+        WHEN status_code IN (401, 403) THEN 'auth_error'
+        WHEN status_code >= 500 THEN 'server_error'
+        ELSE 'client_error'
+    END
+WHERE status_code >= 400;
+
+CREATE INDEX idx_api_usage_user_error_type ON api_usage(user_id, error_type)
+    WHERE error_type IS NOT NULL;
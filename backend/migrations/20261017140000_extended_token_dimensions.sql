@@ -0,0 +1,12 @@
+-- Prompt-cache and reasoning token counts. Cached and cache-write tokens are
+-- part of input_tokens; reasoning tokens are part of output_tokens.
+ALTER TABLE api_usage
+    ADD COLUMN cached_input_tokens INTEGER,
+    ADD COLUMN cache_write_tokens INTEGER,
+    ADD COLUMN reasoning_tokens INTEGER;
+
+-- Separate prices per dimension; NULL falls back to the input/output price
+ALTER TABLE api_keys
+    ADD COLUMN cost_per_1k_cached_input DECIMAL(10,6),
+    ADD COLUMN cost_per_1k_cache_write DECIMAL(10,6),
+    ADD COLUMN cost_per_1k_reasoning DECIMAL(10,6);
@@ -0,0 +1,11 @@
+-- Usage billed in units other than tokens (images, audio seconds, characters, ...)
+ALTER TABLE api_usage
+    ADD COLUMN billing_unit VARCHAR(32) NOT NULL DEFAULT 'tokens',
+    ADD COLUMN quantity DOUBLE PRECISION,
+    ADD COLUMN unit_variant VARCHAR(100);
+
+CREATE INDEX idx_api_usage_billing_unit ON api_usage(user_id, billing_unit, timestamp DESC);
+
+-- Per-unit prices keyed by "unit" or "unit:variant", e.g. {"images:1024x1024:hd": 0.08}
+ALTER TABLE api_keys
+    ADD COLUMN unit_prices JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,23 @@
+-- Central price catalog. model_pattern is an exact model name or a prefix
+-- ending in '*'; the longest matching pattern wins.
+CREATE TABLE model_prices (
+    id UUID PRIMARY KEY,
+    provider VARCHAR(100) NOT NULL,
+    model_pattern VARCHAR(100) NOT NULL,
+    cost_per_1k_input DECIMAL(10,6) NOT NULL,
+    cost_per_1k_output DECIMAL(10,6) NOT NULL,
+    cost_per_1k_cached_input DECIMAL(10,6),
+    cost_per_1k_cache_write DECIMAL(10,6),
+    cost_per_1k_reasoning DECIMAL(10,6),
+    unit_prices JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, model_pattern)
+);
+
+CREATE INDEX idx_model_prices_provider ON model_prices(provider);
+
+-- Per-key prices become optional overrides of the catalog
+ALTER TABLE api_keys
+    ALTER COLUMN cost_per_1k_input DROP NOT NULL,
+    ALTER COLUMN cost_per_1k_output DROP NOT NULL;
@@ -0,0 +1,28 @@
+-- Price versions: each catalog entry applies to usage with
+-- effective_from <= timestamp < effective_to (open-ended when NULL)
+ALTER TABLE model_prices
+    ADD COLUMN effective_from TIMESTAMPTZ NOT NULL DEFAULT '1970-01-01T00:00:00Z',
+    ADD COLUMN effective_to TIMESTAMPTZ,
+    ADD CONSTRAINT model_prices_effective_range CHECK (effective_to IS NULL OR effective_to > effective_from),
+    DROP CONSTRAINT model_prices_provider_model_pattern_key,
+    ADD CONSTRAINT model_prices_version_key UNIQUE (provider, model_pattern, effective_from);
+
+-- Audit trail of retroactive cost recalculations
+CREATE TABLE cost_recalculations (
+    id UUID PRIMARY KEY,
+    requested_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    range_start TIMESTAMPTZ NOT NULL,
+    range_end TIMESTAMPTZ NOT NULL,
+    api_key_id UUID,
+    model_name VARCHAR(100),
+    reason TEXT,
+    rows_scanned BIGINT NOT NULL,
+    rows_changed BIGINT NOT NULL,
+    rows_unpriced BIGINT NOT NULL,
+    old_total_cost DOUBLE PRECISION NOT NULL,
+    new_total_cost DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_cost_recalculations_created_at ON cost_recalculations(created_at DESC);
@@ -0,0 +1,36 @@
+-- Keep sub-cent precision for costs and prices; rounding happens only when
+-- amounts are displayed
+ALTER TABLE api_usage
+    ALTER COLUMN cost TYPE NUMERIC(20,10);
+
+ALTER TABLE api_keys
+    ALTER COLUMN cost_per_1k_input TYPE NUMERIC(20,10),
+    ALTER COLUMN cost_per_1k_output TYPE NUMERIC(20,10),
+    ALTER COLUMN cost_per_1k_cached_input TYPE NUMERIC(20,10),
+    ALTER COLUMN cost_per_1k_cache_write TYPE NUMERIC(20,10),
+    ALTER COLUMN cost_per_1k_reasoning TYPE NUMERIC(20,10);
+
+ALTER TABLE model_prices
+    ALTER COLUMN cost_per_1k_input TYPE NUMERIC(20,10),
+    ALTER COLUMN cost_per_1k_output TYPE NUMERIC(20,10),
+    ALTER COLUMN cost_per_1k_cached_input TYPE NUMERIC(20,10),
+    ALTER COLUMN cost_per_1k_cache_write TYPE NUMERIC(20,10),
+    ALTER COLUMN cost_per_1k_reasoning TYPE NUMERIC(20,10);
+
+ALTER TABLE predictions
+    ALTER COLUMN predicted_daily_cost TYPE NUMERIC(20,10),
+    ALTER COLUMN predicted_weekly_cost TYPE NUMERIC(20,10),
+    ALTER COLUMN predicted_monthly_cost TYPE NUMERIC(20,10),
+    ALTER COLUMN confidence_score TYPE DOUBLE PRECISION;
+
+ALTER TABLE budgets
+    ALTER COLUMN limit_amount TYPE NUMERIC(20,10),
+    ALTER COLUMN alert_threshold TYPE DOUBLE PRECISION;
+
+ALTER TABLE alerts
+    ALTER COLUMN threshold_value TYPE NUMERIC(20,10),
+    ALTER COLUMN current_value TYPE NUMERIC(20,10);
+
+ALTER TABLE cost_recalculations
+    ALTER COLUMN old_total_cost TYPE NUMERIC(20,10),
+    ALTER COLUMN new_total_cost TYPE NUMERIC(20,10);
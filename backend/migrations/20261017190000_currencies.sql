@@ -0,0 +1,67 @@
+-- Prices and costs carry the currency they are billed in
+ALTER TABLE model_prices
+    ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE api_keys
+    ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE api_usage
+    ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE users
+    ADD COLUMN reporting_currency VARCHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE budgets
+    ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE predictions
+    ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD';
+
+-- Dated exchange rates: the USD value of one unit of `currency` from
+-- `rate_date` until the next rate
+CREATE TABLE fx_rates (
+    currency VARCHAR(3) NOT NULL,
+    rate_date DATE NOT NULL,
+    usd_rate NUMERIC(20,10) NOT NULL CHECK (usd_rate > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (currency, rate_date)
+);
+
+-- USD value of one unit of a currency on a date, from the latest rate on or
+-- before it. Raises FX404 when there is none.
+CREATE FUNCTION fx_usd_rate(p_currency VARCHAR, p_date DATE) RETURNS NUMERIC AS $$
+DECLARE
+    rate NUMERIC;
+BEGIN
+    IF p_currency = 'USD' THEN
+        RETURN 1;
+    END IF;
+
+    SELECT usd_rate INTO rate
+    FROM fx_rates
+    WHERE currency = p_currency AND rate_date <= p_date
+    ORDER BY rate_date DESC
+    LIMIT 1;
+
+    IF rate IS NULL THEN
+        RAISE EXCEPTION 'No exchange rate for % on or before %', p_currency, p_date
+            USING ERRCODE = 'FX404';
+    END IF;
+
+    RETURN rate;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+-- Converts an amount between currencies with the rates in effect on a date
+CREATE FUNCTION convert_money(
+    amount NUMERIC,
+    from_currency VARCHAR,
+    to_currency VARCHAR,
+    on_date DATE
+) RETURNS NUMERIC AS $$
+    SELECT CASE
+        WHEN from_currency = to_currency THEN amount
+        ELSE ROUND(amount * fx_usd_rate(from_currency, on_date) / fx_usd_rate(to_currency, on_date), 10)
+    END
+$$ LANGUAGE sql STABLE;
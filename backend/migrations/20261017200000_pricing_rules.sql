@@ -0,0 +1,32 @@
+-- Contract terms of an API key: volume tiers over the calendar month, flat and
+-- batch-API discounts and a monthly minimum commitment
+CREATE TABLE pricing_rules (
+    id UUID PRIMARY KEY,
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    model_pattern VARCHAR(100),
+    discount_percent NUMERIC(9,6) NOT NULL DEFAULT 0,
+    batch_discount_percent NUMERIC(9,6) NOT NULL DEFAULT 0,
+    tiers JSONB NOT NULL DEFAULT '[]'::jsonb,
+    minimum_commitment NUMERIC(20,10),
+    effective_from TIMESTAMPTZ NOT NULL DEFAULT '1970-01-01T00:00:00Z',
+    effective_to TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT pricing_rules_effective_range CHECK (effective_to IS NULL OR effective_to > effective_from)
+);
+
+CREATE INDEX idx_pricing_rules_api_key_id ON pricing_rules(api_key_id);
+
+-- `list_cost` is the catalog or key price; `cost` is after the contract
+-- discount that applied when the call was made
+ALTER TABLE api_usage
+    ADD COLUMN list_cost NUMERIC(20,10),
+    ADD COLUMN is_batch BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN pricing_rule_id UUID REFERENCES pricing_rules(id) ON DELETE SET NULL,
+    ADD COLUMN discount_percent NUMERIC(9,6) NOT NULL DEFAULT 0;
+
+UPDATE api_usage SET list_cost = cost;
+
+ALTER TABLE api_usage
+    ALTER COLUMN list_cost SET NOT NULL;
@@ -0,0 +1,4 @@
+-- Where a catalog price came from: the admin API, the bundled defaults or the
+-- declarative pricing file
+ALTER TABLE model_prices
+    ADD COLUMN source VARCHAR(20) NOT NULL DEFAULT 'api';
@@ -0,0 +1,11 @@
+-- Structured cost attribution tags (team, feature, customer_id, ...)
+ALTER TABLE api_usage
+    ADD COLUMN tags JSONB NOT NULL DEFAULT '{}'::jsonb;
+
+-- Default jsonb_ops, which unlike jsonb_path_ops also serves the key-exists
+-- (`?`) checks used when grouping by a tag
+CREATE INDEX idx_api_usage_tags ON api_usage USING GIN (tags);
+
+-- The tag filter a prediction was generated for
+ALTER TABLE predictions
+    ADD COLUMN tags JSONB NOT NULL DEFAULT '{}'::jsonb;
@@ -0,0 +1,5 @@
+-- Local calendar that day, week and month boundaries are reported in
+ALTER TABLE users
+    ADD COLUMN timezone VARCHAR(64) NOT NULL DEFAULT 'UTC',
+    ADD COLUMN week_start VARCHAR(9) NOT NULL DEFAULT 'monday'
+        CHECK (week_start IN ('monday', 'tuesday', 'wednesday', 'thursday', 'friday', 'saturday', 'sunday'));
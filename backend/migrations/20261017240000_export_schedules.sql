@@ -0,0 +1,54 @@
+-- Recurring usage exports delivered to a local directory or an
+-- S3-compatible bucket
+CREATE TABLE export_schedules (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    cron VARCHAR(100) NOT NULL,
+    -- IANA timezone of the cron expression and period; the user's when NULL
+    timezone VARCHAR(64),
+    period VARCHAR(64) NOT NULL DEFAULT 'yesterday',
+    format VARCHAR(10) NOT NULL CHECK (format IN ('csv', 'json', 'ndjson', 'parquet')),
+    columns TEXT NOT NULL DEFAULT '',
+    tag_columns TEXT,
+    currency VARCHAR(3),
+    filters JSONB NOT NULL DEFAULT '{}',
+    destination JSONB NOT NULL,
+    -- Secret access key of an S3 destination, encrypted like API keys
+    encrypted_secret TEXT,
+    max_attempts INTEGER NOT NULL DEFAULT 3 CHECK (max_attempts BETWEEN 1 AND 10),
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    -- The cron time the pending run covers, and when it is attempted next
+    scheduled_for TIMESTAMPTZ NOT NULL,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    -- Failed attempts of the pending run so far
+    attempt INTEGER NOT NULL DEFAULT 0,
+    running_since TIMESTAMPTZ,
+    last_run_at TIMESTAMPTZ,
+    last_status VARCHAR(20),
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_export_schedules_user_id ON export_schedules(user_id);
+CREATE INDEX idx_export_schedules_due ON export_schedules(next_run_at) WHERE is_active;
+
+CREATE TABLE export_runs (
+    id BIGSERIAL PRIMARY KEY,
+    schedule_id UUID NOT NULL REFERENCES export_schedules(id) ON DELETE CASCADE,
+    scheduled_for TIMESTAMPTZ NOT NULL,
+    attempt INTEGER NOT NULL,
+    period_start TIMESTAMPTZ,
+    period_end TIMESTAMPTZ,
+    status VARCHAR(20) NOT NULL DEFAULT 'running'
+        CHECK (status IN ('running', 'succeeded', 'failed')),
+    rows_exported BIGINT,
+    bytes_written BIGINT,
+    location TEXT,
+    error TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_export_runs_schedule_id ON export_runs(schedule_id, started_at DESC);
@@ -0,0 +1,3 @@
+-- Exact unit quantities, on the same scale as costs
+ALTER TABLE api_usage
+    ALTER COLUMN quantity TYPE NUMERIC(20,10);
@@ -1,5 +1,13 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
+use std::path::PathBuf;
+
+use crate::models::tag::UntaggedTags;
+use crate::models::usage_export::parse_tag_columns;
+
+/// Longest accepted ingestion time window (ten years).
+const MAX_WINDOW_SECS: i64 = 10 * 365 * 24 * 60 * 60;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -8,6 +16,20 @@ pub struct Config {
     pub jwt_secret: String,
     pub server: ServerConfig,
     pub email: Option<EmailConfig>,
+    pub ingestion: IngestionConfig,
+    pub proxy: ProxyConfig,
+    /// Lower-cased emails of users allowed to manage shared settings such as
+    /// the model price catalog.
+    pub admin_emails: Vec<String>,
+    /// Declarative price catalog (JSON, TOML or YAML) applied at startup and on
+    /// reload.
+    pub pricing_file: Option<PathBuf>,
+    /// CSV of exchange rates (`currency,rate_date,usd_rate`) loaded at
+    /// startup.
+    pub fx_rates_file: Option<PathBuf>,
+    /// How usage missing a tag is reported when grouping or listing by it.
+    pub untagged_tags: UntaggedTags,
+    pub export: ExportConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -24,6 +46,55 @@ pub struct EmailConfig {
     pub smtp_password: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct IngestionConfig {
+    pub max_batch_size: usize,
+    pub import_chunk_size: usize,
+    /// How far in the future a client timestamp may be, in seconds.
+    pub max_clock_skew_secs: i64,
+    /// How far in the past a live (non-backfill) event may be, in seconds.
+    pub max_lateness_secs: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProxyConfig {
+    /// Upstream base URLs keyed by canonical provider name.
+    pub upstreams: HashMap<String, String>,
+    pub connect_timeout_secs: u64,
+    /// Longest wait for the next chunk of an upstream response, so long
+    /// streams are not cut off as long as they keep producing output.
+    pub read_timeout_secs: u64,
+    pub max_body_bytes: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportConfig {
+    /// Directory the daily Parquet partitions are written to; the job is
+    /// off when unset.
+    pub parquet_dir: Option<PathBuf>,
+    /// Cron expression (with seconds) of the daily Parquet export.
+    pub parquet_schedule: String,
+    /// Tag keys written as their own `tag_<key>` Parquet columns.
+    pub parquet_tag_columns: Vec<String>,
+    /// Directory under which export schedules with a local destination write,
+    /// one folder per user; local destinations are refused when unset.
+    pub local_dir: Option<PathBuf>,
+    /// Accept plain `http://` S3 endpoints, such as a local MinIO.
+    pub s3_allow_http: bool,
+    /// Lower-cased hosts S3 endpoints are restricted to, private ones
+    /// included; any public host is accepted when empty.
+    pub s3_allowed_hosts: Vec<String>,
+    /// Usage exports streamed at once; each holds a pooled connection for
+    /// its whole download.
+    pub max_concurrent: usize,
+}
+
+impl ProxyConfig {
+    pub fn upstream_for(&self, provider: &str) -> Option<&str> {
+        self.upstreams.get(provider).map(|url| url.trim_end_matches('/'))
+    }
+}
+
 impl Config {
     pub fn from_env() -> anyhow::Result<Self> {
         Ok(Config {
@@ -37,9 +108,114 @@ impl Config {
                     .parse()?,
             },
             email: Self::email_config(),
+            ingestion: IngestionConfig {
+                max_batch_size: env::var("USAGE_BATCH_MAX_ITEMS")
+                    .unwrap_or_else(|_| "1000".to_string())
+                    .parse()?,
+                import_chunk_size: env::var("USAGE_IMPORT_CHUNK_SIZE")
+                    .unwrap_or_else(|_| "500".to_string())
+                    .parse()?,
+                max_clock_skew_secs: Self::window_secs("USAGE_MAX_CLOCK_SKEW_SECS", "300")?,
+                max_lateness_secs: Self::window_secs("USAGE_MAX_LATENESS_SECS", "604800")?,
+            },
+            proxy: ProxyConfig {
+                upstreams: HashMap::from([
+                    (
+                        "openai".to_string(),
+                        env::var("PROXY_UPSTREAM_OPENAI")
+                            .unwrap_or_else(|_| "https://api.openai.com".to_string()),
+                    ),
+                    (
+                        "anthropic".to_string(),
+                        env::var("PROXY_UPSTREAM_ANTHROPIC")
+                            .unwrap_or_else(|_| "https://api.anthropic.com".to_string()),
+                    ),
+                    (
+                        "gemini".to_string(),
+                        env::var("PROXY_UPSTREAM_GEMINI")
+                            .unwrap_or_else(|_| "https://generativelanguage.googleapis.com".to_string()),
+                    ),
+                ]),
+                connect_timeout_secs: env::var("PROXY_CONNECT_TIMEOUT_SECS")
+                    .unwrap_or_else(|_| "10".to_string())
+                    .parse()?,
+                read_timeout_secs: env::var("PROXY_READ_TIMEOUT_SECS")
+                    .unwrap_or_else(|_| "300".to_string())
+                    .parse()?,
+                max_body_bytes: env::var("PROXY_MAX_BODY_BYTES")
+                    .unwrap_or_else(|_| "20971520".to_string())
+                    .parse()?,
+            },
+            admin_emails: env::var("ADMIN_EMAILS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|email| email.trim().to_lowercase())
+                .filter(|email| !email.is_empty())
+                .collect(),
+            pricing_file: env::var("PRICING_FILE")
+                .ok()
+                .filter(|path| !path.trim().is_empty())
+                .map(PathBuf::from),
+            fx_rates_file: env::var("FX_RATES_FILE")
+                .ok()
+                .filter(|path| !path.trim().is_empty())
+                .map(PathBuf::from),
+            untagged_tags: Self::untagged_tags()?,
+            export: ExportConfig {
+                parquet_dir: env::var("PARQUET_EXPORT_DIR")
+                    .ok()
+                    .filter(|path| !path.trim().is_empty())
+                    .map(PathBuf::from),
+                parquet_schedule: env::var("PARQUET_EXPORT_SCHEDULE")
+                    .unwrap_or_else(|_| "0 30 0 * * *".to_string()),
+                parquet_tag_columns: parse_tag_columns(&env::var("PARQUET_TAG_COLUMNS").unwrap_or_default())
+                    .map_err(|e| anyhow::anyhow!("PARQUET_TAG_COLUMNS: {}", e))?,
+                local_dir: env::var("EXPORT_LOCAL_DIR")
+                    .ok()
+                    .filter(|path| !path.trim().is_empty())
+                    .map(PathBuf::from),
+                s3_allow_http: env::var("EXPORT_S3_ALLOW_HTTP")
+                    .unwrap_or_else(|_| "false".to_string())
+                    .parse()?,
+                s3_allowed_hosts: env::var("EXPORT_S3_ALLOWED_HOSTS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|host| host.trim().to_ascii_lowercase())
+                    .filter(|host| !host.is_empty())
+                    .collect(),
+                max_concurrent: env::var("EXPORT_MAX_CONCURRENT")
+                    .unwrap_or_else(|_| "4".to_string())
+                    .parse()?,
+            },
         })
     }
     
+    /// A time window in seconds, between zero and ten years.
+    fn window_secs(name: &str, default: &str) -> anyhow::Result<i64> {
+        let secs: i64 = env::var(name)
+            .unwrap_or_else(|_| default.to_string())
+            .parse()
+            .map_err(|e| anyhow::anyhow!("{}: {}", name, e))?;
+        if !(0..=MAX_WINDOW_SECS).contains(&secs) {
+            anyhow::bail!("{} must be between 0 and {} seconds, not {}", name, MAX_WINDOW_SECS, secs);
+        }
+        
+        Ok(secs)
+    }
+    
+    fn untagged_tags() -> anyhow::Result<UntaggedTags> {
+        let mode = env::var("USAGE_UNTAGGED").unwrap_or_else(|_| "label".to_string());
+        
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "label" => Ok(UntaggedTags::Label(
+                env::var("USAGE_UNTAGGED_LABEL").unwrap_or_else(|_| "(untagged)".to_string()),
+            )),
+            "null" => Ok(UntaggedTags::Null),
+            "exclude" => Ok(UntaggedTags::Exclude),
+            other => anyhow::bail!("USAGE_UNTAGGED must be label, null or exclude, not '{}'", other),
+        }
+    }
+    
     fn email_config() -> Option<EmailConfig> {
         Some(EmailConfig {
             smtp_host: env::var("SMTP_HOST").ok()?,
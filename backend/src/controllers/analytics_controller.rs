@@ -17,8 +17,8 @@ pub struct AnalyticsQuery {
 }
 
 pub async fn get_analytics_overview(
-    State(state): State<AppState>,
-    AuthUser(user_id): AuthUser,
+    State(_state): State<AppState>,
+    AuthUser(_user_id): AuthUser,
     Query(_query): Query<AnalyticsQuery>,
 ) -> Result<Json<serde_json::Value>, ApiError> {
     // Implement comprehensive analytics
@@ -31,8 +31,8 @@ pub async fn get_analytics_overview(
 }
 
 pub async fn detect_anomalies(
-    State(state): State<AppState>,
-    AuthUser(user_id): AuthUser,
+    State(_state): State<AppState>,
+    AuthUser(_user_id): AuthUser,
 ) -> Result<Json<serde_json::Value>, ApiError> {
     // Implement anomaly detection
     Ok(Json(serde_json::json!({
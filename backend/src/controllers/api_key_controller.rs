@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
@@ -5,13 +7,21 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::Validate;
 use base64::Engine;
+use chrono::{DateTime, Utc};
 
 use crate::{
     AppState,
+    controllers::usage_controller::ErrorTypeStats,
+    db::repositories::{UsageRepository, UserRepository},
     errors::ApiError,
     middleware::auth::AuthUser,
     models::api_key::ApiKey,
+    models::latency::LatencyStats,
+    models::model_price::validate_unit_prices,
+    models::money::{validate_currency, validate_non_negative, Money},
+    utils::crypto::encrypt_api_key,
 };
 
 // ==================== REQUEST/RESPONSE TYPES ====================
@@ -23,11 +33,31 @@ pub struct CreateApiKeyRequest {
     pub api_key: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct UpdateApiKeyRequest {
     pub name: Option<String>,
     pub api_key: Option<String>,
     pub is_active: Option<bool>,
+    /// Price overrides; unset prices come from the model price catalog.
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_input: Option<Money>,
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_output: Option<Money>,
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_cached_input: Option<Money>,
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_cache_write: Option<Money>,
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_reasoning: Option<Money>,
+    /// Replaces the per-unit prices, keyed by `unit` or `unit:variant`.
+    #[validate(custom(function = "validate_unit_prices"))]
+    pub unit_prices: Option<HashMap<String, Money>>,
+    /// Currency of the price overrides.
+    #[validate(custom(function = "validate_currency"))]
+    pub currency: Option<String>,
+    /// Drops all price overrides before applying the ones above.
+    #[serde(default)]
+    pub clear_price_overrides: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,6 +75,13 @@ pub struct ApiKeyResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key_preview: Option<String>,
     pub is_active: bool,
+    pub cost_per_1k_input: Option<Money>,
+    pub cost_per_1k_output: Option<Money>,
+    pub cost_per_1k_cached_input: Option<Money>,
+    pub cost_per_1k_cache_write: Option<Money>,
+    pub cost_per_1k_reasoning: Option<Money>,
+    pub unit_prices: HashMap<String, Money>,
+    pub currency: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
@@ -81,9 +118,24 @@ pub struct ApiKeyListResponse {
 #[derive(Debug, Serialize)]
 pub struct ApiKeyUsageStats {
     pub total_requests: i64,
-    pub total_cost: f64,
+    pub total_cost: Money,
     pub requests_today: i64,
-    pub cost_today: f64,
+    pub cost_today: Money,
+    pub total_errors: i64,
+    pub error_rate: f64,
+    pub failed_cost: Money,
+    pub errors_by_type: Vec<ErrorTypeStats>,
+    pub latency: LatencyStats,
+    /// The user's reporting currency, which all costs are converted into.
+    pub currency: String,
+    /// Timezone that "today" is counted in.
+    pub timezone: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyStatsQuery {
+    /// IANA timezone of "today"; defaults to the user's preference.
+    pub timezone: Option<String>,
 }
 
 // ==================== CONVERSIONS ====================
@@ -103,6 +155,13 @@ impl From<ApiKey> for ApiKeyResponse {
             provider: api_key.provider,
             key_preview,
             is_active: api_key.is_active,
+            cost_per_1k_input: api_key.cost_per_1k_input,
+            cost_per_1k_output: api_key.cost_per_1k_output,
+            cost_per_1k_cached_input: api_key.cost_per_1k_cached_input,
+            cost_per_1k_cache_write: api_key.cost_per_1k_cache_write,
+            cost_per_1k_reasoning: api_key.cost_per_1k_reasoning,
+            unit_prices: api_key.unit_prices.0,
+            currency: api_key.currency,
             created_at: api_key.created_at,
         }
     }
@@ -120,11 +179,12 @@ pub async fn create_api_key(
 
     let api_key = sqlx::query_as::<_, ApiKey>(
         r#"
-        INSERT INTO api_keys (user_id, name, provider, encrypted_key, is_active)
-        VALUES ($1, $2, $3, $4, true)
+        INSERT INTO api_keys (id, user_id, name, provider, encrypted_key, is_active)
+        VALUES ($1, $2, $3, $4, $5, true)
         RETURNING *
         "#,
     )
+    .bind(Uuid::new_v4())
     .bind(user)
     .bind(&payload.name)
     .bind(&payload.provider)
@@ -220,6 +280,8 @@ pub async fn update_api_key(
     Path(key_id): Path<Uuid>,
     Json(payload): Json<UpdateApiKeyRequest>,
 ) -> Result<Json<ApiKeyResponse>, ApiError> {
+    payload.validate()?;
+
     let encrypted_key = if let Some(ref key) = payload.api_key {
         Some(encrypt_api_key(key)?)
     } else {
@@ -233,14 +295,29 @@ pub async fn update_api_key(
             name = COALESCE($1, name),
             encrypted_key = COALESCE($2, encrypted_key),
             is_active = COALESCE($3, is_active),
+            cost_per_1k_input = COALESCE($4, CASE WHEN $10 THEN NULL ELSE cost_per_1k_input END),
+            cost_per_1k_output = COALESCE($5, CASE WHEN $10 THEN NULL ELSE cost_per_1k_output END),
+            cost_per_1k_cached_input = COALESCE($6, CASE WHEN $10 THEN NULL ELSE cost_per_1k_cached_input END),
+            cost_per_1k_cache_write = COALESCE($7, CASE WHEN $10 THEN NULL ELSE cost_per_1k_cache_write END),
+            cost_per_1k_reasoning = COALESCE($8, CASE WHEN $10 THEN NULL ELSE cost_per_1k_reasoning END),
+            unit_prices = COALESCE($9, CASE WHEN $10 THEN '{}'::jsonb ELSE unit_prices END),
+            currency = COALESCE($11, currency),
             updated_at = NOW()
-        WHERE id = $4 AND user_id = $5
+        WHERE id = $12 AND user_id = $13
         RETURNING *
         "#,
     )
     .bind(payload.name)
     .bind(encrypted_key)
     .bind(payload.is_active)
+    .bind(payload.cost_per_1k_input)
+    .bind(payload.cost_per_1k_output)
+    .bind(payload.cost_per_1k_cached_input)
+    .bind(payload.cost_per_1k_cache_write)
+    .bind(payload.cost_per_1k_reasoning)
+    .bind(payload.unit_prices.map(sqlx::types::Json))
+    .bind(payload.clear_price_overrides)
+    .bind(payload.currency)
     .bind(key_id)
     .bind(user)
     .fetch_optional(&state.pool)
@@ -307,7 +384,7 @@ pub async fn rotate_api_key(
     OsRng.fill_bytes(&mut random_bytes);
 
     let mut hasher = Sha256::new();
-    hasher.update(&random_bytes);
+    hasher.update(random_bytes);
     hasher.update(user.as_bytes());
     hasher.update(chrono::Utc::now().timestamp().to_le_bytes());
     let hash_result = hasher.finalize();
@@ -316,8 +393,7 @@ pub async fn rotate_api_key(
         "sk_live_{}",
         base64::engine::general_purpose::URL_SAFE_NO_PAD
             .encode(&hash_result[..24])
-            .replace('-', "")
-            .replace('_', "")
+            .replace(['-', '_'], "")
     );
 
     // Encrypt the new key
@@ -364,6 +440,7 @@ pub async fn get_api_key_stats(
     State(state): State<AppState>,
     AuthUser(user): AuthUser,
     Path(key_id): Path<Uuid>,
+    Query(query): Query<ApiKeyStatsQuery>,
 ) -> Result<Json<ApiKeyUsageStats>, ApiError> {
     let _api_key = sqlx::query_as::<_, ApiKey>(
         r#"
@@ -377,97 +454,62 @@ pub async fn get_api_key_stats(
     .await?
     .ok_or(ApiError::NotFound("API key not found".to_string()))?;
 
-    let stats = sqlx::query_as::<_, (i64, f64, i64, f64)>(
+    let user_repo = UserRepository::new(&state.pool);
+    let currency = user_repo.reporting_currency(user).await?;
+    let calendar = user_repo
+        .calendar(user)
+        .await?
+        .with_overrides(query.timezone.as_deref(), None)
+        .map_err(ApiError::ValidationError)?;
+
+    let stats = sqlx::query_as::<_, (i64, Money, i64, Money, i64, Money)>(
         r#"
+        WITH converted AS (
+            SELECT *, convert_money(cost, currency, $2, timestamp::date) as reporting_cost
+            FROM api_usage
+            WHERE api_key_id = $1
+        )
         SELECT 
             COUNT(*)::bigint as total_requests,
-            COALESCE(SUM(cost), 0)::float8 as total_cost,
-            COUNT(CASE WHEN timestamp >= CURRENT_DATE THEN 1 END)::bigint as requests_today,
-            COALESCE(SUM(CASE WHEN timestamp >= CURRENT_DATE THEN cost ELSE 0 END), 0)::float8 as cost_today
-        FROM api_usage
-        WHERE api_key_id = $1
+            COALESCE(SUM(reporting_cost), 0) as total_cost,
+            COUNT(CASE WHEN timestamp >= $3 THEN 1 END)::bigint as requests_today,
+            COALESCE(SUM(CASE WHEN timestamp >= $3 THEN reporting_cost ELSE 0 END), 0) as cost_today,
+            COALESCE(SUM(errors), 0)::bigint as total_errors,
+            COALESCE(SUM(CASE WHEN errors > 0 THEN reporting_cost ELSE 0 END), 0) as failed_cost
+        FROM converted
         "#,
     )
     .bind(key_id)
+    .bind(&currency)
+    .bind(calendar.start_of_today(Utc::now()))
     .fetch_one(&state.pool)
     .await?;
 
+    let usage_repo = UsageRepository::new(&state.pool);
+    let errors_by_type = usage_repo
+        .get_error_breakdown(user, DateTime::UNIX_EPOCH, None, Some(key_id), None, &currency)
+        .await?;
+    let latency = usage_repo
+        .get_latency_stats(user, DateTime::UNIX_EPOCH, None, Some(key_id), None)
+        .await?;
+
+    let error_rate = if stats.0 > 0 {
+        (stats.4 as f64 / stats.0 as f64) * 100.0
+    } else {
+        0.0
+    };
+
     Ok(Json(ApiKeyUsageStats {
         total_requests: stats.0,
         total_cost: stats.1,
         requests_today: stats.2,
         cost_today: stats.3,
+        total_errors: stats.4,
+        error_rate,
+        failed_cost: stats.5,
+        errors_by_type,
+        latency,
+        currency,
+        timezone: calendar.timezone_name().to_string(),
     }))
 }
-
-// ==================== ENCRYPTION HELPERS ====================
-
-fn encrypt_api_key(key: &str) -> Result<String, ApiError> {
-    use aes_gcm::{
-        aead::{Aead, KeyInit},
-        Aes256Gcm, Nonce,
-    };
-    
-    let encryption_key = std::env::var("API_KEY_ENCRYPTION_KEY")
-        .unwrap_or_else(|_| {
-            tracing::warn!("Using default encryption key - NOT SECURE FOR PRODUCTION");
-            "your-32-byte-encryption-key-here!".to_string()
-        });
-    
-    let mut key_bytes = [0u8; 32];
-    let enc_key_bytes = encryption_key.as_bytes();
-    let len = enc_key_bytes.len().min(32);
-    key_bytes[..len].copy_from_slice(&enc_key_bytes[..len]);
-    
-    let cipher = Aes256Gcm::new(&key_bytes.into());
-    
-    let mut nonce_bytes = [0u8; 12];
-    getrandom::getrandom(&mut nonce_bytes)
-        .map_err(|e| ApiError::InternalServerError(format!("Failed to generate nonce: {}", e)))?;
-    let nonce = Nonce::from_slice(&nonce_bytes);
-    
-    let ciphertext = cipher
-        .encrypt(nonce, key.as_bytes())
-        .map_err(|e| ApiError::InternalServerError(format!("Encryption failed: {}", e)))?;
-    
-    let mut combined = nonce_bytes.to_vec();
-    combined.extend_from_slice(&ciphertext);
-    
-    Ok(base64::engine::general_purpose::STANDARD.encode(&combined))
-}
-
-#[allow(dead_code)]
-pub fn decrypt_api_key(encrypted: &str) -> Result<String, ApiError> {
-    use aes_gcm::{
-        aead::{Aead, KeyInit},
-        Aes256Gcm, Nonce,
-    };
-    
-    let encryption_key = std::env::var("API_KEY_ENCRYPTION_KEY")
-        .unwrap_or_else(|_| "your-32-byte-encryption-key-here!".to_string());
-    
-    let mut key_bytes = [0u8; 32];
-    let enc_key_bytes = encryption_key.as_bytes();
-    let len = enc_key_bytes.len().min(32);
-    key_bytes[..len].copy_from_slice(&enc_key_bytes[..len]);
-    
-    let cipher = Aes256Gcm::new(&key_bytes.into());
-    
-    let combined = base64::engine::general_purpose::STANDARD
-        .decode(encrypted)
-        .map_err(|e| ApiError::InternalServerError(format!("Base64 decode failed: {}", e)))?;
-    
-    if combined.len() < 12 {
-        return Err(ApiError::InternalServerError("Invalid encrypted data".to_string()));
-    }
-    
-    let (nonce_bytes, ciphertext) = combined.split_at(12);
-    let nonce = Nonce::from_slice(nonce_bytes);
-    
-    let plaintext = cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|e| ApiError::InternalServerError(format!("Decryption failed: {}", e)))?;
-    
-    String::from_utf8(plaintext)
-        .map_err(|e| ApiError::InternalServerError(format!("Invalid UTF-8: {}", e)))
-}
\ No newline at end of file
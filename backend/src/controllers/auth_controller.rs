@@ -12,10 +12,11 @@ use argon2::{
     Argon2
 };
 use jsonwebtoken::{encode, Header, EncodingKey};
+use validator::Validate;
 
 use crate::{
     AppState,
-    models::user::{CreateUserRequest, LoginRequest, User},
+    models::user::{CreateUserRequest, LoginRequest, UpdatePreferencesRequest, User},
     db::repositories::UserRepository,
     middleware::auth::{Claims, AuthUser},
     errors::ApiError,
@@ -38,6 +39,22 @@ pub struct UserResponse {
     pub id: uuid::Uuid,
     pub email: String,
     pub name: String,
+    pub reporting_currency: String,
+    pub timezone: String,
+    pub week_start: String,
+}
+
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            name: user.name,
+            reporting_currency: user.reporting_currency,
+            timezone: user.timezone,
+            week_start: user.week_start,
+        }
+    }
 }
 
 pub async fn register(
@@ -71,11 +88,7 @@ pub async fn register(
             success: true,
             data: AuthData {
                 token,
-                user: UserResponse {
-                    id: user.id,
-                    email: user.email,
-                    name: user.name,
-                },
+                user: user.into(),
             },
         })
     ))
@@ -108,11 +121,7 @@ pub async fn login(
         success: true,
         data: AuthData {
             token,
-            user: UserResponse {
-                id: user.id,
-                email: user.email,
-                name: user.name,
-            },
+            user: user.into(),
         },
     }))
 }
@@ -130,11 +139,25 @@ pub async fn get_current_user(
     
     Ok(Json(serde_json::json!({
         "success": true,
-        "data": UserResponse {
-            id: user.id,
-            email: user.email,
-            name: user.name,
-        }
+        "data": UserResponse::from(user)
+    })))
+}
+
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Json(req): Json<UpdatePreferencesRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    req.validate()?;
+    
+    let user = UserRepository::new(&state.pool)
+        .update_preferences(user_id, &req)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
+    
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": UserResponse::from(user)
     })))
 }
 
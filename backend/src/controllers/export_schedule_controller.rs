@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    controllers::usage_controller::ApiResponse,
+    db::repositories::ExportScheduleRepository,
+    errors::ApiError,
+    middleware::auth::AuthUser,
+    models::export_schedule::{ExportRun, ExportSchedule, ExportScheduleRequest},
+    services::export_service::ExportService,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ExportRunQuery {
+    pub limit: Option<i64>,
+}
+
+/// Create an export schedule
+pub async fn create_export_schedule(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Json(req): Json<ExportScheduleRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<ExportSchedule>>), ApiError> {
+    let schedule = ExportService::new(&state.pool)
+        .create_schedule(user_id, req, &state.config.export)
+        .await?;
+
+    tracing::info!("Export schedule created - User: {}, Schedule ID: {}", user_id, schedule.id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiResponse {
+            success: true,
+            data: schedule,
+            next_cursor: None,
+        }),
+    ))
+}
+
+/// List the user's export schedules
+pub async fn list_export_schedules(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+) -> Result<Json<ApiResponse<Vec<ExportSchedule>>>, ApiError> {
+    let schedules = ExportScheduleRepository::new(&state.pool)
+        .list_schedules(user_id)
+        .await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: schedules,
+        next_cursor: None,
+    }))
+}
+
+/// Get an export schedule with its last status and error
+pub async fn get_export_schedule(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path(schedule_id): Path<Uuid>,
+) -> Result<Json<ApiResponse<ExportSchedule>>, ApiError> {
+    let schedule = ExportScheduleRepository::new(&state.pool)
+        .get_schedule(user_id, schedule_id)
+        .await?
+        .ok_or(ApiError::NotFound("Export schedule not found".to_string()))?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: schedule,
+        next_cursor: None,
+    }))
+}
+
+/// Replace an export schedule's settings
+pub async fn update_export_schedule(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<ExportScheduleRequest>,
+) -> Result<Json<ApiResponse<ExportSchedule>>, ApiError> {
+    let schedule = ExportService::new(&state.pool)
+        .update_schedule(user_id, schedule_id, req, &state.config.export)
+        .await?;
+
+    tracing::info!("Export schedule updated - User: {}, Schedule ID: {}", user_id, schedule_id);
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: schedule,
+        next_cursor: None,
+    }))
+}
+
+/// Delete an export schedule and its run history
+pub async fn delete_export_schedule(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path(schedule_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = ExportScheduleRepository::new(&state.pool)
+        .delete_schedule(user_id, schedule_id)
+        .await?;
+    if !deleted {
+        return Err(ApiError::NotFound("Export schedule not found".to_string()));
+    }
+
+    tracing::info!("Export schedule deleted - User: {}, Schedule ID: {}", user_id, schedule_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Queue a run of an export schedule now, covering its period up to now
+pub async fn run_export_schedule(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path(schedule_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ApiResponse<ExportSchedule>>), ApiError> {
+    let schedule = ExportScheduleRepository::new(&state.pool)
+        .trigger_schedule(user_id, schedule_id)
+        .await?
+        .ok_or(ApiError::NotFound("Export schedule not found".to_string()))?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ApiResponse {
+            success: true,
+            data: schedule,
+            next_cursor: None,
+        }),
+    ))
+}
+
+/// List an export schedule's runs, newest first
+pub async fn list_export_runs(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path(schedule_id): Path<Uuid>,
+    Query(query): Query<ExportRunQuery>,
+) -> Result<Json<ApiResponse<Vec<ExportRun>>>, ApiError> {
+    let repo = ExportScheduleRepository::new(&state.pool);
+    repo.get_schedule(user_id, schedule_id)
+        .await?
+        .ok_or(ApiError::NotFound("Export schedule not found".to_string()))?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let runs = repo.list_runs(user_id, schedule_id, limit).await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: runs,
+        next_cursor: None,
+    }))
+}
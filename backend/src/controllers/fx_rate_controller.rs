@@ -0,0 +1,88 @@
+use axum::{
+    extract::{Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState,
+    controllers::usage_controller::ApiResponse,
+    errors::ApiError,
+    middleware::auth::{AdminUser, AuthUser},
+    models::fx_rate::{FxRate, FxRateInput},
+    services::fx_service::FxService,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FxRateQuery {
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FxRatesWritten {
+    pub rates_written: u64,
+}
+
+/// List exchange rates, optionally for one currency
+pub async fn list_fx_rates(
+    State(state): State<AppState>,
+    AuthUser(_user_id): AuthUser,
+    Query(query): Query<FxRateQuery>,
+) -> Result<Json<ApiResponse<Vec<FxRate>>>, ApiError> {
+    let rates = FxService::new(&state.pool)
+        .list_rates(query.currency.as_deref())
+        .await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: rates,
+        next_cursor: None,
+    }))
+}
+
+/// Add or replace exchange rates (admin only)
+pub async fn upsert_fx_rates(
+    State(state): State<AppState>,
+    AdminUser(admin_id): AdminUser,
+    Json(rates): Json<Vec<FxRateInput>>,
+) -> Result<Json<ApiResponse<FxRatesWritten>>, ApiError> {
+    let rates_written = FxService::new(&state.pool).upsert_rates(rates).await?;
+
+    tracing::info!("FX rates updated - Admin: {}, Rates: {}", admin_id, rates_written);
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: FxRatesWritten { rates_written },
+        next_cursor: None,
+    }))
+}
+
+/// Load exchange rates from a CSV file (admin only)
+pub async fn import_fx_rates(
+    State(state): State<AppState>,
+    AdminUser(admin_id): AdminUser,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<ApiResponse<FxRatesWritten>>, ApiError> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    if !content_type.starts_with("text/csv") {
+        return Err(ApiError::ValidationError(
+            "Expected Content-Type: text/csv".to_string(),
+        ));
+    }
+
+    let rates_written = FxService::new(&state.pool).import_csv(&body).await?;
+
+    tracing::info!("FX rates imported - Admin: {}, Rates: {}", admin_id, rates_written);
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: FxRatesWritten { rates_written },
+        next_cursor: None,
+    }))
+}
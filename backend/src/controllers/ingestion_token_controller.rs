@@ -0,0 +1,121 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use base64::Engine;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    controllers::usage_controller::ApiResponse,
+    db::repositories::{IngestionTokenRepository, UsageRepository},
+    errors::ApiError,
+    middleware::auth::AuthUser,
+    models::ingestion_token::{CreateIngestionTokenRequest, IngestionToken, INGESTION_TOKEN_PREFIX},
+};
+
+#[derive(Serialize)]
+pub struct IngestionTokenCreateResponse {
+    #[serde(flatten)]
+    pub token: IngestionToken,
+    /// The raw token. It is only ever returned here.
+    pub secret: String,
+}
+
+/// Create an ingestion token; the raw value is shown once
+pub async fn create_ingestion_token(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Json(req): Json<CreateIngestionTokenRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<IngestionTokenCreateResponse>>), ApiError> {
+    use rand::RngCore;
+    use rand::rngs::OsRng;
+
+    let name = req.name.trim();
+    if name.is_empty() || name.len() > 255 {
+        return Err(ApiError::ValidationError("name must be 1-255 characters".to_string()));
+    }
+
+    if let Some(ids) = req.api_key_ids.as_deref() {
+        if ids.is_empty() {
+            return Err(ApiError::ValidationError(
+                "api_key_ids must not be empty; omit it for an unrestricted token".to_string(),
+            ));
+        }
+
+        let mut unique = ids.to_vec();
+        unique.sort();
+        unique.dedup();
+
+        let owned = UsageRepository::new(&state.pool)
+            .get_api_keys(user_id, &unique)
+            .await?;
+        if owned.len() != unique.len() {
+            return Err(ApiError::NotFound("API key not found".to_string()));
+        }
+    }
+
+    let mut random_bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut random_bytes);
+    let secret = format!(
+        "{}{}",
+        INGESTION_TOKEN_PREFIX,
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(random_bytes)
+    );
+    let prefix: String = secret.chars().take(12).collect();
+
+    let token = IngestionTokenRepository::new(&state.pool)
+        .create_token(
+            user_id,
+            name,
+            &IngestionToken::hash(&secret),
+            &prefix,
+            req.api_key_ids.as_deref(),
+        )
+        .await?;
+
+    tracing::info!("Ingestion token created - User: {}, Token ID: {}", user_id, token.id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiResponse {
+            success: true,
+            data: IngestionTokenCreateResponse { token, secret },
+            next_cursor: None,
+        }),
+    ))
+}
+
+/// List the user's ingestion tokens, including revoked ones
+pub async fn list_ingestion_tokens(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+) -> Result<Json<ApiResponse<Vec<IngestionToken>>>, ApiError> {
+    let tokens = IngestionTokenRepository::new(&state.pool)
+        .list_tokens(user_id)
+        .await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: tokens,
+        next_cursor: None,
+    }))
+}
+
+/// Revoke an ingestion token
+pub async fn revoke_ingestion_token(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path(token_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    IngestionTokenRepository::new(&state.pool)
+        .revoke_token(user_id, token_id)
+        .await?
+        .ok_or(ApiError::NotFound("Ingestion token not found".to_string()))?;
+
+    tracing::info!("Ingestion token revoked - User: {}, Token ID: {}", user_id, token_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
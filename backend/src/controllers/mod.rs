@@ -2,4 +2,10 @@ pub mod auth_controller;
 pub mod usage_controller;
 pub mod prediction_controller;
 pub mod analytics_controller;
-pub mod api_key_controller;
\ No newline at end of file
+pub mod api_key_controller;
+pub mod proxy_controller;
+pub mod ingestion_token_controller;
+pub mod model_price_controller;
+pub mod fx_rate_controller;
+pub mod pricing_rule_controller;
+pub mod export_schedule_controller;
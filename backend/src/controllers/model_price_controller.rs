@@ -0,0 +1,184 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    controllers::usage_controller::ApiResponse,
+    errors::ApiError,
+    middleware::auth::{AdminUser, AuthUser},
+    models::model_price::{
+        CostRecalculation, CostRecalculationRequest, CreateModelPriceRequest, ModelPrice,
+        PriceFileReport, UpdateModelPriceRequest,
+    },
+    services::pricing_service::PricingService,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ModelPriceQuery {
+    pub provider: Option<String>,
+}
+
+/// List the model price catalog, optionally for one provider
+pub async fn list_model_prices(
+    State(state): State<AppState>,
+    AuthUser(_user_id): AuthUser,
+    Query(query): Query<ModelPriceQuery>,
+) -> Result<Json<ApiResponse<Vec<ModelPrice>>>, ApiError> {
+    let prices = PricingService::new(&state.pool)
+        .list_prices(query.provider.as_deref())
+        .await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: prices,
+        next_cursor: None,
+    }))
+}
+
+/// Get one catalog price
+pub async fn get_model_price(
+    State(state): State<AppState>,
+    AuthUser(_user_id): AuthUser,
+    Path(price_id): Path<Uuid>,
+) -> Result<Json<ApiResponse<ModelPrice>>, ApiError> {
+    let price = PricingService::new(&state.pool).get_price(price_id).await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: price,
+        next_cursor: None,
+    }))
+}
+
+/// Add a catalog price (admin only)
+pub async fn create_model_price(
+    State(state): State<AppState>,
+    AdminUser(admin_id): AdminUser,
+    Json(req): Json<CreateModelPriceRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<ModelPrice>>), ApiError> {
+    let price = PricingService::new(&state.pool).create_price(req).await?;
+
+    tracing::info!(
+        "Model price created - Admin: {}, Provider: {}, Pattern: {}",
+        admin_id,
+        price.provider,
+        price.model_pattern
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiResponse {
+            success: true,
+            data: price,
+            next_cursor: None,
+        }),
+    ))
+}
+
+/// Change a catalog price (admin only)
+pub async fn update_model_price(
+    State(state): State<AppState>,
+    AdminUser(admin_id): AdminUser,
+    Path(price_id): Path<Uuid>,
+    Json(req): Json<UpdateModelPriceRequest>,
+) -> Result<Json<ApiResponse<ModelPrice>>, ApiError> {
+    let price = PricingService::new(&state.pool)
+        .update_price(price_id, req)
+        .await?;
+
+    tracing::info!("Model price updated - Admin: {}, Price ID: {}", admin_id, price_id);
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: price,
+        next_cursor: None,
+    }))
+}
+
+/// Remove a catalog price (admin only)
+pub async fn delete_model_price(
+    State(state): State<AppState>,
+    AdminUser(admin_id): AdminUser,
+    Path(price_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    PricingService::new(&state.pool).delete_price(price_id).await?;
+
+    tracing::info!("Model price deleted - Admin: {}, Price ID: {}", admin_id, price_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Re-apply the configured pricing file and report what changed (admin only)
+pub async fn reload_price_file(
+    State(state): State<AppState>,
+    AdminUser(admin_id): AdminUser,
+) -> Result<Json<ApiResponse<PriceFileReport>>, ApiError> {
+    let path = state
+        .config
+        .pricing_file
+        .as_deref()
+        .ok_or_else(|| ApiError::ValidationError("PRICING_FILE is not configured".to_string()))?;
+    let report = PricingService::new(&state.pool).apply_price_file(path).await?;
+
+    tracing::info!(
+        "Pricing file reloaded - Admin: {}, Added: {}, Updated: {}, Conflicts: {}",
+        admin_id,
+        report.added.len(),
+        report.updated.len(),
+        report.conflicts.len()
+    );
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: report,
+        next_cursor: None,
+    }))
+}
+
+/// Recompute stored costs for a date range after a price correction (admin only)
+pub async fn recalculate_costs(
+    State(state): State<AppState>,
+    AdminUser(admin_id): AdminUser,
+    Json(req): Json<CostRecalculationRequest>,
+) -> Result<Json<ApiResponse<CostRecalculation>>, ApiError> {
+    let dry_run = req.dry_run;
+    let recalculation = PricingService::new(&state.pool)
+        .recalculate_costs(admin_id, req)
+        .await?;
+
+    tracing::info!(
+        "Cost recalculation {} - Admin: {}, Rows changed: {}, Old total: {:.4}, New total: {:.4}",
+        if dry_run { "previewed" } else { "applied" },
+        admin_id,
+        recalculation.rows_changed,
+        recalculation.old_total_cost,
+        recalculation.new_total_cost
+    );
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: recalculation,
+        next_cursor: None,
+    }))
+}
+
+/// List recent cost recalculations (admin only)
+pub async fn list_cost_recalculations(
+    State(state): State<AppState>,
+    AdminUser(_admin_id): AdminUser,
+) -> Result<Json<ApiResponse<Vec<CostRecalculation>>>, ApiError> {
+    let recalculations = PricingService::new(&state.pool)
+        .list_recalculations()
+        .await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: recalculations,
+        next_cursor: None,
+    }))
+}
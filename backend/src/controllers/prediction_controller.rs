@@ -1,5 +1,5 @@
 use axum::{
-    extract::State,
+    extract::{Query, State},
     Json,
 };
 
@@ -8,17 +8,24 @@ use crate::{
     services::prediction_service::PredictionService,
     middleware::auth::AuthUser,
     errors::ApiError,
-    models::prediction::Prediction,
+    models::prediction::{Prediction, PredictionQuery},
 };
 
 pub async fn get_predictions(
     State(state): State<AppState>,
     AuthUser(user_id): AuthUser,
+    Query(query): Query<PredictionQuery>,
 ) -> Result<Json<serde_json::Value>, ApiError> {
     let predictions = sqlx::query_as::<_, Prediction>(
-        "SELECT * FROM predictions WHERE user_id = $1 ORDER BY created_at DESC LIMIT 10"
+        r#"
+        SELECT * FROM predictions
+        WHERE user_id = $1 AND ($2::jsonb IS NULL OR tags = $2)
+        ORDER BY created_at DESC
+        LIMIT 10
+        "#
     )
     .bind(user_id)
+    .bind(query.tags.map(sqlx::types::Json))
     .fetch_all(&state.pool)
     .await?;
     
@@ -31,9 +38,12 @@ pub async fn get_predictions(
 pub async fn generate_prediction(
     State(state): State<AppState>,
     AuthUser(user_id): AuthUser,
+    Query(query): Query<PredictionQuery>,
 ) -> Result<Json<serde_json::Value>, ApiError> {
     let service = PredictionService::new(&state.pool);
-    let prediction = service.generate_prediction(user_id, None).await?;
+    let prediction = service
+        .generate_prediction(user_id, None, query.tags.as_ref(), query.timezone.as_deref())
+        .await?;
     
     // Broadcast via WebSocket
     let _ = state.ws_tx.send(crate::websocket::WsMessage::PredictionUpdate {
@@ -41,6 +51,7 @@ pub async fn generate_prediction(
         daily_cost: prediction.predicted_daily_cost,
         weekly_cost: prediction.predicted_weekly_cost,
         monthly_cost: prediction.predicted_monthly_cost,
+        currency: prediction.currency.clone(),
     });
     
     Ok(Json(serde_json::json!({
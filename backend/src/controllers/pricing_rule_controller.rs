@@ -0,0 +1,97 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    controllers::usage_controller::ApiResponse,
+    errors::ApiError,
+    middleware::auth::AuthUser,
+    models::pricing_rule::{CreatePricingRuleRequest, PricingRule, TrueUpReport},
+    services::pricing_service::PricingService,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TrueUpQuery {
+    /// Billing month as `YYYY-MM`; defaults to the last full month.
+    pub month: Option<String>,
+}
+
+/// List the pricing rules of an API key
+pub async fn list_pricing_rules(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path(key_id): Path<Uuid>,
+) -> Result<Json<ApiResponse<Vec<PricingRule>>>, ApiError> {
+    let rules = PricingService::new(&state.pool)
+        .list_rules(user_id, key_id)
+        .await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: rules,
+        next_cursor: None,
+    }))
+}
+
+/// Add a pricing rule to an API key
+pub async fn create_pricing_rule(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path(key_id): Path<Uuid>,
+    Json(req): Json<CreatePricingRuleRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<PricingRule>>), ApiError> {
+    let rule = PricingService::new(&state.pool)
+        .create_rule(user_id, key_id, req)
+        .await?;
+
+    tracing::info!(
+        "Pricing rule created - User: {}, Key: {}, Rule: {}",
+        user_id,
+        key_id,
+        rule.name
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiResponse {
+            success: true,
+            data: rule,
+            next_cursor: None,
+        }),
+    ))
+}
+
+/// Remove a pricing rule; usage already priced with it keeps its cost
+pub async fn delete_pricing_rule(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Path((key_id, rule_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    PricingService::new(&state.pool)
+        .delete_rule(user_id, key_id, rule_id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Month-end comparison of list-price and contracted cost per API key
+pub async fn get_true_up(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Query(query): Query<TrueUpQuery>,
+) -> Result<Json<ApiResponse<TrueUpReport>>, ApiError> {
+    let report = PricingService::new(&state.pool)
+        .true_up(user_id, query.month.as_deref())
+        .await?;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: report,
+        next_cursor: None,
+    }))
+}
@@ -0,0 +1,43 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, RawQuery, State},
+    http::{HeaderMap, Method},
+    response::{IntoResponse, Response},
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    services::proxy_service::{ProxiedRequest, ProxyService},
+    middleware::auth::IngestionAuth,
+    errors::ApiError,
+};
+
+/// Forwards any request under `/proxy/:key_id/*path` to the key's provider.
+pub async fn proxy_request(
+    State(state): State<AppState>,
+    auth: IngestionAuth,
+    Path((key_id, path)): Path<(Uuid, String)>,
+    method: Method,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let service = ProxyService::new(&state.pool, &state.ws_tx, &state.http_client, &state.config);
+    
+    let response = service
+        .forward(
+            &auth,
+            key_id,
+            ProxiedRequest {
+                method,
+                path,
+                query,
+                headers,
+                body,
+            },
+        )
+        .await?;
+    
+    Ok((response.status, response.headers, response.body).into_response())
+}
@@ -1,17 +1,26 @@
 use axum::{
+    body::Body,
     extract::{State, Query},
-    http::StatusCode,
+    http::{header::{CONTENT_DISPOSITION, CONTENT_TYPE}, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::ToPrimitive;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     AppState,
-    models::api_usage::{ApiUsage, CreateUsageRequest},
+    models::api_usage::{ApiUsage, CreateUsageRequest, RawUsageRequest, RecordedUsage, UsageListQuery},
+    models::latency::{LatencyHistogram, LatencyHistogramQuery, LatencyStats},
+    models::money::Money,
+    models::usage_aggregate::{UsageAggregateQuery, UsageAggregation},
+    models::tag::{deserialize_tag_filter, TagKey, TagListQuery, Tags},
+    models::usage_export::UsageExportQuery,
     services::usage_service::UsageService,
-    middleware::auth::AuthUser,
+    middleware::auth::{AuthUser, IngestionAuth},
     errors::ApiError,
 };
 
@@ -20,63 +29,340 @@ pub struct UsageQuery {
     pub start_date: Option<String>,
     pub end_date: Option<String>,
     pub api_key_id: Option<Uuid>,
+    /// See [`Period::parse`](crate::utils::period::Period::parse); defaults to `7d`.
     pub period: Option<String>,
+    /// Also report the previous equivalent period and the change from it.
+    #[serde(default)]
+    pub compare: bool,
+    /// IANA timezone of calendar periods; defaults to the user's preference.
+    pub timezone: Option<String>,
+    /// Reporting currency; defaults to the user's preference.
+    pub currency: Option<String>,
+    /// Only usage carrying all of these tags, written `key:value,key:value`.
+    #[serde(default, deserialize_with = "deserialize_tag_filter")]
+    pub tags: Option<Tags>,
 }
 
 #[derive(Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub data: T,
+    /// Cursor for the next page of a paginated listing; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct UsageStats {
-    pub total_cost: f64,
+    /// Start of the period the stats cover.
+    pub period_start: DateTime<Utc>,
+    /// End of the period, exclusive.
+    pub period_end: DateTime<Utc>,
+    pub total_cost: Money,
+    /// Currency of all costs in the stats.
+    pub currency: String,
+    /// Tokens of token-billed usage only; other units are in `usage_by_unit`.
     pub total_tokens: i64,
+    pub total_input_tokens: i64,
+    pub total_output_tokens: i64,
+    pub total_cached_input_tokens: i64,
+    pub total_cache_write_tokens: i64,
+    pub total_reasoning_tokens: i64,
+    /// Share of input tokens served from the prompt cache, in percent.
+    pub cache_hit_rate: f64,
     pub total_requests: i64,
     pub total_errors: i64,
     pub error_rate: f64,
+    /// Cost of calls that failed.
+    pub failed_cost: Money,
+    pub errors_by_type: Vec<ErrorTypeStats>,
+    pub usage_by_unit: Vec<UnitUsageStats>,
     pub avg_response_time: Option<f64>,
+    /// Response time percentiles; break them down by model or endpoint with
+    /// `GET /usage/aggregate`.
+    pub latency: LatencyStats,
+    /// The previous equivalent period, when requested with `compare`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Box<StatsComparison>>,
 }
 
+/// Change of a metric from the previous period. `percent` is `None` when the
+/// previous value is zero.
+#[derive(Debug, Serialize)]
+pub struct Change<T> {
+    pub absolute: T,
+    pub percent: Option<f64>,
+}
+
+impl Change<Money> {
+    fn between(current: Money, previous: Money) -> Self {
+        let percent = (previous != Money::ZERO)
+            .then(|| ((current - previous) / previous.abs() * Money::ONE_HUNDRED).to_f64())
+            .flatten();
+        Self { absolute: current - previous, percent }
+    }
+}
+
+impl Change<i64> {
+    fn between(current: i64, previous: i64) -> Self {
+        let percent = (previous != 0)
+            .then(|| (current - previous) as f64 / previous.abs() as f64 * 100.0);
+        Self { absolute: current - previous, percent }
+    }
+}
+
+impl Change<f64> {
+    fn between(current: f64, previous: f64) -> Self {
+        let percent = (previous != 0.0).then(|| (current - previous) / previous.abs() * 100.0);
+        Self { absolute: current - previous, percent }
+    }
+}
+
+/// Headline metrics of the current period compared with the previous one.
+#[derive(Serialize)]
+pub struct StatsChanges {
+    pub total_cost: Change<Money>,
+    pub total_tokens: Change<i64>,
+    pub total_requests: Change<i64>,
+    pub total_errors: Change<i64>,
+    /// In percentage points for `absolute`.
+    pub error_rate: Change<f64>,
+    pub failed_cost: Change<Money>,
+    /// In percentage points for `absolute`.
+    pub cache_hit_rate: Change<f64>,
+    pub avg_response_time: Option<Change<f64>>,
+    pub p95_response_time_ms: Option<Change<f64>>,
+}
+
+impl StatsChanges {
+    pub fn between(current: &UsageStats, previous: &UsageStats) -> Self {
+        let optional = |current: Option<f64>, previous: Option<f64>| {
+            current.zip(previous).map(|(current, previous)| Change::<f64>::between(current, previous))
+        };
+
+        Self {
+            total_cost: Change::<Money>::between(current.total_cost, previous.total_cost),
+            total_tokens: Change::<i64>::between(current.total_tokens, previous.total_tokens),
+            total_requests: Change::<i64>::between(current.total_requests, previous.total_requests),
+            total_errors: Change::<i64>::between(current.total_errors, previous.total_errors),
+            error_rate: Change::<f64>::between(current.error_rate, previous.error_rate),
+            failed_cost: Change::<Money>::between(current.failed_cost, previous.failed_cost),
+            cache_hit_rate: Change::<f64>::between(current.cache_hit_rate, previous.cache_hit_rate),
+            avg_response_time: optional(current.avg_response_time, previous.avg_response_time),
+            p95_response_time_ms: optional(current.latency.p95_ms, previous.latency.p95_ms),
+        }
+    }
+}
+
+/// The same stats for the equivalent period just before, e.g. the previous
+/// 7 days for `7d` or the previous month to the same day for `mtd`.
+#[derive(Serialize)]
+pub struct StatsComparison {
+    pub previous: UsageStats,
+    pub changes: StatsChanges,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ErrorTypeStats {
+    pub error_type: Option<String>,
+    pub errors: i64,
+    pub cost: Money,
+}
+
+/// Usage and cost per billing unit and variant. `quantity` is in the unit
+/// itself, or tokens for the `tokens` unit.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UnitUsageStats {
+    pub billing_unit: String,
+    pub unit_variant: Option<String>,
+    pub quantity: f64,
+    pub requests: i64,
+    pub cost: Money,
+}
+
+#[derive(Serialize)]
+pub struct BatchItemResult {
+    pub index: usize,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<RecordedUsage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchUsageResponse {
+    pub accepted: usize,
+    pub deduplicated: usize,
+    pub rejected: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+/// Rejected lines reported back in an import summary; the rest are only counted.
+const MAX_REPORTED_IMPORT_ERRORS: usize = 100;
+
+#[derive(Serialize)]
+pub struct ImportLineError {
+    pub line: usize,
+    pub error: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct ImportSummary {
+    pub total_lines: usize,
+    pub accepted: usize,
+    pub deduplicated: usize,
+    pub rejected: usize,
+    pub errors: Vec<ImportLineError>,
+    pub errors_truncated: bool,
+}
+
+impl ImportSummary {
+    pub fn reject(&mut self, line: usize, error: String) {
+        self.rejected += 1;
+        if self.errors.len() < MAX_REPORTED_IMPORT_ERRORS {
+            self.errors.push(ImportLineError { line, error });
+        } else {
+            self.errors_truncated = true;
+        }
+    }
+}
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
 pub async fn record_usage(
     State(state): State<AppState>,
-    AuthUser(user_id): AuthUser,
-    Json(req): Json<CreateUsageRequest>,
-) -> Result<(StatusCode, Json<ApiResponse<ApiUsage>>), ApiError> {
+    auth: IngestionAuth,
+    headers: HeaderMap,
+    Json(mut req): Json<CreateUsageRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<RecordedUsage>>), ApiError> {
+    if let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) {
+        let key = key
+            .to_str()
+            .map_err(|_| ApiError::ValidationError("Idempotency-Key must be valid ASCII".to_string()))?;
+        
+        match req.external_id.as_deref() {
+            Some(external_id) if external_id != key => {
+                return Err(ApiError::ValidationError(
+                    "Idempotency-Key header does not match external_id".to_string(),
+                ));
+            }
+            _ => req.external_id = Some(key.to_string()),
+        }
+    }
+    
     req.validate()?;
     
-    let service = UsageService::new(&state.pool, &state.ws_tx);
-    let usage = service.record_usage(user_id, req).await?;
+    let service = UsageService::new(&state.pool, &state.ws_tx)
+        .with_key_scope(auth.api_key_scope.as_deref());
+    let recorded = service.record_usage(auth.user_id, req, &state.config.ingestion).await?;
+    
+    let status = if recorded.deduplicated {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
     
     Ok((
-        StatusCode::CREATED,
+        status,
         Json(ApiResponse {
             success: true,
-            data: usage,
+            data: recorded,
+            next_cursor: None,
         })
     ))
 }
 
+pub async fn record_raw_usage(
+    State(state): State<AppState>,
+    auth: IngestionAuth,
+    Json(req): Json<RawUsageRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<RecordedUsage>>), ApiError> {
+    req.validate()?;
+    
+    let service = UsageService::new(&state.pool, &state.ws_tx)
+        .with_key_scope(auth.api_key_scope.as_deref());
+    let recorded = service.record_raw_usage(auth.user_id, req, &state.config.ingestion).await?;
+    
+    let status = if recorded.deduplicated {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    
+    Ok((
+        status,
+        Json(ApiResponse {
+            success: true,
+            data: recorded,
+            next_cursor: None,
+        })
+    ))
+}
+
+pub async fn record_usage_batch(
+    State(state): State<AppState>,
+    auth: IngestionAuth,
+    Json(items): Json<Vec<serde_json::Value>>,
+) -> Result<Json<ApiResponse<BatchUsageResponse>>, ApiError> {
+    let service = UsageService::new(&state.pool, &state.ws_tx)
+        .with_key_scope(auth.api_key_scope.as_deref());
+    let report = service.record_batch(auth.user_id, items, &state.config.ingestion).await?;
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: report,
+        next_cursor: None,
+    }))
+}
+
+pub async fn import_usage(
+    State(state): State<AppState>,
+    auth: IngestionAuth,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<ApiResponse<ImportSummary>>, ApiError> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    
+    if !content_type.starts_with("application/x-ndjson") {
+        return Err(ApiError::ValidationError(
+            "Expected Content-Type: application/x-ndjson".to_string(),
+        ));
+    }
+    
+    let service = UsageService::new(&state.pool, &state.ws_tx)
+        .with_key_scope(auth.api_key_scope.as_deref());
+    let summary = service
+        .import_ndjson(
+            auth.user_id,
+            body.into_data_stream(),
+            &state.config.ingestion,
+        )
+        .await?;
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: summary,
+        next_cursor: None,
+    }))
+}
+
 pub async fn get_usage(
     State(state): State<AppState>,
     AuthUser(user_id): AuthUser,
-    Query(query): Query<UsageQuery>,
+    Query(query): Query<UsageListQuery>,
 ) -> Result<Json<ApiResponse<Vec<ApiUsage>>>, ApiError> {
     let service = UsageService::new(&state.pool, &state.ws_tx);
     
-    let usage = service
-        .get_usage(
-            user_id,
-            query.start_date.as_deref(),
-            query.end_date.as_deref(),
-            query.api_key_id,
-        )
-        .await?;
+    let page = service.get_usage(user_id, query).await?;
     
     Ok(Json(ApiResponse {
         success: true,
-        data: usage,
+        data: page.items,
+        next_cursor: page.next_cursor,
     }))
 }
 
@@ -86,30 +372,83 @@ pub async fn get_stats(
     Query(query): Query<UsageQuery>,
 ) -> Result<Json<ApiResponse<UsageStats>>, ApiError> {
     let service = UsageService::new(&state.pool, &state.ws_tx);
-    let period = query.period.as_deref().unwrap_or("7d");
     
-    let stats = service.calculate_stats(user_id, period).await?;
+    let stats = service.calculate_stats(user_id, &query).await?;
     
     Ok(Json(ApiResponse {
         success: true,
         data: stats,
+        next_cursor: None,
+    }))
+}
+
+pub async fn get_aggregate(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Query(query): Query<UsageAggregateQuery>,
+) -> Result<Json<ApiResponse<UsageAggregation>>, ApiError> {
+    let service = UsageService::new(&state.pool, &state.ws_tx);
+    
+    let aggregation = service
+        .aggregate_usage(user_id, query, &state.config.untagged_tags)
+        .await?;
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: aggregation,
+        next_cursor: None,
+    }))
+}
+
+pub async fn get_latency_histogram(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Query(query): Query<LatencyHistogramQuery>,
+) -> Result<Json<ApiResponse<LatencyHistogram>>, ApiError> {
+    let service = UsageService::new(&state.pool, &state.ws_tx);
+    
+    let histogram = service.latency_histogram(user_id, query).await?;
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: histogram,
+        next_cursor: None,
+    }))
+}
+
+pub async fn get_tags(
+    State(state): State<AppState>,
+    AuthUser(user_id): AuthUser,
+    Query(query): Query<TagListQuery>,
+) -> Result<Json<ApiResponse<Vec<TagKey>>>, ApiError> {
+    let service = UsageService::new(&state.pool, &state.ws_tx);
+    
+    let tags = service.list_tags(user_id, query, &state.config.untagged_tags).await?;
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: tags,
+        next_cursor: None,
     }))
 }
 
 pub async fn export_usage(
     State(state): State<AppState>,
     AuthUser(user_id): AuthUser,
-    Query(query): Query<UsageQuery>,
-) -> Result<(StatusCode, String), ApiError> {
+    Query(query): Query<UsageExportQuery>,
+) -> Result<Response, ApiError> {
     let service = UsageService::new(&state.pool, &state.ws_tx);
     
-    let data = service
-        .export_usage(
-            user_id,
-            query.start_date.as_deref(),
-            query.end_date.as_deref(),
-        )
+    let export = service
+        .export_usage(user_id, query, &state.config.export.parquet_tag_columns, &state.export_slots)
         .await?;
     
-    Ok((StatusCode::OK, data))
+    Ok((
+        [
+            (CONTENT_TYPE, export.content_type.to_string()),
+            (CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", export.filename)),
+        ],
+        Body::from_stream(export.body),
+    )
+        .into_response())
 }
\ No newline at end of file
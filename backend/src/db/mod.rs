@@ -1,4 +1,6 @@
 pub mod repositories;
+#[cfg(test)]
+pub mod testing;
 
 use sqlx::{postgres::PgPoolOptions, PgPool};
 
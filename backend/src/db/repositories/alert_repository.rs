@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::alert::Alert;
+use crate::models::budget::Budget;
+use crate::models::money::Money;
+use crate::errors::ApiError;
+
+pub struct AlertRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> AlertRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    pub async fn list_budgets(&self) -> Result<Vec<Budget>, ApiError> {
+        let budgets = sqlx::query_as::<_, Budget>("SELECT * FROM budgets")
+            .fetch_all(self.pool)
+            .await?;
+        
+        Ok(budgets)
+    }
+    
+    /// Whether an alert of this type was already raised for the user and key
+    /// since `since`.
+    pub async fn alert_exists_since(
+        &self,
+        user_id: Uuid,
+        api_key_id: Option<Uuid>,
+        alert_type: &str,
+        since: DateTime<Utc>,
+    ) -> Result<bool, ApiError> {
+        let exists: (bool,) = sqlx::query_as(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM alerts
+                WHERE user_id = $1 AND api_key_id IS NOT DISTINCT FROM $2
+                  AND alert_type = $3 AND created_at >= $4
+            )
+            "#
+        )
+        .bind(user_id)
+        .bind(api_key_id)
+        .bind(alert_type)
+        .bind(since)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(exists.0)
+    }
+    
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_alert(
+        &self,
+        user_id: Uuid,
+        api_key_id: Option<Uuid>,
+        alert_type: &str,
+        severity: &str,
+        threshold_value: Money,
+        current_value: Money,
+        message: &str,
+    ) -> Result<Alert, ApiError> {
+        let alert = sqlx::query_as::<_, Alert>(
+            r#"
+            INSERT INTO alerts (
+                id, user_id, api_key_id, alert_type, severity,
+                threshold_value, current_value, message, is_read, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, false, NOW())
+            RETURNING *
+            "#
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(api_key_id)
+        .bind(alert_type)
+        .bind(severity)
+        .bind(threshold_value)
+        .bind(current_value)
+        .bind(message)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(alert)
+    }
+}
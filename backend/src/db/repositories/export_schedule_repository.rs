@@ -0,0 +1,301 @@
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, PgPool};
+use uuid::Uuid;
+
+use crate::models::export_schedule::{ExportRun, ExportSchedule, ExportScheduleRequest};
+use crate::errors::ApiError;
+
+/// How long a claimed schedule may run before another worker may claim it.
+const STALE_RUN_MINUTES: i32 = 60;
+
+/// Result of a finished export run.
+#[derive(Default)]
+pub struct RunOutcome {
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub rows_exported: Option<i64>,
+    pub bytes_written: Option<i64>,
+    pub location: Option<String>,
+    pub error: Option<String>,
+}
+
+pub struct ExportScheduleRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> ExportScheduleRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    pub async fn create_schedule(
+        &self,
+        user_id: Uuid,
+        req: &ExportScheduleRequest,
+        encrypted_secret: Option<&str>,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<ExportSchedule, ApiError> {
+        let schedule = sqlx::query_as::<_, ExportSchedule>(
+            r#"
+            INSERT INTO export_schedules (
+                id, user_id, name, cron, timezone, period, format, columns, tag_columns,
+                currency, filters, destination, encrypted_secret, max_attempts, is_active,
+                scheduled_for, next_run_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $16)
+            RETURNING *
+            "#
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(&req.name)
+        .bind(&req.cron)
+        .bind(&req.timezone)
+        .bind(&req.period)
+        .bind(req.format.as_str())
+        .bind(&req.columns)
+        .bind(&req.tag_columns)
+        .bind(&req.currency)
+        .bind(Json(&req.filters))
+        .bind(Json(&req.destination))
+        .bind(encrypted_secret)
+        .bind(req.max_attempts.unwrap_or(3))
+        .bind(req.is_active.unwrap_or(true))
+        .bind(next_run_at)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(schedule)
+    }
+    
+    /// Replaces a schedule's settings, keeping the stored secret when none is
+    /// given, and restarts its run cycle at `next_run_at`.
+    pub async fn update_schedule(
+        &self,
+        user_id: Uuid,
+        schedule_id: Uuid,
+        req: &ExportScheduleRequest,
+        encrypted_secret: Option<&str>,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<Option<ExportSchedule>, ApiError> {
+        let schedule = sqlx::query_as::<_, ExportSchedule>(
+            r#"
+            UPDATE export_schedules
+            SET name = $3, cron = $4, timezone = $5, period = $6, format = $7, columns = $8,
+                tag_columns = $9, currency = $10, filters = $11, destination = $12,
+                encrypted_secret = CASE
+                    WHEN destination->>'type' = 's3' AND $12->>'type' = 's3'
+                    THEN COALESCE($13, encrypted_secret)
+                    ELSE $13
+                END,
+                max_attempts = $14, is_active = $15,
+                scheduled_for = $16, next_run_at = $16, attempt = 0, updated_at = NOW()
+            WHERE id = $1 AND user_id = $2
+            RETURNING *
+            "#
+        )
+        .bind(schedule_id)
+        .bind(user_id)
+        .bind(&req.name)
+        .bind(&req.cron)
+        .bind(&req.timezone)
+        .bind(&req.period)
+        .bind(req.format.as_str())
+        .bind(&req.columns)
+        .bind(&req.tag_columns)
+        .bind(&req.currency)
+        .bind(Json(&req.filters))
+        .bind(Json(&req.destination))
+        .bind(encrypted_secret)
+        .bind(req.max_attempts.unwrap_or(3))
+        .bind(req.is_active.unwrap_or(true))
+        .bind(next_run_at)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(schedule)
+    }
+    
+    pub async fn list_schedules(&self, user_id: Uuid) -> Result<Vec<ExportSchedule>, ApiError> {
+        let schedules = sqlx::query_as::<_, ExportSchedule>(
+            "SELECT * FROM export_schedules WHERE user_id = $1 ORDER BY created_at DESC"
+        )
+        .bind(user_id)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(schedules)
+    }
+    
+    pub async fn get_schedule(
+        &self,
+        user_id: Uuid,
+        schedule_id: Uuid,
+    ) -> Result<Option<ExportSchedule>, ApiError> {
+        let schedule = sqlx::query_as::<_, ExportSchedule>(
+            "SELECT * FROM export_schedules WHERE id = $1 AND user_id = $2"
+        )
+        .bind(schedule_id)
+        .bind(user_id)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(schedule)
+    }
+    
+    pub async fn delete_schedule(&self, user_id: Uuid, schedule_id: Uuid) -> Result<bool, ApiError> {
+        let result = sqlx::query("DELETE FROM export_schedules WHERE id = $1 AND user_id = $2")
+            .bind(schedule_id)
+            .bind(user_id)
+            .execute(self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Makes a schedule due now for a fresh run covering the current time.
+    pub async fn trigger_schedule(
+        &self,
+        user_id: Uuid,
+        schedule_id: Uuid,
+    ) -> Result<Option<ExportSchedule>, ApiError> {
+        let schedule = sqlx::query_as::<_, ExportSchedule>(
+            r#"
+            UPDATE export_schedules
+            SET scheduled_for = NOW(), next_run_at = NOW(), attempt = 0, updated_at = NOW()
+            WHERE id = $1 AND user_id = $2
+            RETURNING *
+            "#
+        )
+        .bind(schedule_id)
+        .bind(user_id)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(schedule)
+    }
+    
+    /// Marks up to `limit` due schedules as running and returns them. Rows
+    /// locked by another worker are skipped, and a run that has not finished
+    /// within an hour is assumed dead and may be claimed again.
+    pub async fn claim_due(&self, limit: i64) -> Result<Vec<ExportSchedule>, ApiError> {
+        let schedules = sqlx::query_as::<_, ExportSchedule>(
+            r#"
+            UPDATE export_schedules
+            SET running_since = NOW()
+            WHERE id IN (
+                SELECT id FROM export_schedules
+                WHERE is_active AND next_run_at <= NOW()
+                  AND (running_since IS NULL OR running_since < NOW() - make_interval(mins => $2))
+                ORDER BY next_run_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+            "#
+        )
+        .bind(limit)
+        .bind(STALE_RUN_MINUTES)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(schedules)
+    }
+    
+    pub async fn start_run(&self, schedule: &ExportSchedule) -> Result<i64, ApiError> {
+        let (run_id,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO export_runs (schedule_id, scheduled_for, attempt)
+            VALUES ($1, $2, $3)
+            RETURNING id
+            "#
+        )
+        .bind(schedule.id)
+        .bind(schedule.scheduled_for)
+        .bind(schedule.attempt + 1)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(run_id)
+    }
+    
+    pub async fn finish_run(&self, run_id: i64, outcome: &RunOutcome) -> Result<(), ApiError> {
+        sqlx::query(
+            r#"
+            UPDATE export_runs
+            SET status = CASE WHEN $7::text IS NULL THEN 'succeeded' ELSE 'failed' END,
+                period_start = $2, period_end = $3, rows_exported = $4, bytes_written = $5,
+                location = $6, error = $7, finished_at = NOW()
+            WHERE id = $1
+            "#
+        )
+        .bind(run_id)
+        .bind(outcome.period_start)
+        .bind(outcome.period_end)
+        .bind(outcome.rows_exported)
+        .bind(outcome.bytes_written)
+        .bind(&outcome.location)
+        .bind(&outcome.error)
+        .execute(self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// Releases a claimed schedule after a run. `attempt` is the number of
+    /// failed attempts of the run still pending, zero once it is settled.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn release(
+        &self,
+        schedule_id: Uuid,
+        status: &str,
+        error: Option<&str>,
+        attempt: i32,
+        scheduled_for: DateTime<Utc>,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), ApiError> {
+        sqlx::query(
+            r#"
+            UPDATE export_schedules
+            SET last_status = $2, last_error = $3, attempt = $4, scheduled_for = $5,
+                next_run_at = $6, last_run_at = NOW(), running_since = NULL
+            WHERE id = $1
+            "#
+        )
+        .bind(schedule_id)
+        .bind(status)
+        .bind(error)
+        .bind(attempt)
+        .bind(scheduled_for)
+        .bind(next_run_at)
+        .execute(self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// Most recent runs of a schedule, newest first.
+    pub async fn list_runs(
+        &self,
+        user_id: Uuid,
+        schedule_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<ExportRun>, ApiError> {
+        let runs = sqlx::query_as::<_, ExportRun>(
+            r#"
+            SELECT r.* FROM export_runs r
+            JOIN export_schedules s ON s.id = r.schedule_id
+            WHERE r.schedule_id = $1 AND s.user_id = $2
+            ORDER BY r.started_at DESC, r.id DESC
+            LIMIT $3
+            "#
+        )
+        .bind(schedule_id)
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(runs)
+    }
+}
@@ -0,0 +1,103 @@
+use chrono::NaiveDate;
+use sqlx::PgPool;
+
+use crate::models::fx_rate::{FxRate, FxRateInput};
+use crate::models::money::Money;
+use crate::errors::ApiError;
+
+pub struct FxRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> FxRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    pub async fn list_rates(&self, currency: Option<&str>) -> Result<Vec<FxRate>, ApiError> {
+        let rates = sqlx::query_as::<_, FxRate>(
+            r#"
+            SELECT * FROM fx_rates
+            WHERE $1::text IS NULL OR currency = $1
+            ORDER BY currency, rate_date DESC
+            "#
+        )
+        .bind(currency)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(rates)
+    }
+    
+    /// All rates of the given currencies.
+    pub async fn list_rates_for(&self, currencies: &[String]) -> Result<Vec<FxRate>, ApiError> {
+        let rates = sqlx::query_as::<_, FxRate>(
+            "SELECT * FROM fx_rates WHERE currency = ANY($1) ORDER BY currency, rate_date"
+        )
+        .bind(currencies)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(rates)
+    }
+    
+    /// Inserts the rates, replacing existing rates for the same currency and
+    /// date. Returns the number of rates written.
+    pub async fn upsert_rates(&self, rates: &[FxRateInput]) -> Result<u64, ApiError> {
+        if rates.is_empty() {
+            return Ok(0);
+        }
+        
+        let currencies: Vec<&str> = rates.iter().map(|r| r.currency.as_str()).collect();
+        let dates: Vec<NaiveDate> = rates.iter().map(|r| r.rate_date).collect();
+        let usd_rates: Vec<Money> = rates.iter().map(|r| r.usd_rate).collect();
+        
+        let result = sqlx::query(
+            r#"
+            INSERT INTO fx_rates (currency, rate_date, usd_rate, created_at, updated_at)
+            SELECT c, d, r, NOW(), NOW()
+            FROM UNNEST($1::varchar[], $2::date[], $3::numeric[]) AS t(c, d, r)
+            ON CONFLICT (currency, rate_date)
+            DO UPDATE SET usd_rate = EXCLUDED.usd_rate, updated_at = NOW()
+            "#
+        )
+        .bind(&currencies)
+        .bind(&dates)
+        .bind(&usd_rates)
+        .execute(self.pool)
+        .await?;
+        
+        Ok(result.rows_affected())
+    }
+    
+    /// Sum of amounts, each in its own currency, converted into `currency`
+    /// with the rate of its date.
+    pub async fn sum_converted(
+        &self,
+        amounts: &[(Money, String, NaiveDate)],
+        currency: &str,
+    ) -> Result<Money, ApiError> {
+        if amounts.is_empty() {
+            return Ok(Money::ZERO);
+        }
+        
+        let values: Vec<Money> = amounts.iter().map(|(amount, _, _)| *amount).collect();
+        let currencies: Vec<&str> = amounts.iter().map(|(_, c, _)| c.as_str()).collect();
+        let dates: Vec<NaiveDate> = amounts.iter().map(|(_, _, d)| *d).collect();
+        
+        let total: (Money,) = sqlx::query_as(
+            r#"
+            SELECT COALESCE(SUM(convert_money(a, c, $4, d)), 0)
+            FROM UNNEST($1::numeric[], $2::varchar[], $3::date[]) AS t(a, c, d)
+            "#
+        )
+        .bind(&values)
+        .bind(&currencies)
+        .bind(&dates)
+        .bind(currency)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(total.0)
+    }
+}
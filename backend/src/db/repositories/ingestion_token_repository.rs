@@ -0,0 +1,100 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::ingestion_token::IngestionToken;
+use crate::errors::ApiError;
+
+pub struct IngestionTokenRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> IngestionTokenRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    pub async fn create_token(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        token_hash: &str,
+        token_prefix: &str,
+        api_key_ids: Option<&[Uuid]>,
+    ) -> Result<IngestionToken, ApiError> {
+        let token = sqlx::query_as::<_, IngestionToken>(
+            r#"
+            INSERT INTO ingestion_tokens (id, user_id, name, token_hash, token_prefix, api_key_ids, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW())
+            RETURNING *
+            "#
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(name)
+        .bind(token_hash)
+        .bind(token_prefix)
+        .bind(api_key_ids)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(token)
+    }
+    
+    pub async fn list_tokens(&self, user_id: Uuid) -> Result<Vec<IngestionToken>, ApiError> {
+        let tokens = sqlx::query_as::<_, IngestionToken>(
+            "SELECT * FROM ingestion_tokens WHERE user_id = $1 ORDER BY created_at DESC"
+        )
+        .bind(user_id)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(tokens)
+    }
+    
+    pub async fn revoke_token(
+        &self,
+        user_id: Uuid,
+        token_id: Uuid,
+    ) -> Result<Option<IngestionToken>, ApiError> {
+        let token = sqlx::query_as::<_, IngestionToken>(
+            r#"
+            UPDATE ingestion_tokens
+            SET revoked_at = COALESCE(revoked_at, NOW())
+            WHERE id = $1 AND user_id = $2
+            RETURNING *
+            "#
+        )
+        .bind(token_id)
+        .bind(user_id)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(token)
+    }
+    
+    /// Looks up an unrevoked token by hash and stamps its last use. The stamp
+    /// is only rewritten once a minute, so a busy token does not turn every
+    /// ingested event into a write on the same row.
+    pub async fn authenticate(&self, token_hash: &str) -> Result<Option<IngestionToken>, ApiError> {
+        let token = sqlx::query_as::<_, IngestionToken>(
+            r#"
+            WITH token AS (
+                SELECT * FROM ingestion_tokens
+                WHERE token_hash = $1 AND revoked_at IS NULL
+            ),
+            touched AS (
+                UPDATE ingestion_tokens
+                SET last_used_at = NOW()
+                WHERE id IN (SELECT id FROM token)
+                  AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')
+            )
+            SELECT * FROM token
+            "#
+        )
+        .bind(token_hash)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(token)
+    }
+}
@@ -1,5 +1,15 @@
+pub mod alert_repository;
+pub mod export_schedule_repository;
+pub mod fx_repository;
+pub mod ingestion_token_repository;
+pub mod pricing_repository;
 pub mod usage_repository;
 pub mod user_repository;
 
+pub use alert_repository::AlertRepository;
+pub use export_schedule_repository::ExportScheduleRepository;
+pub use fx_repository::FxRepository;
+pub use ingestion_token_repository::IngestionTokenRepository;
+pub use pricing_repository::PricingRepository;
 pub use usage_repository::UsageRepository;
 pub use user_repository::UserRepository;
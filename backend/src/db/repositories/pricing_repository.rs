@@ -0,0 +1,510 @@
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, Connection, PgConnection, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::models::model_price::{
+    CostRecalculation, CreateModelPriceRequest, ModelPrice, PriceSource, UpdateModelPriceRequest,
+};
+use crate::models::pricing_rule::{CreatePricingRuleRequest, PricingRule, TrueUpLine};
+use crate::errors::ApiError;
+
+pub struct PricingRepository<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> PricingRepository<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    pub async fn list_prices(&self, provider: Option<&str>) -> Result<Vec<ModelPrice>, ApiError> {
+        let prices = sqlx::query_as::<_, ModelPrice>(
+            r#"
+            SELECT * FROM model_prices
+            WHERE $1::text IS NULL OR provider = $1
+            ORDER BY provider, model_pattern, effective_from
+            "#
+        )
+        .bind(provider)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(prices)
+    }
+    
+    pub async fn find_for_providers(&self, providers: &[String]) -> Result<Vec<ModelPrice>, ApiError> {
+        let prices = sqlx::query_as::<_, ModelPrice>(
+            "SELECT * FROM model_prices WHERE provider = ANY($1)"
+        )
+        .bind(providers)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(prices)
+    }
+    
+    pub async fn get_price(&self, price_id: Uuid) -> Result<Option<ModelPrice>, ApiError> {
+        let price = sqlx::query_as::<_, ModelPrice>(
+            "SELECT * FROM model_prices WHERE id = $1"
+        )
+        .bind(price_id)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(price)
+    }
+    
+    pub async fn count_prices(&self) -> Result<i64, ApiError> {
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM model_prices")
+            .fetch_one(self.pool)
+            .await?;
+        
+        Ok(count.0)
+    }
+    
+    /// Inserts a price version from `effective_from` until `effective_to`.
+    ///
+    /// An open-ended version replaces the open-ended earlier version of the same
+    /// pattern by closing it at `effective_from`. Returns `None` if the range
+    /// overlaps another version.
+    pub async fn create_price(
+        &self,
+        price: &CreateModelPriceRequest,
+        effective_from: DateTime<Utc>,
+        source: PriceSource,
+    ) -> Result<Option<ModelPrice>, ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        
+        create_price(&mut conn, price, effective_from, source).await
+    }
+    
+    /// Overwrites every field of a price version with those of `price`, marking
+    /// it as loaded from the pricing file. Returns `None` if the new range
+    /// overlaps another version. An open-ended `price` keeps the current end.
+    pub async fn replace_price(
+        &self,
+        price_id: Uuid,
+        price: &CreateModelPriceRequest,
+    ) -> Result<Option<ModelPrice>, ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        
+        replace_price(&mut conn, price_id, price).await
+    }
+    
+    /// Starts a transaction for changing several catalog entries at once.
+    pub async fn begin(&self) -> Result<PriceCatalogTransaction, ApiError> {
+        Ok(PriceCatalogTransaction { tx: self.pool.begin().await? })
+    }
+    
+    pub async fn update_price(
+        &self,
+        price_id: Uuid,
+        update: &UpdateModelPriceRequest,
+    ) -> Result<Option<ModelPrice>, ApiError> {
+        let updated = sqlx::query_as::<_, ModelPrice>(
+            r#"
+            UPDATE model_prices
+            SET
+                cost_per_1k_input = COALESCE($1, cost_per_1k_input),
+                cost_per_1k_output = COALESCE($2, cost_per_1k_output),
+                cost_per_1k_cached_input = COALESCE($3, cost_per_1k_cached_input),
+                cost_per_1k_cache_write = COALESCE($4, cost_per_1k_cache_write),
+                cost_per_1k_reasoning = COALESCE($5, cost_per_1k_reasoning),
+                unit_prices = COALESCE($6, unit_prices),
+                currency = COALESCE($7, currency),
+                updated_at = NOW()
+            WHERE id = $8
+            RETURNING *
+            "#
+        )
+        .bind(update.cost_per_1k_input)
+        .bind(update.cost_per_1k_output)
+        .bind(update.cost_per_1k_cached_input)
+        .bind(update.cost_per_1k_cache_write)
+        .bind(update.cost_per_1k_reasoning)
+        .bind(update.unit_prices.as_ref().map(Json))
+        .bind(&update.currency)
+        .bind(price_id)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(updated)
+    }
+    
+    pub async fn create_recalculation(
+        &self,
+        recalculation: &CostRecalculation,
+    ) -> Result<CostRecalculation, ApiError> {
+        let created = sqlx::query_as::<_, CostRecalculation>(
+            r#"
+            INSERT INTO cost_recalculations (
+                id, requested_by, range_start, range_end, api_key_id, model_name, reason,
+                rows_scanned, rows_changed, rows_unpriced, old_total_cost, new_total_cost,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            RETURNING *
+            "#
+        )
+        .bind(recalculation.id)
+        .bind(recalculation.requested_by)
+        .bind(recalculation.range_start)
+        .bind(recalculation.range_end)
+        .bind(recalculation.api_key_id)
+        .bind(&recalculation.model_name)
+        .bind(&recalculation.reason)
+        .bind(recalculation.rows_scanned)
+        .bind(recalculation.rows_changed)
+        .bind(recalculation.rows_unpriced)
+        .bind(recalculation.old_total_cost)
+        .bind(recalculation.new_total_cost)
+        .bind(recalculation.created_at)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(created)
+    }
+    
+    /// Stores the counters of a running recalculation, marking it complete
+    /// when `completed_at` is set.
+    pub async fn update_recalculation(&self, recalculation: &CostRecalculation) -> Result<(), ApiError> {
+        sqlx::query(
+            r#"
+            UPDATE cost_recalculations
+            SET rows_scanned = $2, rows_changed = $3, rows_unpriced = $4,
+                old_total_cost = $5, new_total_cost = $6, completed_at = $7
+            WHERE id = $1
+            "#
+        )
+        .bind(recalculation.id)
+        .bind(recalculation.rows_scanned)
+        .bind(recalculation.rows_changed)
+        .bind(recalculation.rows_unpriced)
+        .bind(recalculation.old_total_cost)
+        .bind(recalculation.new_total_cost)
+        .bind(recalculation.completed_at)
+        .execute(self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn list_recalculations(&self, limit: i64) -> Result<Vec<CostRecalculation>, ApiError> {
+        let recalculations = sqlx::query_as::<_, CostRecalculation>(
+            "SELECT * FROM cost_recalculations ORDER BY created_at DESC LIMIT $1"
+        )
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(recalculations)
+    }
+    
+    pub async fn delete_price(&self, price_id: Uuid) -> Result<bool, ApiError> {
+        let result = sqlx::query("DELETE FROM model_prices WHERE id = $1")
+            .bind(price_id)
+            .execute(self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    pub async fn list_rules(&self, api_key_id: Uuid) -> Result<Vec<PricingRule>, ApiError> {
+        let rules = sqlx::query_as::<_, PricingRule>(
+            "SELECT * FROM pricing_rules WHERE api_key_id = $1 ORDER BY effective_from, name"
+        )
+        .bind(api_key_id)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(rules)
+    }
+    
+    pub async fn find_rules_for_keys(&self, api_key_ids: &[Uuid]) -> Result<Vec<PricingRule>, ApiError> {
+        let rules = sqlx::query_as::<_, PricingRule>(
+            "SELECT * FROM pricing_rules WHERE api_key_id = ANY($1)"
+        )
+        .bind(api_key_ids)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(rules)
+    }
+    
+    pub async fn list_all_rules(&self) -> Result<Vec<PricingRule>, ApiError> {
+        let rules = sqlx::query_as::<_, PricingRule>("SELECT * FROM pricing_rules")
+            .fetch_all(self.pool)
+            .await?;
+        
+        Ok(rules)
+    }
+    
+    pub async fn create_rule(
+        &self,
+        api_key_id: Uuid,
+        rule: &CreatePricingRuleRequest,
+        effective_from: DateTime<Utc>,
+    ) -> Result<PricingRule, ApiError> {
+        let created = sqlx::query_as::<_, PricingRule>(
+            r#"
+            INSERT INTO pricing_rules (
+                id, api_key_id, name, model_pattern, discount_percent, batch_discount_percent,
+                tiers, minimum_commitment, effective_from, effective_to, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW(), NOW())
+            RETURNING *
+            "#
+        )
+        .bind(Uuid::new_v4())
+        .bind(api_key_id)
+        .bind(&rule.name)
+        .bind(&rule.model_pattern)
+        .bind(rule.discount_percent)
+        .bind(rule.batch_discount_percent)
+        .bind(Json(&rule.tiers))
+        .bind(rule.minimum_commitment)
+        .bind(effective_from)
+        .bind(rule.effective_to)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(created)
+    }
+    
+    pub async fn delete_rule(&self, api_key_id: Uuid, rule_id: Uuid) -> Result<bool, ApiError> {
+        let result = sqlx::query("DELETE FROM pricing_rules WHERE id = $1 AND api_key_id = $2")
+            .bind(rule_id)
+            .bind(api_key_id)
+            .execute(self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// List-price and contracted cost of each of a user's keys between `start`
+    /// and `end`, in the key's currency, with the minimum commitments of the
+    /// rules in effect during the period.
+    pub async fn get_true_up(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<TrueUpLine>, ApiError> {
+        let lines = sqlx::query_as::<_, TrueUpLine>(
+            r#"
+            WITH period_usage AS (
+                SELECT
+                    u.api_key_id,
+                    SUM(convert_money(u.list_cost, u.currency, k.currency, DATE(u.timestamp))) AS list_cost,
+                    SUM(convert_money(u.cost, u.currency, k.currency, DATE(u.timestamp))) AS contracted_cost
+                FROM api_usage u
+                JOIN api_keys k ON k.id = u.api_key_id
+                WHERE u.user_id = $1 AND u.timestamp >= $2 AND u.timestamp < $3
+                GROUP BY u.api_key_id
+            ),
+            commitments AS (
+                SELECT api_key_id, SUM(minimum_commitment) AS minimum_commitment
+                FROM pricing_rules
+                WHERE minimum_commitment IS NOT NULL
+                  AND effective_from < $3
+                  AND COALESCE(effective_to, 'infinity'::timestamptz) > $2
+                GROUP BY api_key_id
+            )
+            SELECT
+                k.id AS api_key_id,
+                k.name AS api_key_name,
+                k.currency,
+                COALESCE(pu.list_cost, 0) AS list_cost,
+                COALESCE(pu.contracted_cost, 0) AS contracted_cost,
+                COALESCE(c.minimum_commitment, 0) AS minimum_commitment
+            FROM api_keys k
+            LEFT JOIN period_usage pu ON pu.api_key_id = k.id
+            LEFT JOIN commitments c ON c.api_key_id = k.id
+            WHERE k.user_id = $1
+            ORDER BY k.name
+            "#
+        )
+        .bind(user_id)
+        .bind(start)
+        .bind(end)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(lines)
+    }
+}
+
+/// Catalog changes that are committed together; dropping it rolls them back.
+pub struct PriceCatalogTransaction {
+    tx: Transaction<'static, Postgres>,
+}
+
+impl PriceCatalogTransaction {
+    /// As [`PricingRepository::create_price`].
+    pub async fn create_price(
+        &mut self,
+        price: &CreateModelPriceRequest,
+        effective_from: DateTime<Utc>,
+        source: PriceSource,
+    ) -> Result<Option<ModelPrice>, ApiError> {
+        create_price(&mut self.tx, price, effective_from, source).await
+    }
+    
+    /// As [`PricingRepository::replace_price`].
+    pub async fn replace_price(
+        &mut self,
+        price_id: Uuid,
+        price: &CreateModelPriceRequest,
+    ) -> Result<Option<ModelPrice>, ApiError> {
+        replace_price(&mut self.tx, price_id, price).await
+    }
+    
+    pub async fn commit(self) -> Result<(), ApiError> {
+        self.tx.commit().await?;
+        
+        Ok(())
+    }
+}
+
+// Both run in a transaction of their own, or a savepoint when `conn` is
+// already in one, so a conflict leaves no partial change behind.
+async fn create_price(
+    conn: &mut PgConnection,
+    price: &CreateModelPriceRequest,
+    effective_from: DateTime<Utc>,
+    source: PriceSource,
+) -> Result<Option<ModelPrice>, ApiError> {
+    let mut tx = conn.begin().await?;
+    
+    if price.effective_to.is_none() {
+        sqlx::query(
+            r#"
+            UPDATE model_prices
+            SET effective_to = $3, updated_at = NOW()
+            WHERE provider = $1 AND model_pattern = $2
+              AND effective_to IS NULL AND effective_from < $3
+            "#
+        )
+        .bind(&price.provider)
+        .bind(&price.model_pattern)
+        .bind(effective_from)
+        .execute(&mut *tx)
+        .await?;
+    }
+    
+    let overlaps: (bool,) = sqlx::query_as(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM model_prices
+            WHERE provider = $1 AND model_pattern = $2
+              AND effective_from < COALESCE($4, 'infinity'::timestamptz)
+              AND COALESCE(effective_to, 'infinity'::timestamptz) > $3
+        )
+        "#
+    )
+    .bind(&price.provider)
+    .bind(&price.model_pattern)
+    .bind(effective_from)
+    .bind(price.effective_to)
+    .fetch_one(&mut *tx)
+    .await?;
+    
+    if overlaps.0 {
+        return Ok(None);
+    }
+    
+    let created = sqlx::query_as::<_, ModelPrice>(
+        r#"
+        INSERT INTO model_prices (
+            id, provider, model_pattern, cost_per_1k_input, cost_per_1k_output,
+            cost_per_1k_cached_input, cost_per_1k_cache_write, cost_per_1k_reasoning,
+            unit_prices, effective_from, effective_to, currency, source, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, NOW(), NOW())
+        RETURNING *
+        "#
+    )
+    .bind(Uuid::new_v4())
+    .bind(&price.provider)
+    .bind(&price.model_pattern)
+    .bind(price.cost_per_1k_input)
+    .bind(price.cost_per_1k_output)
+    .bind(price.cost_per_1k_cached_input)
+    .bind(price.cost_per_1k_cache_write)
+    .bind(price.cost_per_1k_reasoning)
+    .bind(Json(&price.unit_prices))
+    .bind(effective_from)
+    .bind(price.effective_to)
+    .bind(&price.currency)
+    .bind(source.as_str())
+    .fetch_one(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    
+    Ok(Some(created))
+}
+
+async fn replace_price(
+    conn: &mut PgConnection,
+    price_id: Uuid,
+    price: &CreateModelPriceRequest,
+) -> Result<Option<ModelPrice>, ApiError> {
+    let mut tx = conn.begin().await?;
+    
+    let overlaps: (bool,) = sqlx::query_as(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM model_prices current, model_prices other
+            WHERE current.id = $1 AND other.id <> current.id
+              AND other.provider = current.provider
+              AND other.model_pattern = current.model_pattern
+              AND other.effective_from < COALESCE($2, current.effective_to, 'infinity'::timestamptz)
+              AND COALESCE(other.effective_to, 'infinity'::timestamptz) > current.effective_from
+        )
+        "#
+    )
+    .bind(price_id)
+    .bind(price.effective_to)
+    .fetch_one(&mut *tx)
+    .await?;
+    
+    if overlaps.0 {
+        return Ok(None);
+    }
+    
+    let replaced = sqlx::query_as::<_, ModelPrice>(
+        r#"
+        UPDATE model_prices
+        SET
+            cost_per_1k_input = $1,
+            cost_per_1k_output = $2,
+            cost_per_1k_cached_input = $3,
+            cost_per_1k_cache_write = $4,
+            cost_per_1k_reasoning = $5,
+            unit_prices = $6,
+            effective_to = COALESCE($7, effective_to),
+            currency = $8,
+            source = $9,
+            updated_at = NOW()
+        WHERE id = $10
+        RETURNING *
+        "#
+    )
+    .bind(price.cost_per_1k_input)
+    .bind(price.cost_per_1k_output)
+    .bind(price.cost_per_1k_cached_input)
+    .bind(price.cost_per_1k_cache_write)
+    .bind(price.cost_per_1k_reasoning)
+    .bind(Json(&price.unit_prices))
+    .bind(price.effective_to)
+    .bind(&price.currency)
+    .bind(PriceSource::File.as_str())
+    .bind(price_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    
+    tx.commit().await?;
+    
+    Ok(replaced)
+}
@@ -1,12 +1,37 @@
-use sqlx::PgPool;
-use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+
+use sqlx::{postgres::{PgArguments, PgRow}, query::QueryAs, types::Json, PgPool, Postgres, QueryBuilder, Row, Transaction};
+use chrono::{DateTime, Duration, DurationRound, NaiveDate, Utc};
 use uuid::Uuid;
 
-use crate::models::api_usage::ApiUsage;
+use crate::models::api_usage::{
+    ApiUsage, ExportedUsage, NewUsage, RecordedUsage, SortOrder, UsageCursor, UsageFilter,
+    UsageListQuery,
+};
 use crate::models::api_key::ApiKey;
-use crate::controllers::usage_controller::UsageStats;
+use crate::models::usage_aggregate::{GroupBy, UsageAggregate, UsageAggregateQuery};
+use crate::models::tag::{TagKey, TagValue, Tags, UntaggedTags};
+use crate::models::calendar::Calendar;
+use crate::models::latency::{LatencyDimension, LatencyHistogramGroup, LatencyHistogramQuery, LatencyStats};
+use crate::models::money::Money;
+use crate::controllers::usage_controller::{ErrorTypeStats, UnitUsageStats, UsageStats};
 use crate::errors::ApiError;
 
+#[derive(sqlx::FromRow)]
+struct StatsRow {
+    total_cost: Money,
+    total_tokens: i64,
+    total_input_tokens: i64,
+    total_output_tokens: i64,
+    total_cached_input_tokens: i64,
+    total_cache_write_tokens: i64,
+    total_reasoning_tokens: i64,
+    total_requests: i64,
+    total_errors: i64,
+    failed_cost: Money,
+    avg_response_time: Option<f64>,
+}
+
 pub struct UsageRepository<'a> {
     pool: &'a PgPool,
 }
@@ -16,181 +41,1075 @@ impl<'a> UsageRepository<'a> {
         Self { pool }
     }
     
-    pub async fn create_usage(
-        &self,
-        user_id: Uuid,
-        api_key_id: Uuid,
-        input_tokens: i32,
-        output_tokens: i32,
-        total_tokens: i32,
-        cost: f64,
-        model_name: String,
-        endpoint: Option<String>,
-        status_code: Option<i32>,
-        response_time_ms: Option<i32>,
-        metadata: Option<serde_json::Value>,
-    ) -> Result<ApiUsage, ApiError> {
-        let usage = sqlx::query_as::<_, ApiUsage>(
-            r#"
-            INSERT INTO api_usage (
-                user_id, api_key_id, timestamp, input_tokens, output_tokens,
-                total_tokens, requests, errors, cost, model_name, endpoint,
-                status_code, response_time_ms, metadata
-            )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
-            RETURNING *
-            "#
-        )
-        .bind(user_id)
-        .bind(api_key_id)
-        .bind(Utc::now())
-        .bind(input_tokens)
-        .bind(output_tokens)
-        .bind(total_tokens)
-        .bind(1) // requests
-        .bind(0) // errors
-        .bind(cost)
-        .bind(model_name)
-        .bind(endpoint)
-        .bind(status_code)
-        .bind(response_time_ms)
-        .bind(metadata)
-        .fetch_one(self.pool)
-        .await?;
+    /// Inserts a usage row. When the row carries an `external_id` that was already
+    /// recorded for the user, the stored row is returned and nothing is inserted.
+    pub async fn create_usage(&self, usage: &NewUsage) -> Result<RecordedUsage, ApiError> {
+        let inserted = insert_usage_query(usage)
+            .fetch_optional(self.pool)
+            .await?;
         
-        Ok(usage)
+        match inserted {
+            Some(usage) => Ok(RecordedUsage { usage, deduplicated: false }),
+            None => {
+                let existing = find_by_external_id_query(usage)
+                    .fetch_one(self.pool)
+                    .await?;
+                Ok(RecordedUsage { usage: existing, deduplicated: true })
+            }
+        }
+    }
+    
+    /// Inserts all rows in a single transaction, returning them in input order.
+    pub async fn create_usages(&self, rows: &[NewUsage]) -> Result<Vec<RecordedUsage>, ApiError> {
+        let mut tx = self.pool.begin().await?;
+        let mut recorded = Vec::with_capacity(rows.len());
+        
+        for row in rows {
+            let inserted = insert_usage_query(row).fetch_optional(&mut *tx).await?;
+            
+            recorded.push(match inserted {
+                Some(usage) => RecordedUsage { usage, deduplicated: false },
+                None => RecordedUsage {
+                    usage: find_by_external_id_query(row).fetch_one(&mut *tx).await?,
+                    deduplicated: true,
+                },
+            });
+        }
+        
+        tx.commit().await?;
+        
+        Ok(recorded)
     }
     
-    pub async fn get_usage_by_date_range(
+    /// Queues the hourly windows containing the given events for rollup
+    /// recomputation and flags predictions whose 30-day input covered them.
+    ///
+    /// Each event is `(user_id, api_key_id, timestamp)`.
+    pub async fn mark_for_recompute(
         &self,
-        user_id: Uuid,
-        start: DateTime<Utc>,
-        end: DateTime<Utc>,
-        api_key_id: Option<Uuid>,
-    ) -> Result<Vec<ApiUsage>, ApiError> {
-        let query = sqlx::query_as::<_, ApiUsage>(
+        events: &[(Uuid, Uuid, DateTime<Utc>)],
+        reason: &str,
+    ) -> Result<(), ApiError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        
+        let hours: BTreeSet<(Uuid, Uuid, DateTime<Utc>)> = events
+            .iter()
+            .map(|(user_id, key_id, ts)| {
+                (*user_id, *key_id, ts.duration_trunc(Duration::hours(1)).unwrap_or(*ts))
+            })
+            .collect();
+        
+        let mut user_ids = Vec::with_capacity(hours.len());
+        let mut key_ids = Vec::with_capacity(hours.len());
+        let mut window_starts = Vec::with_capacity(hours.len());
+        let mut ranges: BTreeMap<(Uuid, Uuid), (DateTime<Utc>, DateTime<Utc>)> = BTreeMap::new();
+        
+        for (user_id, key_id, start) in hours {
+            user_ids.push(user_id);
+            key_ids.push(key_id);
+            window_starts.push(start);
+            
+            let range = ranges.entry((user_id, key_id)).or_insert((start, start));
+            range.0 = range.0.min(start);
+            range.1 = range.1.max(start + Duration::hours(1));
+        }
+        
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query(
             r#"
-            SELECT * FROM api_usage
-            WHERE user_id = $1 AND timestamp BETWEEN $2 AND $3
+            INSERT INTO usage_recompute_windows (user_id, api_key_id, window_start, window_end, reason)
+            SELECT u, k, w, w + INTERVAL '1 hour', $4
+            FROM UNNEST($1::uuid[], $2::uuid[], $3::timestamptz[]) AS t(u, k, w)
+            ON CONFLICT (user_id, api_key_id, window_start, reason) WHERE processed_at IS NULL
+            DO NOTHING
             "#
         )
-        .bind(user_id)
-        .bind(start)
-        .bind(end);
+        .bind(&user_ids)
+        .bind(&key_ids)
+        .bind(&window_starts)
+        .bind(reason)
+        .execute(&mut *tx)
+        .await?;
         
-        let usage = if let Some(key_id) = api_key_id {
-            sqlx::query_as::<_, ApiUsage>(
+        for ((user_id, key_id), (from, to)) in ranges {
+            sqlx::query(
                 r#"
-                SELECT * FROM api_usage
-                WHERE user_id = $1 AND timestamp BETWEEN $2 AND $3 AND api_key_id = $4
-                ORDER BY timestamp DESC
+                UPDATE predictions
+                SET is_stale = true
+                WHERE user_id = $1
+                  AND (api_key_id IS NULL OR api_key_id = $2)
+                  AND prediction_date >= $3
+                  AND prediction_date - INTERVAL '30 days' <= $4
                 "#
             )
             .bind(user_id)
-            .bind(start)
-            .bind(end)
             .bind(key_id)
+            .bind(from)
+            .bind(to)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        tx.commit().await?;
+        
+        Ok(())
+    }
+    
+    /// A page of usage in a date range matching the query's filters, ordered
+    /// by its sort column and `id`, starting after `cursor`.
+    pub async fn list_usage(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        query: &UsageListQuery,
+        cursor: Option<&UsageCursor>,
+        limit: i64,
+    ) -> Result<Vec<ApiUsage>, ApiError> {
+        let column = query.sort.column();
+        let direction = query.order.as_sql();
+        let comparison = match query.order {
+            SortOrder::Asc => ">",
+            SortOrder::Desc => "<",
+        };
+    
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("SELECT * FROM api_usage WHERE ");
+        push_usage_filter(&mut builder, user_id, start, end, &query.filter);
+    
+        if let Some(cursor) = cursor {
+            builder.push(format!(" AND ({}, id) {} (CAST(", column, comparison));
+            builder.push_bind(&cursor.value);
+            builder.push(format!(" AS {}), ", query.sort.sql_type()));
+            builder.push_bind(cursor.id);
+            builder.push(")");
+        }
+    
+        builder.push(format!(" ORDER BY {} {}, id {} LIMIT ", column, direction, direction));
+        builder.push_bind(limit);
+    
+        let usage = builder
+            .build_query_as::<ApiUsage>()
             .fetch_all(self.pool)
-            .await?
+            .await?;
+    
+        Ok(usage)
+    }
+    
+    /// Usage metrics between `start` and `end` per combination of the given
+    /// dimensions, with costs converted into `currency`. The SQL is built from
+    /// fixed fragments per dimension; every value, including metadata and tag
+    /// keys, is bound as a parameter. Time buckets follow `calendar`, and
+    /// usage missing a grouped tag is reported per `untagged`. At most `limit`
+    /// groups, those with the largest cost, are returned per time bucket.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn aggregate_usage(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        dimensions: &[GroupBy],
+        query: &UsageAggregateQuery,
+        currency: &str,
+        calendar: &Calendar,
+        untagged: &UntaggedTags,
+        limit: i64,
+    ) -> Result<Vec<UsageAggregate>, ApiError> {
+        // Groups are ranked by cost within their time bucket, so the limit
+        // applies to each bucket rather than cutting off the later ones
+        let time_columns: Vec<String> = dimensions
+            .iter()
+            .enumerate()
+            .filter(|(_, dimension)| dimension.is_time_bucket())
+            .map(|(i, _)| format!("g{}", i + 1))
+            .collect();
+        let partition = if time_columns.is_empty() {
+            String::new()
         } else {
-            query
-                .fetch_all(self.pool)
-                .await?
+            format!("PARTITION BY {} ", time_columns.join(", "))
         };
         
-        Ok(usage)
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT * FROM (SELECT grouped.*, ROW_NUMBER() OVER ({}ORDER BY cost DESC) AS group_rank FROM (SELECT ",
+            partition
+        ));
+        
+        for (i, dimension) in dimensions.iter().enumerate() {
+            push_group_expression(&mut builder, dimension, calendar, untagged);
+            builder.push(format!(" AS g{}, ", i + 1));
+        }
+        
+        builder.push("COALESCE(SUM(convert_money(cost, currency, ");
+        builder.push_bind(currency);
+        builder.push(
+            r#", timestamp::date)), 0) AS cost,
+                COALESCE(SUM(total_tokens), 0)::bigint AS total_tokens,
+                COALESCE(SUM(input_tokens), 0)::bigint AS input_tokens,
+                COALESCE(SUM(output_tokens), 0)::bigint AS output_tokens,
+                COALESCE(SUM(requests), 0)::bigint AS requests,
+                COALESCE(SUM(errors), 0)::bigint AS errors,
+                AVG(response_time_ms)::float8 AS avg_response_time_ms,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY response_time_ms) AS p50_ms,
+                percentile_cont(0.9) WITHIN GROUP (ORDER BY response_time_ms) AS p90_ms,
+                percentile_cont(0.95) WITHIN GROUP (ORDER BY response_time_ms) AS p95_ms,
+                percentile_cont(0.99) WITHIN GROUP (ORDER BY response_time_ms) AS p99_ms,
+                MAX(response_time_ms) AS max_ms
+            FROM api_usage
+            WHERE "#,
+        );
+        push_usage_filter(&mut builder, user_id, start, end, &query.filter);
+        
+        if *untagged == UntaggedTags::Exclude {
+            for dimension in dimensions {
+                if let GroupBy::Tag(key) = dimension {
+                    builder.push(" AND tags ? ");
+                    builder.push_bind(key.clone());
+                }
+            }
+        }
+        
+        // Grouped expressions are referenced by position, since each bound
+        // metadata or tag key is a distinct parameter
+        if !dimensions.is_empty() {
+            let positions: Vec<String> = (1..=dimensions.len()).map(|i| i.to_string()).collect();
+            builder.push(" GROUP BY ");
+            builder.push(positions.join(", "));
+        }
+        
+        builder.push(") grouped) ranked WHERE group_rank <= ");
+        builder.push_bind(limit);
+        
+        let mut order = time_columns;
+        order.push("cost DESC".to_string());
+        builder.push(" ORDER BY ");
+        builder.push(order.join(", "));
+        
+        let rows = builder.build().fetch_all(self.pool).await?;
+        
+        rows.iter()
+            .map(|row| read_aggregate(row, dimensions, calendar))
+            .collect()
     }
     
+    /// Usage totals from `start` up to `end` (open-ended when `None`), with
+    /// costs converted into `currency` at the rate of each usage date, limited
+    /// to usage carrying all of `tags`.
     pub async fn calculate_stats(
         &self,
         user_id: Uuid,
         start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        tags: Option<&Tags>,
+        currency: &str,
     ) -> Result<UsageStats, ApiError> {
-        let stats = sqlx::query_as::<_, (f64, i64, i64, i64, Option<f64>)>(
+        let stats = sqlx::query_as::<_, StatsRow>(
             r#"
             SELECT
-                COALESCE(SUM(cost), 0) as total_cost,
-                COALESCE(SUM(total_tokens), 0) as total_tokens,
-                COALESCE(SUM(requests), 0) as total_requests,
-                COALESCE(SUM(errors), 0) as total_errors,
-                AVG(response_time_ms) as avg_response_time
+                COALESCE(SUM(convert_money(cost, currency, $3, timestamp::date)), 0) as total_cost,
+                COALESCE(SUM(total_tokens), 0)::bigint as total_tokens,
+                COALESCE(SUM(input_tokens), 0)::bigint as total_input_tokens,
+                COALESCE(SUM(output_tokens), 0)::bigint as total_output_tokens,
+                COALESCE(SUM(cached_input_tokens), 0)::bigint as total_cached_input_tokens,
+                COALESCE(SUM(cache_write_tokens), 0)::bigint as total_cache_write_tokens,
+                COALESCE(SUM(reasoning_tokens), 0)::bigint as total_reasoning_tokens,
+                COALESCE(SUM(requests), 0)::bigint as total_requests,
+                COALESCE(SUM(errors), 0)::bigint as total_errors,
+                COALESCE(SUM(CASE WHEN errors > 0 THEN convert_money(cost, currency, $3, timestamp::date) ELSE 0 END), 0) as failed_cost,
+                AVG(response_time_ms)::float8 as avg_response_time
             FROM api_usage
             WHERE user_id = $1 AND timestamp >= $2
+              AND ($5::timestamptz IS NULL OR timestamp < $5)
+              AND ($4::jsonb IS NULL OR tags @> $4)
             "#
         )
         .bind(user_id)
         .bind(start)
+        .bind(currency)
+        .bind(tags.map(Json))
+        .bind(end)
         .fetch_one(self.pool)
         .await?;
         
-        let error_rate = if stats.2 > 0 {
-            (stats.3 as f64 / stats.2 as f64) * 100.0
+        let errors_by_type = self
+            .get_error_breakdown(user_id, start, end, None, tags, currency)
+            .await?;
+        let usage_by_unit = self.get_unit_breakdown(user_id, start, end, tags, currency).await?;
+        let latency = self.get_latency_stats(user_id, start, end, None, tags).await?;
+        
+        let error_rate = if stats.total_requests > 0 {
+            (stats.total_errors as f64 / stats.total_requests as f64) * 100.0
+        } else {
+            0.0
+        };
+        
+        let cache_hit_rate = if stats.total_input_tokens > 0 {
+            (stats.total_cached_input_tokens as f64 / stats.total_input_tokens as f64) * 100.0
         } else {
             0.0
         };
         
         Ok(UsageStats {
-            total_cost: stats.0,
-            total_tokens: stats.1,
-            total_requests: stats.2,
-            total_errors: stats.3,
+            period_start: start,
+            period_end: end.unwrap_or_else(Utc::now),
+            total_cost: stats.total_cost,
+            currency: currency.to_string(),
+            total_tokens: stats.total_tokens,
+            total_input_tokens: stats.total_input_tokens,
+            total_output_tokens: stats.total_output_tokens,
+            total_cached_input_tokens: stats.total_cached_input_tokens,
+            total_cache_write_tokens: stats.total_cache_write_tokens,
+            total_reasoning_tokens: stats.total_reasoning_tokens,
+            cache_hit_rate,
+            total_requests: stats.total_requests,
+            total_errors: stats.total_errors,
             error_rate,
-            avg_response_time: stats.4,
+            failed_cost: stats.failed_cost,
+            errors_by_type,
+            usage_by_unit,
+            avg_response_time: stats.avg_response_time,
+            latency,
+            comparison: None,
         })
     }
     
-    pub async fn get_api_key(&self, api_key_id: Uuid) -> Result<ApiKey, ApiError> {
-        let key = sqlx::query_as::<_, ApiKey>(
-            "SELECT * FROM api_keys WHERE id = $1"
+    /// Response time percentiles and maximum from `start` up to `end`.
+    pub async fn get_latency_stats(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        api_key_id: Option<Uuid>,
+        tags: Option<&Tags>,
+    ) -> Result<LatencyStats, ApiError> {
+        let latency = sqlx::query_as::<_, LatencyStats>(
+            r#"
+            SELECT
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY response_time_ms) as p50_ms,
+                percentile_cont(0.9) WITHIN GROUP (ORDER BY response_time_ms) as p90_ms,
+                percentile_cont(0.95) WITHIN GROUP (ORDER BY response_time_ms) as p95_ms,
+                percentile_cont(0.99) WITHIN GROUP (ORDER BY response_time_ms) as p99_ms,
+                MAX(response_time_ms) as max_ms
+            FROM api_usage
+            WHERE user_id = $1 AND timestamp >= $2
+              AND ($5::timestamptz IS NULL OR timestamp < $5)
+              AND ($3::uuid IS NULL OR api_key_id = $3)
+              AND ($4::jsonb IS NULL OR tags @> $4)
+            "#
         )
+        .bind(user_id)
+        .bind(start)
         .bind(api_key_id)
+        .bind(tags.map(Json))
+        .bind(end)
         .fetch_one(self.pool)
         .await?;
         
-        Ok(key)
+        Ok(latency)
     }
     
-    pub async fn get_daily_costs(
+    /// Calls per response time bucket between `start` and `end`, split at
+    /// `bounds` and broken down by `dimensions`. Calls without a response
+    /// time are left out.
+    pub async fn get_latency_histogram(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        dimensions: &[LatencyDimension],
+        query: &LatencyHistogramQuery,
+        bounds: &[i32],
+    ) -> Result<Vec<LatencyHistogramGroup>, ApiError> {
+        let rows = sqlx::query_as::<_, (Option<String>, Option<String>, i32, i64)>(
+            r#"
+            SELECT
+                CASE WHEN $4 THEN model_name END as model_name,
+                CASE WHEN $5 THEN endpoint END as endpoint,
+                width_bucket(response_time_ms, $6::int[]) as bucket,
+                COUNT(*)::bigint as count
+            FROM api_usage
+            WHERE user_id = $1 AND timestamp BETWEEN $2 AND $3
+              AND response_time_ms IS NOT NULL
+              AND ($7::uuid IS NULL OR api_key_id = $7)
+              AND ($8::text IS NULL OR model_name = $8)
+              AND ($9::text IS NULL OR endpoint = $9)
+              AND ($10::jsonb IS NULL OR tags @> $10)
+            GROUP BY 1, 2, 3
+            ORDER BY 1, 2, 3
+            "#
+        )
+        .bind(user_id)
+        .bind(start)
+        .bind(end)
+        .bind(dimensions.contains(&LatencyDimension::ModelName))
+        .bind(dimensions.contains(&LatencyDimension::Endpoint))
+        .bind(bounds)
+        .bind(query.api_key_id)
+        .bind(&query.model_name)
+        .bind(&query.endpoint)
+        .bind(query.tags.as_ref().map(Json))
+        .fetch_all(self.pool)
+        .await?;
+        
+        let mut groups: Vec<LatencyHistogramGroup> = Vec::new();
+        let mut current: Option<(Option<String>, Option<String>)> = None;
+        
+        for (model_name, endpoint, bucket, count) in rows {
+            let key = (model_name, endpoint);
+            if current.as_ref() != Some(&key) {
+                let mut group = serde_json::Map::new();
+                for dimension in dimensions {
+                    let value = match dimension {
+                        LatencyDimension::ModelName => key.0.clone(),
+                        LatencyDimension::Endpoint => key.1.clone(),
+                    };
+                    group.insert(
+                        dimension.name().to_string(),
+                        value.map_or(serde_json::Value::Null, Into::into),
+                    );
+                }
+                groups.push(LatencyHistogramGroup::new(group, bounds));
+                current = Some(key);
+            }
+            
+            if let Some(group) = groups.last_mut() {
+                group.requests += count;
+                if let Some(bucket) = group.buckets.get_mut(bucket as usize) {
+                    bucket.count += count;
+                }
+            }
+        }
+        
+        Ok(groups)
+    }
+    
+    /// Errors and the cost of failed calls, in `currency`, per error category.
+    pub async fn get_error_breakdown(
         &self,
         user_id: Uuid,
         start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
         api_key_id: Option<Uuid>,
-    ) -> Result<Vec<(String, f64)>, ApiError> {
-        let costs = if let Some(key_id) = api_key_id {
-            sqlx::query_as::<_, (String, f64)>(
-                r#"
-                SELECT 
-                    DATE(timestamp) as date,
-                    SUM(cost) as total_cost
-                FROM api_usage
-                WHERE user_id = $1 AND timestamp >= $2 AND api_key_id = $3
-                GROUP BY DATE(timestamp)
-                ORDER BY DATE(timestamp) ASC
-                "#
-            )
-            .bind(user_id)
-            .bind(start)
-            .bind(key_id)
-            .fetch_all(self.pool)
-            .await?
-        } else {
-            sqlx::query_as::<_, (String, f64)>(
-                r#"
-                SELECT 
-                    DATE(timestamp) as date,
-                    SUM(cost) as total_cost
+        tags: Option<&Tags>,
+        currency: &str,
+    ) -> Result<Vec<ErrorTypeStats>, ApiError> {
+        let breakdown = sqlx::query_as::<_, ErrorTypeStats>(
+            r#"
+            SELECT
+                error_type,
+                COALESCE(SUM(errors), 0)::bigint as errors,
+                COALESCE(SUM(convert_money(cost, currency, $4, timestamp::date)), 0) as cost
+            FROM api_usage
+            WHERE user_id = $1 AND timestamp >= $2 AND errors > 0
+              AND ($6::timestamptz IS NULL OR timestamp < $6)
+              AND ($3::uuid IS NULL OR api_key_id = $3)
+              AND ($5::jsonb IS NULL OR tags @> $5)
+            GROUP BY error_type
+            ORDER BY errors DESC
+            "#
+        )
+        .bind(user_id)
+        .bind(start)
+        .bind(api_key_id)
+        .bind(currency)
+        .bind(tags.map(Json))
+        .bind(end)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(breakdown)
+    }
+    
+    /// Quantity and cost, in `currency`, per billing unit, so units are never
+    /// summed together.
+    pub async fn get_unit_breakdown(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        tags: Option<&Tags>,
+        currency: &str,
+    ) -> Result<Vec<UnitUsageStats>, ApiError> {
+        let breakdown = sqlx::query_as::<_, UnitUsageStats>(
+            r#"
+            SELECT
+                billing_unit,
+                unit_variant,
+                COALESCE(SUM(CASE WHEN billing_unit = 'tokens' THEN total_tokens ELSE quantity END), 0)::float8 as quantity,
+                COALESCE(SUM(requests), 0)::bigint as requests,
+                COALESCE(SUM(convert_money(cost, currency, $3, timestamp::date)), 0) as cost
+            FROM api_usage
+            WHERE user_id = $1 AND timestamp >= $2
+              AND ($5::timestamptz IS NULL OR timestamp < $5)
+              AND ($4::jsonb IS NULL OR tags @> $4)
+            GROUP BY billing_unit, unit_variant
+            ORDER BY cost DESC
+            "#
+        )
+        .bind(user_id)
+        .bind(start)
+        .bind(currency)
+        .bind(tags.map(Json))
+        .bind(end)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(breakdown)
+    }
+    
+    /// A page of usage rows to re-price, ordered by id and starting after
+    /// `after_id`, across all users.
+    pub async fn get_usage_for_recalculation(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        api_key_id: Option<Uuid>,
+        model_name: Option<&str>,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<ApiUsage>, ApiError> {
+        let usage = sqlx::query_as::<_, ApiUsage>(
+            r#"
+            SELECT * FROM api_usage
+            WHERE timestamp >= $1 AND timestamp < $2
+              AND ($3::uuid IS NULL OR api_key_id = $3)
+              AND ($4::text IS NULL OR model_name = $4)
+              AND id > $5
+            ORDER BY id
+            LIMIT $6
+            "#
+        )
+        .bind(start)
+        .bind(end)
+        .bind(api_key_id)
+        .bind(model_name)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(usage)
+    }
+    
+    /// Sets new list and discounted costs and their currencies on the given
+    /// usage rows.
+    pub async fn update_costs(
+        &self,
+        ids: &[i64],
+        list_costs: &[Money],
+        costs: &[Money],
+        currencies: &[String],
+    ) -> Result<(), ApiError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        
+        sqlx::query(
+            r#"
+            UPDATE api_usage AS u
+            SET list_cost = t.list_cost, cost = t.cost, currency = t.currency
+            FROM UNNEST($1::bigint[], $2::numeric[], $3::numeric[], $4::varchar[])
+                AS t(id, list_cost, cost, currency)
+            WHERE u.id = t.id
+            "#
+        )
+        .bind(ids)
+        .bind(list_costs)
+        .bind(costs)
+        .bind(currencies)
+        .execute(self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// Tokens billed to a key in each `(start, before)` window, up to just
+    /// before `before`, optionally only for models matching a catalog-style
+    /// pattern. Returns one volume per window, in order.
+    pub async fn get_token_volumes(
+        &self,
+        api_key_id: Uuid,
+        model_pattern: Option<&str>,
+        windows: &[(DateTime<Utc>, DateTime<Utc>)],
+    ) -> Result<Vec<i64>, ApiError> {
+        if windows.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        // `gpt-4*` becomes the ILIKE pattern `gpt-4%`, anything else matches exactly
+        let like_pattern = model_pattern.map(|pattern| {
+            let escaped = pattern.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            match escaped.strip_suffix('*') {
+                Some(prefix) => format!("{}%", prefix),
+                None => escaped,
+            }
+        });
+        let starts: Vec<DateTime<Utc>> = windows.iter().map(|(start, _)| *start).collect();
+        let befores: Vec<DateTime<Utc>> = windows.iter().map(|(_, before)| *before).collect();
+        
+        let volumes: Vec<(i64,)> = sqlx::query_as(
+            r#"
+            SELECT (
+                SELECT COALESCE(SUM(total_tokens), 0)::bigint
                 FROM api_usage
-                WHERE user_id = $1 AND timestamp >= $2
-                GROUP BY DATE(timestamp)
-                ORDER BY DATE(timestamp) ASC
-                "#
+                WHERE api_key_id = $1 AND billing_unit = 'tokens'
+                  AND timestamp >= w.start AND timestamp < w.before
+                  AND ($4::text IS NULL OR model_name ILIKE $4)
             )
-            .bind(user_id)
-            .bind(start)
-            .fetch_all(self.pool)
-            .await?
-        };
+            FROM UNNEST($2::timestamptz[], $3::timestamptz[]) WITH ORDINALITY AS w(start, before, n)
+            ORDER BY w.n
+            "#
+        )
+        .bind(api_key_id)
+        .bind(&starts)
+        .bind(&befores)
+        .bind(like_pattern)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(volumes.into_iter().map(|(volume,)| volume).collect())
+    }
+    
+    pub async fn get_api_key(&self, user_id: Uuid, api_key_id: Uuid) -> Result<ApiKey, ApiError> {
+        let key = sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE id = $1 AND user_id = $2"
+        )
+        .bind(api_key_id)
+        .bind(user_id)
+        .fetch_optional(self.pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("API key not found".to_string()))?;
+        
+        Ok(key)
+    }
+    
+    pub async fn get_api_keys(
+        &self,
+        user_id: Uuid,
+        api_key_ids: &[Uuid],
+    ) -> Result<Vec<ApiKey>, ApiError> {
+        let keys = sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE user_id = $1 AND id = ANY($2)"
+        )
+        .bind(user_id)
+        .bind(api_key_ids)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(keys)
+    }
+    
+    /// Loads keys regardless of owner, for admin operations.
+    pub async fn get_api_keys_by_ids(&self, api_key_ids: &[Uuid]) -> Result<Vec<ApiKey>, ApiError> {
+        let keys = sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE id = ANY($1)"
+        )
+        .bind(api_key_ids)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(keys)
+    }
+    
+    /// Cost per local day of `calendar` from `start` until `end`, converted into
+    /// `currency` at the rate of that local day, so each day's total uses the
+    /// rate of the day it is reported under.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_daily_costs(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        api_key_id: Option<Uuid>,
+        tags: Option<&Tags>,
+        calendar: &Calendar,
+        currency: &str,
+    ) -> Result<Vec<(NaiveDate, Money)>, ApiError> {
+        let costs = sqlx::query_as::<_, (NaiveDate, Money)>(
+            r#"
+            SELECT 
+                DATE(timestamp AT TIME ZONE $6) as date,
+                SUM(convert_money(cost, currency, $4, DATE(timestamp AT TIME ZONE $6))) as total_cost
+            FROM api_usage
+            WHERE user_id = $1 AND timestamp >= $2 AND timestamp < $7
+              AND ($3::uuid IS NULL OR api_key_id = $3)
+              AND ($5::jsonb IS NULL OR tags @> $5)
+            GROUP BY 1
+            ORDER BY 1 ASC
+            "#
+        )
+        .bind(user_id)
+        .bind(start)
+        .bind(api_key_id)
+        .bind(currency)
+        .bind(tags.map(Json))
+        .bind(calendar.timezone_name())
+        .bind(end)
+        .fetch_all(self.pool)
+        .await?;
         
         Ok(costs)
     }
-}
\ No newline at end of file
+    
+    /// Cost since `start` converted into `currency`, optionally for one key.
+    pub async fn get_spend(
+        &self,
+        user_id: Uuid,
+        api_key_id: Option<Uuid>,
+        start: DateTime<Utc>,
+        currency: &str,
+    ) -> Result<Money, ApiError> {
+        let spend: (Money,) = sqlx::query_as(
+            r#"
+            SELECT COALESCE(SUM(convert_money(cost, currency, $4, timestamp::date)), 0)
+            FROM api_usage
+            WHERE user_id = $1 AND timestamp >= $3
+              AND ($2::uuid IS NULL OR api_key_id = $2)
+            "#
+        )
+        .bind(user_id)
+        .bind(api_key_id)
+        .bind(start)
+        .bind(currency)
+        .fetch_one(self.pool)
+        .await?;
+        
+        Ok(spend.0)
+    }
+    
+    /// Tag keys used in a date range with up to `values_per_key` of their most
+    /// used values each. A `None` value counts the usage without the key.
+    pub async fn list_tags(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        values_per_key: i64,
+    ) -> Result<Vec<TagKey>, ApiError> {
+        let rows = sqlx::query_as::<_, (String, Option<String>, i64)>(
+            r#"
+            WITH usage AS (
+                SELECT tags FROM api_usage
+                WHERE user_id = $1 AND timestamp BETWEEN $2 AND $3
+            ),
+            counts AS (
+                SELECT t.key, t.value, COUNT(*)::bigint AS usage_count
+                FROM usage u, jsonb_each_text(u.tags) AS t
+                GROUP BY t.key, t.value
+            ),
+            ranked AS (
+                SELECT *, ROW_NUMBER() OVER (
+                    PARTITION BY key ORDER BY usage_count DESC, value
+                ) AS rank
+                FROM counts
+            ),
+            -- Each row carries a key at most once, so the rest lack it
+            missing AS (
+                SELECT key, NULL::text AS value,
+                    (SELECT COUNT(*) FROM usage) - SUM(usage_count)::bigint AS usage_count
+                FROM counts
+                GROUP BY key
+            )
+            SELECT * FROM (
+                SELECT key, value, usage_count FROM ranked WHERE rank <= $4
+                UNION ALL
+                SELECT key, value, usage_count FROM missing WHERE usage_count > 0
+            ) listed
+            ORDER BY key, value IS NULL, usage_count DESC, value
+            "#
+        )
+        .bind(user_id)
+        .bind(start)
+        .bind(end)
+        .bind(values_per_key)
+        .fetch_all(self.pool)
+        .await?;
+        
+        let mut keys: Vec<TagKey> = Vec::new();
+        for (key, value, usage_count) in rows {
+            if keys.last().is_none_or(|last| last.key != key) {
+                keys.push(TagKey { key: key.clone(), values: Vec::new() });
+            }
+            if let Some(last) = keys.last_mut() {
+                last.values.push(TagValue { value, usage_count });
+            }
+        }
+        
+        Ok(keys)
+    }
+    
+    /// Users with any usage between `start` and `end`.
+    pub async fn users_with_usage(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Uuid>, ApiError> {
+        let users: Vec<(Uuid,)> = sqlx::query_as(
+            "SELECT DISTINCT user_id FROM api_usage WHERE timestamp BETWEEN $1 AND $2 ORDER BY user_id"
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(self.pool)
+        .await?;
+        
+        Ok(users.into_iter().map(|u| u.0).collect())
+    }
+    
+    /// Opens a cursor over usage matching `filter`, oldest first, with costs
+    /// converted into `currency`. Rows are read in batches with
+    /// [`UsageExportCursor::next_batch`] so an export never holds more than
+    /// one batch in memory.
+    pub async fn open_export_cursor(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        filter: &UsageFilter,
+        currency: &str,
+    ) -> Result<UsageExportCursor, ApiError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "DECLARE usage_export NO SCROLL CURSOR FOR SELECT *, convert_money(cost, currency, "
+        );
+        builder.push_bind(currency);
+        builder.push(", timestamp::date) as reporting_cost, ");
+        builder.push_bind(currency);
+        builder.push("::varchar as reporting_currency FROM api_usage WHERE ");
+        push_usage_filter(&mut builder, user_id, start, end, filter);
+        builder.push(" ORDER BY timestamp, id");
+    
+        // Cursors only live as long as the transaction declaring them
+        let mut tx = self.pool.begin().await?;
+        builder.build().execute(&mut *tx).await?;
+    
+        Ok(UsageExportCursor { tx })
+    }
+}
+
+/// An open export cursor; dropping it rolls back the transaction holding it.
+pub struct UsageExportCursor {
+    tx: Transaction<'static, Postgres>,
+}
+
+impl UsageExportCursor {
+    /// The next `size` rows, empty once the cursor is exhausted.
+    pub async fn next_batch(&mut self, size: i64) -> Result<Vec<ExportedUsage>, ApiError> {
+        let rows = sqlx::query_as::<_, ExportedUsage>(&format!("FETCH {} FROM usage_export", size))
+            .fetch_all(&mut *self.tx)
+            .await?;
+        
+        Ok(rows)
+    }
+    
+    pub async fn close(self) -> Result<(), ApiError> {
+        self.tx.commit().await?;
+        
+        Ok(())
+    }
+}
+
+/// The `WHERE` conditions shared by usage listings and exports: the user,
+/// the date range and the optional filters that are set.
+fn push_usage_filter<'a>(
+    builder: &mut QueryBuilder<'a, Postgres>,
+    user_id: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    filter: &'a UsageFilter,
+) {
+    builder.push("user_id = ");
+    builder.push_bind(user_id);
+    builder.push(" AND timestamp BETWEEN ");
+    builder.push_bind(start);
+    builder.push(" AND ");
+    builder.push_bind(end);
+    
+    if let Some(api_key_id) = filter.api_key_id {
+        builder.push(" AND api_key_id = ");
+        builder.push_bind(api_key_id);
+    }
+    if let Some(model_name) = &filter.model_name {
+        builder.push(" AND model_name = ");
+        builder.push_bind(model_name);
+    }
+    if let Some(endpoint) = &filter.endpoint {
+        builder.push(" AND endpoint = ");
+        builder.push_bind(endpoint);
+    }
+    if let Some(status_min) = filter.status_min {
+        builder.push(" AND status_code >= ");
+        builder.push_bind(status_min);
+    }
+    if let Some(status_max) = filter.status_max {
+        builder.push(" AND status_code <= ");
+        builder.push_bind(status_max);
+    }
+    if let Some(min_cost) = filter.min_cost {
+        builder.push(" AND cost >= ");
+        builder.push_bind(min_cost);
+    }
+    if let Some(max_cost) = filter.max_cost {
+        builder.push(" AND cost <= ");
+        builder.push_bind(max_cost);
+    }
+    if let Some(tags) = &filter.tags {
+        builder.push(" AND tags @> ");
+        builder.push_bind(Json(tags.clone()));
+    }
+}
+
+fn push_group_expression(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    dimension: &GroupBy,
+    calendar: &Calendar,
+    untagged: &UntaggedTags,
+) {
+    let timezone = calendar.timezone_name();
+    
+    match dimension {
+        GroupBy::Hour | GroupBy::Day | GroupBy::Month => {
+            let unit = match dimension {
+                GroupBy::Hour => "hour",
+                GroupBy::Day => "day",
+                _ => "month",
+            };
+            builder.push(format!("(date_trunc('{}', timestamp AT TIME ZONE ", unit));
+            builder.push_bind(timezone);
+            builder.push(") AT TIME ZONE ");
+            builder.push_bind(timezone);
+            builder.push(")")
+        }
+        // date_trunc starts weeks on Monday, so the local time is shifted to
+        // make `week_start` fall on a Monday and shifted back afterwards
+        GroupBy::Week => {
+            let shift = calendar.week_shift_days();
+            builder.push("((date_trunc('week', (timestamp AT TIME ZONE ");
+            builder.push_bind(timezone);
+            builder.push(") + make_interval(days => ");
+            builder.push_bind(shift);
+            builder.push(")) - make_interval(days => ");
+            builder.push_bind(shift);
+            builder.push(")) AT TIME ZONE ");
+            builder.push_bind(timezone);
+            builder.push(")")
+        }
+        GroupBy::ApiKeyId => builder.push("api_key_id"),
+        GroupBy::ModelName => builder.push("model_name"),
+        GroupBy::Endpoint => builder.push("endpoint"),
+        GroupBy::StatusClass => builder.push("(status_code / 100)::text || 'xx'"),
+        GroupBy::Metadata(key) => {
+            builder.push("metadata ->> ");
+            builder.push_bind(key.clone())
+        }
+        GroupBy::Tag(key) => match untagged {
+            UntaggedTags::Label(label) => {
+                builder.push("COALESCE(tags ->> ");
+                builder.push_bind(key.clone());
+                builder.push(", ");
+                builder.push_bind(label.clone());
+                builder.push(")")
+            }
+            UntaggedTags::Null | UntaggedTags::Exclude => {
+                builder.push("tags ->> ");
+                builder.push_bind(key.clone())
+            }
+        },
+    };
+}
+
+/// Reads a row of `aggregate_usage`: one column per dimension, then the metrics.
+fn read_aggregate(
+    row: &PgRow,
+    dimensions: &[GroupBy],
+    calendar: &Calendar,
+) -> Result<UsageAggregate, ApiError> {
+    let mut group = serde_json::Map::new();
+    
+    for (index, dimension) in dimensions.iter().enumerate() {
+        let value = match dimension {
+            GroupBy::Hour | GroupBy::Day | GroupBy::Week | GroupBy::Month => row
+                .try_get::<Option<DateTime<Utc>>, _>(index)?
+                .map(|bucket| bucket.with_timezone(&calendar.timezone).to_rfc3339().into()),
+            GroupBy::ApiKeyId => row
+                .try_get::<Option<Uuid>, _>(index)?
+                .map(|id| id.to_string().into()),
+            _ => row.try_get::<Option<String>, _>(index)?.map(Into::into),
+        };
+        group.insert(dimension.name(), value.unwrap_or(serde_json::Value::Null));
+    }
+    
+    Ok(UsageAggregate {
+        group,
+        cost: row.try_get("cost")?,
+        total_tokens: row.try_get("total_tokens")?,
+        input_tokens: row.try_get("input_tokens")?,
+        output_tokens: row.try_get("output_tokens")?,
+        requests: row.try_get("requests")?,
+        errors: row.try_get("errors")?,
+        avg_response_time_ms: row.try_get("avg_response_time_ms")?,
+        latency: LatencyStats {
+            p50_ms: row.try_get("p50_ms")?,
+            p90_ms: row.try_get("p90_ms")?,
+            p95_ms: row.try_get("p95_ms")?,
+            p99_ms: row.try_get("p99_ms")?,
+            max_ms: row.try_get("max_ms")?,
+        },
+    })
+}
+
+fn insert_usage_query(usage: &NewUsage) -> QueryAs<'_, Postgres, ApiUsage, PgArguments> {
+    sqlx::query_as::<_, ApiUsage>(
+        r#"
+        INSERT INTO api_usage (
+            user_id, api_key_id, timestamp, input_tokens, output_tokens,
+            total_tokens, requests, errors, cost, model_name, endpoint,
+            status_code, response_time_ms, metadata, external_id, error_type,
+            cached_input_tokens, cache_write_tokens, reasoning_tokens,
+            billing_unit, quantity, unit_variant, currency, list_cost, is_batch,
+            pricing_rule_id, discount_percent, tags
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16,
+            $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28
+        )
+        ON CONFLICT (user_id, external_id) WHERE external_id IS NOT NULL DO NOTHING
+        RETURNING *
+        "#
+    )
+    .bind(usage.user_id)
+    .bind(usage.api_key_id)
+    .bind(usage.timestamp)
+    .bind(usage.input_tokens)
+    .bind(usage.output_tokens)
+    .bind(usage.total_tokens)
+    .bind(1) // requests
+    .bind(usage.errors)
+    .bind(usage.cost)
+    .bind(&usage.model_name)
+    .bind(&usage.endpoint)
+    .bind(usage.status_code)
+    .bind(usage.response_time_ms)
+    .bind(&usage.metadata)
+    .bind(&usage.external_id)
+    .bind(usage.error_type.map(|t| t.as_str()))
+    .bind(usage.cached_input_tokens)
+    .bind(usage.cache_write_tokens)
+    .bind(usage.reasoning_tokens)
+    .bind(usage.billing_unit.as_str())
+    .bind(usage.quantity)
+    .bind(&usage.unit_variant)
+    .bind(&usage.currency)
+    .bind(usage.list_cost)
+    .bind(usage.is_batch)
+    .bind(usage.pricing_rule_id)
+    .bind(usage.discount_percent)
+    .bind(Json(&usage.tags))
+}
+
+fn find_by_external_id_query(usage: &NewUsage) -> QueryAs<'_, Postgres, ApiUsage, PgArguments> {
+    sqlx::query_as::<_, ApiUsage>(
+        "SELECT * FROM api_usage WHERE user_id = $1 AND external_id = $2"
+    )
+    .bind(usage.user_id)
+    .bind(&usage.external_id)
+}
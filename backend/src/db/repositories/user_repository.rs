@@ -1,7 +1,9 @@
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::models::user::User;
+use crate::models::calendar::Calendar;
+use crate::models::user::{UpdatePreferencesRequest, User};
+use crate::models::money::base_currency;
 use crate::errors::ApiError;
 
 pub struct UserRepository<'a> {
@@ -58,4 +60,57 @@ impl<'a> UserRepository<'a> {
         
         Ok(user)
     }
+    
+    pub async fn update_preferences(
+        &self,
+        id: Uuid,
+        preferences: &UpdatePreferencesRequest,
+    ) -> Result<Option<User>, ApiError> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET
+                reporting_currency = COALESCE($1, reporting_currency),
+                timezone = COALESCE($3, timezone),
+                week_start = COALESCE($4, week_start),
+                updated_at = NOW()
+            WHERE id = $2
+            RETURNING *
+            "#
+        )
+        .bind(&preferences.reporting_currency)
+        .bind(id)
+        .bind(&preferences.timezone)
+        .bind(&preferences.week_start)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(user)
+    }
+    
+    /// The user's reporting currency, or the base currency for unknown users.
+    pub async fn reporting_currency(&self, id: Uuid) -> Result<String, ApiError> {
+        let currency: Option<(String,)> = sqlx::query_as(
+            "SELECT reporting_currency FROM users WHERE id = $1"
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(currency.map(|c| c.0).unwrap_or_else(base_currency))
+    }
+    
+    /// The user's calendar, or the UTC calendar for unknown users.
+    pub async fn calendar(&self, id: Uuid) -> Result<Calendar, ApiError> {
+        let settings: Option<(String, String)> = sqlx::query_as(
+            "SELECT timezone, week_start FROM users WHERE id = $1"
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await?;
+        
+        Ok(settings
+            .and_then(|(timezone, week_start)| Calendar::parse(&timezone, &week_start).ok())
+            .unwrap_or_default())
+    }
 }
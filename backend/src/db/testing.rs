@@ -0,0 +1,81 @@
+use sqlx::{postgres::PgConnectOptions, ConnectOptions, Connection, PgConnection, PgPool};
+use uuid::Uuid;
+
+/// A freshly migrated database of its own for a test, created next to the one
+/// `DATABASE_URL` points at.
+pub struct TestDatabase {
+    pub pool: PgPool,
+    options: PgConnectOptions,
+    name: String,
+}
+
+impl TestDatabase {
+    /// `None` when `DATABASE_URL` is unset, so the tests needing Postgres are
+    /// skipped rather than failed on machines without one.
+    pub async fn create() -> Option<Self> {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL is not set, skipping a test that needs Postgres");
+            return None;
+        };
+        let options: PgConnectOptions = url.parse().expect("DATABASE_URL is not a valid Postgres URL");
+        let name = format!("test_{}", Uuid::new_v4().simple());
+        
+        let mut admin = options.connect().await.expect("Failed to connect to DATABASE_URL");
+        sqlx::query(&format!("CREATE DATABASE {}", name))
+            .execute(&mut admin)
+            .await
+            .expect("Failed to create the test database");
+        admin.close().await.ok();
+        
+        let pool = PgPool::connect_with(options.clone().database(&name))
+            .await
+            .expect("Failed to connect to the test database");
+        sqlx::migrate!().run(&pool).await.expect("Failed to migrate the test database");
+        
+        Some(Self { pool, options, name })
+    }
+    
+    pub async fn drop(self) {
+        self.pool.close().await;
+        
+        let mut admin: PgConnection = self.options.connect().await.expect("Failed to connect to DATABASE_URL");
+        sqlx::query(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.name))
+            .execute(&mut admin)
+            .await
+            .expect("Failed to drop the test database");
+    }
+}
+
+/// A user with the default settings.
+pub async fn create_user(pool: &PgPool) -> Uuid {
+    let user_id = Uuid::new_v4();
+    
+    sqlx::query("INSERT INTO users (id, email, password, name) VALUES ($1, $2, 'x', 'Test User')")
+        .bind(user_id)
+        .bind(format!("{}@example.com", user_id))
+        .execute(pool)
+        .await
+        .expect("Failed to create a test user");
+    
+    user_id
+}
+
+/// An OpenAI key priced on the key itself at 0.001 per 1k input and output
+/// tokens, in USD.
+pub async fn create_api_key(pool: &PgPool, user_id: Uuid) -> Uuid {
+    let key_id = Uuid::new_v4();
+    
+    sqlx::query(
+        r#"
+        INSERT INTO api_keys (id, user_id, name, provider, encrypted_key, cost_per_1k_input, cost_per_1k_output)
+        VALUES ($1, $2, 'Test key', 'openai', 'x', 0.001, 0.001)
+        "#
+    )
+    .bind(key_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .expect("Failed to create a test API key");
+    
+    key_id
+}
@@ -6,6 +6,9 @@ use axum::{
 use serde_json::json;
 use thiserror::Error;
 
+/// SQLSTATE raised by `fx_usd_rate` when no exchange rate covers a date.
+const MISSING_FX_RATE: &str = "FX404";
+
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("Database error: {0}")]
@@ -17,6 +20,9 @@ pub enum ApiError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
     
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    
     #[error("Not found: {0}")]
     NotFound(String),
     
@@ -31,6 +37,12 @@ pub enum ApiError {
     
     #[error("JWT error: {0}")]
     JwtError(String),
+    
+    #[error("Upstream error: {0}")]
+    BadGateway(String),
+    
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
 }
 
 impl From<validator::ValidationErrors> for ApiError {
@@ -48,18 +60,26 @@ impl From<jsonwebtoken::errors::Error> for ApiError {
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let (status, message) = match &self {
+            ApiError::Database(sqlx::Error::Database(e))
+                if e.code().as_deref() == Some(MISSING_FX_RATE) =>
+            {
+                (StatusCode::UNPROCESSABLE_ENTITY, "Missing exchange rate")
+            }
             ApiError::Database(e) => {
                 tracing::error!("Database error: {:?}", e);
                 (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
             }
             ApiError::ValidationError(_) => (StatusCode::BAD_REQUEST, "Validation failed"),
             ApiError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            ApiError::Forbidden(_) => (StatusCode::FORBIDDEN, "Forbidden"),
             ApiError::NotFound(_) => (StatusCode::NOT_FOUND, "Resource not found"),
             ApiError::InsufficientData(_) => (StatusCode::BAD_REQUEST, "Insufficient data"),
             ApiError::Internal(_) | ApiError::InternalServerError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal error")
             }
             ApiError::JwtError(_) => (StatusCode::UNAUTHORIZED, "Invalid token"),
+            ApiError::BadGateway(_) => (StatusCode::BAD_GATEWAY, "Upstream request failed"),
+            ApiError::ServiceUnavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, "Service unavailable"),
         };
 
         let body = Json(json!({
@@ -1,10 +1,24 @@
+use std::path::Path;
+
+use chrono::{Duration, Utc};
 use sqlx::PgPool;
 use tokio::sync::broadcast;
 use tokio_cron_scheduler::{JobScheduler, Job};
 
+use crate::config::settings::ExportConfig;
+use crate::services::alert_service::AlertService;
+use crate::services::export_service::ExportService;
+use crate::services::prediction_service::PredictionService;
+use crate::services::pricing_service::PricingService;
+use crate::services::s3_client::S3Client;
 use crate::websocket::WsMessage;
 
-pub async fn start_background_jobs(pool: PgPool, ws_tx: broadcast::Sender<WsMessage>) {
+pub async fn start_background_jobs(
+    pool: PgPool,
+    ws_tx: broadcast::Sender<WsMessage>,
+    export: ExportConfig,
+    max_lateness_secs: i64,
+) {
     tracing::info!("Starting background jobs");
     
     let scheduler = JobScheduler::new().await.expect("Failed to create scheduler");
@@ -55,25 +69,144 @@ pub async fn start_background_jobs(pool: PgPool, ws_tx: broadcast::Sender<WsMess
     
     scheduler.add(alert_job).await.expect("Failed to add alert job");
     
+    // Parquet export job - daily, when an export directory is configured
+    if let Some(dir) = export.parquet_dir.clone() {
+        let pool_clone = pool.clone();
+        let tag_columns = export.parquet_tag_columns.clone();
+        let parquet_job = Job::new_async(export.parquet_schedule.as_str(), move |_uuid, _l| {
+            let pool = pool_clone.clone();
+            let dir = dir.clone();
+            let tag_columns = tag_columns.clone();
+            Box::pin(async move {
+                tracing::info!("Running Parquet export job");
+                if let Err(e) = export_parquet(&pool, &dir, &tag_columns, max_lateness_secs).await {
+                    tracing::error!("Parquet export job failed: {:?}", e);
+                }
+            })
+        }).expect("Failed to create Parquet export job");
+        
+        scheduler.add(parquet_job).await.expect("Failed to add Parquet export job");
+    }
+    
+    // Export schedules job - every minute
+    let pool_clone = pool.clone();
+    let http = S3Client::http_client(&export.s3_allowed_hosts).expect("Failed to create export HTTP client");
+    let schedules_job = Job::new_async("0 * * * * *", move |_uuid, _l| {
+        let pool = pool_clone.clone();
+        let export = export.clone();
+        let http = http.clone();
+        Box::pin(async move {
+            if let Err(e) = run_export_schedules(&pool, &export, &http).await {
+                tracing::error!("Export schedules job failed: {:?}", e);
+            }
+        })
+    }).expect("Failed to create export schedules job");
+    
+    scheduler.add(schedules_job).await.expect("Failed to add export schedules job");
+    
     scheduler.start().await.expect("Failed to start scheduler");
     
     tracing::info!("Background jobs started successfully");
 }
 
-async fn run_aggregation(pool: &PgPool) -> anyhow::Result<()> {
+async fn run_aggregation(_pool: &PgPool) -> anyhow::Result<()> {
     // Implement hourly aggregation
     tracing::info!("Aggregation completed");
     Ok(())
 }
 
-async fn run_predictions(pool: &PgPool, ws_tx: &broadcast::Sender<WsMessage>) -> anyhow::Result<()> {
-    // Generate predictions for all active users
-    tracing::info!("Predictions generated");
+/// Refreshes the predictions that late or re-priced usage made stale.
+async fn run_predictions(pool: &PgPool, _ws_tx: &broadcast::Sender<WsMessage>) -> anyhow::Result<()> {
+    let refreshed = PredictionService::new(pool).refresh_stale_predictions().await?;
+    tracing::info!("Predictions refreshed: {}", refreshed);
     Ok(())
 }
 
 async fn check_alerts(pool: &PgPool, ws_tx: &broadcast::Sender<WsMessage>) -> anyhow::Result<()> {
-    // Check budget alerts
-    tracing::info!("Alerts checked");
+    let raised = AlertService::new(pool).check_budgets(ws_tx).await?;
+    tracing::info!("Alerts checked, {} raised", raised);
+    Ok(())
+}
+
+/// Rewrites the (UTC) usage partitions late usage may still reach, through
+/// yesterday, along with those of runs that were missed.
+async fn export_parquet(
+    pool: &PgPool,
+    dir: &Path,
+    tag_columns: &[String],
+    max_lateness_secs: i64,
+) -> anyhow::Result<()> {
+    let today = Utc::now().date_naive();
+    let written = ExportService::new(pool)
+        .write_pending_partitions(dir, today, Duration::seconds(max_lateness_secs), tag_columns)
+        .await?;
+    tracing::info!("Parquet export up to {} completed, {} files written", today - Duration::days(1), written);
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Runs the users' export schedules that are due.
+async fn run_export_schedules(
+    pool: &PgPool,
+    export: &ExportConfig,
+    http: &reqwest::Client,
+) -> anyhow::Result<()> {
+    let ran = ExportService::new(pool).run_due_schedules(export, http).await?;
+    if ran > 0 {
+        tracing::info!("Export schedules run: {}", ran);
+    }
+    Ok(())
+}
+
+/// Applies the pricing file to the catalog and logs the resulting diff.
+pub async fn apply_pricing_file(pool: &PgPool, path: &Path) -> anyhow::Result<()> {
+    let report = PricingService::new(pool).apply_price_file(path).await?;
+    
+    tracing::info!(
+        "Pricing file {} applied: {} added, {} updated, {} unchanged",
+        report.file,
+        report.added.len(),
+        report.updated.len(),
+        report.unchanged
+    );
+    for price in &report.added {
+        tracing::info!("Price added: {} {} from {}", price.provider, price.model_pattern, price.effective_from);
+    }
+    for update in &report.updated {
+        tracing::info!(
+            "Price updated: {} {} from {} ({})",
+            update.current.provider,
+            update.current.model_pattern,
+            update.current.effective_from,
+            update.changed_fields.join(", ")
+        );
+    }
+    for price in &report.not_in_file {
+        tracing::warn!("Price no longer in pricing file, kept: {} {}", price.provider, price.model_pattern);
+    }
+    for conflict in &report.conflicts {
+        tracing::warn!("Price not applied: {}", conflict);
+    }
+    
+    Ok(())
+}
+
+/// Re-applies the pricing file whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub async fn reload_pricing_file_on_hangup(pool: PgPool, path: std::path::PathBuf) {
+    use tokio::signal::unix::{signal, SignalKind};
+    
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            tracing::error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    
+    while hangups.recv().await.is_some() {
+        tracing::info!("SIGHUP received, reloading pricing file");
+        if let Err(e) = apply_pricing_file(&pool, &path).await {
+            tracing::error!("Pricing file reload failed: {}", e);
+        }
+    }
+}
@@ -10,8 +10,10 @@ pub mod utils;
 pub mod websocket;
 pub mod jobs;
 
+use std::sync::Arc;
+
 use sqlx::PgPool;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, Semaphore};
 
 #[derive(Clone)]
 pub struct AppState {
@@ -19,4 +21,8 @@ pub struct AppState {
     pub redis_pool: deadpool_redis::Pool,
     pub config: config::Config,
     pub ws_tx: broadcast::Sender<websocket::WsMessage>,
+    pub http_client: reqwest::Client,
+    /// One permit per usage export being streamed, bounding the pooled
+    /// connections held by downloads.
+    pub export_slots: Arc<Semaphore>,
 }
\ No newline at end of file
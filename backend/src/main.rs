@@ -1,10 +1,14 @@
+use std::sync::Arc;
+
 use api_usage_analyzer::{
-    config::Config, db::create_pool, jobs::start_background_jobs, routes::create_router, AppState,
+    config::Config, db::create_pool, jobs, jobs::start_background_jobs, routes::create_router,
+    services::fx_service::FxService, services::pricing_service::PricingService, AppState,
 };
 use axum::http::{HeaderValue, Method};
+use tokio::sync::Semaphore;
 use tower_http::{
     compression::CompressionLayer,
-    cors::{Any, CorsLayer},
+    cors::CorsLayer,
     trace::TraceLayer,
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -33,6 +37,27 @@ async fn main() -> anyhow::Result<()> {
     sqlx::migrate!().run(&pool).await?;
     tracing::info!("Database migrations completed");
 
+    // Load the pricing file, or seed the model price catalog on first run
+    match &config.pricing_file {
+        Some(path) => {
+            jobs::apply_pricing_file(&pool, path).await?;
+            #[cfg(unix)]
+            tokio::spawn(jobs::reload_pricing_file_on_hangup(pool.clone(), path.clone()));
+        }
+        None => {
+            let seeded = PricingService::new(&pool).seed_defaults().await?;
+            if seeded > 0 {
+                tracing::info!("Seeded {} default model prices", seeded);
+            }
+        }
+    }
+
+    // Load exchange rates kept in a file
+    if let Some(path) = &config.fx_rates_file {
+        let written = FxService::new(&pool).import_file(path).await?;
+        tracing::info!("Loaded {} exchange rates from {}", written, path.display());
+    }
+
     // Create Redis pool
     let redis_cfg = deadpool_redis::Config::from_url(&config.redis_url);
     let redis_pool = redis_cfg
@@ -42,16 +67,29 @@ async fn main() -> anyhow::Result<()> {
     // Create WebSocket broadcast channel
     let (ws_tx, _) = tokio::sync::broadcast::channel(100);
 
+    // Create HTTP client for the metering proxy
+    let http_client = reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(config.proxy.connect_timeout_secs))
+        .read_timeout(std::time::Duration::from_secs(config.proxy.read_timeout_secs))
+        .build()?;
+
     // Create app state
     let state = AppState {
         pool: pool.clone(),
         redis_pool,
         config: config.clone(),
         ws_tx: ws_tx.clone(),
+        http_client,
+        export_slots: Arc::new(Semaphore::new(config.export.max_concurrent)),
     };
 
     // Start background jobs
-    tokio::spawn(start_background_jobs(pool.clone(), ws_tx.clone()));
+    tokio::spawn(start_background_jobs(
+        pool.clone(),
+        ws_tx.clone(),
+        config.export.clone(),
+        config.ingestion.max_lateness_secs,
+    ));
 
     // Build router with fixed CORS configuration
     let app = create_router(state)
@@ -62,6 +100,8 @@ async fn main() -> anyhow::Result<()> {
                 .allow_headers([
                     axum::http::header::CONTENT_TYPE,
                     axum::http::header::AUTHORIZATION,
+                    axum::http::HeaderName::from_static("idempotency-key"),
+                    axum::http::HeaderName::from_static("x-api-key"),
                 ])
                 .allow_credentials(true),
         )
@@ -7,9 +7,15 @@ use axum::{
 };
 use jsonwebtoken::{decode, DecodingKey, Validation};  
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::errors::ApiError;
+use crate::{
+    db::repositories::{IngestionTokenRepository, UserRepository},
+    errors::ApiError,
+    models::ingestion_token::{IngestionToken, INGESTION_TOKEN_PREFIX},
+    AppState,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]       
 pub struct Claims {
@@ -75,6 +81,120 @@ where
     }
 }
 
+/// An authenticated user listed in `ADMIN_EMAILS`.
+#[derive(Clone)]
+pub struct AdminUser(pub Uuid);
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = (StatusCode, Json<serde_json::Value>);
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthUser(user_id) = AuthUser::from_request_parts(parts, state).await?;
+
+        let user = UserRepository::new(&state.pool)
+            .find_by_id(user_id)
+            .await
+            .map_err(|e| {
+                tracing::error!("Admin lookup failed: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({ "error": "Internal error" })),
+                )
+            })?;
+
+        let is_admin = user
+            .map(|u| state.config.admin_emails.contains(&u.email.to_lowercase()))
+            .unwrap_or(false);
+        if !is_admin {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({ "error": "Admin access required" })),
+            ));
+        }
+
+        Ok(AdminUser(user_id))
+    }
+}
+
+/// Caller identity on usage ingestion routes: either a user JWT or an
+/// ingestion token, optionally restricted to specific API keys.
+#[derive(Clone)]
+pub struct IngestionAuth {
+    pub user_id: Uuid,
+    pub token_id: Option<Uuid>,
+    pub api_key_scope: Option<Vec<Uuid>>,
+}
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for IngestionAuth {
+    type Rejection = (StatusCode, Json<serde_json::Value>);
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let unauthorized = |message: String| {
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({ "error": message })),
+            )
+        };
+
+        // Provider SDKs pointed at the proxy send their key in their own header
+        let bearer = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+        let token = bearer
+            .or_else(|| parts.headers.get("x-api-key").and_then(|h| h.to_str().ok()))
+            .or_else(|| parts.headers.get("x-goog-api-key").and_then(|h| h.to_str().ok()))
+            .ok_or_else(|| unauthorized("Missing authorization header".to_string()))?;
+
+        if !token.starts_with(INGESTION_TOKEN_PREFIX) {
+            let user_id = verify_token(token)
+                .map_err(|e| unauthorized(format!("Invalid token: {}", e)))?;
+
+            return Ok(IngestionAuth {
+                user_id,
+                token_id: None,
+                api_key_scope: None,
+            });
+        }
+
+        Self::from_ingestion_token(&state.pool, token)
+            .await
+            .map_err(|e| {
+                tracing::error!("Ingestion token lookup failed: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({ "error": "Internal error" })),
+                )
+            })?
+            .ok_or_else(|| unauthorized("Invalid or revoked ingestion token".to_string()))
+    }
+}
+
+impl IngestionAuth {
+    /// The identity of a raw ingestion token, `None` when it is unknown or
+    /// revoked.
+    pub async fn from_ingestion_token(pool: &PgPool, token: &str) -> Result<Option<Self>, ApiError> {
+        let record = IngestionTokenRepository::new(pool)
+            .authenticate(&IngestionToken::hash(token))
+            .await?;
+
+        Ok(record.map(|record| IngestionAuth {
+            user_id: record.user_id,
+            token_id: Some(record.id),
+            api_key_scope: record.api_key_ids,
+        }))
+    }
+}
+
 pub struct RequireAuth;
 
 impl RequireAuth {
@@ -100,4 +220,95 @@ pub fn verify_token(token: &str) -> Result<Uuid, ApiError> {
     // Parse the user ID from the subject claim
     Uuid::parse_str(&token_data.claims.sub)
         .map_err(|_| ApiError::Unauthorized("Invalid user ID in token".to_string()))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::IngestionConfig;
+    use crate::db::testing::{create_api_key, create_user, TestDatabase};
+    use crate::models::api_usage::CreateUsageRequest;
+    use crate::services::usage_service::UsageService;
+    use tokio::sync::broadcast;
+
+    async fn create_token(pool: &PgPool, user_id: Uuid, raw: &str, api_key_ids: Option<&[Uuid]>) -> Uuid {
+        IngestionTokenRepository::new(pool)
+            .create_token(user_id, "test", &IngestionToken::hash(raw), &raw[..8], api_key_ids)
+            .await
+            .unwrap()
+            .id
+    }
+
+    async fn last_used_at(pool: &PgPool, token_id: Uuid) -> Option<chrono::DateTime<chrono::Utc>> {
+        sqlx::query_scalar("SELECT last_used_at FROM ingestion_tokens WHERE id = $1")
+            .bind(token_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    fn usage_for(api_key_id: Uuid) -> CreateUsageRequest {
+        serde_json::from_value(serde_json::json!({
+            "api_key_id": api_key_id,
+            "model_name": "test-model",
+            "input_tokens": 10,
+            "output_tokens": 5,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn ingestion_token_authenticates_until_revoked_and_stamps_use_once_a_minute() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let token_id = create_token(&db.pool, user_id, "uai_valid-token", None).await;
+
+        let auth = IngestionAuth::from_ingestion_token(&db.pool, "uai_valid-token").await.unwrap().unwrap();
+        let first_use = last_used_at(&db.pool, token_id).await;
+        IngestionAuth::from_ingestion_token(&db.pool, "uai_valid-token").await.unwrap();
+        let repeat_use = last_used_at(&db.pool, token_id).await;
+        sqlx::query("UPDATE ingestion_tokens SET last_used_at = NOW() - INTERVAL '2 minutes' WHERE id = $1")
+            .bind(token_id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        IngestionAuth::from_ingestion_token(&db.pool, "uai_valid-token").await.unwrap();
+        let later_use = last_used_at(&db.pool, token_id).await;
+        let unknown = IngestionAuth::from_ingestion_token(&db.pool, "uai_other-token").await.unwrap();
+        IngestionTokenRepository::new(&db.pool).revoke_token(user_id, token_id).await.unwrap();
+        let revoked = IngestionAuth::from_ingestion_token(&db.pool, "uai_valid-token").await.unwrap();
+        db.drop().await;
+
+        assert_eq!((auth.user_id, auth.token_id, auth.api_key_scope), (user_id, Some(token_id), None));
+        assert!(first_use.is_some());
+        assert_eq!(repeat_use, first_use);
+        assert!(later_use > first_use);
+        assert!(unknown.is_none());
+        assert!(revoked.is_none());
+    }
+
+    #[tokio::test]
+    async fn scoped_ingestion_token_only_records_for_its_keys() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let scoped_key = create_api_key(&db.pool, user_id).await;
+        let other_key = create_api_key(&db.pool, user_id).await;
+        create_token(&db.pool, user_id, "uai_scoped-token", Some(&[scoped_key])).await;
+        let (ws_tx, _) = broadcast::channel(16);
+        let limits = IngestionConfig {
+            max_batch_size: 10,
+            import_chunk_size: 10,
+            max_clock_skew_secs: 300,
+            max_lateness_secs: 3600,
+        };
+
+        let auth = IngestionAuth::from_ingestion_token(&db.pool, "uai_scoped-token").await.unwrap().unwrap();
+        let service = UsageService::new(&db.pool, &ws_tx).with_key_scope(auth.api_key_scope.as_deref());
+        let allowed = service.record_usage(user_id, usage_for(scoped_key), &limits).await;
+        let refused = service.record_usage(user_id, usage_for(other_key), &limits).await;
+        db.drop().await;
+
+        assert_eq!(auth.api_key_scope, Some(vec![scoped_key]));
+        assert!(allowed.is_ok());
+        assert!(matches!(refused, Err(ApiError::Forbidden(_))));
+    }
+}
@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use crate::models::money::Money;
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Alert {
     pub id: Uuid,
@@ -10,8 +12,8 @@ pub struct Alert {
     pub api_key_id: Option<Uuid>,
     pub alert_type: String,
     pub severity: String,
-    pub threshold_value: Option<f64>,
-    pub current_value: Option<f64>,
+    pub threshold_value: Option<Money>,
+    pub current_value: Option<Money>,
     pub message: String,
     pub is_read: bool,
     pub created_at: DateTime<Utc>,
@@ -1,8 +1,13 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{types::Json, FromRow};
 use uuid::Uuid;
 
+use crate::models::money::Money;
+
+/// A provider key. Its prices, when set, override the model price catalog.
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct ApiKey {
     pub id: Uuid,
@@ -10,9 +15,27 @@ pub struct ApiKey {
     pub name: String,
     pub provider: String,
     pub encrypted_key: String,
-    pub cost_per_1k_input: f64,
-    pub cost_per_1k_output: f64,
+    pub cost_per_1k_input: Option<Money>,
+    pub cost_per_1k_output: Option<Money>,
     pub is_active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub cost_per_1k_cached_input: Option<Money>,
+    pub cost_per_1k_cache_write: Option<Money>,
+    pub cost_per_1k_reasoning: Option<Money>,
+    /// Per-unit prices keyed by `unit` or `unit:variant`.
+    pub unit_prices: Json<HashMap<String, Money>>,
+    /// Currency of the key's prices.
+    pub currency: String,
+}
+
+impl ApiKey {
+    pub fn has_price_overrides(&self) -> bool {
+        self.cost_per_1k_input.is_some()
+            || self.cost_per_1k_output.is_some()
+            || self.cost_per_1k_cached_input.is_some()
+            || self.cost_per_1k_cache_write.is_some()
+            || self.cost_per_1k_reasoning.is_some()
+            || !self.unit_prices.is_empty()
+    }
 }
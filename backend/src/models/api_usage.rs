@@ -1,8 +1,14 @@
-use chrono::{DateTime, Utc};
+use base64::Engine;
+use chrono::{DateTime, Duration, DurationRound, SecondsFormat, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{types::Json, FromRow};
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError};
+
+use crate::models::model_price::Pricing;
+use crate::models::money::{round_to_scale, validate_non_negative, Money, AMOUNT_LIMIT};
+use crate::models::tag::{deserialize_tag_filter, validate_tags, Tags};
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct ApiUsage {
@@ -15,24 +21,165 @@ pub struct ApiUsage {
     pub total_tokens: i32,
     pub requests: i32,
     pub errors: i32,
-    pub cost: f64,
+    pub cost: Money,
     pub model_name: Option<String>,
     pub endpoint: Option<String>,
     pub status_code: Option<i32>,
     pub response_time_ms: Option<i32>,
     pub metadata: Option<serde_json::Value>,
+    pub external_id: Option<String>,
+    pub received_at: DateTime<Utc>,
+    pub error_type: Option<String>,
+    pub cached_input_tokens: Option<i32>,
+    pub cache_write_tokens: Option<i32>,
+    pub reasoning_tokens: Option<i32>,
+    pub billing_unit: String,
+    pub quantity: Option<Decimal>,
+    pub unit_variant: Option<String>,
+    /// Currency of `cost`, that of the prices it was computed from.
+    pub currency: String,
+    /// Cost at list price, before any pricing rule discount.
+    pub list_cost: Money,
+    pub is_batch: bool,
+    /// The pricing rule whose discount applied when the call was made.
+    pub pricing_rule_id: Option<Uuid>,
+    pub discount_percent: Money,
+    /// Cost attribution tags, e.g. `team` or `customer_id`.
+    pub tags: Json<Tags>,
+}
+
+/// A usage row with its cost converted into the reporting currency.
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExportedUsage {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub usage: ApiUsage,
+    pub reporting_cost: Money,
+    pub reporting_currency: String,
+}
+
+/// What a usage row is billed by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BillingUnit {
+    #[default]
+    Tokens,
+    Images,
+    AudioSeconds,
+    Characters,
+    Embeddings,
+    Requests,
+}
+
+impl BillingUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BillingUnit::Tokens => "tokens",
+            BillingUnit::Images => "images",
+            BillingUnit::AudioSeconds => "audio_seconds",
+            BillingUnit::Characters => "characters",
+            BillingUnit::Embeddings => "embeddings",
+            BillingUnit::Requests => "requests",
+        }
+    }
+    
+    pub fn parse(unit: &str) -> Option<Self> {
+        match unit {
+            "tokens" => Some(BillingUnit::Tokens),
+            "images" => Some(BillingUnit::Images),
+            "audio_seconds" => Some(BillingUnit::AudioSeconds),
+            "characters" => Some(BillingUnit::Characters),
+            "embeddings" => Some(BillingUnit::Embeddings),
+            "requests" => Some(BillingUnit::Requests),
+            _ => None,
+        }
+    }
+}
+
+/// Why a call failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorType {
+    RateLimited,
+    Timeout,
+    ContentFilter,
+    AuthError,
+    ClientError,
+    ServerError,
+}
+
+impl ErrorType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorType::RateLimited => "rate_limited",
+            ErrorType::Timeout => "timeout",
+            ErrorType::ContentFilter => "content_filter",
+            ErrorType::AuthError => "auth_error",
+            ErrorType::ClientError => "client_error",
+            ErrorType::ServerError => "server_error",
+        }
+    }
+    
+    /// Classifies a failed HTTP status; `None` for successful responses.
+    pub fn from_status(status_code: i32) -> Option<Self> {
+        match status_code {
+            429 => Some(ErrorType::RateLimited),
+            408 | 504 | 524 => Some(ErrorType::Timeout),
+            401 | 403 => Some(ErrorType::AuthError),
+            500.. => Some(ErrorType::ServerError),
+            400.. => Some(ErrorType::ClientError),
+            _ => None,
+        }
+    }
+}
+
+/// A usage row as returned from ingestion, flagged when an earlier row with the
+/// same idempotency key was returned instead of inserting a new one.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedUsage {
+    #[serde(flatten)]
+    pub usage: ApiUsage,
+    pub deduplicated: bool,
 }
 
 #[derive(Debug, Deserialize, Validate)]
+#[validate(schema(function = "validate_quantities"))]
 pub struct CreateUsageRequest {
     pub api_key_id: Uuid,
     
+    /// All prompt tokens, including cached and cache-write tokens.
+    #[serde(default)]
     #[validate(range(min = 0))]
     pub input_tokens: i32,
     
+    /// All completion tokens, including reasoning tokens.
+    #[serde(default)]
     #[validate(range(min = 0))]
     pub output_tokens: i32,
     
+    /// Prompt tokens served from the provider's cache.
+    #[validate(range(min = 0))]
+    pub cached_input_tokens: Option<i32>,
+    
+    /// Prompt tokens written to the provider's cache.
+    #[validate(range(min = 0))]
+    pub cache_write_tokens: Option<i32>,
+    
+    #[validate(range(min = 0))]
+    pub reasoning_tokens: Option<i32>,
+    
+    /// Defaults to `tokens`. Other units are billed by `quantity`.
+    pub billing_unit: Option<BillingUnit>,
+    
+    /// Number of units for non-token usage, e.g. images generated or seconds
+    /// of audio transcribed.
+    #[validate(custom(function = "validate_quantity"))]
+    pub quantity: Option<Decimal>,
+    
+    /// Distinguishes prices within a unit, e.g. `1024x1024:hd` for images.
+    #[validate(length(min = 1, max = 100))]
+    pub unit_variant: Option<String>,
+    
     #[validate(length(min = 1, max = 100))]
     pub model_name: String,
     
@@ -45,18 +192,858 @@ pub struct CreateUsageRequest {
     pub response_time_ms: Option<i32>,
     
     pub metadata: Option<serde_json::Value>,
+    
+    /// Idempotency key, e.g. the provider's request id. Unique per user.
+    #[validate(length(min = 1, max = 255))]
+    pub external_id: Option<String>,
+    
+    /// When the call happened. Defaults to the time the event is received.
+    pub timestamp: Option<DateTime<Utc>>,
+    
+    /// Failure category. Derived from `status_code` when omitted.
+    pub error_type: Option<ErrorType>,
+    
+    /// Made through the provider's batch API, which pricing rules may discount.
+    #[serde(default)]
+    pub is_batch: bool,
+    
+    /// Cost attribution tags, e.g. `{"team": "search", "environment": "prod"}`.
+    #[validate(custom(function = "validate_tags"))]
+    pub tags: Option<Tags>,
+}
+
+/// Unit quantities are stored like amounts, so they share their limit.
+pub const QUANTITY_LIMIT: i64 = AMOUNT_LIMIT;
+
+fn validate_quantity(quantity: &Decimal) -> Result<(), ValidationError> {
+    if *quantity < Decimal::ZERO || *quantity >= Decimal::from(QUANTITY_LIMIT) {
+        return Err(ValidationError::new("quantity_out_of_range")
+            .with_message(format!("quantity must be at least 0 and below {}", QUANTITY_LIMIT).into()));
+    }
+
+    Ok(())
+}
+
+fn validate_quantities(req: &CreateUsageRequest) -> Result<(), ValidationError> {
+    if req.billing_unit.unwrap_or_default() != BillingUnit::Tokens {
+        let has_tokens = req.input_tokens > 0
+            || req.output_tokens > 0
+            || req.cached_input_tokens.unwrap_or(0) > 0
+            || req.cache_write_tokens.unwrap_or(0) > 0
+            || req.reasoning_tokens.unwrap_or(0) > 0;
+        if has_tokens {
+            return Err(ValidationError::new("tokens_with_non_token_unit")
+                .with_message("token counts are only accepted for the tokens billing unit".into()));
+        }
+        if req.quantity.is_none() {
+            return Err(ValidationError::new("quantity_required")
+                .with_message("quantity is required for non-token billing units".into()));
+        }
+        return Ok(());
+    }
+    
+    if req.quantity.is_some() || req.unit_variant.is_some() {
+        return Err(ValidationError::new("quantity_with_tokens")
+            .with_message("quantity and unit_variant are not used for the tokens billing unit".into()));
+    }
+    
+    let cached = req.cached_input_tokens.unwrap_or(0) as i64 + req.cache_write_tokens.unwrap_or(0) as i64;
+    if cached > req.input_tokens as i64 {
+        return Err(ValidationError::new("cached_tokens_exceed_input")
+            .with_message("cached_input_tokens + cache_write_tokens cannot exceed input_tokens".into()));
+    }
+    
+    if req.reasoning_tokens.unwrap_or(0) > req.output_tokens {
+        return Err(ValidationError::new("reasoning_tokens_exceed_output")
+            .with_message("reasoning_tokens cannot exceed output_tokens".into()));
+    }
+    
+    Ok(())
+}
+
+/// A raw provider response to be parsed into a usage row.
+#[derive(Debug, Deserialize, Validate)]
+pub struct RawUsageRequest {
+    pub api_key_id: Uuid,
+    
+    /// The provider's response body, as received.
+    pub response: serde_json::Value,
+    
+    /// The request body sent to the provider, used as a fallback for the model.
+    pub request: Option<serde_json::Value>,
+    
+    /// Overrides the model name found in the response.
+    #[validate(length(min = 1, max = 100))]
+    pub model_name: Option<String>,
+    
+    pub endpoint: Option<String>,
+    
+    /// Overrides the status derived from the response body.
+    #[validate(range(min = 100, max = 599))]
+    pub status_code: Option<i32>,
+    
+    #[validate(range(min = 0))]
+    pub response_time_ms: Option<i32>,
+    
+    pub metadata: Option<serde_json::Value>,
+    
+    /// Defaults to the provider's response id.
+    #[validate(length(min = 1, max = 255))]
+    pub external_id: Option<String>,
+    
+    pub timestamp: Option<DateTime<Utc>>,
+    
+    /// Overrides the failure category derived from the response.
+    pub error_type: Option<ErrorType>,
+    
+    #[serde(default)]
+    pub is_batch: bool,
+    
+    #[validate(custom(function = "validate_tags"))]
+    pub tags: Option<Tags>,
+}
+
+/// Column a usage listing is ordered by. Ties are broken by `id`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageSort {
+    #[default]
+    Timestamp,
+    Cost,
+    TotalTokens,
+}
+
+impl UsageSort {
+    pub fn column(&self) -> &'static str {
+        match self {
+            UsageSort::Timestamp => "timestamp",
+            UsageSort::Cost => "cost",
+            UsageSort::TotalTokens => "total_tokens",
+        }
+    }
+    
+    /// SQL type the cursor value is cast to.
+    pub fn sql_type(&self) -> &'static str {
+        match self {
+            UsageSort::Timestamp => "timestamptz",
+            UsageSort::Cost => "numeric",
+            UsageSort::TotalTokens => "integer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// Which usage a listing or export covers within its date range. Embedded
+/// in the query strings of both, so they filter rows the same way.
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+pub struct UsageFilter {
+    pub api_key_id: Option<Uuid>,
+    
+    #[validate(length(min = 1, max = 100))]
+    pub model_name: Option<String>,
+    
+    pub endpoint: Option<String>,
+    
+    #[serde(default, deserialize_with = "deserialize_optional_number")]
+    #[validate(range(min = 100, max = 599))]
+    pub status_min: Option<i32>,
+    
+    #[serde(default, deserialize_with = "deserialize_optional_number")]
+    #[validate(range(min = 100, max = 599))]
+    pub status_max: Option<i32>,
+    
+    /// Lower bound on `cost`, in each row's own currency.
+    #[validate(custom(function = "validate_non_negative"))]
+    pub min_cost: Option<Money>,
+    
+    #[validate(custom(function = "validate_non_negative"))]
+    pub max_cost: Option<Money>,
+    
+    /// Only usage carrying all of these tags, written `key:value,key:value`.
+    #[serde(default, deserialize_with = "deserialize_tag_filter")]
+    pub tags: Option<Tags>,
+}
+
+/// Filters, ordering and page position of a usage listing.
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct UsageListQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub filter: UsageFilter,
+    
+    #[serde(default)]
+    pub sort: UsageSort,
+    
+    #[serde(default)]
+    pub order: SortOrder,
+    
+    /// The `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<i64>,
+}
+
+/// Deserializes an optional integer given either as a number or as text;
+/// query parameters of a flattened struct only ever arrive as text.
+fn deserialize_optional_number<'de, D>(deserializer: D) -> Result<Option<i32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrText {
+        Number(i32),
+        Text(String),
+    }
+    
+    match Option::<NumberOrText>::deserialize(deserializer)? {
+        Some(NumberOrText::Number(number)) => Ok(Some(number)),
+        Some(NumberOrText::Text(text)) => text
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid integer '{}'", text))),
+        None => Ok(None),
+    }
+}
+
+/// Position after the last row of a page: its sort value and id, along with
+/// the ordering it is only valid for.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageCursor {
+    pub sort: UsageSort,
+    pub order: SortOrder,
+    pub value: String,
+    pub id: i64,
+}
+
+impl UsageCursor {
+    pub fn after(usage: &ApiUsage, sort: UsageSort, order: SortOrder) -> Self {
+        let value = match sort {
+            UsageSort::Timestamp => usage.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            UsageSort::Cost => usage.cost.to_string(),
+            UsageSort::TotalTokens => usage.total_tokens.to_string(),
+        };
+        
+        Self { sort, order, value, id: usage.id }
+    }
+    
+    /// Opaque, URL-safe form handed to clients.
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+    
+    /// `None` for anything that isn't a cursor issued by [`encode`](Self::encode),
+    /// including a value that doesn't parse as its sort column's type.
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let cursor: Self = serde_json::from_slice(&json).ok()?;
+        
+        let valid = match cursor.sort {
+            UsageSort::Timestamp => DateTime::parse_from_rfc3339(&cursor.value).is_ok(),
+            UsageSort::Cost => cursor.value.parse::<Money>().is_ok(),
+            UsageSort::TotalTokens => cursor.value.parse::<i32>().is_ok(),
+        };
+        valid.then_some(cursor)
+    }
+}
+
+/// One page of a usage listing.
+#[derive(Debug)]
+pub struct UsagePage {
+    pub items: Vec<ApiUsage>,
+    pub next_cursor: Option<String>,
+}
+
+/// A priced usage row ready to be written to `api_usage`.
+#[derive(Debug, Clone)]
+pub struct NewUsage {
+    pub user_id: Uuid,
+    pub api_key_id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub input_tokens: i32,
+    pub output_tokens: i32,
+    pub total_tokens: i32,
+    pub cached_input_tokens: Option<i32>,
+    pub cache_write_tokens: Option<i32>,
+    pub reasoning_tokens: Option<i32>,
+    pub billing_unit: BillingUnit,
+    pub quantity: Option<Decimal>,
+    pub unit_variant: Option<String>,
+    pub errors: i32,
+    pub error_type: Option<ErrorType>,
+    pub cost: Money,
+    pub currency: String,
+    pub list_cost: Money,
+    pub is_batch: bool,
+    pub pricing_rule_id: Option<Uuid>,
+    pub discount_percent: Money,
+    pub model_name: String,
+    pub endpoint: Option<String>,
+    pub status_code: Option<i32>,
+    pub response_time_ms: Option<i32>,
+    pub metadata: Option<serde_json::Value>,
+    pub tags: Tags,
+    pub external_id: Option<String>,
+}
+
+/// Token counts of one call, as used for pricing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenCounts {
+    pub input: i32,
+    pub output: i32,
+    pub cached_input: i32,
+    pub cache_write: i32,
+    pub reasoning: i32,
+}
+
+impl From<&CreateUsageRequest> for TokenCounts {
+    fn from(req: &CreateUsageRequest) -> Self {
+        Self {
+            input: req.input_tokens,
+            output: req.output_tokens,
+            cached_input: req.cached_input_tokens.unwrap_or(0),
+            cache_write: req.cache_write_tokens.unwrap_or(0),
+            reasoning: req.reasoning_tokens.unwrap_or(0),
+        }
+    }
+}
+
+/// Per-1k token prices. Missing dimension prices fall back to the input or
+/// output price.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenPricing {
+    pub input: Money,
+    pub output: Money,
+    pub cached_input: Option<Money>,
+    pub cache_write: Option<Money>,
+    pub reasoning: Option<Money>,
+}
+
+/// What a request is billed for.
+#[derive(Debug, Clone, Copy)]
+pub enum BilledQuantity<'a> {
+    Tokens(TokenCounts),
+    Units {
+        unit: BillingUnit,
+        quantity: Decimal,
+        variant: Option<&'a str>,
+    },
+}
+
+impl<'a> From<&'a CreateUsageRequest> for BilledQuantity<'a> {
+    fn from(req: &'a CreateUsageRequest) -> Self {
+        match req.billing_unit.unwrap_or_default() {
+            BillingUnit::Tokens => BilledQuantity::Tokens(TokenCounts::from(req)),
+            unit => BilledQuantity::Units {
+                unit,
+                quantity: req.quantity.unwrap_or_default(),
+                variant: req.unit_variant.as_deref(),
+            },
+        }
+    }
 }
 
 impl ApiUsage {
-    pub fn calculate_cost(
-        input_tokens: i32,
-        output_tokens: i32,
-        cost_per_1k_input: f64,
-        cost_per_1k_output: f64,
-    ) -> f64 {
-        let input_cost = (input_tokens as f64 / 1000.0) * cost_per_1k_input;
-        let output_cost = (output_tokens as f64 / 1000.0) * cost_per_1k_output;
+    /// Whether the event belongs to an hourly bucket that had already closed
+    /// when it was received.
+    pub fn is_late(&self) -> bool {
+        self.timestamp < self.received_at.duration_trunc(Duration::hours(1)).unwrap_or(self.received_at)
+    }
+    
+
+    /// What a stored row was billed for; `None` for an unknown billing unit.
+    pub fn billed_quantity(&self) -> Option<BilledQuantity<'_>> {
+        match BillingUnit::parse(&self.billing_unit)? {
+            BillingUnit::Tokens => Some(BilledQuantity::Tokens(TokenCounts {
+                input: self.input_tokens,
+                output: self.output_tokens,
+                cached_input: self.cached_input_tokens.unwrap_or(0),
+                cache_write: self.cache_write_tokens.unwrap_or(0),
+                reasoning: self.reasoning_tokens.unwrap_or(0),
+            })),
+            unit => Some(BilledQuantity::Units {
+                unit,
+                quantity: self.quantity.unwrap_or_default(),
+                variant: self.unit_variant.as_deref(),
+            }),
+        }
+    }
+    
+    /// Prices each token dimension separately. Cached and cache-write tokens are
+    /// carved out of the input tokens and reasoning tokens out of the output
+    /// tokens; dimensions without their own price use the input/output price.
+    pub fn calculate_token_cost(tokens: &TokenCounts, pricing: &TokenPricing) -> Money {
+        let per_1k = |count: i32, price: Money| Money::from(count.max(0)) * price / Money::ONE_THOUSAND;
+        
+        let uncached_input = tokens.input - tokens.cached_input - tokens.cache_write;
+        let visible_output = tokens.output - tokens.reasoning;
+        
+        let cost = per_1k(uncached_input, pricing.input)
+            + per_1k(tokens.cached_input, pricing.cached_input.unwrap_or(pricing.input))
+            + per_1k(tokens.cache_write, pricing.cache_write.unwrap_or(pricing.input))
+            + per_1k(visible_output, pricing.output)
+            + per_1k(tokens.reasoning, pricing.reasoning.unwrap_or(pricing.output));
         
-        ((input_cost + output_cost) * 100.0).round() / 100.0
+        round_to_scale(cost)
+    }
+    
+    /// Prices a request by its billing unit; `None` when the pricing has no
+    /// price for that unit. Fails when the cost is too large to be stored.
+    pub fn calculate_cost(billed: &BilledQuantity, pricing: &Pricing) -> Result<Option<Money>, String> {
+        let cost = match billed {
+            BilledQuantity::Tokens(tokens) => match pricing.tokens {
+                Some(token_pricing) => Some(Self::calculate_token_cost(tokens, &token_pricing)),
+                None => return Ok(None),
+            },
+            BilledQuantity::Units { unit, quantity, variant } => {
+                let Some(price) = pricing.unit_price(*unit, *variant) else {
+                    return Ok(None);
+                };
+                quantity.checked_mul(price).map(round_to_scale)
+            }
+        };
+        
+        match cost {
+            Some(cost) if cost < Money::from(AMOUNT_LIMIT) => Ok(Some(cost)),
+            _ => Err(format!("cost is out of range; a single usage event must cost less than {}", AMOUNT_LIMIT)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn money(value: &str) -> Money {
+        Money::from_str(value).unwrap()
+    }
+
+    fn gpt_4o_mini() -> TokenPricing {
+        TokenPricing {
+            input: money("0.00015"),
+            output: money("0.0006"),
+            cached_input: None,
+            cache_write: None,
+            reasoning: None,
+        }
+    }
+
+    fn tokens(input: i32, output: i32) -> TokenCounts {
+        TokenCounts {
+            input,
+            output,
+            cached_input: 0,
+            cache_write: 0,
+            reasoning: 0,
+        }
+    }
+
+    #[test]
+    fn tiny_call_keeps_sub_cent_cost() {
+        let cost = ApiUsage::calculate_token_cost(&tokens(10, 5), &gpt_4o_mini());
+
+        assert_eq!(cost, money("0.0000045"));
+    }
+
+    #[test]
+    fn sum_of_many_tiny_calls_is_exact() {
+        let pricing = gpt_4o_mini();
+        let cost = ApiUsage::calculate_token_cost(&tokens(300, 0), &pricing);
+
+        let total: Money = std::iter::repeat_n(cost, 1_000_000).sum();
+
+        assert_eq!(cost, money("0.000045"));
+        assert_eq!(total, money("45"));
+    }
+
+    #[test]
+    fn sum_of_mixed_calls_matches_cost_of_their_total_tokens() {
+        let pricing = gpt_4o_mini();
+        let calls: Vec<TokenCounts> = (0..10_000).map(|i| tokens(i % 97 + 1, i % 13)).collect();
+
+        let summed: Money = calls
+            .iter()
+            .map(|call| ApiUsage::calculate_token_cost(call, &pricing))
+            .sum();
+        let combined = tokens(
+            calls.iter().map(|c| c.input).sum(),
+            calls.iter().map(|c| c.output).sum(),
+        );
+
+        assert_eq!(summed, ApiUsage::calculate_token_cost(&combined, &pricing));
+    }
+
+    fn cached_call() -> TokenCounts {
+        TokenCounts {
+            input: 1000,
+            output: 500,
+            cached_input: 400,
+            cache_write: 100,
+            reasoning: 200,
+        }
+    }
+
+    #[test]
+    fn cached_and_reasoning_tokens_use_their_own_prices() {
+        let pricing = TokenPricing {
+            input: money("0.003"),
+            output: money("0.015"),
+            cached_input: Some(money("0.0003")),
+            cache_write: Some(money("0.00375")),
+            reasoning: Some(money("0.06")),
+        };
+
+        let cost = ApiUsage::calculate_token_cost(&cached_call(), &pricing);
+
+        // 500 uncached + 400 cached + 100 cache-write input, 300 visible + 200 reasoning output.
+        assert_eq!(cost, money("0.0015") + money("0.00012") + money("0.000375") + money("0.0045") + money("0.012"));
+    }
+
+    #[test]
+    fn dimensions_without_a_price_fall_back_to_input_and_output() {
+        let pricing = TokenPricing {
+            input: money("0.003"),
+            output: money("0.015"),
+            ..Default::default()
+        };
+
+        let cost = ApiUsage::calculate_token_cost(&cached_call(), &pricing);
+
+        assert_eq!(cost, ApiUsage::calculate_token_cost(&tokens(1000, 500), &pricing));
+        assert_eq!(cost, money("0.0105"));
+    }
+
+    #[test]
+    fn only_the_priced_dimensions_change_the_cost() {
+        let pricing = TokenPricing {
+            input: money("0.003"),
+            output: money("0.015"),
+            cached_input: Some(money("0.0003")),
+            ..Default::default()
+        };
+
+        let cost = ApiUsage::calculate_token_cost(&cached_call(), &pricing);
+
+        // Cache-write tokens bill at the input price and reasoning tokens at the output price.
+        assert_eq!(cost, money("0.0015") + money("0.00012") + money("0.0003") + money("0.0075"));
+    }
+
+    #[test]
+    fn carve_outs_larger_than_the_totals_never_go_negative() {
+        let counts = TokenCounts {
+            input: 100,
+            output: 10,
+            cached_input: 150,
+            cache_write: 0,
+            reasoning: 20,
+        };
+
+        let cost = ApiUsage::calculate_token_cost(&counts, &gpt_4o_mini());
+
+        assert_eq!(cost, money("0.0000225") + money("0.000012"));
+    }
+
+    #[test]
+    fn unit_cost_is_exact() {
+        let pricing = Pricing {
+            tokens: None,
+            unit_prices: [("audio_seconds".to_string(), money("0.0001"))].into(),
+            currency: "USD".to_string(),
+        };
+        let billed = BilledQuantity::Units {
+            unit: BillingUnit::AudioSeconds,
+            quantity: money("0.3"),
+            variant: None,
+        };
+
+        assert_eq!(ApiUsage::calculate_cost(&billed, &pricing), Ok(Some(money("0.00003"))));
+    }
+
+    #[test]
+    fn rate_limit_and_timeout_statuses_get_their_own_types() {
+        assert_eq!(ErrorType::from_status(429), Some(ErrorType::RateLimited));
+        assert_eq!(ErrorType::from_status(408), Some(ErrorType::Timeout));
+        assert_eq!(ErrorType::from_status(504), Some(ErrorType::Timeout));
+        assert_eq!(ErrorType::from_status(524), Some(ErrorType::Timeout));
+    }
+
+    #[test]
+    fn auth_statuses_are_auth_errors() {
+        assert_eq!(ErrorType::from_status(401), Some(ErrorType::AuthError));
+        assert_eq!(ErrorType::from_status(403), Some(ErrorType::AuthError));
+    }
+
+    #[test]
+    fn other_failures_are_split_into_client_and_server_errors() {
+        assert_eq!(ErrorType::from_status(400), Some(ErrorType::ClientError));
+        assert_eq!(ErrorType::from_status(404), Some(ErrorType::ClientError));
+        assert_eq!(ErrorType::from_status(499), Some(ErrorType::ClientError));
+        assert_eq!(ErrorType::from_status(500), Some(ErrorType::ServerError));
+        assert_eq!(ErrorType::from_status(503), Some(ErrorType::ServerError));
+        assert_eq!(ErrorType::from_status(599), Some(ErrorType::ServerError));
+    }
+
+    #[test]
+    fn successful_statuses_have_no_error_type() {
+        assert_eq!(ErrorType::from_status(200), None);
+        assert_eq!(ErrorType::from_status(204), None);
+        assert_eq!(ErrorType::from_status(302), None);
+        assert_eq!(ErrorType::from_status(399), None);
+    }
+
+    fn image_pricing() -> Pricing {
+        Pricing {
+            tokens: None,
+            unit_prices: [
+                ("images".to_string(), money("0.04")),
+                ("images:1024x1024:hd".to_string(), money("0.08")),
+            ]
+            .into(),
+            currency: "USD".to_string(),
+        }
+    }
+
+    fn images(quantity: Decimal, variant: Option<&str>) -> BilledQuantity<'_> {
+        BilledQuantity::Units {
+            unit: BillingUnit::Images,
+            quantity,
+            variant,
+        }
+    }
+
+    #[test]
+    fn variant_price_is_preferred_over_unit_price() {
+        let cost = ApiUsage::calculate_cost(&images(money("3"), Some("1024x1024:hd")), &image_pricing());
+
+        assert_eq!(cost, Ok(Some(money("0.24"))));
+    }
+
+    #[test]
+    fn unknown_variant_falls_back_to_unit_price() {
+        let pricing = image_pricing();
+
+        assert_eq!(ApiUsage::calculate_cost(&images(money("3"), Some("256x256")), &pricing), Ok(Some(money("0.12"))));
+        assert_eq!(ApiUsage::calculate_cost(&images(money("3"), None), &pricing), Ok(Some(money("0.12"))));
+    }
+
+    #[test]
+    fn cost_too_large_to_store_is_rejected() {
+        let pricing = Pricing {
+            tokens: None,
+            unit_prices: [("images".to_string(), money("8"))].into(),
+            currency: "USD".to_string(),
+        };
+
+        let overflowing = ApiUsage::calculate_cost(&images(money("1e28"), None), &pricing);
+        let unstorable = ApiUsage::calculate_cost(&images(money("9999999999"), None), &pricing);
+        let largest = ApiUsage::calculate_cost(&images(money("1249999999"), None), &pricing);
+
+        assert!(overflowing.unwrap_err().starts_with("cost is out of range"));
+        assert!(unstorable.is_err());
+        assert_eq!(largest, Ok(Some(money("9999999992"))));
+    }
+
+    #[test]
+    fn unpriced_unit_has_no_cost() {
+        let billed = BilledQuantity::Units {
+            unit: BillingUnit::Characters,
+            quantity: money("1000"),
+            variant: None,
+        };
+
+        assert_eq!(ApiUsage::calculate_cost(&billed, &image_pricing()), Ok(None));
+    }
+
+    #[test]
+    fn tokens_have_no_cost_without_token_pricing() {
+        let billed = BilledQuantity::Tokens(tokens(10, 5));
+
+        assert_eq!(ApiUsage::calculate_cost(&billed, &image_pricing()), Ok(None));
+    }
+
+    #[test]
+    fn non_token_units_are_billed_by_quantity() {
+        let req: CreateUsageRequest = serde_json::from_value(serde_json::json!({
+            "api_key_id": Uuid::nil(),
+            "model_name": "dall-e-3",
+            "billing_unit": "images",
+            "quantity": 2.0,
+            "unit_variant": "1024x1024:hd",
+        }))
+        .unwrap();
+
+        let billed = BilledQuantity::from(&req);
+
+        assert_eq!(ApiUsage::calculate_cost(&billed, &image_pricing()), Ok(Some(money("0.16"))));
+    }
+
+    #[test]
+    fn non_token_units_reject_token_counts_and_require_quantity() {
+        let with_tokens: CreateUsageRequest = serde_json::from_value(serde_json::json!({
+            "api_key_id": Uuid::nil(),
+            "model_name": "dall-e-3",
+            "billing_unit": "images",
+            "quantity": 1.0,
+            "input_tokens": 10,
+        }))
+        .unwrap();
+        let without_quantity: CreateUsageRequest = serde_json::from_value(serde_json::json!({
+            "api_key_id": Uuid::nil(),
+            "model_name": "dall-e-3",
+            "billing_unit": "images",
+        }))
+        .unwrap();
+
+        assert!(with_tokens.validate().is_err());
+        assert!(without_quantity.validate().is_err());
+    }
+
+    #[test]
+    fn quantity_must_fit_the_stored_scale() {
+        let with_quantity = |quantity: serde_json::Value| -> CreateUsageRequest {
+            serde_json::from_value(serde_json::json!({
+                "api_key_id": Uuid::nil(),
+                "model_name": "dall-e-3",
+                "billing_unit": "images",
+                "quantity": quantity,
+            }))
+            .unwrap()
+        };
+
+        assert!(with_quantity(serde_json::json!(9_999_999_999.5)).validate().is_ok());
+        assert!(with_quantity(serde_json::json!(10_000_000_000i64)).validate().is_err());
+        assert!(with_quantity(serde_json::json!(1e28)).validate().is_err());
+        assert!(with_quantity(serde_json::json!(-1)).validate().is_err());
+        assert_eq!(with_quantity(serde_json::json!("0.1")).quantity, Some(money("0.1")));
+    }
+
+    fn listed_usage() -> ApiUsage {
+        serde_json::from_value(serde_json::json!({
+            "id": 42,
+            "user_id": Uuid::nil(),
+            "api_key_id": Uuid::nil(),
+            "timestamp": "2026-03-01T12:34:56.789012Z",
+            "input_tokens": 1200,
+            "output_tokens": 300,
+            "total_tokens": 1500,
+            "requests": 1,
+            "errors": 0,
+            "cost": "0.0000450000",
+            "received_at": "2026-03-01T12:34:57Z",
+            "billing_unit": "tokens",
+            "currency": "USD",
+            "list_cost": "0.0000450000",
+            "is_batch": false,
+            "discount_percent": "0",
+            "tags": {},
+        }))
+        .unwrap()
+    }
+
+    fn raw_cursor(sort: &str, value: &str) -> String {
+        let json = serde_json::json!({"sort": sort, "order": "desc", "value": value, "id": 1});
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json.to_string())
+    }
+
+    #[test]
+    fn cursor_round_trips_for_every_sort() {
+        let usage = listed_usage();
+
+        for (sort, value) in [
+            (UsageSort::Timestamp, "2026-03-01T12:34:56.789012Z"),
+            (UsageSort::Cost, "0.0000450000"),
+            (UsageSort::TotalTokens, "1500"),
+        ] {
+            let encoded = UsageCursor::after(&usage, sort, SortOrder::Asc).encode();
+
+            let decoded = UsageCursor::decode(&encoded).unwrap();
+
+            assert_eq!(decoded.sort, sort);
+            assert_eq!(decoded.order, SortOrder::Asc);
+            assert_eq!(decoded.value, value);
+            assert_eq!(decoded.id, 42);
+        }
+    }
+
+    #[test]
+    fn cursor_with_a_value_of_the_wrong_type_is_rejected() {
+        assert!(UsageCursor::decode(&raw_cursor("timestamp", "2026-03-01")).is_none());
+        assert!(UsageCursor::decode(&raw_cursor("timestamp", "1500")).is_none());
+        assert!(UsageCursor::decode(&raw_cursor("cost", "cheap")).is_none());
+        assert!(UsageCursor::decode(&raw_cursor("total_tokens", "0.5")).is_none());
+        assert!(UsageCursor::decode(&raw_cursor("total_tokens", "99999999999")).is_none());
+    }
+
+    #[test]
+    fn cursor_that_is_not_one_of_ours_is_rejected() {
+        assert!(UsageCursor::decode("not base64!").is_none());
+        assert!(UsageCursor::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD.encode("[1, 2]")).is_none());
+        assert!(UsageCursor::decode(&raw_cursor("latency", "10")).is_none());
+    }
+
+    #[test]
+    fn json_prices_deserialize_exactly() {
+        let price: Money = serde_json::from_str("0.00015").unwrap();
+
+        assert_eq!(price, money("0.00015"));
+    }
+
+    fn list_query(uri: &str) -> Result<UsageListQuery, String> {
+        let uri: axum::http::Uri = uri.parse().unwrap();
+        axum::extract::Query::<UsageListQuery>::try_from_uri(&uri)
+            .map(|query| query.0)
+            .map_err(|e| e.body_text())
+    }
+
+    #[test]
+    fn listing_query_reads_its_filter_from_the_query_string() {
+        let query = list_query(
+            "/usage?model_name=gpt-4o&status_min=400&status_max=499&min_cost=0.5&tags=team:search&limit=10",
+        )
+        .unwrap();
+
+        assert_eq!(query.filter.model_name.as_deref(), Some("gpt-4o"));
+        assert_eq!(query.filter.status_min, Some(400));
+        assert_eq!(query.filter.status_max, Some(499));
+        assert_eq!(query.filter.min_cost, Some(money("0.5")));
+        assert_eq!(query.filter.max_cost, None);
+        assert_eq!(query.filter.tags.unwrap()["team"], "search");
+        assert_eq!(query.limit, Some(10));
+    }
+
+    #[test]
+    fn listing_query_rejects_a_non_numeric_status() {
+        assert!(list_query("/usage?status_min=4xx").is_err());
+    }
+
+    #[test]
+    fn listing_query_validates_its_filter() {
+        let query = list_query("/usage?status_min=99").unwrap();
+
+        assert!(query.validate().is_err());
+        assert!(list_query("/usage?status_min=200").unwrap().validate().is_ok());
     }
 }
@@ -3,14 +3,19 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use crate::models::money::Money;
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Budget {
     pub id: Uuid,
     pub user_id: Uuid,
     pub api_key_id: Option<Uuid>,
     pub limit_type: String,
-    pub limit_amount: f64,
+    pub limit_amount: Money,
+    /// Share of the limit at which an alert is raised, e.g. 0.8.
     pub alert_threshold: f64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Currency of `limit_amount`; spend is converted into it.
+    pub currency: String,
 }
\ No newline at end of file
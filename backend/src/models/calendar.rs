@@ -0,0 +1,221 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use validator::ValidationError;
+
+/// Local calendar of a user: the IANA timezone days are counted in and the
+/// day weeks start on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calendar {
+    pub timezone: Tz,
+    pub week_start: Weekday,
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Self { timezone: Tz::UTC, week_start: Weekday::Mon }
+    }
+}
+
+impl Calendar {
+    pub fn parse(timezone: &str, week_start: &str) -> Result<Self, String> {
+        Ok(Self {
+            timezone: parse_timezone(timezone)?,
+            week_start: parse_week_start(week_start)?,
+        })
+    }
+
+    /// This calendar with a per-request timezone or week start in place of
+    /// the stored one.
+    pub fn with_overrides(
+        self,
+        timezone: Option<&str>,
+        week_start: Option<&str>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            timezone: timezone.map(parse_timezone).transpose()?.unwrap_or(self.timezone),
+            week_start: week_start.map(parse_week_start).transpose()?.unwrap_or(self.week_start),
+        })
+    }
+
+    /// Name of the timezone, as bound to `AT TIME ZONE` in SQL.
+    pub fn timezone_name(&self) -> &'static str {
+        self.timezone.name()
+    }
+
+    /// Days a local time is shifted by so that `date_trunc('week', ...)`,
+    /// whose weeks start on Monday, starts them on `week_start` instead.
+    pub fn week_shift_days(&self) -> i32 {
+        (7 - self.week_start.num_days_from_monday() as i32) % 7
+    }
+
+    /// The local date at `at`.
+    pub fn date_of(&self, at: DateTime<Utc>) -> NaiveDate {
+        at.with_timezone(&self.timezone).date_naive()
+    }
+
+    /// First instant of a local date. Where a DST change skips midnight, the
+    /// first local time after the gap.
+    pub fn start_of(&self, date: NaiveDate) -> DateTime<Utc> {
+        let midnight = date.and_time(NaiveTime::MIN);
+
+        (0..=96)
+            .map(|quarter| midnight + Duration::minutes(15 * quarter))
+            .find_map(|local| self.timezone.from_local_datetime(&local).earliest())
+            .map_or_else(|| midnight.and_utc(), |start| start.with_timezone(&Utc))
+    }
+
+    /// First day of the week containing `date`.
+    pub fn week_start_of(&self, date: NaiveDate) -> NaiveDate {
+        let days_in = (date.weekday().num_days_from_monday() + 7
+            - self.week_start.num_days_from_monday())
+            % 7;
+        date - Duration::days(i64::from(days_in))
+    }
+
+    pub fn start_of_today(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.start_of(self.date_of(now))
+    }
+
+    pub fn start_of_week(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.start_of(self.week_start_of(self.date_of(now)))
+    }
+
+    pub fn start_of_month(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = self.date_of(now);
+        self.start_of(today.with_day(1).unwrap_or(today))
+    }
+}
+
+/// Lower-case English name of a weekday, as stored for `week_start`.
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "monday",
+        Weekday::Tue => "tuesday",
+        Weekday::Wed => "wednesday",
+        Weekday::Thu => "thursday",
+        Weekday::Fri => "friday",
+        Weekday::Sat => "saturday",
+        Weekday::Sun => "sunday",
+    }
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz, String> {
+    timezone
+        .parse()
+        .map_err(|_| format!("Unknown timezone '{}'; expected an IANA name such as America/Los_Angeles", timezone))
+}
+
+fn parse_week_start(week_start: &str) -> Result<Weekday, String> {
+    [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+    .into_iter()
+    .find(|day| weekday_name(*day) == week_start)
+    .ok_or_else(|| format!("Unknown week start '{}'; expected a day such as monday or sunday", week_start))
+}
+
+pub fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    parse_timezone(timezone)
+        .map(|_| ())
+        .map_err(|e| ValidationError::new("invalid_timezone").with_message(e.into()))
+}
+
+pub fn validate_week_start(week_start: &str) -> Result<(), ValidationError> {
+    parse_week_start(week_start)
+        .map(|_| ())
+        .map_err(|e| ValidationError::new("invalid_week_start").with_message(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calendar(timezone: &str, week_start: &str) -> Calendar {
+        Calendar::parse(timezone, week_start).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn week_shift_moves_the_week_start_onto_monday() {
+        assert_eq!(calendar("UTC", "monday").week_shift_days(), 0);
+        assert_eq!(calendar("UTC", "tuesday").week_shift_days(), 6);
+        assert_eq!(calendar("UTC", "saturday").week_shift_days(), 2);
+        assert_eq!(calendar("UTC", "sunday").week_shift_days(), 1);
+    }
+
+    #[test]
+    fn week_starts_on_the_configured_day() {
+        // 2026-03-08 is a Sunday
+        let sunday = date(2026, 3, 8);
+
+        assert_eq!(calendar("UTC", "monday").week_start_of(sunday), date(2026, 3, 2));
+        assert_eq!(calendar("UTC", "sunday").week_start_of(sunday), sunday);
+        assert_eq!(calendar("UTC", "saturday").week_start_of(sunday), date(2026, 3, 7));
+        assert_eq!(calendar("UTC", "monday").week_start_of(date(2026, 3, 9)), date(2026, 3, 9));
+    }
+
+    #[test]
+    fn week_containing_a_dst_change_starts_at_local_midnight() {
+        let new_york = calendar("America/New_York", "sunday");
+
+        // Clocks spring forward at 02:00 on Sunday 2026-03-08
+        assert_eq!(new_york.start_of_week(utc(2026, 3, 12, 12, 0)), utc(2026, 3, 8, 5, 0));
+        // ... and fall back at 02:00 on Sunday 2026-11-01
+        assert_eq!(new_york.start_of_week(utc(2026, 11, 4, 12, 0)), utc(2026, 11, 1, 4, 0));
+    }
+
+    #[test]
+    fn day_starts_at_local_midnight_on_both_sides_of_dst() {
+        let new_york = calendar("America/New_York", "monday");
+
+        assert_eq!(new_york.start_of(date(2026, 3, 8)), utc(2026, 3, 8, 5, 0));
+        assert_eq!(new_york.start_of(date(2026, 3, 9)), utc(2026, 3, 9, 4, 0));
+        assert_eq!(new_york.start_of(date(2026, 11, 1)), utc(2026, 11, 1, 4, 0));
+        assert_eq!(new_york.start_of(date(2026, 11, 2)), utc(2026, 11, 2, 5, 0));
+    }
+
+    #[test]
+    fn day_whose_midnight_is_skipped_starts_after_the_gap() {
+        // Havana springs forward from 00:00 to 01:00 on 2026-03-08
+        let havana = calendar("America/Havana", "monday");
+
+        assert_eq!(havana.start_of(date(2026, 3, 8)), utc(2026, 3, 8, 5, 0));
+        assert_eq!(havana.date_of(havana.start_of(date(2026, 3, 8))), date(2026, 3, 8));
+    }
+
+    #[test]
+    fn day_whose_midnight_repeats_starts_at_the_first_one() {
+        // Havana falls back from 01:00 to 00:00 on 2026-11-01
+        let havana = calendar("America/Havana", "monday");
+
+        assert_eq!(havana.start_of(date(2026, 11, 1)), utc(2026, 11, 1, 4, 0));
+    }
+
+    #[test]
+    fn local_date_follows_the_timezone() {
+        let tokyo = calendar("Asia/Tokyo", "monday");
+
+        assert_eq!(tokyo.date_of(utc(2026, 3, 31, 15, 0)), date(2026, 4, 1));
+        assert_eq!(tokyo.start_of_month(utc(2026, 3, 31, 15, 0)), utc(2026, 3, 31, 15, 0));
+        assert_eq!(tokyo.start_of_today(utc(2026, 3, 31, 14, 59)), utc(2026, 3, 30, 15, 0));
+    }
+
+    #[test]
+    fn unknown_timezone_or_week_start_is_rejected() {
+        assert!(Calendar::parse("Mars/Olympus_Mons", "monday").is_err());
+        assert!(Calendar::parse("UTC", "Monday").is_err());
+    }
+}
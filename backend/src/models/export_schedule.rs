@@ -0,0 +1,406 @@
+use std::net::IpAddr;
+use std::path::{Component, Path};
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::api_usage::UsageFilter;
+use crate::models::calendar::validate_timezone;
+use crate::models::money::{validate_non_negative, Money};
+use crate::models::tag::{validate_tags, Tags};
+use crate::models::usage_export::{ExportFormat, UsageExportQuery};
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ExportSchedule {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub cron: String,
+    /// Timezone of `cron` and `period`; the user's when unset.
+    pub timezone: Option<String>,
+    pub period: String,
+    pub format: String,
+    pub columns: String,
+    pub tag_columns: Option<String>,
+    pub currency: Option<String>,
+    pub filters: Json<ExportFilters>,
+    pub destination: Json<ExportDestination>,
+    #[serde(skip_serializing)]
+    pub encrypted_secret: Option<String>,
+    pub max_attempts: i32,
+    pub is_active: bool,
+    /// The cron time the pending run covers.
+    pub scheduled_for: DateTime<Utc>,
+    /// When the pending run is attempted next; later than `scheduled_for`
+    /// while a failed run waits to be retried.
+    pub next_run_at: DateTime<Utc>,
+    /// Failed attempts of the pending run so far.
+    pub attempt: i32,
+    pub running_since: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_status: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ExportSchedule {
+    /// The export query a run of this schedule makes, without a date range.
+    pub fn export_query(&self) -> Result<UsageExportQuery, String> {
+        let format = ExportFormat::parse(&self.format)
+            .ok_or_else(|| format!("Unknown export format '{}'", self.format))?;
+        let filters = &self.filters.0;
+
+        Ok(UsageExportQuery {
+            format,
+            columns: self.columns.clone(),
+            tag_columns: self.tag_columns.clone(),
+            timezone: self.timezone.clone(),
+            currency: self.currency.clone(),
+            filter: UsageFilter {
+                api_key_id: filters.api_key_id,
+                model_name: filters.model_name.clone(),
+                endpoint: filters.endpoint.clone(),
+                status_min: filters.status_min,
+                status_max: filters.status_max,
+                min_cost: filters.min_cost,
+                max_cost: filters.max_cost,
+                tags: filters.tags.clone(),
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// The usage listing filters a scheduled export applies.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct ExportFilters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_id: Option<Uuid>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(min = 1, max = 100))]
+    pub model_name: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 100, max = 599))]
+    pub status_min: Option<i32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 100, max = 599))]
+    pub status_max: Option<i32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom(function = "validate_non_negative"))]
+    pub min_cost: Option<Money>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom(function = "validate_non_negative"))]
+    pub max_cost: Option<Money>,
+
+    /// Only usage carrying all of these tags.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom(function = "validate_tags"))]
+    pub tags: Option<Tags>,
+}
+
+/// Where a scheduled export is delivered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportDestination {
+    /// A directory relative to the user's folder under `EXPORT_LOCAL_DIR`.
+    Local { path: String },
+    /// A bucket on S3 or an S3-compatible service, addressed path-style as
+    /// `<endpoint>/<bucket>/<prefix><file>`.
+    S3 {
+        endpoint: String,
+        bucket: String,
+        #[serde(default = "default_region")]
+        region: String,
+        #[serde(default)]
+        prefix: String,
+        access_key_id: String,
+        /// Only read from requests; it is stored encrypted and never returned.
+        #[serde(default, skip_serializing)]
+        secret_access_key: Option<String>,
+    },
+}
+
+impl ExportDestination {
+    /// Checks the destination is well formed; plain `http://` endpoints are
+    /// only accepted with `allow_http`. S3 endpoints must be one of
+    /// `allowed_hosts` when any are given, and otherwise must not be a
+    /// private, loopback or link-local address. Host names are checked again
+    /// once resolved, by the upload client.
+    pub fn validate(&self, allow_http: bool, allowed_hosts: &[String]) -> Result<(), String> {
+        match self {
+            Self::Local { path } => {
+                let relative = Path::new(path)
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+                if path.len() > 255 || !relative {
+                    return Err("path must be a relative directory of at most 255 characters".to_string());
+                }
+            }
+            Self::S3 { endpoint, bucket, region, prefix, access_key_id, .. } => {
+                let url = reqwest::Url::parse(endpoint)
+                    .map_err(|_| format!("endpoint '{}' is not a URL", endpoint))?;
+                match url.scheme() {
+                    "https" => {}
+                    "http" if allow_http => {}
+                    _ => return Err("endpoint must be an https:// URL".to_string()),
+                }
+                if url.host_str().is_none() || url.query().is_some() {
+                    return Err(format!("endpoint '{}' must be a base URL", endpoint));
+                }
+                let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+                if !allowed_hosts.is_empty() {
+                    if !allowed_hosts.contains(&host) {
+                        return Err(format!("endpoint host '{}' is not an allowed export host", host));
+                    }
+                } else {
+                    let internal = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+                        Ok(ip) => !is_public_ip(ip),
+                        Err(_) => host == "localhost" || host.ends_with(".localhost"),
+                    };
+                    if internal {
+                        return Err(format!("endpoint host '{}' is not a public address", host));
+                    }
+                }
+                let valid_bucket = (3..=63).contains(&bucket.len())
+                    && bucket
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'.');
+                if !valid_bucket {
+                    return Err(format!("bucket '{}' is not a valid bucket name", bucket));
+                }
+                if region.is_empty() || region.len() > 64 {
+                    return Err("region must be 1-64 characters".to_string());
+                }
+                if prefix.starts_with('/') || prefix.len() > 512 {
+                    return Err("prefix must not start with '/' and is at most 512 characters".to_string());
+                }
+                if access_key_id.is_empty() || access_key_id.len() > 128 {
+                    return Err("access_key_id must be 1-128 characters".to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `ip` is publicly routable: not private, loopback, link-local,
+/// shared, reserved, documentation, multicast or unspecified.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (b == 18 || b == 19)))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+            let [first, second, ..] = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && second == 0x0db8))
+        }
+    }
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_period() -> String {
+    "yesterday".to_string()
+}
+
+/// Body of creating or replacing an export schedule.
+#[derive(Debug, Deserialize, Validate)]
+pub struct ExportScheduleRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+
+    /// Cron expression with five (minute first) or six (second first) fields.
+    pub cron: String,
+
+    #[validate(custom(function = "validate_timezone"))]
+    pub timezone: Option<String>,
+
+    /// Period exported by each run, resolved at the cron time; see
+    /// [`Period::parse`](crate::utils::period::Period::parse).
+    #[serde(default = "default_period")]
+    pub period: String,
+
+    pub format: ExportFormat,
+
+    #[serde(default)]
+    pub columns: String,
+
+    pub tag_columns: Option<String>,
+
+    pub currency: Option<String>,
+
+    #[serde(default)]
+    #[validate(nested)]
+    pub filters: ExportFilters,
+
+    pub destination: ExportDestination,
+
+    #[validate(range(min = 1, max = 10))]
+    pub max_attempts: Option<i32>,
+
+    pub is_active: Option<bool>,
+}
+
+/// One attempt at running an export schedule.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ExportRun {
+    pub id: i64,
+    pub schedule_id: Uuid,
+    pub scheduled_for: DateTime<Utc>,
+    pub attempt: i32,
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub status: String,
+    pub rows_exported: Option<i64>,
+    pub bytes_written: Option<i64>,
+    /// Path or `s3://` URL of the delivered file.
+    pub location: Option<String>,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Parses a cron expression; five-field expressions run at second zero.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, String> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+
+    cron::Schedule::from_str(&expression).map_err(|e| format!("Invalid cron expression: {}", e))
+}
+
+/// The first time `schedule` fires in `timezone` after `after`.
+pub fn next_fire(schedule: &cron::Schedule, timezone: Tz, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    schedule
+        .after(&after.with_timezone(&timezone))
+        .next()
+        .map(|at| at.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s3(endpoint: &str) -> ExportDestination {
+        ExportDestination::S3 {
+            endpoint: endpoint.to_string(),
+            bucket: "usage-exports".to_string(),
+            region: default_region(),
+            prefix: String::new(),
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: None,
+        }
+    }
+
+    fn hosts(hosts: &[&str]) -> Vec<String> {
+        hosts.iter().map(|host| host.to_string()).collect()
+    }
+
+    #[test]
+    fn public_endpoints_are_accepted() {
+        assert!(s3("https://s3.eu-west-1.amazonaws.com").validate(false, &[]).is_ok());
+        assert!(s3("https://8.8.8.8").validate(false, &[]).is_ok());
+        assert!(s3("https://[2606:4700::1111]").validate(false, &[]).is_ok());
+    }
+
+    #[test]
+    fn internal_endpoints_are_refused() {
+        for endpoint in [
+            "https://127.0.0.1",
+            "https://10.0.0.5:9000",
+            "https://169.254.169.254",
+            "https://192.168.1.10",
+            "https://100.64.0.1",
+            "https://0.0.0.0",
+            "https://[::1]",
+            "https://[fd00::1]",
+            "https://[fe80::1]",
+            "https://[::ffff:127.0.0.1]",
+            "https://localhost:9000",
+            "https://minio.localhost",
+        ] {
+            assert!(s3(endpoint).validate(false, &[]).is_err(), "{} was accepted", endpoint);
+        }
+    }
+
+    #[test]
+    fn allowed_hosts_restrict_endpoints_and_may_be_private() {
+        let allowed = hosts(&["localhost", "minio.internal"]);
+
+        assert!(s3("http://localhost:9000").validate(true, &allowed).is_ok());
+        assert!(s3("https://MINIO.internal").validate(false, &allowed).is_ok());
+        assert!(s3("https://s3.eu-west-1.amazonaws.com").validate(false, &allowed).is_err());
+    }
+
+    #[test]
+    fn plain_http_needs_to_be_allowed() {
+        assert!(s3("http://s3.example.com").validate(false, &[]).is_err());
+        assert!(s3("http://s3.example.com").validate(true, &[]).is_ok());
+        assert!(s3("ftp://s3.example.com").validate(true, &[]).is_err());
+    }
+
+    #[test]
+    fn public_ip_ranges() {
+        let public = |ip: &str| is_public_ip(ip.parse().unwrap());
+
+        assert!(public("8.8.8.8"));
+        assert!(public("100.128.0.1"));
+        assert!(public("2a00:1450::1"));
+        assert!(!public("172.16.0.1"));
+        assert!(!public("198.18.0.1"));
+        assert!(!public("203.0.113.7"));
+        assert!(!public("224.0.0.1"));
+        assert!(!public("255.255.255.255"));
+        assert!(!public("2001:db8::1"));
+        assert!(!public("ff02::1"));
+    }
+
+    #[test]
+    fn local_paths_must_stay_relative() {
+        let local = |path: &str| ExportDestination::Local { path: path.to_string() }.validate(false, &[]);
+
+        assert!(local("finance/daily").is_ok());
+        assert!(local("../other-user").is_err());
+        assert!(local("/etc").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+use crate::models::money::{round_to_scale, validate_currency, Money, BASE_CURRENCY};
+
+/// USD value of one unit of `currency` from `rate_date` until the next rate.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FxRate {
+    pub currency: String,
+    pub rate_date: NaiveDate,
+    pub usd_rate: Money,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A rate to add, or to replace the rate of the same currency and date.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct FxRateInput {
+    #[validate(custom(function = "validate_currency"))]
+    pub currency: String,
+
+    pub rate_date: NaiveDate,
+
+    #[validate(custom(function = "validate_positive_rate"))]
+    pub usd_rate: Money,
+}
+
+fn validate_positive_rate(rate: &Money) -> Result<(), ValidationError> {
+    if *rate <= Money::ZERO {
+        return Err(ValidationError::new("invalid_rate")
+            .with_message("usd_rate must be positive".into()));
+    }
+
+    Ok(())
+}
+
+/// Exchange rates held in memory, looked up like `fx_usd_rate` does: the
+/// latest rate on or before a date.
+#[derive(Debug, Clone, Default)]
+pub struct FxTable {
+    /// Rates of each currency, oldest first.
+    usd_rates: HashMap<String, Vec<(NaiveDate, Money)>>,
+}
+
+impl FxTable {
+    pub fn new(rates: Vec<FxRate>) -> Self {
+        let mut usd_rates: HashMap<String, Vec<(NaiveDate, Money)>> = HashMap::new();
+        for rate in rates {
+            usd_rates
+                .entry(rate.currency)
+                .or_default()
+                .push((rate.rate_date, rate.usd_rate));
+        }
+        for rates in usd_rates.values_mut() {
+            rates.sort_by_key(|(date, _)| *date);
+        }
+
+        Self { usd_rates }
+    }
+
+    /// USD value of one unit of `currency` on a date.
+    pub fn usd_rate(&self, currency: &str, on: NaiveDate) -> Option<Money> {
+        if currency == BASE_CURRENCY {
+            return Some(Money::ONE);
+        }
+
+        let rates = self.usd_rates.get(currency)?;
+        let index = rates.partition_point(|(date, _)| *date <= on).checked_sub(1)?;
+        Some(rates[index].1)
+    }
+
+    /// Converts an amount between currencies with the rates in effect on a
+    /// date, as `convert_money` does; `None` when a rate is missing.
+    pub fn convert(&self, amount: Money, from: &str, to: &str, on: NaiveDate) -> Option<Money> {
+        if from == to {
+            return Some(amount);
+        }
+
+        let from_rate = self.usd_rate(from, on)?;
+        let to_rate = self.usd_rate(to, on)?;
+        Some(round_to_scale(amount * from_rate / to_rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn money(value: &str) -> Money {
+        Money::from_str(value).unwrap()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 1, day).unwrap()
+    }
+
+    fn rate(currency: &str, day: u32, usd_rate: &str) -> FxRate {
+        FxRate {
+            currency: currency.to_string(),
+            rate_date: date(day),
+            usd_rate: money(usd_rate),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn table() -> FxTable {
+        FxTable::new(vec![
+            rate("EUR", 10, "1.10"),
+            rate("EUR", 1, "1.08"),
+            rate("GBP", 1, "1.25"),
+        ])
+    }
+
+    #[test]
+    fn latest_rate_on_or_before_the_date_applies() {
+        let fx = table();
+
+        assert_eq!(fx.usd_rate("EUR", date(1)), Some(money("1.08")));
+        assert_eq!(fx.usd_rate("EUR", date(9)), Some(money("1.08")));
+        assert_eq!(fx.usd_rate("EUR", date(10)), Some(money("1.10")));
+        assert_eq!(fx.usd_rate("USD", date(1)), Some(Money::ONE));
+    }
+
+    #[test]
+    fn no_rate_before_the_first_one() {
+        let fx = FxTable::new(vec![rate("EUR", 10, "1.10")]);
+
+        assert_eq!(fx.usd_rate("EUR", date(9)), None);
+        assert_eq!(fx.usd_rate("JPY", date(10)), None);
+    }
+
+    #[test]
+    fn converts_through_usd() {
+        let fx = table();
+
+        assert_eq!(fx.convert(money("10"), "EUR", "USD", date(1)), Some(money("10.8")));
+        assert_eq!(fx.convert(money("1.25"), "USD", "GBP", date(1)), Some(money("1")));
+        assert_eq!(fx.convert(money("10"), "GBP", "EUR", date(10)), Some(money("11.3636363636")));
+        assert_eq!(fx.convert(money("10"), "JPY", "JPY", date(1)), Some(money("10")));
+        assert_eq!(fx.convert(money("10"), "JPY", "USD", date(1)), None);
+    }
+}
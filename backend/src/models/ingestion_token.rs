@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Prefix that distinguishes ingestion tokens from user JWTs.
+pub const INGESTION_TOKEN_PREFIX: &str = "uai_";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct IngestionToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub token_prefix: String,
+    /// When set, the token may only record usage for these API keys.
+    pub api_key_ids: Option<Vec<Uuid>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl IngestionToken {
+    /// SHA-256 hex digest under which a raw token is stored.
+    pub fn hash(raw_token: &str) -> String {
+        format!("{:x}", Sha256::digest(raw_token.as_bytes()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateIngestionTokenRequest {
+    pub name: String,
+    pub api_key_ids: Option<Vec<Uuid>>,
+}
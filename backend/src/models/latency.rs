@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::tag::{deserialize_tag_filter, Tags};
+
+/// Upper bounds, in milliseconds, of the histogram buckets when the request
+/// does not set `buckets`.
+pub const DEFAULT_LATENCY_BUCKETS: [i32; 8] = [100, 250, 500, 1000, 2500, 5000, 10000, 30000];
+
+/// Most bucket bounds a histogram may have.
+pub const MAX_LATENCY_BUCKETS: usize = 50;
+
+/// Distribution of `response_time_ms` over calls that reported one.
+/// Percentiles are interpolated; all are `None` without any such call.
+#[derive(Debug, Default, Serialize, sqlx::FromRow)]
+pub struct LatencyStats {
+    pub p50_ms: Option<f64>,
+    pub p90_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    pub max_ms: Option<i32>,
+}
+
+/// A dimension a latency histogram can be broken down by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyDimension {
+    ModelName,
+    Endpoint,
+}
+
+impl LatencyDimension {
+    /// Parses a comma-separated list of `model_name` and `endpoint`.
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        let mut dimensions = Vec::new();
+
+        for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let dimension = match name {
+                "model_name" => Self::ModelName,
+                "endpoint" => Self::Endpoint,
+                _ => {
+                    return Err(format!(
+                        "Unknown group_by dimension '{}'; expected model_name or endpoint",
+                        name
+                    ))
+                }
+            };
+            if !dimensions.contains(&dimension) {
+                dimensions.push(dimension);
+            }
+        }
+
+        Ok(dimensions)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ModelName => "model_name",
+            Self::Endpoint => "endpoint",
+        }
+    }
+}
+
+/// Parses comma-separated bucket bounds in milliseconds, which must be
+/// positive and strictly increasing.
+pub fn parse_bucket_bounds(value: &str) -> Result<Vec<i32>, String> {
+    let bounds = value
+        .split(',')
+        .map(str::trim)
+        .filter(|bound| !bound.is_empty())
+        .map(|bound| {
+            bound
+                .parse::<i32>()
+                .ok()
+                .filter(|bound| *bound > 0)
+                .ok_or_else(|| format!("bucket bound '{}' must be a positive number of milliseconds", bound))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if bounds.is_empty() || bounds.len() > MAX_LATENCY_BUCKETS {
+        return Err(format!("buckets takes 1 to {} bounds", MAX_LATENCY_BUCKETS));
+    }
+    if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("bucket bounds must be strictly increasing".to_string());
+    }
+
+    Ok(bounds)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct LatencyHistogramQuery {
+    /// Comma-separated bucket bounds in milliseconds, e.g. `100,500,1000`.
+    pub buckets: Option<String>,
+
+    /// Breaks the histogram down by `model_name`, `endpoint` or both.
+    #[serde(default)]
+    pub group_by: String,
+
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub api_key_id: Option<Uuid>,
+
+    #[validate(length(min = 1, max = 100))]
+    pub model_name: Option<String>,
+
+    pub endpoint: Option<String>,
+
+    /// Only usage carrying all of these tags, written `key:value,key:value`.
+    #[serde(default, deserialize_with = "deserialize_tag_filter")]
+    pub tags: Option<Tags>,
+}
+
+/// Calls whose response time is at least `from_ms` and below `to_ms`; the
+/// last bucket is open-ended.
+#[derive(Debug, Serialize)]
+pub struct LatencyBucket {
+    pub from_ms: i32,
+    pub to_ms: Option<i32>,
+    pub count: i64,
+}
+
+/// Histogram of one combination of the grouped dimensions.
+#[derive(Debug, Serialize)]
+pub struct LatencyHistogramGroup {
+    /// Value of each grouped dimension, keyed by its name.
+    pub group: serde_json::Map<String, serde_json::Value>,
+    pub requests: i64,
+    pub buckets: Vec<LatencyBucket>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencyHistogram {
+    pub bounds: Vec<i32>,
+    pub group_by: Vec<String>,
+    pub groups: Vec<LatencyHistogramGroup>,
+}
+
+impl LatencyHistogramGroup {
+    /// An empty histogram over the buckets delimited by `bounds`.
+    pub fn new(group: serde_json::Map<String, serde_json::Value>, bounds: &[i32]) -> Self {
+        let buckets = (0..=bounds.len())
+            .map(|index| LatencyBucket {
+                from_ms: if index == 0 { 0 } else { bounds[index - 1] },
+                to_ms: bounds.get(index).copied(),
+                count: 0,
+            })
+            .collect();
+
+        Self { group, requests: 0, buckets }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_run_from_zero_to_each_bound_and_past_the_last() {
+        let group = LatencyHistogramGroup::new(serde_json::Map::new(), &[100, 500]);
+
+        let edges: Vec<(i32, Option<i32>)> = group.buckets.iter().map(|b| (b.from_ms, b.to_ms)).collect();
+        assert_eq!(edges, vec![(0, Some(100)), (100, Some(500)), (500, None)]);
+        assert!(group.buckets.iter().all(|b| b.count == 0));
+        assert_eq!(group.requests, 0);
+    }
+
+    #[test]
+    fn bucket_bounds_must_be_positive_and_strictly_increasing() {
+        assert_eq!(parse_bucket_bounds(" 100, 500 ,1000,"), Ok(vec![100, 500, 1000]));
+        assert_eq!(parse_bucket_bounds("7"), Ok(vec![7]));
+
+        assert_eq!(
+            parse_bucket_bounds("100,0"),
+            Err("bucket bound '0' must be a positive number of milliseconds".to_string())
+        );
+        assert!(parse_bucket_bounds("100,fast").is_err());
+        assert_eq!(
+            parse_bucket_bounds("100,100"),
+            Err("bucket bounds must be strictly increasing".to_string())
+        );
+        assert!(parse_bucket_bounds("500,100").is_err());
+        assert_eq!(parse_bucket_bounds(" , "), Err("buckets takes 1 to 50 bounds".to_string()));
+        let too_many: Vec<String> = (1..=51).map(|bound| bound.to_string()).collect();
+        assert!(parse_bucket_bounds(&too_many.join(",")).is_err());
+    }
+}
@@ -3,4 +3,15 @@ pub mod api_key;
 pub mod user;
 pub mod prediction;
 pub mod alert;
-pub mod budget;
\ No newline at end of file
+pub mod budget;
+pub mod ingestion_token;
+pub mod model_price;
+pub mod money;
+pub mod fx_rate;
+pub mod pricing_rule;
+pub mod usage_aggregate;
+pub mod tag;
+pub mod calendar;
+pub mod latency;
+pub mod usage_export;
+pub mod export_schedule;
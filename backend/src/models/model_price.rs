@@ -0,0 +1,539 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::models::api_key::ApiKey;
+use crate::models::api_usage::{BillingUnit, TokenPricing};
+use crate::models::fx_rate::FxTable;
+use crate::models::money::{
+    base_currency, validate_currency, validate_non_negative, Money, BASE_CURRENCY,
+};
+
+/// A catalog price for the models of one provider matching `model_pattern`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ModelPrice {
+    pub id: Uuid,
+    pub provider: String,
+    /// An exact model name, or a prefix followed by `*`.
+    pub model_pattern: String,
+    pub cost_per_1k_input: Money,
+    pub cost_per_1k_output: Money,
+    pub cost_per_1k_cached_input: Option<Money>,
+    pub cost_per_1k_cache_write: Option<Money>,
+    pub cost_per_1k_reasoning: Option<Money>,
+    /// Per-unit prices keyed by `unit` or `unit:variant`.
+    pub unit_prices: Json<HashMap<String, Money>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// First instant this version applies to.
+    pub effective_from: DateTime<Utc>,
+    /// End of this version, exclusive; open-ended when `None`.
+    pub effective_to: Option<DateTime<Utc>>,
+    pub currency: String,
+    /// Where the price came from; see [`PriceSource`].
+    pub source: String,
+}
+
+/// Origin of a catalog price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    Api,
+    Default,
+    File,
+}
+
+impl PriceSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriceSource::Api => "api",
+            PriceSource::Default => "default",
+            PriceSource::File => "file",
+        }
+    }
+}
+
+impl ModelPrice {
+    pub fn is_effective_at(&self, at: DateTime<Utc>) -> bool {
+        self.effective_from <= at && self.effective_to.is_none_or(|to| at < to)
+    }
+
+    /// How specifically the pattern matches `model`: `None` if it does not
+    /// match, otherwise longer is more specific and an exact match beats any
+    /// prefix.
+    pub fn match_rank(&self, model: &str) -> Option<usize> {
+        pattern_rank(&self.model_pattern, model)
+    }
+
+    /// This price with all amounts converted into `currency` with the rates
+    /// in effect on `on`; `None` when a rate is missing.
+    pub fn converted(&self, currency: &str, fx: &FxTable, on: NaiveDate) -> Option<ModelPrice> {
+        let convert = |amount: Money| fx.convert(amount, &self.currency, currency, on);
+        let convert_opt = |amount: Option<Money>| match amount {
+            Some(amount) => convert(amount).map(Some),
+            None => Some(None),
+        };
+
+        let mut unit_prices = HashMap::with_capacity(self.unit_prices.len());
+        for (key, price) in self.unit_prices.iter() {
+            unit_prices.insert(key.clone(), convert(*price)?);
+        }
+
+        Some(ModelPrice {
+            cost_per_1k_input: convert(self.cost_per_1k_input)?,
+            cost_per_1k_output: convert(self.cost_per_1k_output)?,
+            cost_per_1k_cached_input: convert_opt(self.cost_per_1k_cached_input)?,
+            cost_per_1k_cache_write: convert_opt(self.cost_per_1k_cache_write)?,
+            cost_per_1k_reasoning: convert_opt(self.cost_per_1k_reasoning)?,
+            unit_prices: Json(unit_prices),
+            currency: currency.to_string(),
+            ..self.clone()
+        })
+    }
+}
+
+/// How specifically a model pattern (an exact name, or a prefix followed by
+/// `*`) matches `model`, compared case-insensitively.
+pub fn pattern_rank(pattern: &str, model: &str) -> Option<usize> {
+    let pattern = pattern.to_ascii_lowercase();
+    let model = model.to_ascii_lowercase();
+
+    match pattern.strip_suffix('*') {
+        Some(prefix) if model.starts_with(prefix) => Some(prefix.len()),
+        None if model == pattern => Some(usize::MAX),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateModelPriceRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub provider: String,
+
+    #[validate(length(min = 1, max = 100))]
+    pub model_pattern: String,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_input: Money,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_output: Money,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_cached_input: Option<Money>,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_cache_write: Option<Money>,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_reasoning: Option<Money>,
+
+    #[serde(default)]
+    #[validate(custom(function = "validate_unit_prices"))]
+    pub unit_prices: HashMap<String, Money>,
+
+    /// When this price starts to apply. Defaults to the beginning of time;
+    /// an open-ended earlier version of the same pattern is closed here.
+    pub effective_from: Option<DateTime<Utc>>,
+
+    /// When this price stops applying, exclusive.
+    pub effective_to: Option<DateTime<Utc>>,
+
+    #[serde(default = "base_currency")]
+    #[validate(custom(function = "validate_currency"))]
+    pub currency: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateModelPriceRequest {
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_input: Option<Money>,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_output: Option<Money>,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_cached_input: Option<Money>,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_cache_write: Option<Money>,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub cost_per_1k_reasoning: Option<Money>,
+
+    /// Replaces the per-unit prices.
+    #[validate(custom(function = "validate_unit_prices"))]
+    pub unit_prices: Option<HashMap<String, Money>>,
+
+    #[validate(custom(function = "validate_currency"))]
+    pub currency: Option<String>,
+}
+
+impl CreateModelPriceRequest {
+    /// Fields of `price` that differ from this request. `effective_to` is only
+    /// compared when set, since adding a later open-ended version closes the
+    /// earlier one.
+    pub fn changed_fields(&self, price: &ModelPrice) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.cost_per_1k_input != price.cost_per_1k_input {
+            changed.push("cost_per_1k_input");
+        }
+        if self.cost_per_1k_output != price.cost_per_1k_output {
+            changed.push("cost_per_1k_output");
+        }
+        if self.cost_per_1k_cached_input != price.cost_per_1k_cached_input {
+            changed.push("cost_per_1k_cached_input");
+        }
+        if self.cost_per_1k_cache_write != price.cost_per_1k_cache_write {
+            changed.push("cost_per_1k_cache_write");
+        }
+        if self.cost_per_1k_reasoning != price.cost_per_1k_reasoning {
+            changed.push("cost_per_1k_reasoning");
+        }
+        if self.unit_prices != price.unit_prices.0 {
+            changed.push("unit_prices");
+        }
+        if self.effective_to.is_some() && self.effective_to != price.effective_to {
+            changed.push("effective_to");
+        }
+        if self.currency != price.currency {
+            changed.push("currency");
+        }
+
+        changed
+    }
+}
+
+/// Filters for a retroactive cost recalculation.
+#[derive(Debug, Deserialize, Validate)]
+pub struct CostRecalculationRequest {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub api_key_id: Option<Uuid>,
+
+    #[validate(length(min = 1, max = 100))]
+    pub model_name: Option<String>,
+
+    #[validate(length(max = 1000))]
+    pub reason: Option<String>,
+
+    /// Computes the totals without changing any costs or writing an audit record.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Audit record of a cost recalculation. Its counters are updated as each
+/// page of rows is re-priced.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct CostRecalculation {
+    pub id: Uuid,
+    pub requested_by: Option<Uuid>,
+    pub range_start: DateTime<Utc>,
+    pub range_end: DateTime<Utc>,
+    pub api_key_id: Option<Uuid>,
+    pub model_name: Option<String>,
+    pub reason: Option<String>,
+    pub rows_scanned: i64,
+    pub rows_changed: i64,
+    /// Rows left at their old cost because no price applied to them.
+    pub rows_unpriced: i64,
+    /// Totals across currencies, converted into the base currency.
+    pub old_total_cost: Money,
+    pub new_total_cost: Money,
+    pub created_at: DateTime<Utc>,
+    /// `None` while the recalculation runs, or if it was interrupted.
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// A catalog price changed by the pricing file.
+#[derive(Debug, Serialize)]
+pub struct PriceUpdate {
+    pub previous: ModelPrice,
+    pub current: ModelPrice,
+    pub changed_fields: Vec<&'static str>,
+}
+
+/// What applying the pricing file changed in the catalog.
+#[derive(Debug, Default, Serialize)]
+pub struct PriceFileReport {
+    pub file: String,
+    pub added: Vec<ModelPrice>,
+    pub updated: Vec<PriceUpdate>,
+    pub unchanged: usize,
+    /// Prices loaded from the file earlier that it no longer lists. They are
+    /// kept, since usage may have been priced with them.
+    pub not_in_file: Vec<ModelPrice>,
+    /// Entries not applied because their period overlaps another version.
+    pub conflicts: Vec<String>,
+}
+
+/// Checks that unit price keys name a non-token unit and prices are non-negative.
+pub fn validate_unit_prices(prices: &HashMap<String, Money>) -> Result<(), ValidationError> {
+    for (key, price) in prices {
+        let unit = key.split(':').next().unwrap_or_default();
+        let unit: BillingUnit = serde_json::from_value(serde_json::Value::String(unit.to_string()))
+            .map_err(|_| {
+                ValidationError::new("unknown_billing_unit")
+                    .with_message(format!("Unknown billing unit in '{}'", key).into())
+            })?;
+
+        if unit == BillingUnit::Tokens {
+            return Err(ValidationError::new("token_unit_price").with_message(
+                "Tokens are priced per 1k tokens, not through unit_prices".into(),
+            ));
+        }
+
+        if *price < Money::ZERO {
+            return Err(ValidationError::new("invalid_unit_price")
+                .with_message(format!("Invalid price for '{}'", key).into()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Prices that apply to one call: the catalog entry for its model with the
+/// API key's overrides on top.
+#[derive(Debug, Clone)]
+pub struct Pricing {
+    /// `None` when neither the catalog nor the key prices tokens for the model.
+    pub tokens: Option<TokenPricing>,
+    pub unit_prices: HashMap<String, Money>,
+    /// Currency of all the prices above.
+    pub currency: String,
+}
+
+impl Pricing {
+    /// Merges the key's overrides field by field over the catalog entry. When
+    /// the key has overrides in another currency, the catalog entry is
+    /// converted into it with the rates in effect on `on`, or left out if
+    /// there is no rate, so prices never mix currencies.
+    pub fn resolve(
+        catalog_price: Option<&ModelPrice>,
+        api_key: &ApiKey,
+        fx: &FxTable,
+        on: NaiveDate,
+    ) -> Self {
+        let overrides = api_key.has_price_overrides();
+        let catalog_price = match catalog_price {
+            Some(price) if overrides && price.currency != api_key.currency => {
+                price.converted(&api_key.currency, fx, on).map(Cow::Owned)
+            }
+            price => price.map(Cow::Borrowed),
+        };
+        let catalog_price = catalog_price.as_deref();
+        let currency = if overrides {
+            api_key.currency.clone()
+        } else {
+            catalog_price.map_or(BASE_CURRENCY, |p| p.currency.as_str()).to_string()
+        };
+
+        let input = api_key
+            .cost_per_1k_input
+            .or(catalog_price.map(|p| p.cost_per_1k_input));
+        let output = api_key
+            .cost_per_1k_output
+            .or(catalog_price.map(|p| p.cost_per_1k_output));
+
+        let tokens = input.zip(output).map(|(input, output)| TokenPricing {
+            input,
+            output,
+            cached_input: api_key
+                .cost_per_1k_cached_input
+                .or(catalog_price.and_then(|p| p.cost_per_1k_cached_input)),
+            cache_write: api_key
+                .cost_per_1k_cache_write
+                .or(catalog_price.and_then(|p| p.cost_per_1k_cache_write)),
+            reasoning: api_key
+                .cost_per_1k_reasoning
+                .or(catalog_price.and_then(|p| p.cost_per_1k_reasoning)),
+        });
+
+        let mut unit_prices = catalog_price
+            .map(|p| p.unit_prices.0.clone())
+            .unwrap_or_default();
+        unit_prices.extend(api_key.unit_prices.iter().map(|(k, v)| (k.clone(), *v)));
+
+        Self { tokens, unit_prices, currency }
+    }
+
+    /// Price of one unit, preferring a variant-specific price over the plain
+    /// unit price.
+    pub fn unit_price(&self, unit: BillingUnit, variant: Option<&str>) -> Option<Money> {
+        variant
+            .and_then(|v| self.unit_prices.get(&format!("{}:{}", unit.as_str(), v)))
+            .or_else(|| self.unit_prices.get(unit.as_str()))
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::fx_rate::FxRate;
+    use std::str::FromStr;
+
+    fn money(value: &str) -> Money {
+        Money::from_str(value).unwrap()
+    }
+
+    fn day() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 1, 1).unwrap()
+    }
+
+    fn catalog_price() -> ModelPrice {
+        ModelPrice {
+            id: Uuid::new_v4(),
+            provider: "openai".to_string(),
+            model_pattern: "gpt-4o".to_string(),
+            cost_per_1k_input: money("0.0025"),
+            cost_per_1k_output: money("0.01"),
+            cost_per_1k_cached_input: Some(money("0.00125")),
+            cost_per_1k_cache_write: None,
+            cost_per_1k_reasoning: None,
+            unit_prices: Json([("images".to_string(), money("0.04"))].into()),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            effective_from: DateTime::<Utc>::MIN_UTC,
+            effective_to: None,
+            currency: "USD".to_string(),
+            source: PriceSource::Default.as_str().to_string(),
+        }
+    }
+
+    fn api_key(currency: &str) -> ApiKey {
+        ApiKey {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            name: "test".to_string(),
+            provider: "openai".to_string(),
+            encrypted_key: String::new(),
+            cost_per_1k_input: None,
+            cost_per_1k_output: None,
+            is_active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            cost_per_1k_cached_input: None,
+            cost_per_1k_cache_write: None,
+            cost_per_1k_reasoning: None,
+            unit_prices: Json(HashMap::new()),
+            currency: currency.to_string(),
+        }
+    }
+
+    fn eur_at_1_25() -> FxTable {
+        FxTable::new(vec![FxRate {
+            currency: "EUR".to_string(),
+            rate_date: day(),
+            usd_rate: money("1.25"),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }])
+    }
+
+    #[test]
+    fn catalog_price_applies_without_overrides() {
+        let pricing = Pricing::resolve(Some(&catalog_price()), &api_key("EUR"), &eur_at_1_25(), day());
+        let tokens = pricing.tokens.unwrap();
+
+        assert_eq!(pricing.currency, "USD");
+        assert_eq!(tokens.input, money("0.0025"));
+        assert_eq!(tokens.cached_input, Some(money("0.00125")));
+    }
+
+    #[test]
+    fn overrides_are_merged_per_field() {
+        let mut key = api_key("USD");
+        key.cost_per_1k_output = Some(money("0.02"));
+
+        let pricing = Pricing::resolve(Some(&catalog_price()), &key, &FxTable::default(), day());
+        let tokens = pricing.tokens.unwrap();
+
+        assert_eq!(pricing.currency, "USD");
+        assert_eq!(tokens.input, money("0.0025"));
+        assert_eq!(tokens.output, money("0.02"));
+        assert_eq!(tokens.cached_input, Some(money("0.00125")));
+        assert_eq!(pricing.unit_prices.get("images"), Some(&money("0.04")));
+    }
+
+    #[test]
+    fn catalog_is_converted_into_the_currency_of_the_overrides() {
+        let mut key = api_key("EUR");
+        key.cost_per_1k_output = Some(money("0.008"));
+        key.unit_prices = Json([("audio_seconds".to_string(), money("0.0001"))].into());
+
+        let pricing = Pricing::resolve(Some(&catalog_price()), &key, &eur_at_1_25(), day());
+        let tokens = pricing.tokens.unwrap();
+
+        assert_eq!(pricing.currency, "EUR");
+        assert_eq!(tokens.input, money("0.002"));
+        assert_eq!(tokens.output, money("0.008"));
+        assert_eq!(tokens.cached_input, Some(money("0.001")));
+        assert_eq!(pricing.unit_prices.get("images"), Some(&money("0.032")));
+        assert_eq!(pricing.unit_prices.get("audio_seconds"), Some(&money("0.0001")));
+    }
+
+    #[test]
+    fn catalog_is_left_out_without_an_exchange_rate() {
+        let mut key = api_key("EUR");
+        key.cost_per_1k_output = Some(money("0.008"));
+
+        let pricing = Pricing::resolve(Some(&catalog_price()), &key, &FxTable::default(), day());
+
+        assert_eq!(pricing.currency, "EUR");
+        assert!(pricing.tokens.is_none());
+        assert!(pricing.unit_prices.is_empty());
+    }
+
+    #[test]
+    fn create_request_ignores_unknown_fields() {
+        let req: CreateModelPriceRequest = serde_json::from_value(serde_json::json!({
+            "provider": "openai",
+            "model_pattern": "gpt-4o",
+            "cost_per_1k_input": "0.0025",
+            "cost_per_1k_output": "0.01",
+            "notes": "list price",
+        }))
+        .unwrap();
+
+        assert_eq!(req.currency, "USD");
+    }
+
+    #[test]
+    fn exact_pattern_matches_only_that_model() {
+        assert_eq!(pattern_rank("gpt-4o", "gpt-4o"), Some(usize::MAX));
+        assert_eq!(pattern_rank("gpt-4o", "gpt-4o-mini"), None);
+        assert_eq!(pattern_rank("gpt-4o-mini", "gpt-4o"), None);
+    }
+
+    #[test]
+    fn prefix_pattern_ranks_by_prefix_length() {
+        assert_eq!(pattern_rank("gpt-4o*", "gpt-4o-2024-08-06"), Some(6));
+        assert_eq!(pattern_rank("gpt-4o-mini*", "gpt-4o-mini-2024-07-18"), Some(11));
+        assert_eq!(pattern_rank("gpt-4o*", "gpt-4o"), Some(6));
+        assert_eq!(pattern_rank("gpt-4o*", "gpt-4-turbo"), None);
+    }
+
+    #[test]
+    fn exact_match_beats_any_prefix() {
+        let model = "claude-3-5-sonnet-20241022";
+
+        assert!(pattern_rank(model, model) > pattern_rank("claude-3-5-sonnet-2024*", model));
+    }
+
+    #[test]
+    fn bare_wildcard_matches_everything_least_specifically() {
+        assert_eq!(pattern_rank("*", "anything"), Some(0));
+    }
+
+    #[test]
+    fn patterns_match_case_insensitively() {
+        assert_eq!(pattern_rank("GPT-4o", "gpt-4O"), Some(usize::MAX));
+        assert_eq!(pattern_rank("Claude-3*", "claude-3-opus"), Some(8));
+    }
+}
@@ -0,0 +1,50 @@
+use rust_decimal::RoundingStrategy;
+use validator::ValidationError;
+
+/// Exact decimal amount used for all costs and prices. Amounts keep sub-cent
+/// precision in storage and aggregation and are only rounded for display.
+pub type Money = rust_decimal::Decimal;
+
+/// Decimal places stored for costs and prices (`NUMERIC(20,10)`).
+pub const MONEY_SCALE: u32 = 10;
+
+/// Stored amounts must stay below this, the smallest value `NUMERIC(20,10)`
+/// cannot hold.
+pub const AMOUNT_LIMIT: i64 = 10_000_000_000;
+
+/// Currency of prices and reports unless configured otherwise; exchange
+/// rates are stored against it.
+pub const BASE_CURRENCY: &str = "USD";
+
+/// Rounds a computed amount to the stored scale.
+pub fn round_to_scale(amount: Money) -> Money {
+    amount.round_dp_with_strategy(MONEY_SCALE, RoundingStrategy::MidpointNearestEven)
+}
+
+/// Rounds an amount to cents for display.
+pub fn round_for_display(amount: Money) -> Money {
+    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+pub fn validate_non_negative(amount: &Money) -> Result<(), ValidationError> {
+    if *amount < Money::ZERO {
+        return Err(ValidationError::new("negative_amount")
+            .with_message("amount cannot be negative".into()));
+    }
+
+    Ok(())
+}
+
+pub fn base_currency() -> String {
+    BASE_CURRENCY.to_string()
+}
+
+/// Checks for an ISO 4217 style code: three uppercase ASCII letters.
+pub fn validate_currency(currency: &str) -> Result<(), ValidationError> {
+    if currency.len() != 3 || !currency.bytes().all(|b| b.is_ascii_uppercase()) {
+        return Err(ValidationError::new("invalid_currency")
+            .with_message("currency must be a three-letter code such as USD".into()));
+    }
+
+    Ok(())
+}
@@ -1,18 +1,37 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{types::Json, FromRow};
 use uuid::Uuid;
 
+use crate::models::money::Money;
+use crate::models::tag::{deserialize_tag_filter, Tags};
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Prediction {
     pub id: Uuid,
     pub user_id: Uuid,
     pub api_key_id: Option<Uuid>,
     pub prediction_date: DateTime<Utc>,
-    pub predicted_daily_cost: f64,
-    pub predicted_weekly_cost: f64,
-    pub predicted_monthly_cost: f64,
+    pub predicted_daily_cost: Money,
+    pub predicted_weekly_cost: Money,
+    pub predicted_monthly_cost: Money,
     pub confidence_score: f64,
     pub model_used: String,
     pub created_at: DateTime<Utc>,
+    pub is_stale: bool,
+    /// Currency of the predicted costs, the user's reporting currency.
+    pub currency: String,
+    /// Tags the predicted usage was limited to; empty for all usage.
+    pub tags: Json<Tags>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PredictionQuery {
+    /// Only usage carrying all of these tags, written `key:value,key:value`.
+    #[serde(default, deserialize_with = "deserialize_tag_filter")]
+    pub tags: Option<Tags>,
+
+    /// IANA timezone the daily costs are counted in; defaults to the user's
+    /// preference.
+    pub timezone: Option<String>,
 }
@@ -0,0 +1,241 @@
+use chrono::{DateTime, Datelike, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::models::model_price::pattern_rank;
+use crate::models::money::{round_to_scale, validate_non_negative, Money};
+
+/// A volume tier: calls made once the month's token volume has reached
+/// `from_tokens` get `discount_percent` off the list price.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceTier {
+    pub from_tokens: i64,
+    pub discount_percent: Money,
+}
+
+/// Contract terms of an API key, applied on top of its list prices. Tiers are
+/// over the calendar month (UTC).
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PricingRule {
+    pub id: Uuid,
+    pub api_key_id: Uuid,
+    pub name: String,
+    /// Limits the rule to matching models; applies to all models when `None`.
+    pub model_pattern: Option<String>,
+    pub discount_percent: Money,
+    /// Extra discount for calls made through the provider's batch API.
+    pub batch_discount_percent: Money,
+    pub tiers: Json<Vec<PriceTier>>,
+    /// Monthly minimum spend, in the API key's currency.
+    pub minimum_commitment: Option<Money>,
+    pub effective_from: DateTime<Utc>,
+    pub effective_to: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PricingRule {
+    pub fn is_effective_at(&self, at: DateTime<Utc>) -> bool {
+        self.effective_from <= at && self.effective_to.is_none_or(|to| at < to)
+    }
+
+    /// How specifically the rule matches `model`; a rule without a pattern
+    /// matches every model, least specifically.
+    pub fn match_rank(&self, model: &str) -> Option<usize> {
+        match &self.model_pattern {
+            Some(pattern) => pattern_rank(pattern, model).map(|rank| rank.saturating_add(1)),
+            None => Some(0),
+        }
+    }
+
+    /// Total discount, in percent, for a call made after `volume` tokens this
+    /// month. The tier, flat and batch discounts compound.
+    pub fn discount_percent_for(&self, volume: i64, is_batch: bool) -> Money {
+        let tier = self
+            .tiers
+            .iter()
+            .filter(|tier| tier.from_tokens <= volume)
+            .max_by_key(|tier| tier.from_tokens)
+            .map_or(Money::ZERO, |tier| tier.discount_percent);
+        let batch = if is_batch { self.batch_discount_percent } else { Money::ZERO };
+
+        let remaining = [tier, self.discount_percent, batch]
+            .iter()
+            .fold(Money::ONE, |acc, percent| acc * (Money::ONE_HUNDRED - percent) / Money::ONE_HUNDRED);
+
+        (Money::ONE - remaining) * Money::ONE_HUNDRED
+    }
+}
+
+/// Start of the billing month (UTC) containing `at`; tiers and commitments
+/// run over calendar months.
+pub fn billing_month_start(at: DateTime<Utc>) -> DateTime<Utc> {
+    let date = at.date_naive();
+    date.with_day(1).unwrap_or(date).and_time(NaiveTime::MIN).and_utc()
+}
+
+/// Cost after taking `discount_percent` off `list_cost`.
+pub fn apply_discount(list_cost: Money, discount_percent: Money) -> Money {
+    round_to_scale(list_cost * (Money::ONE_HUNDRED - discount_percent) / Money::ONE_HUNDRED)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePricingRuleRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+
+    #[validate(length(min = 1, max = 100))]
+    pub model_pattern: Option<String>,
+
+    #[serde(default)]
+    #[validate(custom(function = "validate_percent"))]
+    pub discount_percent: Money,
+
+    #[serde(default)]
+    #[validate(custom(function = "validate_percent"))]
+    pub batch_discount_percent: Money,
+
+    #[serde(default)]
+    #[validate(custom(function = "validate_tiers"))]
+    pub tiers: Vec<PriceTier>,
+
+    #[validate(custom(function = "validate_non_negative"))]
+    pub minimum_commitment: Option<Money>,
+
+    /// Defaults to the beginning of time.
+    pub effective_from: Option<DateTime<Utc>>,
+
+    pub effective_to: Option<DateTime<Utc>>,
+}
+
+/// Month-end comparison of list-price and contracted cost for one API key,
+/// in the key's currency.
+#[derive(Debug, Serialize, FromRow)]
+pub struct TrueUpLine {
+    pub api_key_id: Uuid,
+    pub api_key_name: String,
+    pub currency: String,
+    pub list_cost: Money,
+    pub contracted_cost: Money,
+    /// Sum of the minimum commitments of the key's rules in effect during the month.
+    pub minimum_commitment: Money,
+    #[sqlx(skip)]
+    pub savings: Money,
+    /// Commitment not covered by contracted cost.
+    #[sqlx(skip)]
+    pub shortfall: Money,
+    /// Contracted cost plus shortfall.
+    #[sqlx(skip)]
+    pub amount_due: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrueUpReport {
+    /// The billing month, as `YYYY-MM`.
+    pub month: String,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub api_keys: Vec<TrueUpLine>,
+}
+
+fn validate_percent(percent: &Money) -> Result<(), ValidationError> {
+    if *percent < Money::ZERO || *percent > Money::ONE_HUNDRED {
+        return Err(ValidationError::new("invalid_percent")
+            .with_message("percentages must be between 0 and 100".into()));
+    }
+
+    Ok(())
+}
+
+fn validate_tiers(tiers: &[PriceTier]) -> Result<(), ValidationError> {
+    for tier in tiers {
+        if tier.from_tokens < 0 {
+            return Err(ValidationError::new("invalid_tier")
+                .with_message("from_tokens cannot be negative".into()));
+        }
+        validate_percent(&tier.discount_percent)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::str::FromStr;
+
+    fn money(value: &str) -> Money {
+        Money::from_str(value).unwrap()
+    }
+
+    fn tier(from_tokens: i64, discount_percent: &str) -> PriceTier {
+        PriceTier { from_tokens, discount_percent: money(discount_percent) }
+    }
+
+    fn rule(discount_percent: &str, batch_discount_percent: &str, tiers: Vec<PriceTier>) -> PricingRule {
+        PricingRule {
+            id: Uuid::new_v4(),
+            api_key_id: Uuid::new_v4(),
+            name: "contract".to_string(),
+            model_pattern: None,
+            discount_percent: money(discount_percent),
+            batch_discount_percent: money(batch_discount_percent),
+            tiers: Json(tiers),
+            minimum_commitment: None,
+            effective_from: DateTime::UNIX_EPOCH,
+            effective_to: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, second).unwrap()
+    }
+
+    #[test]
+    fn rule_without_discounts_costs_list_price() {
+        let rule = rule("0", "0", Vec::new());
+
+        assert_eq!(rule.discount_percent_for(5_000_000, true), Money::ZERO);
+    }
+
+    #[test]
+    fn tier_applies_once_its_volume_is_reached() {
+        let rule = rule("0", "0", vec![tier(10_000_000, "20"), tier(1_000_000, "10")]);
+
+        assert_eq!(rule.discount_percent_for(0, false), Money::ZERO);
+        assert_eq!(rule.discount_percent_for(999_999, false), Money::ZERO);
+        assert_eq!(rule.discount_percent_for(1_000_000, false), money("10"));
+        assert_eq!(rule.discount_percent_for(9_999_999, false), money("10"));
+        assert_eq!(rule.discount_percent_for(10_000_000, false), money("20"));
+    }
+
+    #[test]
+    fn tier_flat_and_batch_discounts_compound() {
+        let rule = rule("5", "50", vec![tier(1_000_000, "10")]);
+
+        assert_eq!(rule.discount_percent_for(0, false), money("5"));
+        assert_eq!(rule.discount_percent_for(1_000_000, false), money("14.5"));
+        assert_eq!(rule.discount_percent_for(1_000_000, true), money("57.25"));
+    }
+
+    #[test]
+    fn full_discount_makes_calls_free() {
+        let rule = rule("100", "0", Vec::new());
+
+        assert_eq!(rule.discount_percent_for(0, false), money("100"));
+        assert_eq!(apply_discount(money("0.0123"), money("100")), Money::ZERO);
+    }
+
+    #[test]
+    fn billing_month_starts_at_midnight_utc_on_the_first() {
+        assert_eq!(billing_month_start(utc(2026, 3, 15, 13, 45, 0)), utc(2026, 3, 1, 0, 0, 0));
+        assert_eq!(billing_month_start(utc(2026, 3, 1, 0, 0, 0)), utc(2026, 3, 1, 0, 0, 0));
+        assert_eq!(billing_month_start(utc(2024, 2, 29, 23, 59, 59)), utc(2024, 2, 1, 0, 0, 0));
+        assert_eq!(billing_month_start(utc(2026, 12, 31, 23, 59, 59)), utc(2026, 12, 1, 0, 0, 0));
+    }
+}
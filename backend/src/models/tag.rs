@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use validator::{Validate, ValidationError};
+
+/// Cost attribution tags of a usage row, e.g. `team`, `feature`,
+/// `customer_id` and `environment`.
+pub type Tags = BTreeMap<String, String>;
+
+pub const MAX_TAGS: usize = 20;
+pub const MAX_TAG_KEY_LENGTH: usize = 64;
+pub const MAX_TAG_VALUE_LENGTH: usize = 255;
+
+/// How usage without a tag shows up when grouping or listing by that tag.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UntaggedTags {
+    /// Reported under this value.
+    Label(String),
+    /// Reported with a `null` value.
+    Null,
+    /// Left out.
+    Exclude,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct TagListQuery {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+
+    /// Most values listed per key.
+    #[validate(range(min = 1, max = 1000))]
+    pub values_per_key: Option<i64>,
+}
+
+/// Distinct values of one tag key, most used first.
+#[derive(Debug, Serialize)]
+pub struct TagKey {
+    pub key: String,
+    pub values: Vec<TagValue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagValue {
+    /// `None` for usage without the key, unless reported under a label.
+    pub value: Option<String>,
+    pub usage_count: i64,
+}
+
+pub fn validate_tag_key(key: &str) -> Result<(), String> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_TAG_KEY_LENGTH
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-' | '.'));
+
+    if !valid {
+        return Err(format!(
+            "tag key '{}' must be 1-{} lowercase letters, digits, '_', '-' or '.'",
+            key, MAX_TAG_KEY_LENGTH
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn validate_tags(tags: &Tags) -> Result<(), ValidationError> {
+    if tags.len() > MAX_TAGS {
+        return Err(ValidationError::new("too_many_tags")
+            .with_message(format!("at most {} tags are allowed", MAX_TAGS).into()));
+    }
+
+    for (key, value) in tags {
+        validate_tag_key(key)
+            .map_err(|e| ValidationError::new("invalid_tag_key").with_message(e.into()))?;
+        if value.is_empty() || value.len() > MAX_TAG_VALUE_LENGTH {
+            return Err(ValidationError::new("invalid_tag_value").with_message(
+                format!("tag '{}' must have a value of 1-{} bytes", key, MAX_TAG_VALUE_LENGTH).into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a tag filter written `key:value,key:value`; usage must carry all of
+/// the tags to match.
+pub fn parse_tag_filter(filter: &str) -> Result<Tags, String> {
+    let mut tags = Tags::new();
+
+    for pair in filter.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair
+            .split_once(':')
+            .ok_or_else(|| format!("tag filter '{}' must be written key:value", pair))?;
+        let key = key.trim();
+        validate_tag_key(key)?;
+        tags.insert(key.to_string(), value.trim().to_string());
+    }
+
+    Ok(tags)
+}
+
+/// Deserializes an optional `key:value,...` query parameter into a tag filter.
+/// An empty filter is `None`.
+pub fn deserialize_tag_filter<'de, D>(deserializer: D) -> Result<Option<Tags>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let filter: Option<String> = Option::deserialize(deserializer)?;
+
+    match filter {
+        Some(filter) => {
+            let tags = parse_tag_filter(&filter).map_err(serde::de::Error::custom)?;
+            Ok((!tags.is_empty()).then_some(tags))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn filter_pairs_are_parsed_and_trimmed() {
+        let filter = parse_tag_filter(" team:search , environment: prod,,customer_id:acme:eu").unwrap();
+
+        assert_eq!(
+            filter,
+            tags(&[("team", "search"), ("environment", "prod"), ("customer_id", "acme:eu")])
+        );
+    }
+
+    #[test]
+    fn empty_filter_has_no_tags() {
+        assert!(parse_tag_filter("").unwrap().is_empty());
+        assert!(parse_tag_filter(" , ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn later_pairs_override_earlier_ones() {
+        assert_eq!(parse_tag_filter("team:a,team:b").unwrap(), tags(&[("team", "b")]));
+    }
+
+    #[test]
+    fn filter_without_a_value_or_with_a_bad_key_is_rejected() {
+        assert!(parse_tag_filter("team").is_err());
+        assert!(parse_tag_filter("Team:search").is_err());
+        assert!(parse_tag_filter(":search").is_err());
+        assert!(parse_tag_filter("team name:search").is_err());
+    }
+
+    #[test]
+    fn valid_tags_pass() {
+        let valid = tags(&[("team", "search"), ("feature.v2", "x"), ("customer_id", "acme-1")]);
+
+        assert!(validate_tags(&valid).is_ok());
+        assert!(validate_tags(&Tags::new()).is_ok());
+    }
+
+    #[test]
+    fn tag_keys_must_be_lowercase_and_short() {
+        let long_key = "k".repeat(MAX_TAG_KEY_LENGTH + 1);
+
+        assert!(validate_tags(&tags(&[("Team", "search")])).is_err());
+        assert!(validate_tags(&tags(&[("team/name", "search")])).is_err());
+        assert!(validate_tags(&tags(&[(&long_key, "search")])).is_err());
+        assert!(validate_tags(&tags(&[(&long_key[1..], "search")])).is_ok());
+    }
+
+    #[test]
+    fn tag_values_must_be_non_empty_and_short() {
+        let long_value = "v".repeat(MAX_TAG_VALUE_LENGTH + 1);
+
+        assert!(validate_tags(&tags(&[("team", "")])).is_err());
+        assert!(validate_tags(&tags(&[("team", &long_value)])).is_err());
+        assert!(validate_tags(&tags(&[("team", &long_value[1..])])).is_ok());
+    }
+
+    #[test]
+    fn too_many_tags_are_rejected() {
+        let many: Tags = (0..=MAX_TAGS).map(|i| (format!("key{}", i), "v".to_string())).collect();
+
+        assert!(validate_tags(&many).is_err());
+        assert!(validate_tags(&many.into_iter().take(MAX_TAGS).collect()).is_ok());
+    }
+}
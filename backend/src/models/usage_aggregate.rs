@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::api_usage::UsageFilter;
+use crate::models::latency::LatencyStats;
+use crate::models::money::Money;
+use crate::models::tag::validate_tag_key;
+
+/// Most dimensions a single aggregation may group by.
+pub const MAX_GROUP_DIMENSIONS: usize = 5;
+
+/// A dimension usage can be grouped by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupBy {
+    Hour,
+    Day,
+    Week,
+    Month,
+    ApiKeyId,
+    ModelName,
+    Endpoint,
+    /// `2xx`, `4xx`, `5xx`, ... from the status code.
+    StatusClass,
+    /// A top-level key of the usage metadata, written `metadata.<key>`.
+    Metadata(String),
+    /// A usage tag, written `tag.<key>`.
+    Tag(String),
+}
+
+impl GroupBy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "hour" => Ok(Self::Hour),
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            "api_key_id" => Ok(Self::ApiKeyId),
+            "model_name" => Ok(Self::ModelName),
+            "endpoint" => Ok(Self::Endpoint),
+            "status_class" => Ok(Self::StatusClass),
+            _ => {
+                if let Some(key) = value.strip_prefix("tag.") {
+                    validate_tag_key(key)?;
+                    return Ok(Self::Tag(key.to_string()));
+                }
+                match value.strip_prefix("metadata.") {
+                    Some(key) if !key.is_empty() && key.len() <= 100 => Ok(Self::Metadata(key.to_string())),
+                    _ => Err(format!(
+                        "Unknown group_by dimension '{}'; expected hour, day, week, month, api_key_id, \
+                         model_name, endpoint, status_class, metadata.<key> or tag.<key>",
+                        value
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Parses a comma-separated list of dimensions, rejecting duplicates.
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        let mut dimensions: Vec<Self> = Vec::new();
+
+        for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let dimension = Self::parse(name)?;
+            if dimensions.contains(&dimension) {
+                return Err(format!("group_by lists '{}' twice", name));
+            }
+            dimensions.push(dimension);
+        }
+
+        if dimensions.len() > MAX_GROUP_DIMENSIONS {
+            return Err(format!("group_by takes at most {} dimensions", MAX_GROUP_DIMENSIONS));
+        }
+
+        Ok(dimensions)
+    }
+
+    /// Key of the dimension in each group of the response.
+    pub fn name(&self) -> String {
+        match self {
+            Self::Hour => "hour".to_string(),
+            Self::Day => "day".to_string(),
+            Self::Week => "week".to_string(),
+            Self::Month => "month".to_string(),
+            Self::ApiKeyId => "api_key_id".to_string(),
+            Self::ModelName => "model_name".to_string(),
+            Self::Endpoint => "endpoint".to_string(),
+            Self::StatusClass => "status_class".to_string(),
+            Self::Metadata(key) => format!("metadata.{}", key),
+            Self::Tag(key) => format!("tag.{}", key),
+        }
+    }
+
+    pub fn is_time_bucket(&self) -> bool {
+        matches!(self, Self::Hour | Self::Day | Self::Week | Self::Month)
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UsageAggregateQuery {
+    /// Comma-separated dimensions, e.g. `day,model_name,tag.team`.
+    /// A single overall group when empty.
+    #[serde(default)]
+    pub group_by: String,
+
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub filter: UsageFilter,
+
+    /// Reporting currency; defaults to the user's preference.
+    pub currency: Option<String>,
+
+    /// IANA timezone of the time buckets; defaults to the user's preference.
+    pub timezone: Option<String>,
+
+    /// Day `week` buckets start on; defaults to the user's preference.
+    pub week_start: Option<String>,
+
+    /// Most groups returned per time bucket, or in total without one; those
+    /// with the largest cost are kept.
+    #[validate(range(min = 1, max = 10000))]
+    pub limit: Option<i64>,
+}
+
+/// Metrics of one group of usage.
+#[derive(Debug, Serialize)]
+pub struct UsageAggregate {
+    /// Value of each grouped dimension, keyed by its name.
+    pub group: serde_json::Map<String, serde_json::Value>,
+    pub cost: Money,
+    pub total_tokens: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub requests: i64,
+    pub errors: i64,
+    pub avg_response_time_ms: Option<f64>,
+    pub latency: LatencyStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageAggregation {
+    pub currency: String,
+    /// Timezone the time buckets are in.
+    pub timezone: String,
+    pub week_start: String,
+    pub group_by: Vec<String>,
+    pub groups: Vec<UsageAggregate>,
+}
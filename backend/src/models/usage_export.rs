@@ -0,0 +1,412 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use validator::Validate;
+
+use crate::models::api_usage::{ExportedUsage, UsageFilter};
+use crate::models::tag::validate_tag_key;
+
+/// File format of a usage export.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    /// A single JSON array of row objects.
+    #[default]
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// Apache Parquet with the fixed schema of `export_service::parquet_schema`.
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Ndjson => "ndjson",
+            Self::Parquet => "parquet",
+        }
+    }
+
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "ndjson" => Some(Self::Ndjson),
+            "parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Json => "application/json",
+            Self::Ndjson => "application/x-ndjson",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        self.as_str()
+    }
+
+    /// The text format written by [`UsageExportWriter`], `None` for Parquet.
+    pub fn text_format(&self) -> Option<TextFormat> {
+        match self {
+            Self::Csv => Some(TextFormat::Csv),
+            Self::Json => Some(TextFormat::Json),
+            Self::Ndjson => Some(TextFormat::Ndjson),
+            Self::Parquet => None,
+        }
+    }
+}
+
+/// The export formats [`UsageExportWriter`] writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    Csv,
+    Json,
+    Ndjson,
+}
+
+/// A column that can be selected for export, in the default column order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportColumn {
+    Id,
+    Timestamp,
+    ReceivedAt,
+    ApiKeyId,
+    ModelName,
+    Endpoint,
+    StatusCode,
+    ErrorType,
+    ResponseTimeMs,
+    Requests,
+    Errors,
+    InputTokens,
+    OutputTokens,
+    TotalTokens,
+    CachedInputTokens,
+    CacheWriteTokens,
+    ReasoningTokens,
+    BillingUnit,
+    Quantity,
+    UnitVariant,
+    IsBatch,
+    Cost,
+    ListCost,
+    Currency,
+    DiscountPercent,
+    PricingRuleId,
+    ReportingCost,
+    ReportingCurrency,
+    ExternalId,
+    Tags,
+    Metadata,
+}
+
+impl ExportColumn {
+    pub const ALL: [Self; 31] = [
+        Self::Id,
+        Self::Timestamp,
+        Self::ReceivedAt,
+        Self::ApiKeyId,
+        Self::ModelName,
+        Self::Endpoint,
+        Self::StatusCode,
+        Self::ErrorType,
+        Self::ResponseTimeMs,
+        Self::Requests,
+        Self::Errors,
+        Self::InputTokens,
+        Self::OutputTokens,
+        Self::TotalTokens,
+        Self::CachedInputTokens,
+        Self::CacheWriteTokens,
+        Self::ReasoningTokens,
+        Self::BillingUnit,
+        Self::Quantity,
+        Self::UnitVariant,
+        Self::IsBatch,
+        Self::Cost,
+        Self::ListCost,
+        Self::Currency,
+        Self::DiscountPercent,
+        Self::PricingRuleId,
+        Self::ReportingCost,
+        Self::ReportingCurrency,
+        Self::ExternalId,
+        Self::Tags,
+        Self::Metadata,
+    ];
+
+    /// Parses a comma-separated list of column names, keeping the order
+    /// given; an empty list selects every column.
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        let mut columns = Vec::new();
+
+        for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let column = Self::ALL
+                .into_iter()
+                .find(|column| column.name() == name)
+                .ok_or_else(|| format!("Unknown export column '{}'", name))?;
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+
+        if columns.is_empty() {
+            return Ok(Self::ALL.to_vec());
+        }
+
+        Ok(columns)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Timestamp => "timestamp",
+            Self::ReceivedAt => "received_at",
+            Self::ApiKeyId => "api_key_id",
+            Self::ModelName => "model_name",
+            Self::Endpoint => "endpoint",
+            Self::StatusCode => "status_code",
+            Self::ErrorType => "error_type",
+            Self::ResponseTimeMs => "response_time_ms",
+            Self::Requests => "requests",
+            Self::Errors => "errors",
+            Self::InputTokens => "input_tokens",
+            Self::OutputTokens => "output_tokens",
+            Self::TotalTokens => "total_tokens",
+            Self::CachedInputTokens => "cached_input_tokens",
+            Self::CacheWriteTokens => "cache_write_tokens",
+            Self::ReasoningTokens => "reasoning_tokens",
+            Self::BillingUnit => "billing_unit",
+            Self::Quantity => "quantity",
+            Self::UnitVariant => "unit_variant",
+            Self::IsBatch => "is_batch",
+            Self::Cost => "cost",
+            Self::ListCost => "list_cost",
+            Self::Currency => "currency",
+            Self::DiscountPercent => "discount_percent",
+            Self::PricingRuleId => "pricing_rule_id",
+            Self::ReportingCost => "reporting_cost",
+            Self::ReportingCurrency => "reporting_currency",
+            Self::ExternalId => "external_id",
+            Self::Tags => "tags",
+            Self::Metadata => "metadata",
+        }
+    }
+
+    /// The column's value in `row`, with timestamps rendered in `timezone`.
+    fn value(&self, row: &ExportedUsage, timezone: Tz) -> Value {
+        let usage = &row.usage;
+        match self {
+            Self::Id => json(usage.id),
+            Self::Timestamp => json(local_time(usage.timestamp, timezone)),
+            Self::ReceivedAt => json(local_time(usage.received_at, timezone)),
+            Self::ApiKeyId => json(usage.api_key_id),
+            Self::ModelName => json(&usage.model_name),
+            Self::Endpoint => json(&usage.endpoint),
+            Self::StatusCode => json(usage.status_code),
+            Self::ErrorType => json(&usage.error_type),
+            Self::ResponseTimeMs => json(usage.response_time_ms),
+            Self::Requests => json(usage.requests),
+            Self::Errors => json(usage.errors),
+            Self::InputTokens => json(usage.input_tokens),
+            Self::OutputTokens => json(usage.output_tokens),
+            Self::TotalTokens => json(usage.total_tokens),
+            Self::CachedInputTokens => json(usage.cached_input_tokens),
+            Self::CacheWriteTokens => json(usage.cache_write_tokens),
+            Self::ReasoningTokens => json(usage.reasoning_tokens),
+            Self::BillingUnit => json(&usage.billing_unit),
+            Self::Quantity => json(usage.quantity),
+            Self::UnitVariant => json(&usage.unit_variant),
+            Self::IsBatch => json(usage.is_batch),
+            Self::Cost => json(usage.cost),
+            Self::ListCost => json(usage.list_cost),
+            Self::Currency => json(&usage.currency),
+            Self::DiscountPercent => json(usage.discount_percent),
+            Self::PricingRuleId => json(usage.pricing_rule_id),
+            Self::ReportingCost => json(row.reporting_cost),
+            Self::ReportingCurrency => json(&row.reporting_currency),
+            Self::ExternalId => json(&usage.external_id),
+            Self::Tags => json(&usage.tags.0),
+            Self::Metadata => json(&usage.metadata),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct UsageExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+
+    /// Comma-separated columns to include, in order; all by default. Not
+    /// available for Parquet, whose schema is fixed.
+    #[serde(default)]
+    pub columns: String,
+
+    /// Comma-separated tag keys written as their own `tag_<key>` Parquet
+    /// columns; `PARQUET_TAG_COLUMNS` by default.
+    pub tag_columns: Option<String>,
+
+    /// IANA timezone timestamps are written in; the user's by default.
+    pub timezone: Option<String>,
+
+    /// Currency of `reporting_cost`; the user's reporting currency by default.
+    pub currency: Option<String>,
+
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub filter: UsageFilter,
+}
+
+/// Writes exported rows as CSV, JSON or NDJSON, one batch at a time.
+/// Parquet is written by `export_service::ParquetUsageWriter` instead.
+pub struct UsageExportWriter {
+    format: TextFormat,
+    columns: Vec<ExportColumn>,
+    timezone: Tz,
+    rows: usize,
+}
+
+impl UsageExportWriter {
+    pub fn new(format: TextFormat, columns: Vec<ExportColumn>, timezone: Tz) -> Self {
+        Self { format, columns, timezone, rows: 0 }
+    }
+
+    /// What precedes the first row: the CSV header or the opening bracket.
+    pub fn header(&self) -> Vec<u8> {
+        match self.format {
+            TextFormat::Csv => {
+                let names: Vec<&str> = self.columns.iter().map(|c| c.name()).collect();
+                let mut header = names.join(",");
+                header.push_str("\r\n");
+                header.into_bytes()
+            }
+            TextFormat::Json => b"[".to_vec(),
+            TextFormat::Ndjson => Vec::new(),
+        }
+    }
+
+    /// Appends `rows` to `out`.
+    pub fn write_rows(&mut self, rows: &[ExportedUsage], out: &mut Vec<u8>) {
+        for row in rows {
+            let values = self.columns.iter().map(|c| (c.name(), c.value(row, self.timezone)));
+            match self.format {
+                TextFormat::Csv => {
+                    let fields: Vec<String> = values.map(|(_, value)| csv_field(&value)).collect();
+                    out.extend_from_slice(fields.join(",").as_bytes());
+                    out.extend_from_slice(b"\r\n");
+                }
+                TextFormat::Json | TextFormat::Ndjson => {
+                    if self.format == TextFormat::Json {
+                        out.extend_from_slice(if self.rows > 0 { b",\n" } else { b"\n" });
+                    }
+                    // Written by hand so keys keep the selected column order
+                    out.push(b'{');
+                    for (i, (name, value)) in values.enumerate() {
+                        if i > 0 {
+                            out.push(b',');
+                        }
+                        out.extend_from_slice(Value::from(name).to_string().as_bytes());
+                        out.push(b':');
+                        out.extend_from_slice(value.to_string().as_bytes());
+                    }
+                    out.push(b'}');
+                    if self.format == TextFormat::Ndjson {
+                        out.push(b'\n');
+                    }
+                }
+            }
+            self.rows += 1;
+        }
+    }
+
+    /// What follows the last row.
+    pub fn footer(&self) -> Vec<u8> {
+        match self.format {
+            TextFormat::Json if self.rows > 0 => b"\n]\n".to_vec(),
+            TextFormat::Json => b"]\n".to_vec(),
+            TextFormat::Csv | TextFormat::Ndjson => Vec::new(),
+        }
+    }
+}
+
+/// Parses a comma-separated list of tag keys to flatten into columns.
+pub fn parse_tag_columns(value: &str) -> Result<Vec<String>, String> {
+    let mut keys: Vec<String> = Vec::new();
+
+    for key in value.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+        validate_tag_key(key)?;
+        if !keys.iter().any(|k| k == key) {
+            keys.push(key.to_string());
+        }
+    }
+
+    Ok(keys)
+}
+
+fn json<T: Serialize>(value: T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+fn local_time(at: DateTime<Utc>, timezone: Tz) -> String {
+    at.with_timezone(&timezone).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// A CSV field per RFC 4180: empty for null, JSON text for objects, and
+/// quoted when it contains a separator, quote or line break. Text starting
+/// like a formula is prefixed with `'` so spreadsheets show it as text
+/// rather than evaluating it.
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(text) if text.starts_with(['=', '+', '-', '@', '\t', '\r']) => format!("'{}", text),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes_separators_quotes_and_line_breaks() {
+        assert_eq!(csv_field(&Value::Null), "");
+        assert_eq!(csv_field(&json("gpt-4o")), "gpt-4o");
+        assert_eq!(csv_field(&json("a,b")), "\"a,b\"");
+        assert_eq!(csv_field(&json("say \"hi\"")), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field(&json("line\nbreak")), "\"line\nbreak\"");
+    }
+
+    #[test]
+    fn csv_field_keeps_formulas_as_text() {
+        assert_eq!(csv_field(&json("=HYPERLINK(\"x\")")), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field(&json("+1")), "'+1");
+        assert_eq!(csv_field(&json("-1")), "'-1");
+        assert_eq!(csv_field(&json("@SUM(A1)")), "'@SUM(A1)");
+        assert_eq!(csv_field(&json("\tcmd")), "'\tcmd");
+    }
+
+    #[test]
+    fn csv_field_leaves_numbers_and_objects_unprefixed() {
+        assert_eq!(csv_field(&json(-3)), "-3");
+        assert_eq!(csv_field(&serde_json::json!({"team": "search"})), "\"{\"\"team\"\":\"\"search\"\"}\"");
+    }
+}
@@ -2,6 +2,10 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::calendar::{validate_timezone, validate_week_start};
+use crate::models::money::validate_currency;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
@@ -14,6 +18,12 @@ pub struct User {
     pub is_active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Currency that stats, exports and predictions are reported in.
+    pub reporting_currency: String,
+    /// IANA timezone that days, weeks and months are reported in.
+    pub timezone: String,
+    /// Day weeks start on, e.g. `monday` or `sunday`.
+    pub week_start: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,4 +37,16 @@ pub struct CreateUserRequest {
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdatePreferencesRequest {
+    #[validate(custom(function = "validate_currency"))]
+    pub reporting_currency: Option<String>,
+
+    #[validate(custom(function = "validate_timezone"))]
+    pub timezone: Option<String>,
+
+    #[validate(custom(function = "validate_week_start"))]
+    pub week_start: Option<String>,
+}
@@ -1,11 +1,13 @@
 use axum::{
-    routing::{delete, get, post, put},
+    extract::DefaultBodyLimit,
+    routing::{any, delete, get, post, put},
     Router,
 };
 
 use crate::controllers::{
-    analytics_controller, api_key_controller, auth_controller, prediction_controller,
-    usage_controller,
+    analytics_controller, api_key_controller, auth_controller, export_schedule_controller,
+    fx_rate_controller, ingestion_token_controller, model_price_controller, prediction_controller,
+    pricing_rule_controller, proxy_controller, usage_controller,
 };
 use crate::middleware::auth::RequireAuth;
 use crate::websocket::websocket_handler;
@@ -16,9 +18,16 @@ pub fn create_router(state: AppState) -> Router {
         .route("/health", get(health_check))
         .route("/ws", get(websocket_handler))
         .nest("/api/v1", api_routes())
+        .nest("/proxy", proxy_routes(state.config.proxy.max_body_bytes))
         .with_state(state)
 }
 
+fn proxy_routes(max_body_bytes: usize) -> Router<AppState> {
+    Router::new()
+        .route("/:key_id/*path", any(proxy_controller::proxy_request))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+}
+
 fn api_routes() -> Router<AppState> {
     Router::new()
         .nest("/auth", auth_routes())
@@ -26,6 +35,10 @@ fn api_routes() -> Router<AppState> {
         .nest("/predictions", prediction_routes())
         .nest("/analytics", analytics_routes())
         .nest("/api-keys", api_key_routes())
+        .nest("/ingestion-tokens", ingestion_token_routes())
+        .nest("/model-prices", model_price_routes())
+        .nest("/fx-rates", fx_rate_routes())
+        .nest("/export-schedules", export_schedule_routes())
 }
 
 fn auth_routes() -> Router<AppState> {
@@ -33,14 +46,22 @@ fn auth_routes() -> Router<AppState> {
         .route("/register", post(auth_controller::register))
         .route("/login", post(auth_controller::login))
         .route("/me", get(auth_controller::get_current_user))
+        .route("/me/preferences", put(auth_controller::update_preferences))
 }
 
 fn usage_routes() -> Router<AppState> {
     Router::new()
         .route("/", post(usage_controller::record_usage))
+        .route("/batch", post(usage_controller::record_usage_batch))
+        .route("/raw", post(usage_controller::record_raw_usage))
+        .route("/import", post(usage_controller::import_usage))
         .route("/", get(usage_controller::get_usage))
         .route("/stats", get(usage_controller::get_stats))
+        .route("/aggregate", get(usage_controller::get_aggregate))
+        .route("/latency-histogram", get(usage_controller::get_latency_histogram))
+        .route("/tags", get(usage_controller::get_tags))
         .route("/export", get(usage_controller::export_usage))
+        .route("/true-up", get(pricing_rule_controller::get_true_up))
         .layer(axum::middleware::from_fn_with_state(
             (),
             RequireAuth::middleware,
@@ -81,6 +102,15 @@ fn api_key_routes() -> Router<AppState> {
         .route("/:key_id", put(api_key_controller::update_api_key))
         .route("/:key_id", delete(api_key_controller::delete_api_key))
         .route("/:key_id/stats", get(api_key_controller::get_api_key_stats))
+        .route(
+            "/:key_id/pricing-rules",
+            get(pricing_rule_controller::list_pricing_rules)
+                .post(pricing_rule_controller::create_pricing_rule),
+        )
+        .route(
+            "/:key_id/pricing-rules/:rule_id",
+            delete(pricing_rule_controller::delete_pricing_rule),
+        )
         .route("/:key_id/rotate", post(|state, user, path| async move {
             api_key_controller::rotate_api_key(state, user, path).await
         }))
@@ -90,6 +120,65 @@ fn api_key_routes() -> Router<AppState> {
         ))
 }
 
+fn ingestion_token_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", post(ingestion_token_controller::create_ingestion_token))
+        .route("/", get(ingestion_token_controller::list_ingestion_tokens))
+        .route(
+            "/:token_id",
+            delete(ingestion_token_controller::revoke_ingestion_token),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            (),
+            RequireAuth::middleware,
+        ))
+}
+
+fn model_price_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(model_price_controller::list_model_prices))
+        .route("/", post(model_price_controller::create_model_price))
+        .route("/reload", post(model_price_controller::reload_price_file))
+        .route(
+            "/recalculations",
+            post(model_price_controller::recalculate_costs)
+                .get(model_price_controller::list_cost_recalculations),
+        )
+        .route("/:price_id", get(model_price_controller::get_model_price))
+        .route("/:price_id", put(model_price_controller::update_model_price))
+        .route("/:price_id", delete(model_price_controller::delete_model_price))
+        .layer(axum::middleware::from_fn_with_state(
+            (),
+            RequireAuth::middleware,
+        ))
+}
+
+fn fx_rate_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(fx_rate_controller::list_fx_rates))
+        .route("/", post(fx_rate_controller::upsert_fx_rates))
+        .route("/import", post(fx_rate_controller::import_fx_rates))
+        .layer(axum::middleware::from_fn_with_state(
+            (),
+            RequireAuth::middleware,
+        ))
+}
+
+fn export_schedule_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", post(export_schedule_controller::create_export_schedule))
+        .route("/", get(export_schedule_controller::list_export_schedules))
+        .route("/:schedule_id", get(export_schedule_controller::get_export_schedule))
+        .route("/:schedule_id", put(export_schedule_controller::update_export_schedule))
+        .route("/:schedule_id", delete(export_schedule_controller::delete_export_schedule))
+        .route("/:schedule_id/run", post(export_schedule_controller::run_export_schedule))
+        .route("/:schedule_id/runs", get(export_schedule_controller::list_export_runs))
+        .layer(axum::middleware::from_fn_with_state(
+            (),
+            RequireAuth::middleware,
+        ))
+}
+
 async fn health_check() -> axum::Json<serde_json::Value> {
     axum::Json(serde_json::json!({
         "status": "healthy",
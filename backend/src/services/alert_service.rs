@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+
+use crate::{
+    db::repositories::{AlertRepository, UsageRepository, UserRepository},
+    errors::ApiError,
+    models::budget::Budget,
+    models::calendar::Calendar,
+    models::money::Money,
+    utils::helpers::format_currency,
+    websocket::WsMessage,
+};
+
+pub struct AlertService<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> AlertService<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    /// Raises an alert for each budget whose spend in the current period,
+    /// converted into the budget's currency, has reached its alert threshold.
+    /// Periods start at midnight in the owner's timezone, on the owner's week
+    /// start day for weekly budgets. Each budget alerts at most once per
+    /// period. Returns the number of alerts raised.
+    pub async fn check_budgets(&self, ws_tx: &broadcast::Sender<WsMessage>) -> Result<usize, ApiError> {
+        let alert_repo = AlertRepository::new(self.pool);
+        let mut raised = 0;
+        
+        for budget in alert_repo.list_budgets().await? {
+            match self.check_budget(&budget, ws_tx).await {
+                Ok(true) => raised += 1,
+                Ok(false) => {}
+                // One budget without an exchange rate must not block the others
+                Err(e) => tracing::warn!("Budget {} not checked: {}", budget.id, e),
+            }
+        }
+        
+        Ok(raised)
+    }
+    
+    async fn check_budget(
+        &self,
+        budget: &Budget,
+        ws_tx: &broadcast::Sender<WsMessage>,
+    ) -> Result<bool, ApiError> {
+        let calendar = UserRepository::new(self.pool).calendar(budget.user_id).await?;
+        let Some(start) = period_start(&budget.limit_type, &calendar, Utc::now()) else {
+            return Ok(false);
+        };
+        
+        let spend = UsageRepository::new(self.pool)
+            .get_spend(budget.user_id, budget.api_key_id, start, &budget.currency)
+            .await?;
+        let threshold = budget.limit_amount
+            * Money::try_from(budget.alert_threshold).unwrap_or(Money::ONE);
+        if spend < threshold {
+            return Ok(false);
+        }
+        
+        let alert_repo = AlertRepository::new(self.pool);
+        let alert_type = format!("budget_{}", budget.limit_type);
+        if alert_repo
+            .alert_exists_since(budget.user_id, budget.api_key_id, &alert_type, start)
+            .await?
+        {
+            return Ok(false);
+        }
+        
+        let severity = if spend >= budget.limit_amount { "critical" } else { "warning" };
+        let message = format!(
+            "{} spend of {} has reached {:.0}% of the {} budget",
+            capitalize(&budget.limit_type),
+            format_currency(spend, &budget.currency),
+            budget.alert_threshold * 100.0,
+            format_currency(budget.limit_amount, &budget.currency),
+        );
+        
+        alert_repo
+            .create_alert(
+                budget.user_id,
+                budget.api_key_id,
+                &alert_type,
+                severity,
+                threshold,
+                spend,
+                &message,
+            )
+            .await?;
+        
+        let _ = ws_tx.send(WsMessage::AlertNotification {
+            user_id: budget.user_id,
+            alert_type,
+            message,
+        });
+        
+        Ok(true)
+    }
+}
+
+/// Start of the current budget period in `calendar`, or `None` for an
+/// unknown limit type.
+fn period_start(limit_type: &str, calendar: &Calendar, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match limit_type {
+        "daily" => Some(calendar.start_of_today(now)),
+        "weekly" => Some(calendar.start_of_week(now)),
+        "monthly" => Some(calendar.start_of_month(now)),
+        _ => None,
+    }
+}
+
+fn capitalize(value: &str) -> String {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,777 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Decimal128Array, Int32Array, Int64Array, RecordBatch,
+    StringArray, TimestampMicrosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    config::settings::ExportConfig,
+    models::api_usage::{ApiUsage, ExportedUsage, UsageFilter},
+    models::calendar::Calendar,
+    models::export_schedule::{next_fire, parse_cron, ExportDestination, ExportSchedule, ExportScheduleRequest},
+    models::money::{validate_currency, Money, MONEY_SCALE},
+    models::usage_export::{
+        parse_tag_columns, ExportColumn, UsageExportQuery, UsageExportWriter,
+    },
+    db::repositories::export_schedule_repository::RunOutcome,
+    db::repositories::usage_repository::UsageExportCursor,
+    db::repositories::{ExportScheduleRepository, UsageRepository, UserRepository},
+    services::s3_client::S3Client,
+    services::usage_service::UsageService,
+    errors::ApiError,
+    utils::crypto::{decrypt_api_key, encrypt_api_key},
+    utils::period::Period,
+};
+
+/// Rows per Parquet row group, which is also how many rows a writer holds
+/// in memory before emitting them.
+const PARQUET_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Rows fetched from the export cursor per batch encoded.
+pub const EXPORT_BATCH_SIZE: i64 = 1000;
+
+/// Due export schedules claimed at a time.
+const SCHEDULES_PER_CLAIM: i64 = 10;
+
+/// Wait before retrying a failed scheduled export, doubled on each further
+/// failure.
+const RETRY_DELAY_SECS: i64 = 60;
+
+/// Precision of Parquet decimal columns; their scale is `MONEY_SCALE`.
+const DECIMAL_PRECISION: u8 = 38;
+
+/// The Parquet schema of usage exports. It only ever gains columns at the
+/// end, and the `tag_<key>` columns follow in the order requested.
+pub fn parquet_schema(tag_columns: &[String]) -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    let decimal = DataType::Decimal128(DECIMAL_PRECISION, MONEY_SCALE as i8);
+    
+    let mut fields = vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("user_id", DataType::Utf8, false),
+        Field::new("api_key_id", DataType::Utf8, false),
+        Field::new("timestamp", timestamp.clone(), false),
+        Field::new("received_at", timestamp, false),
+        Field::new("model_name", DataType::Utf8, true),
+        Field::new("endpoint", DataType::Utf8, true),
+        Field::new("status_code", DataType::Int32, true),
+        Field::new("error_type", DataType::Utf8, true),
+        Field::new("response_time_ms", DataType::Int32, true),
+        Field::new("requests", DataType::Int32, false),
+        Field::new("errors", DataType::Int32, false),
+        Field::new("input_tokens", DataType::Int32, false),
+        Field::new("output_tokens", DataType::Int32, false),
+        Field::new("total_tokens", DataType::Int32, false),
+        Field::new("cached_input_tokens", DataType::Int32, true),
+        Field::new("cache_write_tokens", DataType::Int32, true),
+        Field::new("reasoning_tokens", DataType::Int32, true),
+        Field::new("billing_unit", DataType::Utf8, false),
+        Field::new("quantity", decimal.clone(), true),
+        Field::new("unit_variant", DataType::Utf8, true),
+        Field::new("is_batch", DataType::Boolean, false),
+        Field::new("cost", decimal.clone(), false),
+        Field::new("list_cost", decimal.clone(), false),
+        Field::new("discount_percent", decimal.clone(), false),
+        Field::new("currency", DataType::Utf8, false),
+        Field::new("pricing_rule_id", DataType::Utf8, true),
+        Field::new("reporting_cost", decimal, false),
+        Field::new("reporting_currency", DataType::Utf8, false),
+        Field::new("external_id", DataType::Utf8, true),
+        Field::new("metadata", DataType::Utf8, true),
+        Field::new("tags", DataType::Utf8, false),
+    ];
+    fields.extend(tag_columns.iter().map(|key| Field::new(format!("tag_{}", key), DataType::Utf8, true)));
+    
+    Arc::new(Schema::new(fields))
+}
+
+/// Writes exported rows as a Parquet file, handing back the encoded bytes
+/// as each row group is completed so the file never has to be held whole.
+pub struct ParquetUsageWriter {
+    writer: ArrowWriter<Vec<u8>>,
+    schema: SchemaRef,
+    tag_columns: Vec<String>,
+}
+
+impl ParquetUsageWriter {
+    pub fn new(tag_columns: Vec<String>) -> Result<Self, ApiError> {
+        let schema = parquet_schema(&tag_columns);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(PARQUET_ROW_GROUP_SIZE)
+            .build();
+        let writer = ArrowWriter::try_new(Vec::new(), schema.clone(), Some(properties))
+            .map_err(|e| ApiError::Internal(format!("Failed to start Parquet file: {}", e)))?;
+        
+        Ok(Self { writer, schema, tag_columns })
+    }
+    
+    /// Adds `rows`, returning whatever part of the file is complete.
+    pub fn write_rows(&mut self, rows: &[ExportedUsage]) -> Result<Vec<u8>, ApiError> {
+        if !rows.is_empty() {
+            let batch = self.record_batch(rows)?;
+            self.writer
+                .write(&batch)
+                .map_err(|e| ApiError::Internal(format!("Failed to write Parquet rows: {}", e)))?;
+        }
+        
+        Ok(std::mem::take(self.writer.inner_mut()))
+    }
+    
+    /// Writes the remaining rows and the footer, returning the rest of the file.
+    pub fn finish(self) -> Result<Vec<u8>, ApiError> {
+        self.writer
+            .into_inner()
+            .map_err(|e| ApiError::Internal(format!("Failed to finish Parquet file: {}", e)))
+    }
+    
+    fn record_batch(&self, rows: &[ExportedUsage]) -> Result<RecordBatch, ApiError> {
+        let usage: Vec<&ApiUsage> = rows.iter().map(|row| &row.usage).collect();
+        
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from_iter_values(usage.iter().map(|u| u.id))),
+            Arc::new(StringArray::from_iter_values(usage.iter().map(|u| u.user_id.to_string()))),
+            Arc::new(StringArray::from_iter_values(usage.iter().map(|u| u.api_key_id.to_string()))),
+            Arc::new(
+                TimestampMicrosecondArray::from_iter_values(usage.iter().map(|u| u.timestamp.timestamp_micros()))
+                    .with_timezone("UTC"),
+            ),
+            Arc::new(
+                TimestampMicrosecondArray::from_iter_values(usage.iter().map(|u| u.received_at.timestamp_micros()))
+                    .with_timezone("UTC"),
+            ),
+            Arc::new(StringArray::from_iter(usage.iter().map(|u| u.model_name.as_deref()))),
+            Arc::new(StringArray::from_iter(usage.iter().map(|u| u.endpoint.as_deref()))),
+            Arc::new(Int32Array::from_iter(usage.iter().map(|u| u.status_code))),
+            Arc::new(StringArray::from_iter(usage.iter().map(|u| u.error_type.as_deref()))),
+            Arc::new(Int32Array::from_iter(usage.iter().map(|u| u.response_time_ms))),
+            Arc::new(Int32Array::from_iter_values(usage.iter().map(|u| u.requests))),
+            Arc::new(Int32Array::from_iter_values(usage.iter().map(|u| u.errors))),
+            Arc::new(Int32Array::from_iter_values(usage.iter().map(|u| u.input_tokens))),
+            Arc::new(Int32Array::from_iter_values(usage.iter().map(|u| u.output_tokens))),
+            Arc::new(Int32Array::from_iter_values(usage.iter().map(|u| u.total_tokens))),
+            Arc::new(Int32Array::from_iter(usage.iter().map(|u| u.cached_input_tokens))),
+            Arc::new(Int32Array::from_iter(usage.iter().map(|u| u.cache_write_tokens))),
+            Arc::new(Int32Array::from_iter(usage.iter().map(|u| u.reasoning_tokens))),
+            Arc::new(StringArray::from_iter_values(usage.iter().map(|u| u.billing_unit.as_str()))),
+            optional_decimal_column(usage.iter().map(|u| u.quantity))?,
+            Arc::new(StringArray::from_iter(usage.iter().map(|u| u.unit_variant.as_deref()))),
+            Arc::new(BooleanArray::from_iter(usage.iter().map(|u| Some(u.is_batch)))),
+            decimal_column(usage.iter().map(|u| u.cost))?,
+            decimal_column(usage.iter().map(|u| u.list_cost))?,
+            decimal_column(usage.iter().map(|u| u.discount_percent))?,
+            Arc::new(StringArray::from_iter_values(usage.iter().map(|u| u.currency.as_str()))),
+            Arc::new(StringArray::from_iter(usage.iter().map(|u| u.pricing_rule_id.map(|id| id.to_string())))),
+            decimal_column(rows.iter().map(|row| row.reporting_cost))?,
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|row| row.reporting_currency.as_str()))),
+            Arc::new(StringArray::from_iter(usage.iter().map(|u| u.external_id.as_deref()))),
+            Arc::new(StringArray::from_iter(usage.iter().map(|u| u.metadata.as_ref().map(|m| m.to_string())))),
+            Arc::new(StringArray::from_iter_values(
+                usage.iter().map(|u| serde_json::to_string(&u.tags.0).unwrap_or_default()),
+            )),
+        ];
+        for key in &self.tag_columns {
+            columns.push(Arc::new(StringArray::from_iter(
+                usage.iter().map(|u| u.tags.0.get(key).map(String::as_str)),
+            )));
+        }
+        
+        RecordBatch::try_new(self.schema.clone(), columns)
+            .map_err(|e| ApiError::Internal(format!("Failed to build Parquet rows: {}", e)))
+    }
+}
+
+fn decimal_column(values: impl Iterator<Item = Money>) -> Result<ArrayRef, ApiError> {
+    optional_decimal_column(values.map(Some))
+}
+
+/// Decimals on the stored `NUMERIC(20,10)` scale, such as amounts and unit
+/// quantities.
+fn optional_decimal_column(values: impl Iterator<Item = Option<Money>>) -> Result<ArrayRef, ApiError> {
+    let values = values.map(|value| {
+        value.map(|mut value| {
+            value.rescale(MONEY_SCALE);
+            value.mantissa()
+        })
+    });
+    let array = Decimal128Array::from_iter(values)
+        .with_precision_and_scale(DECIMAL_PRECISION, MONEY_SCALE as i8)
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    
+    Ok(Arc::new(array))
+}
+
+/// Encodes exported rows in the requested format.
+pub enum ExportEncoder {
+    Text(UsageExportWriter),
+    Parquet(Box<ParquetUsageWriter>),
+}
+
+impl ExportEncoder {
+    /// The encoder for an export query, with text timestamps in `timezone`.
+    /// Parquet files have every column plus the tag columns of the query, or
+    /// `default_tag_columns` when it names none.
+    pub fn for_query(
+        query: &UsageExportQuery,
+        timezone: Tz,
+        default_tag_columns: &[String],
+    ) -> Result<Self, ApiError> {
+        let tag_columns = match &query.tag_columns {
+            Some(value) => parse_tag_columns(value).map_err(ApiError::ValidationError)?,
+            None => default_tag_columns.to_vec(),
+        };
+        
+        let Some(format) = query.format.text_format() else {
+            if !query.columns.trim().is_empty() {
+                return Err(ApiError::ValidationError(
+                    "columns cannot be selected for Parquet, whose schema is fixed".to_string(),
+                ));
+            }
+            return Ok(Self::Parquet(Box::new(ParquetUsageWriter::new(tag_columns)?)));
+        };
+        
+        let columns = ExportColumn::parse_list(&query.columns).map_err(ApiError::ValidationError)?;
+        
+        Ok(Self::Text(UsageExportWriter::new(format, columns, timezone)))
+    }
+    
+    pub fn header(&self) -> Vec<u8> {
+        match self {
+            Self::Text(writer) => writer.header(),
+            Self::Parquet(_) => Vec::new(),
+        }
+    }
+    
+    pub fn write_rows(&mut self, rows: &[ExportedUsage]) -> Result<Vec<u8>, ApiError> {
+        match self {
+            Self::Text(writer) => {
+                let mut chunk = Vec::new();
+                writer.write_rows(rows, &mut chunk);
+                Ok(chunk)
+            }
+            Self::Parquet(writer) => writer.write_rows(rows),
+        }
+    }
+    
+    pub fn finish(self) -> Result<Vec<u8>, ApiError> {
+        match self {
+            Self::Text(writer) => Ok(writer.footer()),
+            Self::Parquet(writer) => writer.finish(),
+        }
+    }
+}
+
+/// What `write_export` wrote.
+struct WrittenExport {
+    rows: i64,
+    bytes: i64,
+    /// Hex SHA-256 of the file.
+    sha256: String,
+}
+
+pub struct ExportService<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> ExportService<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    /// Writes the daily partitions that may have changed since the last run:
+    /// every UTC day before `today` that usage up to `lateness` late can still
+    /// land on, and any earlier days after the newest partition in `dir`,
+    /// which a run was missed for. Returns the number of files written.
+    pub async fn write_pending_partitions(
+        &self,
+        dir: &Path,
+        today: NaiveDate,
+        lateness: Duration,
+        tag_columns: &[String],
+    ) -> Result<usize, ApiError> {
+        let newest = newest_partition(dir).await.map_err(file_error)?;
+        let earliest_late = (Utc::now() - lateness).date_naive();
+        let mut written = 0;
+    
+        for date in partition_dates(today, earliest_late, newest) {
+            written += self.write_daily_partition(dir, date, tag_columns).await?;
+        }
+    
+        Ok(written)
+    }
+    
+    /// Writes each user's usage on a UTC `date` to
+    /// `<dir>/date=<date>/<user_id>.parquet`, replacing any earlier file for
+    /// that day. Users without usage that day get no file. Returns the number
+    /// of files written.
+    pub async fn write_daily_partition(
+        &self,
+        dir: &Path,
+        date: NaiveDate,
+        tag_columns: &[String],
+    ) -> Result<usize, ApiError> {
+        let repo = UsageRepository::new(self.pool);
+        let calendar = Calendar::default();
+        let start = calendar.start_of(date);
+        // Timestamps are stored to the microsecond, so this ends the day
+        // exactly while keeping the listing's inclusive end
+        let end = start + Duration::days(1) - Duration::microseconds(1);
+        
+        let partition = dir.join(format!("date={}", date));
+        let mut written = 0;
+        
+        for user_id in repo.users_with_usage(start, end).await? {
+            let path = partition.join(format!("{}.parquet", user_id));
+            match self.write_user_file(user_id, start, end, &path, tag_columns).await {
+                Ok(()) => written += 1,
+                Err(e) => tracing::error!("Parquet export of {} for user {} failed: {}", date, user_id, e),
+            }
+        }
+        
+        Ok(written)
+    }
+    
+    /// Writes to a temporary file first, so readers never see a partial one.
+    async fn write_user_file(
+        &self,
+        user_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        path: &Path,
+        tag_columns: &[String],
+    ) -> Result<(), ApiError> {
+        let currency = UserRepository::new(self.pool).reporting_currency(user_id).await?;
+        let cursor = UsageRepository::new(self.pool)
+            .open_export_cursor(user_id, start, end, &UsageFilter::default(), &currency)
+            .await?;
+        let encoder = ExportEncoder::Parquet(Box::new(ParquetUsageWriter::new(tag_columns.to_vec())?));
+        
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await.map_err(file_error)?;
+        }
+        let partial = path.with_extension("parquet.tmp");
+        write_export(cursor, encoder, &partial).await?;
+        tokio::fs::rename(&partial, path).await.map_err(file_error)?;
+        
+        Ok(())
+    }
+    
+    /// Validates and stores a new export schedule, first due at the next
+    /// time its cron expression fires.
+    pub async fn create_schedule(
+        &self,
+        user_id: Uuid,
+        mut req: ExportScheduleRequest,
+        config: &ExportConfig,
+    ) -> Result<ExportSchedule, ApiError> {
+        let (encrypted_secret, next_run_at) = self.prepare_schedule(user_id, &mut req, config, true).await?;
+        
+        ExportScheduleRepository::new(self.pool)
+            .create_schedule(user_id, &req, encrypted_secret.as_deref(), next_run_at)
+            .await
+    }
+    
+    /// Replaces a schedule's settings. An S3 destination keeps its stored
+    /// secret unless a new one is given, and any pending retry is dropped.
+    pub async fn update_schedule(
+        &self,
+        user_id: Uuid,
+        schedule_id: Uuid,
+        mut req: ExportScheduleRequest,
+        config: &ExportConfig,
+    ) -> Result<ExportSchedule, ApiError> {
+        let repo = ExportScheduleRepository::new(self.pool);
+        let existing = repo
+            .get_schedule(user_id, schedule_id)
+            .await?
+            .ok_or_else(|| ApiError::NotFound("Export schedule not found".to_string()))?;
+        let has_secret = existing.encrypted_secret.is_some()
+            && matches!(existing.destination.0, ExportDestination::S3 { .. });
+        
+        let (encrypted_secret, next_run_at) =
+            self.prepare_schedule(user_id, &mut req, config, !has_secret).await?;
+        
+        repo.update_schedule(user_id, schedule_id, &req, encrypted_secret.as_deref(), next_run_at)
+            .await?
+            .ok_or_else(|| ApiError::NotFound("Export schedule not found".to_string()))
+    }
+    
+    /// Checks a schedule request, normalising its currency and taking out the
+    /// S3 secret, which is returned encrypted with the first run time.
+    async fn prepare_schedule(
+        &self,
+        user_id: Uuid,
+        req: &mut ExportScheduleRequest,
+        config: &ExportConfig,
+        require_secret: bool,
+    ) -> Result<(Option<String>, DateTime<Utc>), ApiError> {
+        req.validate()?;
+        let filters = &req.filters;
+        UsageService::check_filter_bounds(filters.status_min, filters.status_max, filters.min_cost, filters.max_cost)?;
+        
+        let cron = parse_cron(&req.cron).map_err(ApiError::ValidationError)?;
+        Period::parse(&req.period).map_err(ApiError::ValidationError)?;
+        if let Some(currency) = req.currency.as_mut() {
+            *currency = currency.to_ascii_uppercase();
+            validate_currency(currency).map_err(|e| ApiError::ValidationError(e.to_string()))?;
+        }
+        
+        let calendar = UserRepository::new(self.pool)
+            .calendar(user_id)
+            .await?
+            .with_overrides(req.timezone.as_deref(), None)
+            .map_err(ApiError::ValidationError)?;
+        let query = UsageExportQuery {
+            format: req.format,
+            columns: req.columns.clone(),
+            tag_columns: req.tag_columns.clone(),
+            ..Default::default()
+        };
+        ExportEncoder::for_query(&query, calendar.timezone, &[])?;
+        
+        req.destination
+            .validate(config.s3_allow_http, &config.s3_allowed_hosts)
+            .map_err(ApiError::ValidationError)?;
+        let encrypted_secret = match &mut req.destination {
+            ExportDestination::Local { .. } if config.local_dir.is_none() => {
+                return Err(ApiError::ValidationError(
+                    "Local export destinations are not enabled on this server".to_string(),
+                ));
+            }
+            ExportDestination::Local { .. } => None,
+            ExportDestination::S3 { secret_access_key, .. } => match secret_access_key.take() {
+                Some(secret) if !secret.is_empty() => Some(encrypt_api_key(&secret)?),
+                _ if require_secret => {
+                    return Err(ApiError::ValidationError(
+                        "secret_access_key is required for an S3 destination".to_string(),
+                    ));
+                }
+                _ => None,
+            },
+        };
+        
+        let next_run_at = next_fire(&cron, calendar.timezone, Utc::now()).ok_or_else(|| {
+            ApiError::ValidationError("The cron expression never fires".to_string())
+        })?;
+        
+        Ok((encrypted_secret, next_run_at))
+    }
+    
+    /// Runs every due export schedule, a few at a time, returning how many
+    /// were run. A failed run is retried with exponential backoff until the
+    /// schedule's attempts are used up, and is then skipped until the next
+    /// cron time.
+    pub async fn run_due_schedules(
+        &self,
+        config: &ExportConfig,
+        http: &reqwest::Client,
+    ) -> Result<usize, ApiError> {
+        let repo = ExportScheduleRepository::new(self.pool);
+        let mut ran = 0;
+        
+        loop {
+            let schedules = repo.claim_due(SCHEDULES_PER_CLAIM).await?;
+            if schedules.is_empty() {
+                return Ok(ran);
+            }
+            
+            for schedule in &schedules {
+                self.run_schedule(schedule, config, http).await?;
+            }
+            ran += schedules.len();
+        }
+    }
+    
+    async fn run_schedule(
+        &self,
+        schedule: &ExportSchedule,
+        config: &ExportConfig,
+        http: &reqwest::Client,
+    ) -> Result<(), ApiError> {
+        let repo = ExportScheduleRepository::new(self.pool);
+        let run_id = repo.start_run(schedule).await?;
+        
+        let mut outcome = RunOutcome::default();
+        let result = self.deliver(schedule, config, http, &mut outcome).await;
+        if let Err(e) = &result {
+            outcome.error = Some(e.to_string());
+        }
+        repo.finish_run(run_id, &outcome).await?;
+        
+        let now = Utc::now();
+        let attempt = schedule.attempt + 1;
+        match result {
+            Ok(()) => {
+                let next = self.next_run(schedule, now).await;
+                repo.release(schedule.id, "succeeded", None, 0, next, next).await
+            }
+            Err(e) if attempt < schedule.max_attempts => {
+                let delay = Duration::seconds(RETRY_DELAY_SECS << (attempt - 1).min(10));
+                tracing::warn!(
+                    "Export schedule {} failed (attempt {} of {}), retrying in {}s: {}",
+                    schedule.id, attempt, schedule.max_attempts, delay.num_seconds(), e
+                );
+                repo.release(schedule.id, "retrying", Some(&e.to_string()), attempt, schedule.scheduled_for, now + delay)
+                    .await
+            }
+            Err(e) => {
+                tracing::error!("Export schedule {} failed after {} attempts: {}", schedule.id, attempt, e);
+                let next = self.next_run(schedule, now).await;
+                repo.release(schedule.id, "failed", Some(&e.to_string()), 0, next, next).await
+            }
+        }
+    }
+    
+    /// Exports the schedule's period ending at its cron time and delivers the
+    /// file, recording what was done in `outcome`.
+    async fn deliver(
+        &self,
+        schedule: &ExportSchedule,
+        config: &ExportConfig,
+        http: &reqwest::Client,
+        outcome: &mut RunOutcome,
+    ) -> Result<(), ApiError> {
+        // The settings may have been tightened since the schedule was saved
+        schedule
+            .destination
+            .validate(config.s3_allow_http, &config.s3_allowed_hosts)
+            .map_err(ApiError::ValidationError)?;
+        let users = UserRepository::new(self.pool);
+        let calendar = users
+            .calendar(schedule.user_id)
+            .await?
+            .with_overrides(schedule.timezone.as_deref(), None)
+            .map_err(ApiError::ValidationError)?;
+        let range = Period::parse(&schedule.period)
+            .and_then(|period| period.resolve(&calendar, schedule.scheduled_for))
+            .map_err(ApiError::ValidationError)?;
+        outcome.period_start = Some(range.start);
+        outcome.period_end = Some(range.end);
+        
+        let query = schedule.export_query().map_err(ApiError::ValidationError)?;
+        let currency = match &schedule.currency {
+            Some(currency) => currency.clone(),
+            None => users.reporting_currency(schedule.user_id).await?,
+        };
+        let encoder = ExportEncoder::for_query(&query, calendar.timezone, &config.parquet_tag_columns)?;
+        
+        // The period's end is exclusive while the listing's is not
+        let end = range.end - Duration::microseconds(1);
+        let filename = format!(
+            "usage-{}-to-{}.{}",
+            calendar.date_of(range.start),
+            calendar.date_of(end),
+            query.format.extension()
+        );
+        let cursor = UsageRepository::new(self.pool)
+            .open_export_cursor(schedule.user_id, range.start, end, &query.filter, &currency)
+            .await?;
+        
+        let written = match &schedule.destination.0 {
+            ExportDestination::Local { path } => {
+                let root = config.local_dir.as_ref().ok_or_else(|| {
+                    ApiError::Internal("EXPORT_LOCAL_DIR is not set".to_string())
+                })?;
+                let dir = root.join(schedule.user_id.to_string()).join(path);
+                tokio::fs::create_dir_all(&dir).await.map_err(file_error)?;
+                
+                let target = dir.join(&filename);
+                let partial = dir.join(format!("{}.tmp", filename));
+                let written = write_export(cursor, encoder, &partial).await?;
+                tokio::fs::rename(&partial, &target).await.map_err(file_error)?;
+                
+                outcome.location = Some(target.display().to_string());
+                written
+            }
+            ExportDestination::S3 { endpoint, bucket, region, prefix, access_key_id, .. } => {
+                let secret = schedule
+                    .encrypted_secret
+                    .as_deref()
+                    .ok_or_else(|| ApiError::Internal("The S3 secret access key is missing".to_string()))?;
+                let secret = decrypt_api_key(secret)?;
+                let client = S3Client::new(http, endpoint, region, access_key_id, &secret)?;
+                let key = format!("{}{}", prefix, filename);
+                
+                let partial = std::env::temp_dir().join(format!("usage-export-{}.tmp", Uuid::new_v4()));
+                let result = match write_export(cursor, encoder, &partial).await {
+                    Ok(written) => client
+                        .put_object(bucket, &key, &partial, query.format.content_type(), &written.sha256)
+                        .await
+                        .map(|()| written),
+                    Err(e) => Err(e),
+                };
+                // Best effort: the file may not exist if writing failed early
+                let _ = tokio::fs::remove_file(&partial).await;
+                
+                let written = result?;
+                outcome.location = Some(format!("s3://{}/{}", bucket, key));
+                written
+            }
+        };
+        
+        outcome.rows_exported = Some(written.rows);
+        outcome.bytes_written = Some(written.bytes);
+        
+        Ok(())
+    }
+    
+    /// The cron time following the schedule's current one. When runs were
+    /// missed it has already passed, so they are caught up one at a time, each
+    /// covering the period of its own cron time.
+    async fn next_run(&self, schedule: &ExportSchedule, now: DateTime<Utc>) -> DateTime<Utc> {
+        let timezone = match &schedule.timezone {
+            Some(timezone) => timezone.parse::<Tz>().ok(),
+            None => UserRepository::new(self.pool)
+                .calendar(schedule.user_id)
+                .await
+                .ok()
+                .map(|calendar| calendar.timezone),
+        };
+        
+        parse_cron(&schedule.cron)
+            .ok()
+            .zip(timezone)
+            .and_then(|(cron, timezone)| next_fire(&cron, timezone, schedule.scheduled_for))
+            // Only a schedule edited outside the API gets here; try again tomorrow
+            .unwrap_or_else(|| now + Duration::days(1))
+    }
+}
+
+/// Writes an export to `path`, hashing it on the way.
+async fn write_export(
+    mut cursor: UsageExportCursor,
+    mut encoder: ExportEncoder,
+    path: &Path,
+) -> Result<WrittenExport, ApiError> {
+    let mut file = tokio::fs::File::create(path).await.map_err(file_error)?;
+    let mut hasher = Sha256::new();
+    let mut written = WrittenExport { rows: 0, bytes: 0, sha256: String::new() };
+    
+    let mut chunk = encoder.header();
+    loop {
+        if !chunk.is_empty() {
+            hasher.update(&chunk);
+            written.bytes += chunk.len() as i64;
+            file.write_all(&chunk).await.map_err(file_error)?;
+        }
+        
+        let rows = cursor.next_batch(EXPORT_BATCH_SIZE).await?;
+        if rows.is_empty() {
+            break;
+        }
+        written.rows += rows.len() as i64;
+        chunk = encoder.write_rows(&rows)?;
+    }
+    cursor.close().await?;
+    
+    let footer = encoder.finish()?;
+    hasher.update(&footer);
+    written.bytes += footer.len() as i64;
+    file.write_all(&footer).await.map_err(file_error)?;
+    file.sync_all().await.map_err(file_error)?;
+    
+    written.sha256 = format!("{:x}", hasher.finalize());
+    Ok(written)
+}
+
+/// The days to write partitions for: from the day after `newest` if that
+/// is earlier than `earliest_late`, otherwise from `earliest_late`, through
+/// the day before `today`.
+fn partition_dates(today: NaiveDate, earliest_late: NaiveDate, newest: Option<NaiveDate>) -> Vec<NaiveDate> {
+    let first = match newest {
+        Some(newest) => earliest_late.min(newest + Duration::days(1)),
+        None => earliest_late,
+    };
+    
+    first.iter_days().take_while(|date| *date < today).collect()
+}
+
+/// The latest `date=YYYY-MM-DD` partition under `dir`, if any.
+async fn newest_partition(dir: &Path) -> std::io::Result<Option<NaiveDate>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut newest = None;
+    
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let date = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("date="))
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+        newest = newest.max(date);
+    }
+    
+    Ok(newest)
+}
+
+fn file_error(e: std::io::Error) -> ApiError {
+    ApiError::Internal(format!("Failed to write export file: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn partitions_cover_the_lateness_window_up_to_yesterday() {
+        let dates = partition_dates(date("2026-10-17"), date("2026-10-10"), Some(date("2026-10-16")));
+
+        assert_eq!(dates.first(), Some(&date("2026-10-10")));
+        assert_eq!(dates.last(), Some(&date("2026-10-16")));
+        assert_eq!(dates.len(), 7);
+    }
+
+    #[test]
+    fn partitions_backfill_days_missed_before_the_window() {
+        let dates = partition_dates(date("2026-10-17"), date("2026-10-10"), Some(date("2026-09-30")));
+
+        assert_eq!(dates.first(), Some(&date("2026-10-01")));
+        assert_eq!(dates.last(), Some(&date("2026-10-16")));
+    }
+
+    #[test]
+    fn first_run_writes_the_lateness_window() {
+        let dates = partition_dates(date("2026-10-17"), date("2026-10-16"), None);
+
+        assert_eq!(dates, vec![date("2026-10-16")]);
+    }
+
+    #[test]
+    fn no_partitions_before_the_window_reaches_a_full_day() {
+        assert!(partition_dates(date("2026-10-17"), date("2026-10-17"), Some(date("2026-10-16"))).is_empty());
+    }
+
+    #[tokio::test]
+    async fn newest_partition_ignores_other_entries() {
+        let dir = std::env::temp_dir().join(format!("parquet-partitions-{}", Uuid::new_v4()));
+        for name in ["date=2026-10-01", "date=2026-10-15", "date=2026-10-15.tmp", "notes"] {
+            tokio::fs::create_dir_all(dir.join(name)).await.unwrap();
+        }
+
+        let newest = newest_partition(&dir).await.unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(newest, Some(date("2026-10-15")));
+        assert_eq!(newest_partition(&dir).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use sqlx::PgPool;
+use validator::Validate;
+
+use crate::{
+    db::repositories::FxRepository,
+    errors::ApiError,
+    models::fx_rate::{FxRate, FxRateInput},
+    models::money::Money,
+};
+
+pub struct FxService<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> FxService<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    pub async fn list_rates(&self, currency: Option<&str>) -> Result<Vec<FxRate>, ApiError> {
+        let currency = currency.map(str::to_ascii_uppercase);
+        
+        FxRepository::new(self.pool).list_rates(currency.as_deref()).await
+    }
+    
+    /// Validates and stores the rates, replacing rates of the same currency
+    /// and date. Returns the number of rates written.
+    pub async fn upsert_rates(&self, rates: Vec<FxRateInput>) -> Result<u64, ApiError> {
+        if rates.is_empty() {
+            return Err(ApiError::ValidationError("No rates given".to_string()));
+        }
+        
+        // One statement cannot write the same currency and date twice
+        let mut seen = HashSet::new();
+        for (index, rate) in rates.iter().enumerate() {
+            rate.validate()
+                .map_err(|e| ApiError::ValidationError(format!("Rate {}: {}", index, e)))?;
+            if !seen.insert((rate.currency.as_str(), rate.rate_date)) {
+                return Err(ApiError::ValidationError(format!(
+                    "Rate {}: {} already has a rate for {}",
+                    index, rate.currency, rate.rate_date
+                )));
+            }
+        }
+        
+        FxRepository::new(self.pool).upsert_rates(&rates).await
+    }
+    
+    /// Loads rates from CSV with `currency,rate_date,usd_rate` rows, e.g.
+    /// `EUR,2026-01-01,1.0842`. A header row, blank lines and `#` comments
+    /// are skipped.
+    pub async fn import_csv(&self, csv: &str) -> Result<u64, ApiError> {
+        let rates = parse_rates_csv(csv)?;
+        
+        self.upsert_rates(rates).await
+    }
+    
+    /// Loads rates from a CSV file shaped like the import body.
+    pub async fn import_file(&self, path: &Path) -> Result<u64, ApiError> {
+        let csv = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| ApiError::ValidationError(format!("FX rates file {}: {}", path.display(), e)))?;
+        
+        self.import_csv(&csv)
+            .await
+            .map_err(|e| match e {
+                ApiError::ValidationError(reason) => {
+                    ApiError::ValidationError(format!("FX rates file {}: {}", path.display(), reason))
+                }
+                other => other,
+            })
+    }
+}
+
+fn parse_rates_csv(csv: &str) -> Result<Vec<FxRateInput>, ApiError> {
+    let mut rates = Vec::new();
+    let mut lines_by_rate = HashMap::new();
+    let mut first_row = true;
+    
+    for (index, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Only the first row that isn't blank or a comment can be the header
+        if std::mem::take(&mut first_row) && line.to_ascii_lowercase().starts_with("currency") {
+            continue;
+        }
+        
+        let invalid = |reason: &str| {
+            ApiError::ValidationError(format!("Line {}: {}", index + 1, reason))
+        };
+        
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [currency, rate_date, usd_rate] = fields[..] else {
+            return Err(invalid("expected currency,rate_date,usd_rate"));
+        };
+        
+        let rate = FxRateInput {
+            currency: currency.to_ascii_uppercase(),
+            rate_date: NaiveDate::parse_from_str(rate_date, "%Y-%m-%d")
+                .map_err(|_| invalid("rate_date must be YYYY-MM-DD"))?,
+            usd_rate: Money::from_str(usd_rate)
+                .map_err(|_| invalid("usd_rate must be a decimal number"))?,
+        };
+        rate.validate().map_err(|e| invalid(&e.to_string()))?;
+        if let Some(first) = lines_by_rate.insert((rate.currency.clone(), rate.rate_date), index + 1) {
+            return Err(invalid(&format!("duplicates the rate on line {}", first)));
+        }
+        
+        rates.push(rate);
+    }
+    
+    Ok(rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_after_blank_lines_and_comments_is_skipped() {
+        let csv = "\n# ECB reference rates\n\ncurrency,rate_date,usd_rate\nEUR,2026-01-01,1.0842\n";
+
+        let rates = parse_rates_csv(csv).unwrap();
+
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0].currency, "EUR");
+        assert_eq!(rates[0].usd_rate, Money::from_str("1.0842").unwrap());
+    }
+
+    #[test]
+    fn header_is_only_recognized_as_the_first_row() {
+        let csv = "EUR,2026-01-01,1.0842\ncurrency,rate_date,usd_rate\n";
+
+        assert!(matches!(parse_rates_csv(csv), Err(ApiError::ValidationError(msg)) if msg.starts_with("Line 2:")));
+    }
+
+    #[test]
+    fn duplicate_currency_and_date_names_both_lines() {
+        let csv = "EUR,2026-01-01,1.0842\nGBP,2026-01-01,1.27\neur,2026-01-01,1.09\n";
+
+        assert!(matches!(
+            parse_rates_csv(csv),
+            Err(ApiError::ValidationError(msg)) if msg == "Line 3: duplicates the rate on line 1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn duplicate_currency_and_date_is_rejected_before_the_database() {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let rate = |usd_rate: &str| FxRateInput {
+            currency: "EUR".to_string(),
+            rate_date: NaiveDate::from_ymd_opt(2026, 1, 1).unwrap(),
+            usd_rate: Money::from_str(usd_rate).unwrap(),
+        };
+
+        let result = FxService::new(&pool).upsert_rates(vec![rate("1.08"), rate("1.09")]).await;
+
+        assert!(matches!(
+            result,
+            Err(ApiError::ValidationError(msg)) if msg == "Rate 1: EUR already has a rate for 2026-01-01"
+        ));
+    }
+
+    #[test]
+    fn errors_name_the_line_of_the_file() {
+        let csv = "currency,rate_date,usd_rate\n\nEUR,01/01/2026,1.0842\n";
+
+        assert!(matches!(parse_rates_csv(csv), Err(ApiError::ValidationError(msg)) if msg.starts_with("Line 3:")));
+    }
+}
@@ -1,3 +1,10 @@
 pub mod usage_service;
+pub mod fx_service;
 pub mod prediction_service;
-// pub mod alert_service;
\ No newline at end of file
+pub mod price_file;
+pub mod pricing_service;
+pub mod providers;
+pub mod proxy_service;
+pub mod alert_service;
+pub mod export_service;
+pub mod s3_client;
\ No newline at end of file
@@ -1,13 +1,25 @@
-use sqlx::PgPool;
-use chrono::{Duration, Utc};
+use sqlx::{types::Json, PgPool};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::prelude::ToPrimitive;
 use uuid::Uuid;
 
 use crate::{
+    models::calendar::Calendar,
+    models::money::{round_to_scale, Money},
     models::prediction::Prediction,
-    db::repositories::UsageRepository,
+    models::tag::Tags,
+    db::repositories::{UsageRepository, UserRepository},
     errors::ApiError,
+    utils::helpers::format_currency,
 };
 
+struct Forecast {
+    daily: Money,
+    weekly: Money,
+    monthly: Money,
+    confidence: f64,
+}
+
 pub struct PredictionService<'a> {
     pool: &'a PgPool,
 }
@@ -17,17 +29,148 @@ impl<'a> PredictionService<'a> {
         Self { pool }
     }
     
+    /// Predicts spend from the last 30 local days of usage, limited to usage
+    /// carrying all of `tags` when given. Days follow the user's timezone
+    /// unless `timezone` overrides it.
     pub async fn generate_prediction(
         &self,
         user_id: Uuid,
         api_key_id: Option<Uuid>,
+        tags: Option<&Tags>,
+        timezone: Option<&str>,
     ) -> Result<Prediction, ApiError> {
-        let repo = UsageRepository::new(self.pool);
+        let user_repo = UserRepository::new(self.pool);
+        let currency = user_repo.reporting_currency(user_id).await?;
+        let calendar = user_repo
+            .calendar(user_id)
+            .await?
+            .with_overrides(timezone, None)
+            .map_err(ApiError::ValidationError)?;
+        
+        let now = Utc::now();
+        let forecast = self.forecast(user_id, api_key_id, tags, &calendar, &currency, now).await?;
+        
+        let prediction = sqlx::query_as::<_, Prediction>(
+            r#"
+            INSERT INTO predictions (
+                id, user_id, api_key_id, prediction_date,
+                predicted_daily_cost, predicted_weekly_cost, predicted_monthly_cost,
+                confidence_score, model_used, created_at, currency, tags
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING *
+            "#
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(api_key_id)
+        .bind(now)
+        .bind(forecast.daily)
+        .bind(forecast.weekly)
+        .bind(forecast.monthly)
+        .bind(forecast.confidence)
+        .bind("linear_regression")
+        .bind(now)
+        .bind(&currency)
+        .bind(Json(tags.cloned().unwrap_or_default()))
+        .fetch_one(self.pool)
+        .await?;
+        
+        tracing::info!(
+            "Generated prediction for user {}: {}/day",
+            user_id,
+            format_currency(forecast.daily, &currency)
+        );
         
-        let thirty_days_ago = Utc::now() - Duration::days(30);
+        Ok(prediction)
+    }
+    
+    /// Recomputes the predictions flagged stale by late or re-priced usage,
+    /// from the 30 days before each one's date as the data now stands, and
+    /// closes the recompute windows queued so far. Returns how many were
+    /// refreshed; the ones that fail stay stale for the next run.
+    pub async fn refresh_stale_predictions(&self) -> Result<usize, ApiError> {
+        // Claim the queue first, so usage flagged while this runs marks its
+        // predictions stale again instead of being lost
+        sqlx::query("UPDATE usage_recompute_windows SET processed_at = NOW() WHERE processed_at IS NULL")
+            .execute(self.pool)
+            .await?;
+        
+        let stale = sqlx::query_as::<_, Prediction>(
+            "UPDATE predictions SET is_stale = false WHERE is_stale RETURNING *"
+        )
+        .fetch_all(self.pool)
+        .await?;
+        
+        let mut refreshed = 0;
+        for prediction in &stale {
+            match self.refresh(prediction).await {
+                Ok(()) => refreshed += 1,
+                Err(e) => {
+                    tracing::warn!("Stale prediction {} not refreshed: {:?}", prediction.id, e);
+                    sqlx::query("UPDATE predictions SET is_stale = true WHERE id = $1")
+                        .bind(prediction.id)
+                        .execute(self.pool)
+                        .await?;
+                }
+            }
+        }
+        
+        Ok(refreshed)
+    }
+    
+    async fn refresh(&self, prediction: &Prediction) -> Result<(), ApiError> {
+        let calendar = UserRepository::new(self.pool).calendar(prediction.user_id).await?;
+        let tags = Some(&prediction.tags.0).filter(|tags| !tags.is_empty());
+        
+        let forecast = self
+            .forecast(
+                prediction.user_id,
+                prediction.api_key_id,
+                tags,
+                &calendar,
+                &prediction.currency,
+                prediction.prediction_date,
+            )
+            .await?;
+        
+        sqlx::query(
+            r#"
+            UPDATE predictions
+            SET predicted_daily_cost = $2,
+                predicted_weekly_cost = $3,
+                predicted_monthly_cost = $4,
+                confidence_score = $5
+            WHERE id = $1
+            "#
+        )
+        .bind(prediction.id)
+        .bind(forecast.daily)
+        .bind(forecast.weekly)
+        .bind(forecast.monthly)
+        .bind(forecast.confidence)
+        .execute(self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// Forecasts spend in `currency` from the 30 local days of usage before
+    /// `as_of`.
+    async fn forecast(
+        &self,
+        user_id: Uuid,
+        api_key_id: Option<Uuid>,
+        tags: Option<&Tags>,
+        calendar: &Calendar,
+        currency: &str,
+        as_of: DateTime<Utc>,
+    ) -> Result<Forecast, ApiError> {
+        let repo = UsageRepository::new(self.pool);
+        let thirty_days_ago = calendar.start_of(calendar.date_of(as_of) - Duration::days(30));
         
         let daily_costs = repo
-            .get_daily_costs(user_id, thirty_days_ago, api_key_id)
+            .get_daily_costs(user_id, thirty_days_ago, as_of, api_key_id, tags, calendar, currency)
             .await?;
         
         if daily_costs.len() < 7 {
@@ -36,67 +179,41 @@ impl<'a> PredictionService<'a> {
             ));
         }
         
-        let costs: Vec<f64> = daily_costs.iter().map(|(_, cost)| *cost).collect();
-        let avg_daily_cost = costs.iter().sum::<f64>() / costs.len() as f64;
+        let costs: Vec<Money> = daily_costs.iter().map(|(_, cost)| *cost).collect();
+        let avg_daily_cost = average(&costs);
         
         // Calculate trend
         let recent_window = 7.min(costs.len());
-        let recent_costs: Vec<f64> = costs.iter().rev().take(recent_window).cloned().collect();
-        let older_costs: Vec<f64> = costs.iter().take(recent_window).cloned().collect();
+        let recent_costs: Vec<Money> = costs.iter().rev().take(recent_window).cloned().collect();
+        let older_costs: Vec<Money> = costs.iter().take(recent_window).cloned().collect();
         
-        let recent_avg = recent_costs.iter().sum::<f64>() / recent_costs.len() as f64;
-        let older_avg = older_costs.iter().sum::<f64>() / older_costs.len() as f64;
+        let recent_avg = average(&recent_costs);
+        let older_avg = average(&older_costs);
         
-        let trend_factor = if older_avg > 0.0 {
+        let trend_factor = if older_avg > Money::ZERO {
             recent_avg / older_avg
         } else {
-            1.0
+            Money::ONE
         };
         
-        let daily_prediction = avg_daily_cost * trend_factor;
-        let weekly_prediction = daily_prediction * 7.0;
-        let monthly_prediction = daily_prediction * 30.0;
+        let daily_prediction = round_to_scale(avg_daily_cost * trend_factor);
         
-        let variance = self.calculate_variance(&costs);
-        let coefficient_of_variation = if avg_daily_cost > 0.0 {
-            variance / avg_daily_cost
+        // The confidence score is a statistic, not an amount, so f64 is precise enough
+        let cost_values: Vec<f64> = costs.iter().filter_map(|c| c.to_f64()).collect();
+        let avg_daily_value = avg_daily_cost.to_f64().unwrap_or_default();
+        let variance = self.calculate_variance(&cost_values);
+        let coefficient_of_variation = if avg_daily_value > 0.0 {
+            variance / avg_daily_value
         } else {
             1.0
         };
         
-        let confidence = (1.0 - coefficient_of_variation.min(0.5)).max(0.5).min(0.95);
-        
-        let prediction = sqlx::query_as::<_, Prediction>(
-            r#"
-            INSERT INTO predictions (
-                id, user_id, api_key_id, prediction_date,
-                predicted_daily_cost, predicted_weekly_cost, predicted_monthly_cost,
-                confidence_score, model_used, created_at
-            )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
-            RETURNING *
-            "#
-        )
-        .bind(Uuid::new_v4())
-        .bind(user_id)
-        .bind(api_key_id)
-        .bind(Utc::now())
-        .bind(daily_prediction)
-        .bind(weekly_prediction)
-        .bind(monthly_prediction)
-        .bind(confidence)
-        .bind("linear_regression")
-        .bind(Utc::now())
-        .fetch_one(self.pool)
-        .await?;
-        
-        tracing::info!(
-            "Generated prediction for user {}: ${:.2}/day",
-            user_id,
-            daily_prediction
-        );
-        
-        Ok(prediction)
+        Ok(Forecast {
+            daily: daily_prediction,
+            weekly: daily_prediction * Money::from(7),
+            monthly: daily_prediction * Money::from(30),
+            confidence: (1.0 - coefficient_of_variation.min(0.5)).clamp(0.5, 0.95),
+        })
     }
     
     fn calculate_variance(&self, values: &[f64]) -> f64 {
@@ -112,4 +229,105 @@ impl<'a> PredictionService<'a> {
         
         (squared_diffs / values.len() as f64).sqrt()
     }
-}
\ No newline at end of file
+}
+
+fn average(values: &[Money]) -> Money {
+    values.iter().sum::<Money>() / Money::from(values.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::{create_api_key, create_user, TestDatabase};
+
+    async fn insert_usage(pool: &PgPool, user_id: Uuid, api_key_id: Uuid, timestamp: DateTime<Utc>, cost: i64) {
+        sqlx::query(
+            "INSERT INTO api_usage (user_id, api_key_id, timestamp, cost, list_cost) VALUES ($1, $2, $3, $4, $4)"
+        )
+        .bind(user_id)
+        .bind(api_key_id)
+        .bind(timestamp)
+        .bind(Money::from(cost))
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn late_usage_refreshes_the_predictions_it_made_stale() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let now = Utc::now();
+        for days_ago in 1..=10 {
+            insert_usage(&db.pool, user_id, api_key_id, now - Duration::days(days_ago), 1).await;
+        }
+        let service = PredictionService::new(&db.pool);
+        let prediction = service.generate_prediction(user_id, None, None, None).await.unwrap();
+
+        let late_at = now - Duration::days(2);
+        insert_usage(&db.pool, user_id, api_key_id, late_at, 10).await;
+        UsageRepository::new(&db.pool)
+            .mark_for_recompute(&[(user_id, api_key_id, late_at)], "late_data")
+            .await
+            .unwrap();
+        let flagged: bool = sqlx::query_scalar("SELECT is_stale FROM predictions WHERE id = $1")
+            .bind(prediction.id)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+
+        let refreshed = service.refresh_stale_predictions().await.unwrap();
+        let after = sqlx::query_as::<_, Prediction>("SELECT * FROM predictions WHERE id = $1")
+            .bind(prediction.id)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM usage_recompute_windows WHERE processed_at IS NULL")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        db.drop().await;
+
+        assert!(flagged);
+        assert_eq!(refreshed, 1);
+        assert!(!after.is_stale);
+        assert!(after.predicted_daily_cost > prediction.predicted_daily_cost);
+        assert_eq!(after.prediction_date, prediction.prediction_date);
+        assert_eq!(pending, 0);
+    }
+
+    #[tokio::test]
+    async fn prediction_that_can_no_longer_be_computed_stays_stale() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let now = Utc::now();
+        for days_ago in 1..=10 {
+            insert_usage(&db.pool, user_id, api_key_id, now - Duration::days(days_ago), 1).await;
+        }
+        let service = PredictionService::new(&db.pool);
+        let prediction = service.generate_prediction(user_id, None, None, None).await.unwrap();
+        sqlx::query("DELETE FROM api_usage WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE predictions SET is_stale = true WHERE id = $1")
+            .bind(prediction.id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let refreshed = service.refresh_stale_predictions().await.unwrap();
+        let still_stale: bool = sqlx::query_scalar("SELECT is_stale FROM predictions WHERE id = $1")
+            .bind(prediction.id)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        db.drop().await;
+
+        assert_eq!(refreshed, 0);
+        assert!(still_stale);
+    }
+}
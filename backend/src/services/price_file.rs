@@ -0,0 +1,278 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::{
+    errors::ApiError,
+    models::model_price::CreateModelPriceRequest,
+    models::money::{base_currency, Money},
+    services::pricing_service::PricingService,
+};
+
+/// Layout of a pricing file: a `prices` list of catalog entries, in the same
+/// shape as `POST /model-prices`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PriceFile {
+    prices: Vec<PriceFileEntry>,
+}
+
+/// A catalog entry of a pricing file. Unlike the API request, unknown fields
+/// are rejected, so a misspelled price in a hand-edited file is not silently
+/// dropped.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PriceFileEntry {
+    provider: String,
+    model_pattern: String,
+    cost_per_1k_input: Money,
+    cost_per_1k_output: Money,
+    cost_per_1k_cached_input: Option<Money>,
+    cost_per_1k_cache_write: Option<Money>,
+    cost_per_1k_reasoning: Option<Money>,
+    #[serde(default)]
+    unit_prices: HashMap<String, Money>,
+    effective_from: Option<DateTime<Utc>>,
+    effective_to: Option<DateTime<Utc>>,
+    #[serde(default = "base_currency")]
+    currency: String,
+}
+
+impl From<PriceFileEntry> for CreateModelPriceRequest {
+    fn from(entry: PriceFileEntry) -> Self {
+        Self {
+            provider: entry.provider,
+            model_pattern: entry.model_pattern,
+            cost_per_1k_input: entry.cost_per_1k_input,
+            cost_per_1k_output: entry.cost_per_1k_output,
+            cost_per_1k_cached_input: entry.cost_per_1k_cached_input,
+            cost_per_1k_cache_write: entry.cost_per_1k_cache_write,
+            cost_per_1k_reasoning: entry.cost_per_1k_reasoning,
+            unit_prices: entry.unit_prices,
+            effective_from: entry.effective_from,
+            effective_to: entry.effective_to,
+            currency: entry.currency,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PriceFileFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl PriceFileFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Reads and validates a pricing file, returning its entries with providers
+/// and patterns normalized as the admin API does.
+pub async fn load_price_file(path: &Path) -> Result<Vec<CreateModelPriceRequest>, ApiError> {
+    let invalid = |reason: String| {
+        ApiError::ValidationError(format!("Pricing file {}: {}", path.display(), reason))
+    };
+
+    let format = PriceFileFormat::from_path(path)
+        .ok_or_else(|| invalid("expected a .json, .toml, .yaml or .yml file".to_string()))?;
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| invalid(e.to_string()))?;
+
+    parse_price_file(&contents, format).map_err(invalid)
+}
+
+/// Parses the file, reporting syntax and type errors with the path of the
+/// offending field and, where the format gives one, its line and column.
+fn parse_price_file(
+    contents: &str,
+    format: PriceFileFormat,
+) -> Result<Vec<CreateModelPriceRequest>, String> {
+    let file: PriceFile = match format {
+        PriceFileFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_str(contents);
+            serde_path_to_error::deserialize(&mut deserializer).map_err(|e| e.to_string())?
+        }
+        PriceFileFormat::Toml => {
+            let deserializer = toml::Deserializer::new(contents);
+            serde_path_to_error::deserialize(deserializer).map_err(|e| e.to_string())?
+        }
+        PriceFileFormat::Yaml => {
+            let deserializer = serde_yaml::Deserializer::from_str(contents);
+            serde_path_to_error::deserialize(deserializer).map_err(|e| e.to_string())?
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let mut prices = Vec::with_capacity(file.prices.len());
+
+    for (index, entry) in file.prices.into_iter().enumerate() {
+        let mut price = CreateModelPriceRequest::from(entry);
+        price.provider = PricingService::catalog_provider(price.provider.trim());
+        price.model_pattern = price.model_pattern.trim().to_string();
+
+        let entry = format!("prices[{}] ({} {})", index, price.provider, price.model_pattern);
+        price.validate().map_err(|e| format!("{}: {}", entry, e))?;
+
+        let effective_from = price.effective_from.unwrap_or(DateTime::UNIX_EPOCH);
+        if price.effective_to.is_some_and(|to| to <= effective_from) {
+            return Err(format!("{}: effective_to must be after effective_from", entry));
+        }
+
+        let key = (price.provider.clone(), price.model_pattern.clone(), effective_from);
+        if !seen.insert(key) {
+            return Err(format!("{}: duplicate entry for this effective_from", entry));
+        }
+
+        prices.push(price);
+    }
+
+    Ok(prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn money(value: &str) -> Money {
+        Money::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn json_file_is_parsed_and_normalized() {
+        let contents = r#"{
+            "prices": [
+                {
+                    "provider": " Azure ",
+                    "model_pattern": " gpt-4o* ",
+                    "cost_per_1k_input": "0.0025",
+                    "cost_per_1k_output": 0.01,
+                    "unit_prices": {"images": "0.04"}
+                }
+            ]
+        }"#;
+
+        let prices = parse_price_file(contents, PriceFileFormat::Json).unwrap();
+
+        assert_eq!(prices.len(), 1);
+        assert_eq!(prices[0].provider, "openai");
+        assert_eq!(prices[0].model_pattern, "gpt-4o*");
+        assert_eq!(prices[0].cost_per_1k_input, money("0.0025"));
+        assert_eq!(prices[0].cost_per_1k_output, money("0.01"));
+        assert_eq!(prices[0].unit_prices.get("images"), Some(&money("0.04")));
+        assert_eq!(prices[0].currency, "USD");
+        assert_eq!(prices[0].effective_from, None);
+    }
+
+    #[test]
+    fn toml_file_is_parsed() {
+        let contents = r#"
+            [[prices]]
+            provider = "anthropic"
+            model_pattern = "claude-3-5-sonnet*"
+            cost_per_1k_input = "0.003"
+            cost_per_1k_output = "0.015"
+            effective_from = "2024-10-22T00:00:00Z"
+            currency = "EUR"
+        "#;
+
+        let prices = parse_price_file(contents, PriceFileFormat::Toml).unwrap();
+
+        assert_eq!(prices[0].provider, "anthropic");
+        assert_eq!(prices[0].effective_from, Some("2024-10-22T00:00:00Z".parse().unwrap()));
+        assert_eq!(prices[0].currency, "EUR");
+    }
+
+    #[test]
+    fn yaml_file_is_parsed() {
+        let contents = "
+prices:
+  - provider: google
+    model_pattern: gemini-1.5-pro
+    cost_per_1k_input: 0.00125
+    cost_per_1k_output: 0.005
+    cost_per_1k_cached_input: 0.0003125
+";
+
+        let prices = parse_price_file(contents, PriceFileFormat::Yaml).unwrap();
+
+        assert_eq!(prices[0].provider, "gemini");
+        assert_eq!(prices[0].cost_per_1k_cached_input, Some(money("0.0003125")));
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected_with_their_path() {
+        let misspelled = r#"{"prices": [{"provider": "openai", "model_pattern": "gpt-4o",
+            "cost_per_1k_input": 1, "cost_per_1k_output": 1, "cost_per_1k_reasonning": 2}]}"#;
+        let extra_section = r#"{"prices": [], "rules": []}"#;
+
+        let error = parse_price_file(misspelled, PriceFileFormat::Json).unwrap_err();
+
+        assert!(error.starts_with("prices[0]"), "{}", error);
+        assert!(error.contains("cost_per_1k_reasonning"), "{}", error);
+        assert!(parse_price_file(extra_section, PriceFileFormat::Json).is_err());
+    }
+
+    #[test]
+    fn syntax_errors_report_their_line() {
+        let contents = "[[prices]]\nprovider = \"openai\"\nmodel_pattern = gpt-4o\n";
+
+        let error = parse_price_file(contents, PriceFileFormat::Toml).unwrap_err();
+
+        assert!(error.contains("line 3"), "{}", error);
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        let negative = r#"{"prices": [{"provider": "openai", "model_pattern": "gpt-4o",
+            "cost_per_1k_input": -1, "cost_per_1k_output": 1}]}"#;
+        let empty_range = r#"{"prices": [{"provider": "openai", "model_pattern": "gpt-4o",
+            "cost_per_1k_input": 1, "cost_per_1k_output": 1,
+            "effective_from": "2025-01-01T00:00:00Z", "effective_to": "2025-01-01T00:00:00Z"}]}"#;
+
+        let error = parse_price_file(negative, PriceFileFormat::Json).unwrap_err();
+
+        assert!(error.starts_with("prices[0] (openai gpt-4o)"), "{}", error);
+        assert!(parse_price_file(empty_range, PriceFileFormat::Json)
+            .unwrap_err()
+            .contains("effective_to must be after effective_from"));
+    }
+
+    #[test]
+    fn duplicate_versions_are_rejected_after_normalization() {
+        let contents = r#"{"prices": [
+            {"provider": "openai", "model_pattern": "gpt-4o", "cost_per_1k_input": 1, "cost_per_1k_output": 1},
+            {"provider": "Azure", "model_pattern": "gpt-4o ", "cost_per_1k_input": 2, "cost_per_1k_output": 2},
+            {"provider": "openai", "model_pattern": "gpt-4o", "cost_per_1k_input": 3, "cost_per_1k_output": 3,
+             "effective_from": "2025-01-01T00:00:00Z"}
+        ]}"#;
+
+        let error = parse_price_file(contents, PriceFileFormat::Json).unwrap_err();
+
+        assert!(error.starts_with("prices[1]"), "{}", error);
+        assert!(error.contains("duplicate entry"), "{}", error);
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert!(matches!(PriceFileFormat::from_path(Path::new("prices.JSON")), Some(PriceFileFormat::Json)));
+        assert!(matches!(PriceFileFormat::from_path(Path::new("prices.yml")), Some(PriceFileFormat::Yaml)));
+        assert!(matches!(PriceFileFormat::from_path(Path::new("prices.toml")), Some(PriceFileFormat::Toml)));
+        assert!(PriceFileFormat::from_path(Path::new("prices.csv")).is_none());
+        assert!(PriceFileFormat::from_path(Path::new("prices")).is_none());
+    }
+}
@@ -0,0 +1,850 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use chrono::{DateTime, Months, NaiveDate, NaiveTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::repositories::{FxRepository, PricingRepository, UsageRepository},
+    errors::ApiError,
+    models::api_key::ApiKey,
+    models::api_usage::{ApiUsage, BillingUnit, NewUsage},
+    models::fx_rate::FxTable,
+    models::money::{Money, BASE_CURRENCY},
+    models::model_price::{
+        CostRecalculation, CostRecalculationRequest, CreateModelPriceRequest, ModelPrice,
+        PriceFileReport, PriceSource, PriceUpdate, Pricing, UpdateModelPriceRequest,
+    },
+    models::pricing_rule::{
+        apply_discount, billing_month_start, CreatePricingRuleRequest, PricingRule, TrueUpReport,
+    },
+    services::{price_file::load_price_file, providers},
+};
+
+/// Price list seeded into an empty catalog on first run.
+const DEFAULT_PRICES: &str = include_str!("../../pricing/default_prices.json");
+
+/// Usage rows re-priced per page during a cost recalculation.
+const RECALCULATION_PAGE_SIZE: i64 = 1000;
+
+/// Most recent recalculations returned by the audit listing.
+const MAX_LISTED_RECALCULATIONS: i64 = 100;
+
+/// Catalog entries for a set of providers, and the pricing rules of the keys
+/// involved, loaded once per ingestion request.
+pub struct PriceCatalog {
+    prices: Vec<ModelPrice>,
+    rules: Vec<PricingRule>,
+    /// Rates for converting catalog prices into the currency of a key's
+    /// price overrides.
+    fx: FxTable,
+    /// Tokens of the rows priced under each tiered rule that are not stored
+    /// yet, with the timestamps of their calls.
+    unsaved_volumes: HashMap<Uuid, Vec<(DateTime<Utc>, i64)>>,
+}
+
+impl PriceCatalog {
+    fn new(prices: Vec<ModelPrice>, rules: Vec<PricingRule>, fx: FxTable) -> Self {
+        Self { prices, rules, fx, unsaved_volumes: HashMap::new() }
+    }
+    
+    /// The most specific catalog entry for a model of the given provider that
+    /// was in effect at `at`.
+    pub fn find(&self, provider: &str, model: &str, at: DateTime<Utc>) -> Option<&ModelPrice> {
+        let provider = PricingService::catalog_provider(provider);
+        // Gemini reports models as `models/<name>`
+        let model = model.strip_prefix("models/").unwrap_or(model);
+        
+        self.prices
+            .iter()
+            .filter(|p| p.provider == provider && p.is_effective_at(at))
+            .filter_map(|p| p.match_rank(model).map(|rank| (rank, p)))
+            .max_by_key(|(rank, _)| *rank)
+            .map(|(_, p)| p)
+    }
+    
+    /// Prices for a call of `model` made with `api_key` at `at`.
+    pub fn pricing_for(&self, api_key: &ApiKey, model: &str, at: DateTime<Utc>) -> Pricing {
+        Pricing::resolve(self.find(&api_key.provider, model, at), api_key, &self.fx, at.date_naive())
+    }
+    
+    /// The most specific pricing rule of a key in effect at `at`. Of equally
+    /// specific rules, the one that took effect last wins, then the one
+    /// created last.
+    pub fn rule_for(&self, api_key_id: Uuid, model: &str, at: DateTime<Utc>) -> Option<&PricingRule> {
+        let model = model.strip_prefix("models/").unwrap_or(model);
+        
+        self.rules
+            .iter()
+            .filter(|r| r.api_key_id == api_key_id && r.is_effective_at(at))
+            .filter_map(|r| r.match_rank(model).map(|rank| (rank, r)))
+            .max_by_key(|(rank, r)| (*rank, r.effective_from, r.created_at, r.id))
+            .map(|(_, r)| r)
+    }
+    
+    /// Applies the most specific pricing rule of the row's key in effect at its
+    /// timestamp, using the tier reached by the month's volume before the call:
+    /// stored rows plus rows priced earlier with this catalog that are not
+    /// stored yet. Rows may come in any order. `list_cost` must already be set.
+    pub async fn apply_rules(
+        &mut self,
+        repo: &UsageRepository<'_>,
+        usage: &mut NewUsage,
+    ) -> Result<(), ApiError> {
+        self.apply_rules_in_order(repo, std::slice::from_mut(usage)).await
+    }
+    
+    /// Applies pricing rules to rows in the order of their timestamps, so each
+    /// row's tier counts the rows before it. The stored volumes are loaded with
+    /// one query per tiered rule rather than one per row.
+    pub async fn apply_rules_in_order(
+        &mut self,
+        repo: &UsageRepository<'_>,
+        rows: &mut [NewUsage],
+    ) -> Result<(), ApiError> {
+        let rule_ids: Vec<Option<Uuid>> = rows
+            .iter()
+            .map(|row| self.rule_for(row.api_key_id, &row.model_name, row.timestamp).map(|rule| rule.id))
+            .collect();
+        
+        let mut tiered_rows: HashMap<Uuid, Vec<usize>> = HashMap::new();
+        for (index, rule_id) in rule_ids.iter().enumerate() {
+            if let Some(rule) = rule_id.and_then(|id| self.rule(id)).filter(|rule| !rule.tiers.is_empty()) {
+                tiered_rows.entry(rule.id).or_default().push(index);
+            }
+        }
+        
+        let mut stored_volumes: HashMap<usize, i64> = HashMap::new();
+        for (rule_id, indexes) in tiered_rows {
+            let Some(rule) = self.rule(rule_id) else { continue };
+            let windows: Vec<(DateTime<Utc>, DateTime<Utc>)> = indexes
+                .iter()
+                .map(|&index| (billing_month_start(rows[index].timestamp), rows[index].timestamp))
+                .collect();
+            let volumes = repo
+                .get_token_volumes(rule.api_key_id, rule.model_pattern.as_deref(), &windows)
+                .await?;
+            stored_volumes.extend(indexes.into_iter().zip(volumes));
+        }
+        
+        let mut order: Vec<usize> = (0..rows.len()).collect();
+        order.sort_by_key(|&index| rows[index].timestamp);
+        
+        for index in order {
+            if let Some(rule_id) = rule_ids[index] {
+                let stored = stored_volumes.get(&index).copied().unwrap_or(0);
+                self.apply_rule(rule_id, stored, &mut rows[index]);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    fn rule(&self, rule_id: Uuid) -> Option<&PricingRule> {
+        self.rules.iter().find(|rule| rule.id == rule_id)
+    }
+    
+    /// Prices a row under a rule, given the stored volume of the month before
+    /// it, and counts it towards the volume of the rows after it.
+    fn apply_rule(&mut self, rule_id: Uuid, stored_volume: i64, usage: &mut NewUsage) {
+        let Some(rule) = self.rule(rule_id) else {
+            return;
+        };
+        
+        let tiered = !rule.tiers.is_empty();
+        let volume = if tiered {
+            let month_start = billing_month_start(usage.timestamp);
+            let unsaved: i64 = self
+                .unsaved_volumes
+                .get(&rule_id)
+                .into_iter()
+                .flatten()
+                .filter(|(timestamp, _)| month_start <= *timestamp && *timestamp < usage.timestamp)
+                .map(|(_, tokens)| tokens)
+                .sum();
+            stored_volume + unsaved
+        } else {
+            0
+        };
+        
+        usage.pricing_rule_id = Some(rule_id);
+        usage.discount_percent = rule.discount_percent_for(volume, usage.is_batch);
+        usage.cost = apply_discount(usage.list_cost, usage.discount_percent);
+        
+        if tiered && usage.billing_unit == BillingUnit::Tokens {
+            self.unsaved_volumes
+                .entry(rule_id)
+                .or_default()
+                .push((usage.timestamp, i64::from(usage.total_tokens)));
+        }
+    }
+    
+    /// Forgets the rows priced so far once they are stored, since the stored
+    /// volume now includes them.
+    pub fn rows_stored(&mut self) {
+        self.unsaved_volumes.clear();
+    }
+}
+
+pub struct PricingService<'a> {
+    pool: &'a PgPool,
+}
+
+impl<'a> PricingService<'a> {
+    pub fn new(pool: &'a PgPool) -> Self {
+        Self { pool }
+    }
+    
+    /// Provider name as stored in the catalog, so aliases like `azure` share
+    /// the `openai` prices.
+    pub fn catalog_provider(provider: &str) -> String {
+        providers::canonical_provider(provider)
+            .map(str::to_string)
+            .unwrap_or_else(|| provider.to_ascii_lowercase())
+    }
+    
+    /// Loads the catalog entries for the providers of the given keys.
+    pub async fn catalog_for<'k>(
+        &self,
+        api_keys: impl IntoIterator<Item = &'k ApiKey>,
+    ) -> Result<PriceCatalog, ApiError> {
+        let api_keys: Vec<&ApiKey> = api_keys.into_iter().collect();
+        let key_ids: Vec<Uuid> = api_keys.iter().map(|key| key.id).collect();
+        let mut providers: Vec<String> = api_keys
+            .iter()
+            .map(|key| Self::catalog_provider(&key.provider))
+            .collect();
+        providers.sort();
+        providers.dedup();
+        
+        if providers.is_empty() {
+            return Ok(PriceCatalog::new(Vec::new(), Vec::new(), FxTable::default()));
+        }
+        
+        let repo = PricingRepository::new(self.pool);
+        let prices = repo.find_for_providers(&providers).await?;
+        let rules = repo.find_rules_for_keys(&key_ids).await?;
+        
+        // Rates are only needed to convert catalog prices for keys whose
+        // overrides are in another currency
+        let mut currencies: Vec<String> = api_keys
+            .iter()
+            .filter(|key| key.has_price_overrides())
+            .map(|key| key.currency.clone())
+            .collect();
+        let fx = if currencies.is_empty() {
+            FxTable::default()
+        } else {
+            currencies.extend(prices.iter().map(|price| price.currency.clone()));
+            currencies.sort();
+            currencies.dedup();
+            FxTable::new(FxRepository::new(self.pool).list_rates_for(&currencies).await?)
+        };
+        
+        Ok(PriceCatalog::new(prices, rules, fx))
+    }
+    
+    /// Loads the whole catalog, for imports that may span many providers.
+    pub async fn full_catalog(&self) -> Result<PriceCatalog, ApiError> {
+        let repo = PricingRepository::new(self.pool);
+        let prices = repo.list_prices(None).await?;
+        let rules = repo.list_all_rules().await?;
+        let fx = FxTable::new(FxRepository::new(self.pool).list_rates(None).await?);
+        
+        Ok(PriceCatalog::new(prices, rules, fx))
+    }
+    
+    pub async fn list_prices(&self, provider: Option<&str>) -> Result<Vec<ModelPrice>, ApiError> {
+        let provider = provider.map(Self::catalog_provider);
+        
+        PricingRepository::new(self.pool)
+            .list_prices(provider.as_deref())
+            .await
+    }
+    
+    pub async fn get_price(&self, price_id: Uuid) -> Result<ModelPrice, ApiError> {
+        PricingRepository::new(self.pool)
+            .get_price(price_id)
+            .await?
+            .ok_or_else(|| ApiError::NotFound("Model price not found".to_string()))
+    }
+    
+    pub async fn create_price(
+        &self,
+        mut req: CreateModelPriceRequest,
+    ) -> Result<ModelPrice, ApiError> {
+        req.validate()?;
+        req.provider = Self::catalog_provider(req.provider.trim());
+        req.model_pattern = req.model_pattern.trim().to_string();
+        
+        let effective_from = req.effective_from.unwrap_or(DateTime::UNIX_EPOCH);
+        if req.effective_to.is_some_and(|to| to <= effective_from) {
+            return Err(ApiError::ValidationError(
+                "effective_to must be after effective_from".to_string(),
+            ));
+        }
+        
+        PricingRepository::new(self.pool)
+            .create_price(&req, effective_from, PriceSource::Api)
+            .await?
+            .ok_or_else(|| {
+                ApiError::ValidationError(format!(
+                    "A price for {} model pattern '{}' already applies in this period",
+                    req.provider, req.model_pattern
+                ))
+            })
+    }
+    
+    pub async fn update_price(
+        &self,
+        price_id: Uuid,
+        req: UpdateModelPriceRequest,
+    ) -> Result<ModelPrice, ApiError> {
+        req.validate()?;
+        
+        PricingRepository::new(self.pool)
+            .update_price(price_id, &req)
+            .await?
+            .ok_or_else(|| ApiError::NotFound("Model price not found".to_string()))
+    }
+    
+    pub async fn delete_price(&self, price_id: Uuid) -> Result<(), ApiError> {
+        if !PricingRepository::new(self.pool).delete_price(price_id).await? {
+            return Err(ApiError::NotFound("Model price not found".to_string()));
+        }
+        
+        Ok(())
+    }
+    
+    /// Seeds the bundled default prices if the catalog is empty. Returns the
+    /// number of prices inserted.
+    pub async fn seed_defaults(&self) -> Result<usize, ApiError> {
+        let repo = PricingRepository::new(self.pool);
+        if repo.count_prices().await? > 0 {
+            return Ok(0);
+        }
+        
+        let defaults: Vec<CreateModelPriceRequest> = serde_json::from_str(DEFAULT_PRICES)
+            .map_err(|e| ApiError::Internal(format!("Invalid bundled price list: {}", e)))?;
+        
+        let mut inserted = 0;
+        for price in &defaults {
+            let effective_from = price.effective_from.unwrap_or(DateTime::UNIX_EPOCH);
+            if repo.create_price(price, effective_from, PriceSource::Default).await?.is_some() {
+                inserted += 1;
+            }
+        }
+        
+        Ok(inserted)
+    }
+    
+    /// Upserts the prices of a pricing file into the catalog, matching entries
+    /// to versions by provider, pattern and `effective_from`, and reports what
+    /// changed. The whole file is validated before anything is written, and
+    /// its changes are committed together.
+    pub async fn apply_price_file(&self, path: &Path) -> Result<PriceFileReport, ApiError> {
+        let mut entries = load_price_file(path).await?;
+        // Earlier versions first, so adding a later one closes them
+        entries.sort_by_key(|price| price.effective_from.unwrap_or(DateTime::UNIX_EPOCH));
+        
+        let repo = PricingRepository::new(self.pool);
+        let existing: HashMap<(String, String, DateTime<Utc>), ModelPrice> = repo
+            .list_prices(None)
+            .await?
+            .into_iter()
+            .map(|price| {
+                let key = (price.provider.clone(), price.model_pattern.clone(), price.effective_from);
+                (key, price)
+            })
+            .collect();
+        
+        let mut report = PriceFileReport {
+            file: path.display().to_string(),
+            ..Default::default()
+        };
+        let mut listed = HashSet::new();
+        let mut tx = repo.begin().await?;
+        
+        for entry in &entries {
+            let effective_from = entry.effective_from.unwrap_or(DateTime::UNIX_EPOCH);
+            let key = (entry.provider.clone(), entry.model_pattern.clone(), effective_from);
+            let conflict = || {
+                format!(
+                    "{} {} from {} overlaps another version",
+                    entry.provider, entry.model_pattern, effective_from
+                )
+            };
+            
+            match existing.get(&key) {
+                Some(previous) => {
+                    let changed_fields = entry.changed_fields(previous);
+                    if changed_fields.is_empty() {
+                        report.unchanged += 1;
+                    } else {
+                        match tx.replace_price(previous.id, entry).await? {
+                            Some(current) => report.updated.push(PriceUpdate {
+                                previous: previous.clone(),
+                                current,
+                                changed_fields,
+                            }),
+                            None => report.conflicts.push(conflict()),
+                        }
+                    }
+                }
+                None => match tx.create_price(entry, effective_from, PriceSource::File).await? {
+                    Some(created) => report.added.push(created),
+                    None => report.conflicts.push(conflict()),
+                },
+            }
+            
+            listed.insert(key);
+        }
+        
+        tx.commit().await?;
+        
+        report.not_in_file = existing
+            .into_iter()
+            .filter(|(key, price)| price.source == PriceSource::File.as_str() && !listed.contains(key))
+            .map(|(_, price)| price)
+            .collect();
+        
+        Ok(report)
+    }
+    
+    /// Re-prices usage in a date range with the prices in effect at each row's
+    /// timestamp, then queues the affected rollup windows and predictions for
+    /// recomputation with reason `price_correction`.
+    ///
+    /// The audit record is written first and its counters updated after each
+    /// page. Pages are updated on their own, so an interrupted run, left
+    /// without `completed_at`, can simply be repeated. Rows no price applies to
+    /// keep their cost.
+    pub async fn recalculate_costs(
+        &self,
+        requested_by: Uuid,
+        req: CostRecalculationRequest,
+    ) -> Result<CostRecalculation, ApiError> {
+        req.validate()?;
+        if req.end <= req.start {
+            return Err(ApiError::ValidationError("end must be after start".to_string()));
+        }
+        
+        let usage_repo = UsageRepository::new(self.pool);
+        let fx_repo = FxRepository::new(self.pool);
+        let pricing_repo = PricingRepository::new(self.pool);
+        let catalog = self.full_catalog().await?;
+        let mut api_keys: HashMap<Uuid, ApiKey> = HashMap::new();
+        
+        let mut recalculation = CostRecalculation {
+            id: Uuid::new_v4(),
+            requested_by: Some(requested_by),
+            range_start: req.start,
+            range_end: req.end,
+            api_key_id: req.api_key_id,
+            model_name: req.model_name.clone(),
+            reason: req.reason.clone(),
+            rows_scanned: 0,
+            rows_changed: 0,
+            rows_unpriced: 0,
+            old_total_cost: Money::ZERO,
+            new_total_cost: Money::ZERO,
+            created_at: Utc::now(),
+            completed_at: None,
+        };
+        if !req.dry_run {
+            recalculation = pricing_repo.create_recalculation(&recalculation).await?;
+        }
+        let mut after_id = 0;
+        
+        loop {
+            let rows = usage_repo
+                .get_usage_for_recalculation(
+                    req.start,
+                    req.end,
+                    req.api_key_id,
+                    req.model_name.as_deref(),
+                    after_id,
+                    RECALCULATION_PAGE_SIZE,
+                )
+                .await?;
+            let Some(last) = rows.last() else {
+                break;
+            };
+            after_id = last.id;
+            
+            let mut missing_keys: Vec<Uuid> = rows
+                .iter()
+                .map(|row| row.api_key_id)
+                .filter(|id| !api_keys.contains_key(id))
+                .collect();
+            missing_keys.sort();
+            missing_keys.dedup();
+            if !missing_keys.is_empty() {
+                for key in usage_repo.get_api_keys_by_ids(&missing_keys).await? {
+                    api_keys.insert(key.id, key);
+                }
+            }
+            
+            let mut changed_ids = Vec::new();
+            let mut changed_list_costs = Vec::new();
+            let mut changed_costs = Vec::new();
+            let mut changed_currencies = Vec::new();
+            let mut affected = Vec::new();
+            let mut old_costs = Vec::with_capacity(rows.len());
+            let mut new_costs = Vec::with_capacity(rows.len());
+            
+            for row in &rows {
+                recalculation.rows_scanned += 1;
+                let date = row.timestamp.date_naive();
+                old_costs.push((row.cost, row.currency.clone(), date));
+                
+                let new_cost = api_keys.get(&row.api_key_id).and_then(|api_key| {
+                    let pricing = catalog.pricing_for(
+                        api_key,
+                        row.model_name.as_deref().unwrap_or_default(),
+                        row.timestamp,
+                    );
+                    // A price too large for the row leaves it as it was, like a missing one
+                    ApiUsage::calculate_cost(&row.billed_quantity()?, &pricing)
+                        .ok()
+                        .flatten()
+                        .map(|list_cost| (list_cost, pricing.currency))
+                });
+                
+                let Some((list_cost, currency)) = new_cost else {
+                    recalculation.rows_unpriced += 1;
+                    new_costs.push((row.cost, row.currency.clone(), date));
+                    continue;
+                };
+                
+                // The contract discount that applied when the call was made stays
+                let new_cost = apply_discount(list_cost, row.discount_percent);
+                new_costs.push((new_cost, currency.clone(), date));
+                if new_cost != row.cost || list_cost != row.list_cost || currency != row.currency {
+                    recalculation.rows_changed += 1;
+                    changed_ids.push(row.id);
+                    changed_list_costs.push(list_cost);
+                    changed_costs.push(new_cost);
+                    changed_currencies.push(currency);
+                    affected.push((row.user_id, row.api_key_id, row.timestamp));
+                }
+            }
+            
+            recalculation.old_total_cost += fx_repo.sum_converted(&old_costs, BASE_CURRENCY).await?;
+            recalculation.new_total_cost += fx_repo.sum_converted(&new_costs, BASE_CURRENCY).await?;
+            
+            if !req.dry_run {
+                usage_repo
+                    .update_costs(&changed_ids, &changed_list_costs, &changed_costs, &changed_currencies)
+                    .await?;
+                usage_repo.mark_for_recompute(&affected, "price_correction").await?;
+                pricing_repo.update_recalculation(&recalculation).await?;
+            }
+        }
+        
+        if !req.dry_run {
+            recalculation.completed_at = Some(Utc::now());
+            pricing_repo.update_recalculation(&recalculation).await?;
+        }
+        
+        Ok(recalculation)
+    }
+    
+    pub async fn list_recalculations(&self) -> Result<Vec<CostRecalculation>, ApiError> {
+        PricingRepository::new(self.pool)
+            .list_recalculations(MAX_LISTED_RECALCULATIONS)
+            .await
+    }
+    
+    pub async fn list_rules(&self, user_id: Uuid, api_key_id: Uuid) -> Result<Vec<PricingRule>, ApiError> {
+        UsageRepository::new(self.pool).get_api_key(user_id, api_key_id).await?;
+        
+        PricingRepository::new(self.pool).list_rules(api_key_id).await
+    }
+    
+    pub async fn create_rule(
+        &self,
+        user_id: Uuid,
+        api_key_id: Uuid,
+        mut req: CreatePricingRuleRequest,
+    ) -> Result<PricingRule, ApiError> {
+        req.validate()?;
+        UsageRepository::new(self.pool).get_api_key(user_id, api_key_id).await?;
+        
+        req.name = req.name.trim().to_string();
+        req.model_pattern = req.model_pattern.map(|pattern| pattern.trim().to_string());
+        req.tiers.sort_by_key(|tier| tier.from_tokens);
+        
+        let effective_from = req.effective_from.unwrap_or(DateTime::UNIX_EPOCH);
+        if req.effective_to.is_some_and(|to| to <= effective_from) {
+            return Err(ApiError::ValidationError(
+                "effective_to must be after effective_from".to_string(),
+            ));
+        }
+        
+        PricingRepository::new(self.pool)
+            .create_rule(api_key_id, &req, effective_from)
+            .await
+    }
+    
+    pub async fn delete_rule(&self, user_id: Uuid, api_key_id: Uuid, rule_id: Uuid) -> Result<(), ApiError> {
+        UsageRepository::new(self.pool).get_api_key(user_id, api_key_id).await?;
+        
+        if !PricingRepository::new(self.pool).delete_rule(api_key_id, rule_id).await? {
+            return Err(ApiError::NotFound("Pricing rule not found".to_string()));
+        }
+        
+        Ok(())
+    }
+    
+    /// Compares list-price and contracted cost per API key over a billing
+    /// month (`YYYY-MM`, by default the last full month), charging any
+    /// minimum commitment the contracted cost falls short of.
+    pub async fn true_up(&self, user_id: Uuid, month: Option<&str>) -> Result<TrueUpReport, ApiError> {
+        let period_start = match month {
+            Some(month) => NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+                .map_err(|_| ApiError::ValidationError("month must be formatted as YYYY-MM".to_string()))?
+                .and_time(NaiveTime::MIN)
+                .and_utc(),
+            None => billing_month_start(Utc::now())
+                .checked_sub_months(Months::new(1))
+                .ok_or_else(|| ApiError::Internal("Invalid billing month".to_string()))?,
+        };
+        let period_end = period_start
+            .checked_add_months(Months::new(1))
+            .ok_or_else(|| ApiError::ValidationError("month is out of range".to_string()))?;
+        
+        let mut api_keys = PricingRepository::new(self.pool)
+            .get_true_up(user_id, period_start, period_end)
+            .await?;
+        for line in &mut api_keys {
+            line.savings = line.list_cost - line.contracted_cost;
+            line.shortfall = (line.minimum_commitment - line.contracted_cost).max(Money::ZERO);
+            line.amount_due = line.contracted_cost + line.shortfall;
+        }
+        
+        Ok(TrueUpReport {
+            month: period_start.format("%Y-%m").to_string(),
+            period_start,
+            period_end,
+            api_keys,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::IngestionConfig;
+    use crate::db::testing::{create_api_key, create_user, TestDatabase};
+    use crate::services::usage_service::UsageService;
+    use chrono::TimeZone;
+    use futures_util::stream;
+    use sqlx::types::Json;
+    use tokio::sync::broadcast;
+
+    fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+    }
+
+    fn price(provider: &str, pattern: &str, input: i64) -> ModelPrice {
+        ModelPrice {
+            id: Uuid::new_v4(),
+            provider: provider.to_string(),
+            model_pattern: pattern.to_string(),
+            cost_per_1k_input: Money::from(input),
+            cost_per_1k_output: Money::from(input),
+            cost_per_1k_cached_input: None,
+            cost_per_1k_cache_write: None,
+            cost_per_1k_reasoning: None,
+            unit_prices: Json(HashMap::new()),
+            created_at: at(2024, 1, 1),
+            updated_at: at(2024, 1, 1),
+            effective_from: DateTime::<Utc>::MIN_UTC,
+            effective_to: None,
+            currency: BASE_CURRENCY.to_string(),
+            source: PriceSource::Default.as_str().to_string(),
+        }
+    }
+
+    fn rule(api_key_id: Uuid, pattern: Option<&str>, effective_from: DateTime<Utc>, created_at: DateTime<Utc>) -> PricingRule {
+        PricingRule {
+            id: Uuid::new_v4(),
+            api_key_id,
+            name: pattern.unwrap_or("all models").to_string(),
+            model_pattern: pattern.map(str::to_string),
+            discount_percent: Money::ZERO,
+            batch_discount_percent: Money::ZERO,
+            tiers: Json(Vec::new()),
+            minimum_commitment: None,
+            effective_from,
+            effective_to: None,
+            created_at,
+            updated_at: created_at,
+        }
+    }
+
+    fn catalog(prices: Vec<ModelPrice>) -> PriceCatalog {
+        PriceCatalog::new(prices, Vec::new(), FxTable::default())
+    }
+
+    fn found_input(catalog: &PriceCatalog, provider: &str, model: &str, when: DateTime<Utc>) -> Option<Money> {
+        catalog.find(provider, model, when).map(|p| p.cost_per_1k_input)
+    }
+
+    #[test]
+    fn most_specific_pattern_wins() {
+        let catalog = catalog(vec![
+            price("openai", "gpt-4o*", 1),
+            price("openai", "gpt-4o-mini*", 2),
+            price("openai", "gpt-4o-mini", 3),
+        ]);
+        let now = at(2025, 1, 1);
+
+        assert_eq!(found_input(&catalog, "openai", "gpt-4o-2024-08-06", now), Some(Money::from(1)));
+        assert_eq!(found_input(&catalog, "openai", "gpt-4o-mini-2024-07-18", now), Some(Money::from(2)));
+        assert_eq!(found_input(&catalog, "openai", "gpt-4o-mini", now), Some(Money::from(3)));
+        assert_eq!(found_input(&catalog, "openai", "o1", now), None);
+    }
+
+    #[test]
+    fn provider_aliases_share_the_catalog() {
+        let catalog = catalog(vec![price("openai", "gpt-4o", 1), price("gemini", "gemini-1.5-pro", 2)]);
+        let now = at(2025, 1, 1);
+
+        assert_eq!(found_input(&catalog, "Azure_OpenAI", "gpt-4o", now), Some(Money::from(1)));
+        assert_eq!(found_input(&catalog, "google", "gemini-1.5-pro", now), Some(Money::from(2)));
+        assert_eq!(found_input(&catalog, "anthropic", "gpt-4o", now), None);
+    }
+
+    #[test]
+    fn gemini_model_prefix_is_ignored() {
+        let catalog = catalog(vec![price("gemini", "gemini-1.5-flash*", 1)]);
+
+        assert_eq!(
+            found_input(&catalog, "gemini", "models/gemini-1.5-flash-002", at(2025, 1, 1)),
+            Some(Money::from(1))
+        );
+    }
+
+    #[test]
+    fn price_version_in_effect_at_the_call_is_used() {
+        let mut old = price("openai", "gpt-4o", 1);
+        old.effective_to = Some(at(2024, 10, 1));
+        let mut new = price("openai", "gpt-4o", 2);
+        new.effective_from = at(2024, 10, 1);
+        let catalog = catalog(vec![old, new]);
+
+        assert_eq!(found_input(&catalog, "openai", "gpt-4o", at(2024, 9, 30)), Some(Money::from(1)));
+        assert_eq!(found_input(&catalog, "openai", "gpt-4o", at(2024, 10, 1)), Some(Money::from(2)));
+    }
+
+    #[test]
+    fn specific_pattern_out_of_effect_falls_back_to_prefix() {
+        let mut exact = price("openai", "gpt-4o-mini", 2);
+        exact.effective_from = at(2025, 1, 1);
+        let catalog = catalog(vec![price("openai", "gpt-4o*", 1), exact]);
+
+        assert_eq!(found_input(&catalog, "openai", "gpt-4o-mini", at(2024, 12, 31)), Some(Money::from(1)));
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        let key = Uuid::new_v4();
+        let rules = vec![
+            rule(key, None, at(2024, 1, 1), at(2024, 1, 1)),
+            rule(key, Some("gpt-4o*"), at(2024, 1, 1), at(2024, 1, 1)),
+            rule(Uuid::new_v4(), Some("gpt-4o-mini"), at(2024, 1, 1), at(2024, 1, 1)),
+        ];
+        let catalog = PriceCatalog::new(Vec::new(), rules.clone(), FxTable::default());
+        let now = at(2025, 1, 1);
+
+        assert_eq!(catalog.rule_for(key, "gpt-4o-mini", now).map(|r| r.id), Some(rules[1].id));
+        assert_eq!(catalog.rule_for(key, "models/gemini-1.5-pro", now).map(|r| r.id), Some(rules[0].id));
+        assert!(catalog.rule_for(Uuid::new_v4(), "gpt-4o-mini", now).is_none());
+    }
+
+    #[test]
+    fn equally_specific_rules_are_decided_by_effective_date_then_creation() {
+        let key = Uuid::new_v4();
+        let earlier = rule(key, Some("gpt-4o*"), at(2024, 1, 1), at(2024, 6, 1));
+        let later = rule(key, Some("GPT-4O*"), at(2024, 3, 1), at(2024, 2, 1));
+        let recreated = rule(key, Some("gpt-4o*"), at(2024, 3, 1), at(2024, 5, 1));
+        let now = at(2025, 1, 1);
+
+        for rules in [
+            vec![earlier.clone(), later.clone(), recreated.clone()],
+            vec![recreated.clone(), later.clone(), earlier.clone()],
+            vec![later.clone(), recreated.clone(), earlier.clone()],
+        ] {
+            let catalog = PriceCatalog::new(Vec::new(), rules, FxTable::default());
+
+            assert_eq!(catalog.rule_for(key, "gpt-4o", now).map(|r| r.id), Some(recreated.id));
+        }
+    }
+
+    #[tokio::test]
+    async fn tiers_count_stored_and_earlier_imported_rows_before_each_call() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        sqlx::query(
+            r#"INSERT INTO pricing_rules (id, api_key_id, name, tiers)
+               VALUES ($1, $2, 'volume', '[{"from_tokens": 1000, "discount_percent": 10}, {"from_tokens": 2000, "discount_percent": 20}]')"#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(api_key_id)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        for (day, tokens) in [(2, 900), (20, 5000)] {
+            sqlx::query(
+                "INSERT INTO api_usage (user_id, api_key_id, timestamp, input_tokens, total_tokens, cost, list_cost) VALUES ($1, $2, $3, $4, $4, 0, 0)",
+            )
+            .bind(user_id)
+            .bind(api_key_id)
+            .bind(at(2026, 3, day))
+            .bind(tokens)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        }
+        let line = |day: u32, month: u32, tokens: i32| {
+            serde_json::json!({
+                "api_key_id": api_key_id,
+                "model_name": "gpt-4o",
+                "input_tokens": tokens,
+                "timestamp": at(2026, month, day) + chrono::Duration::hours(12),
+            })
+            .to_string()
+        };
+        // Out of order and split over chunks of two
+        let body = [line(10, 3, 200), line(5, 3, 100), line(25, 3, 10), line(1, 4, 10)].join("\n");
+        let limits = IngestionConfig {
+            max_batch_size: 10,
+            import_chunk_size: 2,
+            max_clock_skew_secs: 300,
+            max_lateness_secs: 3600,
+        };
+        let (ws_tx, _) = broadcast::channel(16);
+
+        UsageService::new(&db.pool, &ws_tx)
+            .import_ndjson(user_id, stream::iter([Ok::<_, std::io::Error>(axum::body::Bytes::from(body))]), &limits)
+            .await
+            .unwrap();
+        let discounts: Vec<(DateTime<Utc>, Money)> = sqlx::query_as(
+            "SELECT timestamp, discount_percent FROM api_usage WHERE pricing_rule_id IS NOT NULL ORDER BY timestamp",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        db.drop().await;
+
+        let percents: Vec<Money> = discounts.into_iter().map(|(_, percent)| percent).collect();
+        // 5 March follows 900 stored tokens; 10 March also the 100 imported
+        // before it; 25 March the 5000 stored on the 20th; April starts over
+        assert_eq!(percents, vec![Money::ZERO, Money::from(10), Money::from(20), Money::ZERO]);
+    }
+}
@@ -0,0 +1,97 @@
+use serde_json::Value;
+
+use crate::models::api_usage::ErrorType;
+
+use super::{request_model, string_at, token_count, ParsedUsage, ResponseParser};
+
+/// Messages API bodies.
+pub struct AnthropicParser;
+
+impl ResponseParser for AnthropicParser {
+    fn provider(&self) -> &'static str {
+        "Anthropic"
+    }
+    
+    fn expected_fields(&self) -> &'static [&'static str] {
+        &["model", "usage.input_tokens", "usage.output_tokens"]
+    }
+    
+    fn parse(&self, response: &Value, request: Option<&Value>) -> Option<ParsedUsage> {
+        if response.get("type").and_then(Value::as_str) == Some("error") {
+            let error_type = string_at(response, "/error/type").unwrap_or_default();
+            
+            return Some(ParsedUsage {
+                model_name: request_model(request),
+                status_code: error_status(&error_type),
+                ..Default::default()
+            });
+        }
+        
+        let usage = response.get("usage")?;
+        let uncached_input = token_count(usage, "/input_tokens")?;
+        let output_tokens = token_count(usage, "/output_tokens")?;
+        let cache_read = token_count(usage, "/cache_read_input_tokens");
+        let cache_write = token_count(usage, "/cache_creation_input_tokens");
+        
+        // Anthropic reports cache reads and writes separately from input_tokens;
+        // fold them in so input_tokens is the full prompt size.
+        let input_tokens = uncached_input
+            .saturating_add(cache_read.unwrap_or(0))
+            .saturating_add(cache_write.unwrap_or(0));
+        
+        let finish_reason = string_at(response, "/stop_reason");
+        
+        Some(ParsedUsage {
+            model_name: string_at(response, "/model").or_else(|| request_model(request)),
+            input_tokens,
+            output_tokens,
+            cached_input_tokens: cache_read,
+            cache_write_tokens: cache_write,
+            reasoning_tokens: None,
+            error_type: (finish_reason.as_deref() == Some("refusal"))
+                .then_some(ErrorType::ContentFilter),
+            finish_reason,
+            status_code: 200,
+            request_id: string_at(response, "/id"),
+        })
+    }
+    
+    /// Streams open with a `message_start` event carrying the message and its
+    /// input usage; `message_delta` events then report the output so far.
+    fn parse_stream(&self, events: &[Value], request: Option<&Value>) -> Option<ParsedUsage> {
+        if let Some(error) = events.iter().find(|e| e.get("type").and_then(Value::as_str) == Some("error")) {
+            return self.parse(error, request);
+        }
+        
+        let message = events
+            .iter()
+            .find(|e| e.get("type").and_then(Value::as_str) == Some("message_start"))
+            .and_then(|e| e.get("message"))?;
+        let mut parsed = self.parse(message, request)?;
+        
+        for delta in events.iter().filter(|e| e.get("type").and_then(Value::as_str) == Some("message_delta")) {
+            if let Some(output_tokens) = token_count(delta, "/usage/output_tokens") {
+                parsed.output_tokens = output_tokens;
+            }
+            if let Some(stop_reason) = string_at(delta, "/delta/stop_reason") {
+                parsed.error_type = (stop_reason == "refusal").then_some(ErrorType::ContentFilter);
+                parsed.finish_reason = Some(stop_reason);
+            }
+        }
+        
+        Some(parsed)
+    }
+}
+
+fn error_status(error_type: &str) -> i32 {
+    match error_type {
+        "invalid_request_error" => 400,
+        "authentication_error" => 401,
+        "permission_error" => 403,
+        "not_found_error" => 404,
+        "request_too_large" => 413,
+        "rate_limit_error" => 429,
+        "overloaded_error" => 529,
+        _ => 500,
+    }
+}
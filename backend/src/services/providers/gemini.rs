@@ -0,0 +1,60 @@
+use serde_json::Value;
+
+use crate::models::api_usage::ErrorType;
+
+use super::{request_model, string_at, token_count, ParsedUsage, ResponseParser};
+
+/// `generateContent` bodies from the Gemini API.
+pub struct GeminiParser;
+
+impl ResponseParser for GeminiParser {
+    fn provider(&self) -> &'static str {
+        "Gemini"
+    }
+    
+    fn expected_fields(&self) -> &'static [&'static str] {
+        &[
+            "modelVersion",
+            "usageMetadata.promptTokenCount",
+            "usageMetadata.candidatesTokenCount",
+        ]
+    }
+    
+    fn parse(&self, response: &Value, request: Option<&Value>) -> Option<ParsedUsage> {
+        if let Some(error) = response.get("error").filter(|e| e.is_object()) {
+            return Some(ParsedUsage {
+                model_name: request_model(request),
+                status_code: token_count(error, "/code").unwrap_or(500),
+                ..Default::default()
+            });
+        }
+        
+        let usage = response.get("usageMetadata")?;
+        let input_tokens = token_count(usage, "/promptTokenCount")?;
+        let candidates = token_count(usage, "/candidatesTokenCount").unwrap_or(0);
+        let thoughts = token_count(usage, "/thoughtsTokenCount");
+        
+        // Thinking tokens are billed as output but reported outside candidatesTokenCount
+        let output_tokens = candidates.saturating_add(thoughts.unwrap_or(0));
+        
+        let finish_reason = string_at(response, "/candidates/0/finishReason");
+        let blocked = response.pointer("/promptFeedback/blockReason").is_some()
+            || matches!(
+                finish_reason.as_deref(),
+                Some("SAFETY" | "PROHIBITED_CONTENT" | "BLOCKLIST" | "SPII" | "RECITATION")
+            );
+        
+        Some(ParsedUsage {
+            model_name: string_at(response, "/modelVersion").or_else(|| request_model(request)),
+            input_tokens,
+            output_tokens,
+            cached_input_tokens: token_count(usage, "/cachedContentTokenCount"),
+            cache_write_tokens: None,
+            reasoning_tokens: thoughts,
+            finish_reason,
+            status_code: 200,
+            error_type: blocked.then_some(ErrorType::ContentFilter),
+            request_id: string_at(response, "/responseId"),
+        })
+    }
+}
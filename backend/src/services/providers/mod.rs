@@ -0,0 +1,282 @@
+//! Parsers that turn raw provider response bodies into usage fields.
+//!
+//! Each provider gets its own [`ResponseParser`]; [`parser_for`] picks one from
+//! the `provider` stored on the API key.
+
+pub mod anthropic;
+pub mod gemini;
+pub mod openai;
+
+use serde_json::Value;
+
+use crate::errors::ApiError;
+use crate::models::api_usage::ErrorType;
+
+/// Usage extracted from a provider response.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedUsage {
+    pub model_name: Option<String>,
+    pub input_tokens: i32,
+    pub output_tokens: i32,
+    pub cached_input_tokens: Option<i32>,
+    pub cache_write_tokens: Option<i32>,
+    pub reasoning_tokens: Option<i32>,
+    pub finish_reason: Option<String>,
+    pub status_code: i32,
+    /// Failures the status code alone does not reveal, such as content filtering.
+    pub error_type: Option<ErrorType>,
+    /// The provider's own id for the response, usable as an idempotency key.
+    pub request_id: Option<String>,
+}
+
+pub trait ResponseParser: Send + Sync {
+    /// Provider name used in error messages.
+    fn provider(&self) -> &'static str;
+    
+    /// Fields a successful response must contain, listed when parsing fails.
+    fn expected_fields(&self) -> &'static [&'static str];
+    
+    /// Extracts usage from a response body. Returns `None` when the body is not
+    /// a shape this parser understands.
+    fn parse(&self, response: &Value, request: Option<&Value>) -> Option<ParsedUsage>;
+    
+    /// Extracts usage from the events of a streamed response. By default the
+    /// last event this parser understands wins, which suits providers whose
+    /// final event carries the totals.
+    fn parse_stream(&self, events: &[Value], request: Option<&Value>) -> Option<ParsedUsage> {
+        events.iter().rev().find_map(|event| self.parse(event, request))
+    }
+}
+
+/// Maps a provider name as stored on `api_keys.provider` to the canonical name
+/// used for parsers and proxy upstreams.
+pub fn canonical_provider(provider: &str) -> Option<&'static str> {
+    match provider.to_ascii_lowercase().as_str() {
+        "openai" | "azure" | "azure_openai" => Some("openai"),
+        "anthropic" => Some("anthropic"),
+        "gemini" | "google" => Some("gemini"),
+        _ => None,
+    }
+}
+
+/// Returns the parser for a provider name as stored on `api_keys.provider`.
+pub fn parser_for(provider: &str) -> Option<&'static dyn ResponseParser> {
+    match canonical_provider(provider)? {
+        "openai" => Some(&openai::OpenAiParser),
+        "anthropic" => Some(&anthropic::AnthropicParser),
+        "gemini" => Some(&gemini::GeminiParser),
+        _ => None,
+    }
+}
+
+/// Parses a response, turning an unknown shape into a validation error that
+/// lists the fields the provider's parser looks for.
+pub fn parse_response(
+    parser: &dyn ResponseParser,
+    response: &Value,
+    request: Option<&Value>,
+) -> Result<ParsedUsage, ApiError> {
+    parser.parse(response, request).ok_or_else(|| unrecognized_response(parser))
+}
+
+/// The validation error for a response missing fields the parser needs.
+pub fn unrecognized_response(parser: &dyn ResponseParser) -> ApiError {
+    ApiError::ValidationError(format!(
+        "Unrecognized {} response: expected fields {}",
+        parser.provider(),
+        parser.expected_fields().join(", ")
+    ))
+}
+
+/// The JSON `data:` payloads of a server-sent events body, skipping the
+/// `[DONE]` marker and anything that is not JSON.
+pub fn sse_events(body: &[u8]) -> Vec<Value> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim)
+        .filter(|data| *data != "[DONE]")
+        .filter_map(|data| serde_json::from_str(data).ok())
+        .collect()
+}
+
+pub(crate) fn token_count(value: &Value, pointer: &str) -> Option<i32> {
+    value
+        .pointer(pointer)
+        .and_then(Value::as_i64)
+        .and_then(|n| i32::try_from(n).ok())
+}
+
+pub(crate) fn string_at(value: &Value, pointer: &str) -> Option<String> {
+    value.pointer(pointer).and_then(Value::as_str).map(str::to_string)
+}
+
+/// The `model` field of the original request body, if one was supplied.
+pub(crate) fn request_model(request: Option<&Value>) -> Option<String> {
+    request.and_then(|r| string_at(r, "/model"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn events(body: &str) -> Vec<Value> {
+        sse_events(body.as_bytes())
+    }
+
+    #[test]
+    fn sse_events_skip_done_marker_and_non_data_lines() {
+        let parsed = events("event: ping\ndata: {\"a\":1}\n\n: comment\ndata:{\"b\":2}\n\ndata: [DONE]\n\n");
+
+        assert_eq!(parsed, vec![json!({"a": 1}), json!({"b": 2})]);
+    }
+
+    #[test]
+    fn openai_chat_response_reads_cached_and_reasoning_tokens() {
+        let response = json!({
+            "id": "chatcmpl-1",
+            "model": "o3-mini",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+            "usage": {
+                "prompt_tokens": 120,
+                "completion_tokens": 80,
+                "total_tokens": 200,
+                "prompt_tokens_details": {"cached_tokens": 100},
+                "completion_tokens_details": {"reasoning_tokens": 64}
+            }
+        });
+
+        let parsed = openai::OpenAiParser.parse(&response, None).unwrap();
+
+        assert_eq!(parsed.model_name.as_deref(), Some("o3-mini"));
+        assert_eq!((parsed.input_tokens, parsed.output_tokens), (120, 80));
+        assert_eq!(parsed.cached_input_tokens, Some(100));
+        assert_eq!(parsed.reasoning_tokens, Some(64));
+        assert_eq!(parsed.cache_write_tokens, None);
+        assert_eq!(parsed.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(parsed.request_id.as_deref(), Some("chatcmpl-1"));
+        assert_eq!(parsed.status_code, 200);
+    }
+
+    #[test]
+    fn anthropic_response_folds_cache_reads_and_writes_into_input() {
+        let response = json!({
+            "id": "msg_1",
+            "type": "message",
+            "model": "claude-sonnet-4",
+            "stop_reason": "end_turn",
+            "usage": {
+                "input_tokens": 10,
+                "cache_read_input_tokens": 200,
+                "cache_creation_input_tokens": 50,
+                "output_tokens": 30
+            }
+        });
+
+        let parsed = anthropic::AnthropicParser.parse(&response, None).unwrap();
+
+        assert_eq!(parsed.model_name.as_deref(), Some("claude-sonnet-4"));
+        assert_eq!((parsed.input_tokens, parsed.output_tokens), (260, 30));
+        assert_eq!(parsed.cached_input_tokens, Some(200));
+        assert_eq!(parsed.cache_write_tokens, Some(50));
+        assert_eq!(parsed.reasoning_tokens, None);
+        assert_eq!(parsed.finish_reason.as_deref(), Some("end_turn"));
+        assert_eq!(parsed.request_id.as_deref(), Some("msg_1"));
+    }
+
+    #[test]
+    fn gemini_response_bills_thoughts_as_output() {
+        let response = json!({
+            "responseId": "g1",
+            "modelVersion": "gemini-2.5-pro",
+            "candidates": [{"content": {"parts": [{"text": "Hi"}]}, "finishReason": "STOP"}],
+            "usageMetadata": {
+                "promptTokenCount": 90,
+                "cachedContentTokenCount": 40,
+                "candidatesTokenCount": 20,
+                "thoughtsTokenCount": 15,
+                "totalTokenCount": 125
+            }
+        });
+
+        let parsed = gemini::GeminiParser.parse(&response, None).unwrap();
+
+        assert_eq!(parsed.model_name.as_deref(), Some("gemini-2.5-pro"));
+        assert_eq!((parsed.input_tokens, parsed.output_tokens), (90, 35));
+        assert_eq!(parsed.cached_input_tokens, Some(40));
+        assert_eq!(parsed.reasoning_tokens, Some(15));
+        assert_eq!(parsed.cache_write_tokens, None);
+        assert_eq!(parsed.finish_reason.as_deref(), Some("STOP"));
+        assert_eq!(parsed.request_id.as_deref(), Some("g1"));
+    }
+
+    #[test]
+    fn openai_chat_stream_takes_usage_from_last_chunk() {
+        let body = concat!(
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"delta\":{\"content\":\"Hi\"}}],\"usage\":null}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":5}}\n\n",
+            "data: [DONE]\n\n",
+        );
+
+        let parsed = openai::OpenAiParser.parse_stream(&events(body), None).unwrap();
+
+        assert_eq!(parsed.model_name.as_deref(), Some("gpt-4o"));
+        assert_eq!((parsed.input_tokens, parsed.output_tokens), (12, 5));
+    }
+
+    #[test]
+    fn openai_responses_stream_reads_completed_event() {
+        let body = concat!(
+            "data: {\"type\":\"response.output_text.delta\",\"delta\":\"Hi\"}\n\n",
+            "data: {\"type\":\"response.completed\",\"response\":{\"id\":\"r1\",\"model\":\"gpt-4.1\",\"status\":\"completed\",\"usage\":{\"input_tokens\":7,\"output_tokens\":3}}}\n\n",
+        );
+
+        let parsed = openai::OpenAiParser.parse_stream(&events(body), None).unwrap();
+
+        assert_eq!(parsed.model_name.as_deref(), Some("gpt-4.1"));
+        assert_eq!((parsed.input_tokens, parsed.output_tokens), (7, 3));
+    }
+
+    #[test]
+    fn openai_stream_without_usage_is_not_parsed() {
+        let body = "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"delta\":{}}]}\n\ndata: [DONE]\n\n";
+
+        assert!(openai::OpenAiParser.parse_stream(&events(body), None).is_none());
+    }
+
+    #[test]
+    fn anthropic_stream_combines_message_start_and_deltas() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"m1\",\"model\":\"claude-sonnet-4\",\"usage\":{\"input_tokens\":20,\"cache_read_input_tokens\":5,\"output_tokens\":1}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":42}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        let parsed = anthropic::AnthropicParser.parse_stream(&events(body), None).unwrap();
+
+        assert_eq!(parsed.model_name.as_deref(), Some("claude-sonnet-4"));
+        assert_eq!(parsed.input_tokens, 25);
+        assert_eq!(parsed.cached_input_tokens, Some(5));
+        assert_eq!(parsed.output_tokens, 42);
+        assert_eq!(parsed.finish_reason.as_deref(), Some("end_turn"));
+        assert_eq!(parsed.request_id.as_deref(), Some("m1"));
+    }
+
+    #[test]
+    fn gemini_stream_takes_cumulative_usage_from_last_chunk() {
+        let body = concat!(
+            "data: {\"modelVersion\":\"gemini-2.0-flash\",\"usageMetadata\":{\"promptTokenCount\":9,\"candidatesTokenCount\":1}}\r\n\r\n",
+            "data: {\"modelVersion\":\"gemini-2.0-flash\",\"usageMetadata\":{\"promptTokenCount\":9,\"candidatesTokenCount\":30}}\r\n\r\n",
+        );
+
+        let parsed = gemini::GeminiParser.parse_stream(&events(body), None).unwrap();
+
+        assert_eq!((parsed.input_tokens, parsed.output_tokens), (9, 30));
+    }
+}
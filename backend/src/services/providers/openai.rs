@@ -0,0 +1,105 @@
+use serde_json::Value;
+
+use crate::models::api_usage::ErrorType;
+
+use super::{request_model, string_at, token_count, ParsedUsage, ResponseParser};
+
+/// Chat Completions, Responses and Embeddings API bodies.
+pub struct OpenAiParser;
+
+impl ResponseParser for OpenAiParser {
+    fn provider(&self) -> &'static str {
+        "OpenAI"
+    }
+    
+    fn expected_fields(&self) -> &'static [&'static str] {
+        &[
+            "model",
+            "usage.prompt_tokens",
+            "usage.completion_tokens (or usage.input_tokens/usage.output_tokens)",
+        ]
+    }
+    
+    fn parse(&self, response: &Value, request: Option<&Value>) -> Option<ParsedUsage> {
+        if let Some(error) = response.get("error").filter(|e| e.is_object()) {
+            let error_type = error
+                .get("code")
+                .and_then(Value::as_str)
+                .or_else(|| error.get("type").and_then(Value::as_str))
+                .unwrap_or_default();
+            
+            return Some(ParsedUsage {
+                model_name: request_model(request),
+                status_code: error_status(error_type),
+                ..Default::default()
+            });
+        }
+        
+        let usage = response.get("usage")?;
+        let model_name = string_at(response, "/model").or_else(|| request_model(request));
+        let request_id = string_at(response, "/id");
+        
+        // Chat Completions / Embeddings
+        if let Some(input_tokens) = token_count(usage, "/prompt_tokens") {
+            let finish_reason = string_at(response, "/choices/0/finish_reason");
+            
+            return Some(ParsedUsage {
+                model_name,
+                input_tokens,
+                output_tokens: token_count(usage, "/completion_tokens").unwrap_or(0),
+                cached_input_tokens: token_count(usage, "/prompt_tokens_details/cached_tokens"),
+                reasoning_tokens: token_count(usage, "/completion_tokens_details/reasoning_tokens"),
+                finish_reason: finish_reason.clone(),
+                status_code: 200,
+                error_type: content_filter(finish_reason.as_deref()),
+                request_id,
+                ..Default::default()
+            });
+        }
+        
+        // Responses API
+        let input_tokens = token_count(usage, "/input_tokens")?;
+        let output_tokens = token_count(usage, "/output_tokens")?;
+        
+        let finish_reason = string_at(response, "/incomplete_details/reason")
+            .or_else(|| string_at(response, "/status"));
+        
+        Some(ParsedUsage {
+            model_name,
+            input_tokens,
+            output_tokens,
+            cached_input_tokens: token_count(usage, "/input_tokens_details/cached_tokens"),
+            reasoning_tokens: token_count(usage, "/output_tokens_details/reasoning_tokens"),
+            error_type: content_filter(finish_reason.as_deref()),
+            finish_reason,
+            status_code: 200,
+            request_id,
+            ..Default::default()
+        })
+    }
+    
+    /// Chat Completions streams carry usage in their last chunk when
+    /// `stream_options.include_usage` is set; Responses API streams end with a
+    /// `response.completed` event wrapping the full response.
+    fn parse_stream(&self, events: &[Value], request: Option<&Value>) -> Option<ParsedUsage> {
+        events
+            .iter()
+            .rev()
+            .find_map(|event| self.parse(event.get("response").unwrap_or(event), request))
+    }
+}
+
+fn content_filter(finish_reason: Option<&str>) -> Option<ErrorType> {
+    (finish_reason == Some("content_filter")).then_some(ErrorType::ContentFilter)
+}
+
+fn error_status(error_type: &str) -> i32 {
+    match error_type {
+        "rate_limit_exceeded" | "insufficient_quota" => 429,
+        "invalid_api_key" | "authentication_error" => 401,
+        "permission_denied" => 403,
+        "model_not_found" | "not_found" => 404,
+        "server_error" => 500,
+        _ => 400,
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+};
+use futures_util::{stream, StreamExt};
+use serde_json::Value;
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    config::settings::IngestionConfig,
+    config::Config,
+    db::repositories::UsageRepository,
+    errors::ApiError,
+    middleware::auth::IngestionAuth,
+    models::api_key::ApiKey,
+    models::api_usage::{RawUsageRequest, RecordedUsage},
+    services::providers::{self, ParsedUsage},
+    services::usage_service::UsageService,
+    utils::crypto::decrypt_api_key,
+    websocket::WsMessage,
+};
+
+/// Request headers that are never forwarded upstream: hop-by-hop headers and
+/// the caller's own credentials, which are replaced by the provider key.
+const STRIPPED_REQUEST_HEADERS: &[&str] = &[
+    "host",
+    "authorization",
+    "x-api-key",
+    "x-goog-api-key",
+    "content-length",
+    "connection",
+    "transfer-encoding",
+    "accept-encoding",
+    "idempotency-key",
+];
+
+const STRIPPED_RESPONSE_HEADERS: &[&str] = &[
+    "content-length",
+    "connection",
+    "transfer-encoding",
+    "content-encoding",
+];
+
+pub struct ProxiedRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+pub struct ProxiedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Body,
+}
+
+/// Forwards requests to a provider with the stored key and meters the response.
+///
+/// Upstream responses are passed on chunk by chunk, so streamed (SSE)
+/// completions reach the caller as they are generated. A copy of the body is
+/// kept and metered once the response has been sent.
+pub struct ProxyService<'a> {
+    pool: &'a PgPool,
+    ws_tx: &'a broadcast::Sender<WsMessage>,
+    client: &'a reqwest::Client,
+    config: &'a Config,
+}
+
+impl<'a> ProxyService<'a> {
+    pub fn new(
+        pool: &'a PgPool,
+        ws_tx: &'a broadcast::Sender<WsMessage>,
+        client: &'a reqwest::Client,
+        config: &'a Config,
+    ) -> Self {
+        Self { pool, ws_tx, client, config }
+    }
+    
+    pub async fn forward(
+        &self,
+        auth: &IngestionAuth,
+        api_key_id: Uuid,
+        req: ProxiedRequest,
+    ) -> Result<ProxiedResponse, ApiError> {
+        let user_id = auth.user_id;
+        UsageService::new(self.pool, self.ws_tx)
+            .with_key_scope(auth.api_key_scope.as_deref())
+            .check_key_scope(api_key_id)?;
+        
+        let repo = UsageRepository::new(self.pool);
+        let api_key = repo.get_api_key(user_id, api_key_id).await?;
+        
+        if !api_key.is_active {
+            return Err(ApiError::ValidationError("API key is inactive".to_string()));
+        }
+        
+        let provider = providers::canonical_provider(&api_key.provider).ok_or_else(|| {
+            ApiError::ValidationError(format!("Provider '{}' cannot be proxied", api_key.provider))
+        })?;
+        let base_url = self.config.proxy.upstream_for(provider).ok_or_else(|| {
+            ApiError::ValidationError(format!("No upstream configured for provider '{}'", provider))
+        })?;
+        
+        let secret = decrypt_api_key(&api_key.encrypted_key)?;
+        
+        let mut url = format!("{}/{}", base_url, req.path.trim_start_matches('/'));
+        if let Some(query) = req.query.as_deref().and_then(forwardable_query) {
+            url.push('?');
+            url.push_str(&query);
+        }
+        
+        let mut headers = req.headers.clone();
+        for name in STRIPPED_REQUEST_HEADERS {
+            headers.remove(*name);
+        }
+        inject_credentials(&mut headers, provider, &secret)?;
+        
+        let started = Instant::now();
+        
+        let upstream = self
+            .client
+            .request(req.method.clone(), &url)
+            .headers(headers)
+            .body(req.body.clone())
+            .send()
+            .await
+            .map_err(|e| ApiError::BadGateway(format!("{} request failed: {}", provider, e)))?;
+        
+        let status = upstream.status();
+        let mut response_headers = upstream.headers().clone();
+        let event_stream = response_headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        
+        for name in STRIPPED_RESPONSE_HEADERS {
+            response_headers.remove(*name);
+        }
+        
+        let meter = ProxyMeter {
+            pool: self.pool.clone(),
+            ws_tx: self.ws_tx.clone(),
+            ingestion: self.config.ingestion.clone(),
+            user_id,
+            api_key,
+            provider,
+            endpoint: format!("/{}", req.path.trim_start_matches('/')),
+            request_body: req.body,
+            status,
+            event_stream,
+            started,
+        };
+        let body = MeteredBody::new(Some(meter), event_stream, self.config.proxy.max_body_bytes);
+        
+        let body = stream::unfold(Some((upstream.bytes_stream(), body)), |state| async move {
+            let (mut upstream, mut body) = state?;
+            match upstream.next().await {
+                Some(Ok(chunk)) => {
+                    body.capture(&chunk);
+                    Some((Ok(chunk), Some((upstream, body))))
+                }
+                // Dropping the state meters what was received so far
+                Some(Err(e)) => Some((Err(e), None)),
+                None => None,
+            }
+        });
+        
+        Ok(ProxiedResponse {
+            status,
+            headers: response_headers,
+            body: Body::from_stream(body),
+        })
+    }
+}
+
+/// A proxied response body on its way to the caller. Its usage is recorded
+/// when it is dropped: once the upstream body has ended, failed, or the
+/// caller has gone away.
+struct MeteredBody {
+    meter: Option<ProxyMeter>,
+    captured: Vec<u8>,
+    /// The latest bytes of an event stream that outgrew the limit, where its
+    /// final usage is reported.
+    tail: VecDeque<u8>,
+    event_stream: bool,
+    capture_limit: usize,
+    truncated: bool,
+}
+
+impl MeteredBody {
+    fn new(meter: Option<ProxyMeter>, event_stream: bool, capture_limit: usize) -> Self {
+        Self {
+            meter,
+            captured: Vec::new(),
+            tail: VecDeque::new(),
+            event_stream,
+            capture_limit,
+            truncated: false,
+        }
+    }
+    
+    /// Keeps a copy of `chunk` for metering. A JSON body past the limit is no
+    /// longer copied and is metered as an unparseable one. An event stream
+    /// keeps its first and last half of the limit instead: the opening events
+    /// name the model and the closing ones carry the final usage.
+    fn capture(&mut self, chunk: &[u8]) {
+        if !self.event_stream {
+            if self.truncated {
+                return;
+            }
+            if self.captured.len() + chunk.len() <= self.capture_limit {
+                self.captured.extend_from_slice(chunk);
+            } else {
+                self.truncated = true;
+                self.captured = Vec::new();
+            }
+            return;
+        }
+        
+        let head_limit = self.capture_limit / 2;
+        let tail_limit = self.capture_limit - head_limit;
+        
+        let head_len = chunk.len().min(head_limit.saturating_sub(self.captured.len()));
+        self.captured.extend_from_slice(&chunk[..head_len]);
+        self.tail.extend(&chunk[head_len..]);
+        
+        if self.tail.len() > tail_limit {
+            self.tail.drain(..self.tail.len() - tail_limit);
+            self.truncated = true;
+        }
+    }
+    
+    /// The captured body. The gap of a truncated event stream is closed on
+    /// the first whole line of its tail, so the cut events are skipped as
+    /// unparseable rather than glued together.
+    fn take_body(&mut self) -> Vec<u8> {
+        let mut body = std::mem::take(&mut self.captured);
+        let tail = Vec::from(std::mem::take(&mut self.tail));
+        
+        if self.event_stream && self.truncated {
+            let resume = tail.iter().position(|b| *b == b'\n').map_or(tail.len(), |pos| pos + 1);
+            body.push(b'\n');
+            body.extend_from_slice(&tail[resume..]);
+        } else {
+            body.extend_from_slice(&tail);
+        }
+        
+        body
+    }
+}
+
+impl Drop for MeteredBody {
+    fn drop(&mut self) {
+        if let Some(meter) = self.meter.take() {
+            let body = self.take_body();
+            tokio::spawn(meter.record(body));
+        }
+    }
+}
+
+/// What is needed to record a proxied call after its response has been sent.
+struct ProxyMeter {
+    pool: PgPool,
+    ws_tx: broadcast::Sender<WsMessage>,
+    ingestion: IngestionConfig,
+    user_id: Uuid,
+    api_key: ApiKey,
+    provider: &'static str,
+    endpoint: String,
+    request_body: Bytes,
+    status: StatusCode,
+    event_stream: bool,
+    started: Instant,
+}
+
+impl ProxyMeter {
+    /// A metering failure must not fail the proxied call itself, so it is
+    /// only logged.
+    async fn record(self, body: Vec<u8>) {
+        if let Err(e) = self.try_record(&body).await {
+            tracing::error!("Failed to record proxied usage for key {}: {:?}", self.api_key.id, e);
+        }
+    }
+    
+    async fn try_record(&self, body: &[u8]) -> Result<RecordedUsage, ApiError> {
+        let elapsed_ms = i32::try_from(self.started.elapsed().as_millis()).unwrap_or(i32::MAX);
+        let request: Option<Value> = serde_json::from_slice(&self.request_body).ok();
+        
+        let parser = providers::parser_for(self.provider).ok_or_else(|| {
+            ApiError::ValidationError(format!("Provider '{}' cannot be metered", self.provider))
+        })?;
+        let (response, parsed) = if self.event_stream {
+            let events = providers::sse_events(body);
+            let parsed = parser.parse_stream(&events, request.as_ref());
+            (Value::Array(events), parsed)
+        } else {
+            let response: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+            let parsed = parser.parse(&response, request.as_ref());
+            (response, parsed)
+        };
+        
+        // Unparseable or cut-off bodies are still recorded as a request
+        let mut parsed = parsed.unwrap_or_else(|| ParsedUsage {
+            model_name: providers::request_model(request.as_ref()),
+            ..Default::default()
+        });
+        if parsed.model_name.is_none() {
+            parsed.model_name = model_from_path(&self.endpoint);
+        }
+        
+        let raw = RawUsageRequest {
+            api_key_id: self.api_key.id,
+            response,
+            request,
+            model_name: None,
+            endpoint: Some(self.endpoint.clone()),
+            status_code: Some(i32::from(self.status.as_u16())),
+            response_time_ms: Some(elapsed_ms),
+            metadata: Some(serde_json::json!({ "proxied": true })),
+            external_id: None,
+            timestamp: None,
+            error_type: None,
+            is_batch: false,
+            tags: None,
+        };
+        let usage_req = UsageService::request_from_parsed(raw, parsed, parser)?;
+        
+        UsageService::new(&self.pool, &self.ws_tx)
+            .record_with_key(self.user_id, &self.api_key, usage_req, &self.ingestion)
+            .await
+    }
+}
+
+fn inject_credentials(headers: &mut HeaderMap, provider: &str, secret: &str) -> Result<(), ApiError> {
+    let invalid = |_| ApiError::InternalServerError("Stored API key is not a valid header value".to_string());
+    
+    match provider {
+        "anthropic" => {
+            headers.insert(HeaderName::from_static("x-api-key"), HeaderValue::from_str(secret).map_err(invalid)?);
+            headers
+                .entry(HeaderName::from_static("anthropic-version"))
+                .or_insert(HeaderValue::from_static("2023-06-01"));
+        }
+        "gemini" => {
+            headers.insert(HeaderName::from_static("x-goog-api-key"), HeaderValue::from_str(secret).map_err(invalid)?);
+        }
+        _ => {
+            headers.insert(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", secret)).map_err(invalid)?,
+            );
+        }
+    }
+    
+    Ok(())
+}
+
+/// Drops the Gemini-style `key` parameter, which carries the caller's token
+/// rather than a provider key.
+fn forwardable_query(query: &str) -> Option<String> {
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty() && *pair != "key" && !pair.starts_with("key="))
+        .collect();
+    
+    if kept.is_empty() {
+        None
+    } else {
+        Some(kept.join("&"))
+    }
+}
+
+/// Extracts the model from Gemini-style paths such as
+/// `/v1beta/models/gemini-1.5-pro:generateContent`.
+fn model_from_path(path: &str) -> Option<String> {
+    let rest = path.split("/models/").nth(1)?;
+    let model = rest.split([':', '/']).next()?;
+    
+    if model.is_empty() {
+        None
+    } else {
+        Some(model.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::testing::{create_api_key, create_user, TestDatabase};
+    use crate::services::providers::ResponseParser;
+
+    fn captured(event_stream: bool, capture_limit: usize, body: &str) -> Vec<u8> {
+        let mut metered = MeteredBody::new(None, event_stream, capture_limit);
+        for chunk in body.as_bytes().chunks(100) {
+            metered.capture(chunk);
+        }
+        metered.take_body()
+    }
+
+    fn chat_stream(deltas: usize) -> String {
+        let mut body = String::new();
+        for _ in 0..deltas {
+            body.push_str("data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"delta\":{\"content\":\"Hi\"}}],\"usage\":null}\n\n");
+        }
+        body.push_str("data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":5}}\n\n");
+        body.push_str("data: [DONE]\n\n");
+        body
+    }
+
+    #[test]
+    fn event_stream_past_the_limit_keeps_its_opening_and_closing_events() {
+        let mut body = String::from(
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"m1\",\"model\":\"claude-sonnet-4\",\"usage\":{\"input_tokens\":20,\"output_tokens\":1}}}\n\n",
+        );
+        for _ in 0..1000 {
+            body.push_str("data: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n");
+        }
+        body.push_str("data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":42}}\n\n");
+
+        let kept = captured(true, 2048, &body);
+        let parsed = providers::anthropic::AnthropicParser
+            .parse_stream(&providers::sse_events(&kept), None)
+            .unwrap();
+
+        assert!(kept.len() <= 2049);
+        assert_eq!((parsed.input_tokens, parsed.output_tokens), (20, 42));
+        assert_eq!(parsed.finish_reason.as_deref(), Some("end_turn"));
+    }
+
+    #[test]
+    fn event_stream_within_the_limit_is_kept_whole() {
+        let body = chat_stream(3);
+
+        assert_eq!(captured(true, body.len(), &body), body.as_bytes());
+    }
+
+    #[test]
+    fn json_body_past_the_limit_is_dropped() {
+        assert!(captured(false, 64, &"x".repeat(65)).is_empty());
+        assert_eq!(captured(false, 64, "{\"a\":1}"), b"{\"a\":1}");
+    }
+
+    #[tokio::test]
+    async fn metering_records_a_cut_stream_with_its_final_usage_and_an_oversized_body_as_a_request() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let api_key = UsageRepository::new(&db.pool).get_api_key(user_id, api_key_id).await.unwrap();
+        let (ws_tx, _) = broadcast::channel(16);
+        let meter = |event_stream: bool| ProxyMeter {
+            pool: db.pool.clone(),
+            ws_tx: ws_tx.clone(),
+            ingestion: IngestionConfig {
+                max_batch_size: 100,
+                import_chunk_size: 100,
+                max_clock_skew_secs: 300,
+                max_lateness_secs: 3600,
+            },
+            user_id,
+            api_key: api_key.clone(),
+            provider: "openai",
+            endpoint: "/v1/chat/completions".to_string(),
+            request_body: Bytes::from_static(b"{\"model\":\"gpt-4o-mini\"}"),
+            status: StatusCode::OK,
+            event_stream,
+            started: Instant::now(),
+        };
+
+        let streamed = meter(true)
+            .try_record(&captured(true, 1024, &chat_stream(500)))
+            .await
+            .unwrap();
+        let oversized = meter(false)
+            .try_record(&captured(false, 1024, &format!("{{\"pad\":\"{}\"}}", "x".repeat(2048))))
+            .await
+            .unwrap();
+        db.drop().await;
+
+        assert_eq!(streamed.usage.model_name.as_deref(), Some("gpt-4o"));
+        assert_eq!((streamed.usage.input_tokens, streamed.usage.output_tokens), (12, 5));
+        assert_eq!(oversized.usage.model_name.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!((oversized.usage.input_tokens, oversized.usage.output_tokens), (0, 0));
+        assert_eq!(oversized.usage.requests, 1);
+    }
+}
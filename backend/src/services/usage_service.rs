@@ -1,124 +1,1360 @@
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use futures_util::{future, stream::{self, BoxStream}, Stream, StreamExt, TryStreamExt};
 use sqlx::PgPool;
 use chrono::{DateTime, Utc, Duration};
 use uuid::Uuid;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, Semaphore};
+use validator::Validate;
 
 use crate::{
-    models::api_usage::{ApiUsage, CreateUsageRequest},
-    db::repositories::UsageRepository,
-    controllers::usage_controller::UsageStats,
+    config::settings::IngestionConfig,
+    models::api_key::ApiKey,
+    models::api_usage::{
+        ApiUsage, BilledQuantity, CreateUsageRequest, ErrorType, NewUsage, RawUsageRequest,
+        RecordedUsage, UsageCursor, UsageListQuery, UsagePage,
+    },
+    models::money::{validate_currency, Money},
+    models::usage_aggregate::{GroupBy, UsageAggregateQuery, UsageAggregation},
+    models::tag::{TagKey, TagListQuery, TagValue, UntaggedTags},
+    models::usage_export::UsageExportQuery,
+    models::calendar::{weekday_name, Calendar},
+    models::latency::{
+        parse_bucket_bounds, LatencyDimension, LatencyHistogram, LatencyHistogramQuery,
+        DEFAULT_LATENCY_BUCKETS,
+    },
+    db::repositories::{UsageRepository, UserRepository},
+    controllers::usage_controller::{
+        BatchItemResult, BatchUsageResponse, ImportSummary, StatsChanges, StatsComparison,
+        UsageQuery, UsageStats,
+    },
+    services::export_service::{ExportEncoder, EXPORT_BATCH_SIZE},
+    services::pricing_service::{PriceCatalog, PricingService},
+    services::providers::{self, ParsedUsage, ResponseParser},
     websocket::WsMessage,
     errors::ApiError,
+    utils::period::Period,
 };
 
+/// Longest NDJSON line accepted by `import_ndjson`.
+const MAX_IMPORT_LINE_BYTES: usize = 64 * 1024;
+
+/// Usage rows per page when the listing does not set `limit`.
+const DEFAULT_PAGE_SIZE: i64 = 100;
+
+/// Groups returned by an aggregation that does not set `limit`.
+const DEFAULT_AGGREGATE_GROUPS: i64 = 1000;
+
+/// Values listed per tag key when the listing does not set `values_per_key`.
+const DEFAULT_TAG_VALUES: i64 = 100;
+
+/// A usage export ready to be sent: its body is produced batch by batch
+/// from a database cursor as the client reads it.
+pub struct UsageExport {
+    pub content_type: &'static str,
+    pub filename: String,
+    pub body: BoxStream<'static, Result<Bytes, ApiError>>,
+}
+
+/// Cost and tokens of newly recorded usage per currency, for live updates.
+#[derive(Default)]
+struct FreshTotals(BTreeMap<String, (Money, i32)>);
+
+impl FreshTotals {
+    fn add(&mut self, usage: &ApiUsage) {
+        let (cost, tokens) = self.0.entry(usage.currency.clone()).or_default();
+        *cost += usage.cost;
+        *tokens = tokens.saturating_add(usage.total_tokens);
+    }
+    
+    /// Sends one update per currency, so costs are never summed across currencies.
+    fn send(self, ws_tx: &broadcast::Sender<WsMessage>, user_id: Uuid) {
+        for (currency, (cost, tokens)) in self.0 {
+            let _ = ws_tx.send(WsMessage::UsageUpdate {
+                user_id,
+                cost,
+                currency,
+                tokens,
+                timestamp: Utc::now().to_rfc3339(),
+            });
+        }
+    }
+}
+
 pub struct UsageService<'a> {
     pool: &'a PgPool,
     ws_tx: &'a broadcast::Sender<WsMessage>,
+    key_scope: Option<&'a [Uuid]>,
 }
 
 impl<'a> UsageService<'a> {
     pub fn new(pool: &'a PgPool, ws_tx: &'a broadcast::Sender<WsMessage>) -> Self {
-        Self { pool, ws_tx }
+        Self { pool, ws_tx, key_scope: None }
+    }
+    
+    /// Restricts ingestion to the given API keys, as for a scoped ingestion token.
+    pub fn with_key_scope(mut self, key_scope: Option<&'a [Uuid]>) -> Self {
+        self.key_scope = key_scope;
+        self
+    }
+    
+    pub fn check_key_scope(&self, api_key_id: Uuid) -> Result<(), ApiError> {
+        match self.key_scope {
+            Some(scope) if !scope.contains(&api_key_id) => Err(ApiError::Forbidden(
+                "This ingestion token may not record usage for this API key".to_string(),
+            )),
+            _ => Ok(()),
+        }
     }
     
     pub async fn record_usage(
         &self,
         user_id: Uuid,
         req: CreateUsageRequest,
-    ) -> Result<ApiUsage, ApiError> {
+        limits: &IngestionConfig,
+    ) -> Result<RecordedUsage, ApiError> {
+        self.check_key_scope(req.api_key_id)?;
         let repo = UsageRepository::new(self.pool);
         
         // Get API key pricing
-        let api_key = repo.get_api_key(req.api_key_id).await?;
+        let api_key = repo.get_api_key(user_id, req.api_key_id).await?;
+        
+        self.record_with_key(user_id, &api_key, req, limits).await
+    }
+    
+    /// Parses a raw provider response with the parser for the key's provider
+    /// and records it like any other usage event.
+    pub async fn record_raw_usage(
+        &self,
+        user_id: Uuid,
+        raw: RawUsageRequest,
+        limits: &IngestionConfig,
+    ) -> Result<RecordedUsage, ApiError> {
+        self.check_key_scope(raw.api_key_id)?;
+        let repo = UsageRepository::new(self.pool);
+        let api_key = repo.get_api_key(user_id, raw.api_key_id).await?;
+        
+        let parser = providers::parser_for(&api_key.provider).ok_or_else(|| {
+            ApiError::ValidationError(format!(
+                "Raw responses from provider '{}' cannot be parsed; supported providers are openai, anthropic and gemini",
+                api_key.provider
+            ))
+        })?;
+        let parsed = providers::parse_response(parser, &raw.response, raw.request.as_ref())?;
+        let req = Self::request_from_parsed(raw, parsed, parser)?;
+        
+        self.record_with_key(user_id, &api_key, req, limits).await
+    }
+    
+    /// Builds a usage request from parsed provider usage, letting the explicit
+    /// fields of `raw` override what was parsed. A response naming no model,
+    /// when `raw` does not either, is rejected like any unrecognized one.
+    pub fn request_from_parsed(
+        raw: RawUsageRequest,
+        parsed: ParsedUsage,
+        parser: &dyn ResponseParser,
+    ) -> Result<CreateUsageRequest, ApiError> {
+        let model_name = raw
+            .model_name
+            .or(parsed.model_name)
+            .ok_or_else(|| providers::unrecognized_response(parser))?;
+        let mut metadata = match raw.metadata {
+            Some(serde_json::Value::Object(map)) => map,
+            None => serde_json::Map::new(),
+            Some(_) => {
+                return Err(ApiError::ValidationError("metadata must be a JSON object".to_string()));
+            }
+        };
+        if let Some(finish_reason) = parsed.finish_reason {
+            metadata.insert("finish_reason".to_string(), finish_reason.into());
+        }
+        
+        let req = CreateUsageRequest {
+            api_key_id: raw.api_key_id,
+            input_tokens: parsed.input_tokens,
+            output_tokens: parsed.output_tokens,
+            cached_input_tokens: parsed.cached_input_tokens,
+            cache_write_tokens: parsed.cache_write_tokens,
+            reasoning_tokens: parsed.reasoning_tokens,
+            billing_unit: None,
+            quantity: None,
+            unit_variant: None,
+            model_name,
+            endpoint: raw.endpoint,
+            status_code: raw.status_code.or(Some(parsed.status_code)),
+            response_time_ms: raw.response_time_ms,
+            metadata: Some(serde_json::Value::Object(metadata)),
+            external_id: raw.external_id.or(parsed.request_id),
+            timestamp: raw.timestamp,
+            error_type: raw.error_type.or(parsed.error_type),
+            is_batch: raw.is_batch,
+            tags: raw.tags,
+        };
+        req.validate()?;
+        
+        Ok(req)
+    }
+    
+    /// Records a request against an API key the caller has already loaded.
+    pub async fn record_with_key(
+        &self,
+        user_id: Uuid,
+        api_key: &ApiKey,
+        req: CreateUsageRequest,
+        limits: &IngestionConfig,
+    ) -> Result<RecordedUsage, ApiError> {
+        let repo = UsageRepository::new(self.pool);
+        
+        let timestamp = Self::event_time(req.timestamp, limits, true)
+            .map_err(ApiError::ValidationError)?;
+        
+        let mut catalog = PricingService::new(self.pool).catalog_for([api_key]).await?;
+        let mut new_usage = Self::price_usage(user_id, api_key, &catalog, req, timestamp)
+            .map_err(ApiError::ValidationError)?;
+        catalog.apply_rules(&repo, &mut new_usage).await?;
+        let recorded = repo.create_usage(&new_usage).await?;
+        Self::flag_late_rows(&repo, std::slice::from_ref(&recorded)).await?;
+        
+        // Replays of an already recorded event were broadcast the first time
+        if !recorded.deduplicated {
+            let _ = self.ws_tx.send(WsMessage::UsageUpdate {
+                user_id,
+                cost: recorded.usage.cost,
+                currency: recorded.usage.currency.clone(),
+                tokens: recorded.usage.total_tokens,
+                timestamp: recorded.usage.timestamp.to_rfc3339(),
+            });
+        }
+        
+        Ok(recorded)
+    }
+    
+    /// Records a batch of usage items, reporting success or failure per index.
+    ///
+    /// Items are validated and priced individually; the valid ones are inserted
+    /// in one transaction and announced with a single aggregated WebSocket update.
+    /// A batch must hold between one and `max_batch_size` items.
+    pub async fn record_batch(
+        &self,
+        user_id: Uuid,
+        items: Vec<serde_json::Value>,
+        limits: &IngestionConfig,
+    ) -> Result<BatchUsageResponse, ApiError> {
+        if items.is_empty() {
+            return Err(ApiError::ValidationError("Batch must contain at least one item".to_string()));
+        }
+        if items.len() > limits.max_batch_size {
+            return Err(ApiError::ValidationError(format!(
+                "Batch contains {} items, the maximum is {}",
+                items.len(),
+                limits.max_batch_size
+            )));
+        }
+        
+        let repo = UsageRepository::new(self.pool);
         
-        // Calculate cost
+        let requests: Vec<Result<CreateUsageRequest, String>> = items
+            .into_iter()
+            .map(|item| {
+                let req: CreateUsageRequest =
+                    serde_json::from_value(item).map_err(|e| e.to_string())?;
+                req.validate().map_err(|e| e.to_string())?;
+                Ok(req)
+            })
+            .collect();
+        
+        let mut key_ids: Vec<Uuid> = requests
+            .iter()
+            .filter_map(|req| req.as_ref().ok().map(|r| r.api_key_id))
+            .collect();
+        key_ids.sort();
+        key_ids.dedup();
+        
+        let api_keys: HashMap<Uuid, ApiKey> = repo
+            .get_api_keys(user_id, &key_ids)
+            .await?
+            .into_iter()
+            .map(|key| (key.id, key))
+            .collect();
+        let mut catalog = PricingService::new(self.pool).catalog_for(api_keys.values()).await?;
+        
+        let mut errors: Vec<(usize, String)> = Vec::new();
+        let mut pending_indexes: Vec<usize> = Vec::new();
+        let mut pending_rows: Vec<NewUsage> = Vec::new();
+        
+        for (index, req) in requests.into_iter().enumerate() {
+            let req = match req {
+                Ok(req) => req,
+                Err(e) => {
+                    errors.push((index, e));
+                    continue;
+                }
+            };
+            
+            if let Err(e) = self.check_key_scope(req.api_key_id) {
+                errors.push((index, e.to_string()));
+                continue;
+            }
+            
+            let timestamp = match Self::event_time(req.timestamp, limits, true) {
+                Ok(timestamp) => timestamp,
+                Err(e) => {
+                    errors.push((index, e));
+                    continue;
+                }
+            };
+            
+            let Some(api_key) = api_keys.get(&req.api_key_id) else {
+                errors.push((index, "API key not found".to_string()));
+                continue;
+            };
+            
+            match Self::price_usage(user_id, api_key, &catalog, req, timestamp) {
+                Ok(row) => {
+                    pending_indexes.push(index);
+                    pending_rows.push(row);
+                }
+                Err(e) => errors.push((index, e)),
+            }
+        }
+        
+        catalog.apply_rules_in_order(&repo, &mut pending_rows).await?;
+        
+        let inserted = if pending_rows.is_empty() {
+            Vec::new()
+        } else {
+            repo.create_usages(&pending_rows).await?
+        };
+        Self::flag_late_rows(&repo, &inserted).await?;
+        
+        let fresh: Vec<&ApiUsage> = inserted
+            .iter()
+            .filter(|r| !r.deduplicated)
+            .map(|r| &r.usage)
+            .collect();
+        
+        let mut totals = FreshTotals::default();
+        for usage in &fresh {
+            totals.add(usage);
+        }
+        totals.send(self.ws_tx, user_id);
+        
+        let accepted = inserted.len();
+        let deduplicated = inserted.len() - fresh.len();
+        let rejected = errors.len();
+        
+        let mut results: Vec<BatchItemResult> = pending_indexes
+            .into_iter()
+            .zip(inserted)
+            .map(|(index, usage)| BatchItemResult {
+                index,
+                success: true,
+                data: Some(usage),
+                error: None,
+            })
+            .chain(errors.into_iter().map(|(index, error)| BatchItemResult {
+                index,
+                success: false,
+                data: None,
+                error: Some(error),
+            }))
+            .collect();
+        results.sort_by_key(|r| r.index);
+        
+        Ok(BatchUsageResponse {
+            accepted,
+            deduplicated,
+            rejected,
+            results,
+        })
+    }
+    
+    /// Imports a newline-delimited JSON stream of backfill lines.
+    ///
+    /// The body is parsed line by line as it arrives and valid rows are written in
+    /// chunks of `import_chunk_size`, so memory use does not grow with the upload
+    /// size. Every line must carry its original `timestamp`; the lateness limit
+    /// does not apply to backfills.
+    pub async fn import_ndjson<S, E>(
+        &self,
+        user_id: Uuid,
+        mut body: S,
+        limits: &IngestionConfig,
+    ) -> Result<ImportSummary, ApiError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let repo = UsageRepository::new(self.pool);
+        let chunk_size = limits.import_chunk_size.max(1);
+        let mut summary = ImportSummary::default();
+        let mut api_keys: HashMap<Uuid, Option<ApiKey>> = HashMap::new();
+        let mut catalog = PricingService::new(self.pool).full_catalog().await?;
+        let mut pending: Vec<NewUsage> = Vec::with_capacity(chunk_size);
+        let mut totals = FreshTotals::default();
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut line_number = 0;
+        let mut finished = false;
+        
+        while !finished {
+            match body.next().await {
+                Some(chunk) => {
+                    let chunk = chunk.map_err(|e| {
+                        ApiError::ValidationError(format!("Failed to read request body: {}", e))
+                    })?;
+                    buffer.extend_from_slice(&chunk);
+                }
+                None => {
+                    // A final line without a trailing newline still counts
+                    if !buffer.is_empty() {
+                        buffer.push(b'\n');
+                    }
+                    finished = true;
+                }
+            }
+            
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                line_number += 1;
+                if pos > MAX_IMPORT_LINE_BYTES {
+                    return Err(Self::line_too_long(line_number));
+                }
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                summary.total_lines += 1;
+                
+                match self.parse_backfill_line(user_id, line, limits, &repo, &catalog, &mut api_keys).await? {
+                    Ok(row) => pending.push(row),
+                    Err(error) => summary.reject(line_number, error),
+                }
+                
+                if pending.len() >= chunk_size {
+                    Self::flush_import_chunk(&repo, &mut catalog, &mut pending, &mut summary, &mut totals).await?;
+                }
+            }
+            
+            // A line still missing its end may already be too long
+            if buffer.len() > MAX_IMPORT_LINE_BYTES {
+                return Err(Self::line_too_long(line_number + 1));
+            }
+        }
+        
+        Self::flush_import_chunk(&repo, &mut catalog, &mut pending, &mut summary, &mut totals).await?;
+        totals.send(self.ws_tx, user_id);
+        
+        Ok(summary)
+    }
+    
+    fn line_too_long(line_number: usize) -> ApiError {
+        ApiError::ValidationError(format!(
+            "Line {} exceeds the maximum length of {} bytes",
+            line_number, MAX_IMPORT_LINE_BYTES
+        ))
+    }
+    
+    /// Parses, validates and prices one backfill line at list price; pricing
+    /// rules are applied when its chunk is written. The outer error is fatal,
+    /// the inner one rejects just this line.
+    async fn parse_backfill_line(
+        &self,
+        user_id: Uuid,
+        line: &str,
+        limits: &IngestionConfig,
+        repo: &UsageRepository<'_>,
+        catalog: &PriceCatalog,
+        api_keys: &mut HashMap<Uuid, Option<ApiKey>>,
+    ) -> Result<Result<NewUsage, String>, ApiError> {
+        let parsed: CreateUsageRequest = match serde_json::from_str(line) {
+            Ok(parsed) => parsed,
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        
+        if let Err(e) = parsed.validate() {
+            return Ok(Err(e.to_string()));
+        }
+        
+        if let Err(e) = self.check_key_scope(parsed.api_key_id) {
+            return Ok(Err(e.to_string()));
+        }
+        
+        let Some(timestamp) = parsed.timestamp else {
+            return Ok(Err("timestamp is required for backfilled usage".to_string()));
+        };
+        let timestamp = match Self::event_time(Some(timestamp), limits, false) {
+            Ok(timestamp) => timestamp,
+            Err(e) => return Ok(Err(e)),
+        };
+        
+        let api_key = match api_keys.entry(parsed.api_key_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let key = repo.get_api_keys(user_id, &[parsed.api_key_id]).await?.pop();
+                entry.insert(key)
+            }
+        };
+        
+        let Some(api_key) = api_key else {
+            return Ok(Err("API key not found".to_string()));
+        };
+        
+        Ok(Self::price_usage(user_id, api_key, catalog, parsed, timestamp))
+    }
+    
+    /// Applies pricing rules to the pending rows, writes them and adds the newly
+    /// inserted ones to `totals`.
+    async fn flush_import_chunk(
+        repo: &UsageRepository<'_>,
+        catalog: &mut PriceCatalog,
+        pending: &mut Vec<NewUsage>,
+        summary: &mut ImportSummary,
+        totals: &mut FreshTotals,
+    ) -> Result<(), ApiError> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+        
+        catalog.apply_rules_in_order(repo, pending).await?;
+        let recorded = repo.create_usages(pending).await?;
+        catalog.rows_stored();
+        pending.clear();
+        Self::flag_late_rows(repo, &recorded).await?;
+        
+        for r in &recorded {
+            summary.accepted += 1;
+            if r.deduplicated {
+                summary.deduplicated += 1;
+            } else {
+                totals.add(&r.usage);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Resolves the event time of a request, checking it against the clock-skew
+    /// and (for live ingestion) maximum-lateness windows.
+    fn event_time(
+        timestamp: Option<DateTime<Utc>>,
+        limits: &IngestionConfig,
+        enforce_lateness: bool,
+    ) -> Result<DateTime<Utc>, String> {
+        let now = Utc::now();
+        let Some(timestamp) = timestamp else {
+            return Ok(now);
+        };
+        
+        let latest = Duration::try_seconds(limits.max_clock_skew_secs)
+            .and_then(|skew| now.checked_add_signed(skew));
+        if latest.is_some_and(|latest| timestamp > latest) {
+            return Err(format!(
+                "timestamp {} is in the future (maximum clock skew is {}s)",
+                timestamp.to_rfc3339(),
+                limits.max_clock_skew_secs
+            ));
+        }
+        
+        let earliest = Duration::try_seconds(limits.max_lateness_secs)
+            .and_then(|lateness| now.checked_sub_signed(lateness));
+        if enforce_lateness && earliest.is_some_and(|earliest| timestamp < earliest) {
+            return Err(format!(
+                "timestamp {} is older than the maximum lateness of {}s; use the import endpoint for backfills",
+                timestamp.to_rfc3339(),
+                limits.max_lateness_secs
+            ));
+        }
+        
+        Ok(timestamp)
+    }
+    
+    /// Marks rollups and predictions covering newly inserted late events for
+    /// recomputation.
+    async fn flag_late_rows(
+        repo: &UsageRepository<'_>,
+        recorded: &[RecordedUsage],
+    ) -> Result<(), ApiError> {
+        let late: Vec<(Uuid, Uuid, DateTime<Utc>)> = recorded
+            .iter()
+            .filter(|r| !r.deduplicated && r.usage.is_late())
+            .map(|r| (r.usage.user_id, r.usage.api_key_id, r.usage.timestamp))
+            .collect();
+        
+        repo.mark_for_recompute(&late, "late_data").await
+    }
+    
+    /// Prices a validated request at list price from the catalog entry for its
+    /// model and the API key's overrides. Fails when nothing prices the
+    /// request's unit. Pricing rules are applied separately.
+    fn price_usage(
+        user_id: Uuid,
+        api_key: &ApiKey,
+        catalog: &PriceCatalog,
+        req: CreateUsageRequest,
+        timestamp: DateTime<Utc>,
+    ) -> Result<NewUsage, String> {
         let total_tokens = req.input_tokens + req.output_tokens;
-        let cost = ApiUsage::calculate_cost(
-            req.input_tokens,
-            req.output_tokens,
-            api_key.cost_per_1k_input,
-            api_key.cost_per_1k_output,
-        );
+        let error_type = req
+            .error_type
+            .or_else(|| req.status_code.and_then(ErrorType::from_status));
         
-        // Create usage record
-        let usage = repo.create_usage(
+        let billing_unit = req.billing_unit.unwrap_or_default();
+        let pricing = catalog.pricing_for(api_key, &req.model_name, timestamp);
+        let cost = ApiUsage::calculate_cost(&BilledQuantity::from(&req), &pricing)?.ok_or_else(|| {
+            let unit = match &req.unit_variant {
+                Some(variant) => format!("{}:{}", billing_unit.as_str(), variant),
+                None => billing_unit.as_str().to_string(),
+            };
+            format!(
+                "No {} price for model '{}'; add it to the model price catalog or set a price on the API key",
+                unit, req.model_name
+            )
+        })?;
+        
+        Ok(NewUsage {
             user_id,
-            req.api_key_id,
-            req.input_tokens,
-            req.output_tokens,
+            api_key_id: req.api_key_id,
+            timestamp,
+            input_tokens: req.input_tokens,
+            output_tokens: req.output_tokens,
             total_tokens,
+            cached_input_tokens: req.cached_input_tokens,
+            cache_write_tokens: req.cache_write_tokens,
+            reasoning_tokens: req.reasoning_tokens,
+            billing_unit,
+            quantity: req.quantity,
+            unit_variant: req.unit_variant,
+            errors: i32::from(error_type.is_some()),
+            error_type,
             cost,
-            req.model_name,
-            req.endpoint,
-            req.status_code,
-            req.response_time_ms,
-            req.metadata,
-        ).await?;
-        
-        // Broadcast WebSocket update
-        let _ = self.ws_tx.send(WsMessage::UsageUpdate {
-            user_id,
-            cost,
-            tokens: total_tokens,
-            timestamp: usage.timestamp.to_rfc3339(),
-        });
-        
-        Ok(usage)
+            currency: pricing.currency,
+            list_cost: cost,
+            is_batch: req.is_batch,
+            pricing_rule_id: None,
+            discount_percent: Money::ZERO,
+            model_name: req.model_name,
+            endpoint: req.endpoint,
+            status_code: req.status_code,
+            response_time_ms: req.response_time_ms,
+            metadata: req.metadata,
+            tags: req.tags.unwrap_or_default(),
+            external_id: req.external_id,
+        })
     }
     
+    /// Lists usage one page at a time; pass the returned `next_cursor` back
+    /// with the same filters and ordering to get the following page.
     pub async fn get_usage(
         &self,
         user_id: Uuid,
-        start_date: Option<&str>,
-        end_date: Option<&str>,
-        api_key_id: Option<Uuid>,
-    ) -> Result<Vec<ApiUsage>, ApiError> {
-        let repo = UsageRepository::new(self.pool);
+        query: UsageListQuery,
+    ) -> Result<UsagePage, ApiError> {
+        query.validate()?;
+        Self::check_filter_bounds(query.filter.status_min, query.filter.status_max, query.filter.min_cost, query.filter.max_cost)?;
         
-        let start = start_date
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc))
-            .unwrap_or_else(|| Utc::now() - Duration::days(7));
+        let cursor = match query.cursor.as_deref() {
+            Some(cursor) => {
+                let cursor = UsageCursor::decode(cursor)
+                    .ok_or_else(|| ApiError::ValidationError("Invalid cursor".to_string()))?;
+                if cursor.sort != query.sort || cursor.order != query.order {
+                    return Err(ApiError::ValidationError(
+                        "cursor was issued for a different sort or order".to_string(),
+                    ));
+                }
+                Some(cursor)
+            }
+            None => None,
+        };
         
-        let end = end_date
-            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-            .map(|dt| dt.with_timezone(&Utc))
-            .unwrap_or_else(Utc::now);
+        let repo = UsageRepository::new(self.pool);
+        let (start, end) = Self::date_range(query.start_date.as_deref(), query.end_date.as_deref());
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
         
-        repo.get_usage_by_date_range(user_id, start, end, api_key_id).await
+        // One extra row tells whether another page follows
+        let mut items = repo
+            .list_usage(user_id, start, end, &query, cursor.as_ref(), limit + 1)
+            .await?;
+        let next_cursor = if items.len() as i64 > limit {
+            items.truncate(limit as usize);
+            items
+                .last()
+                .map(|last| UsageCursor::after(last, query.sort, query.order).encode())
+        } else {
+            None
+        };
+        
+        Ok(UsagePage { items, next_cursor })
     }
     
+    /// Stats for the requested period, by default the last 7 days, along
+    /// with the previous equivalent period when `compare` is set.
     pub async fn calculate_stats(
         &self,
         user_id: Uuid,
-        period: &str,
+        query: &UsageQuery,
     ) -> Result<UsageStats, ApiError> {
         let repo = UsageRepository::new(self.pool);
+        let currency = self.reporting_currency(user_id, query.currency.as_deref()).await?;
+        let calendar = self.calendar(user_id, query.timezone.as_deref(), None).await?;
+        
+        let period = Period::parse(query.period.as_deref().unwrap_or("7d"))
+            .map_err(ApiError::ValidationError)?;
+        let range = period
+            .resolve(&calendar, Utc::now())
+            .map_err(ApiError::ValidationError)?;
+        let tags = query.tags.as_ref();
+        
+        let mut stats = repo
+            .calculate_stats(user_id, range.start, Some(range.end), tags, &currency)
+            .await?;
         
-        let days = match period {
-            "24h" | "1d" => 1,
-            "7d" => 7,
-            "30d" => 30,
-            "90d" => 90,
-            _ => 7,
+        if query.compare {
+            let previous_range = period
+                .previous(&range, &calendar)
+                .map_err(ApiError::ValidationError)?;
+            let previous = repo
+                .calculate_stats(user_id, previous_range.start, Some(previous_range.end), tags, &currency)
+                .await?;
+            let changes = StatsChanges::between(&stats, &previous);
+            stats.comparison = Some(Box::new(StatsComparison { previous, changes }));
+        }
+        
+        Ok(stats)
+    }
+    
+    /// Usage metrics grouped by the dimensions listed in `group_by`.
+    pub async fn aggregate_usage(
+        &self,
+        user_id: Uuid,
+        query: UsageAggregateQuery,
+        untagged: &UntaggedTags,
+    ) -> Result<UsageAggregation, ApiError> {
+        query.validate()?;
+        let dimensions = GroupBy::parse_list(&query.group_by).map_err(ApiError::ValidationError)?;
+        
+        let repo = UsageRepository::new(self.pool);
+        let currency = self.reporting_currency(user_id, query.currency.as_deref()).await?;
+        let calendar = self
+            .calendar(user_id, query.timezone.as_deref(), query.week_start.as_deref())
+            .await?;
+        let (start, end) = Self::date_range(query.start_date.as_deref(), query.end_date.as_deref());
+        let limit = query.limit.unwrap_or(DEFAULT_AGGREGATE_GROUPS);
+        
+        let groups = repo
+            .aggregate_usage(
+                user_id, start, end, &dimensions, &query, &currency, &calendar, untagged, limit,
+            )
+            .await?;
+        
+        Ok(UsageAggregation {
+            currency,
+            timezone: calendar.timezone_name().to_string(),
+            week_start: weekday_name(calendar.week_start).to_string(),
+            group_by: dimensions.iter().map(GroupBy::name).collect(),
+            groups,
+        })
+    }
+    
+    /// Distribution of response times over the given buckets, by default
+    /// `DEFAULT_LATENCY_BUCKETS`.
+    pub async fn latency_histogram(
+        &self,
+        user_id: Uuid,
+        query: LatencyHistogramQuery,
+    ) -> Result<LatencyHistogram, ApiError> {
+        query.validate()?;
+        let dimensions = LatencyDimension::parse_list(&query.group_by).map_err(ApiError::ValidationError)?;
+        let bounds = match query.buckets.as_deref() {
+            Some(buckets) => parse_bucket_bounds(buckets).map_err(ApiError::ValidationError)?,
+            None => DEFAULT_LATENCY_BUCKETS.to_vec(),
         };
         
-        let start = Utc::now() - Duration::days(days);
+        let repo = UsageRepository::new(self.pool);
+        let (start, end) = Self::date_range(query.start_date.as_deref(), query.end_date.as_deref());
+        
+        let groups = repo
+            .get_latency_histogram(user_id, start, end, &dimensions, &query, &bounds)
+            .await?;
         
-        repo.calculate_stats(user_id, start).await
+        Ok(LatencyHistogram {
+            bounds,
+            group_by: dimensions.iter().map(|d| d.name().to_string()).collect(),
+            groups,
+        })
     }
     
+    /// Tag keys in use and their most used values, with usage missing a key
+    /// reported per `untagged`.
+    pub async fn list_tags(
+        &self,
+        user_id: Uuid,
+        query: TagListQuery,
+        untagged: &UntaggedTags,
+    ) -> Result<Vec<TagKey>, ApiError> {
+        query.validate()?;
+        
+        let repo = UsageRepository::new(self.pool);
+        let (start, end) = Self::date_range(query.start_date.as_deref(), query.end_date.as_deref());
+        let values_per_key = query.values_per_key.unwrap_or(DEFAULT_TAG_VALUES);
+        
+        let mut keys = repo.list_tags(user_id, start, end, values_per_key).await?;
+        
+        for key in &mut keys {
+            let Some(index) = key.values.iter().position(|v| v.value.is_none()) else {
+                continue;
+            };
+            match untagged {
+                UntaggedTags::Null => {}
+                UntaggedTags::Exclude => {
+                    key.values.remove(index);
+                }
+                UntaggedTags::Label(label) => {
+                    let untagged = key.values.remove(index);
+                    match key.values.iter_mut().find(|v| v.value.as_deref() == Some(label)) {
+                        Some(value) => value.usage_count += untagged.usage_count,
+                        None => key.values.push(TagValue {
+                            value: Some(label.clone()),
+                            usage_count: untagged.usage_count,
+                        }),
+                    }
+                }
+            }
+        }
+        
+        Ok(keys)
+    }
+    
+    /// Exports usage matching the listing filters, oldest first, as CSV,
+    /// JSON or NDJSON with the selected columns and timestamps in the
+    /// requested or the user's timezone, or as Parquet. Refused while every
+    /// one of `slots` is held by another download.
     pub async fn export_usage(
         &self,
         user_id: Uuid,
-        start_date: Option<&str>,
-        end_date: Option<&str>,
+        query: UsageExportQuery,
+        default_tag_columns: &[String],
+        slots: &Arc<Semaphore>,
+    ) -> Result<UsageExport, ApiError> {
+        query.validate()?;
+        Self::check_filter_bounds(query.filter.status_min, query.filter.status_max, query.filter.min_cost, query.filter.max_cost)?;
+        
+        let currency = self.reporting_currency(user_id, query.currency.as_deref()).await?;
+        let calendar = self.calendar(user_id, query.timezone.as_deref(), None).await?;
+        let (start, end) = Self::date_range(query.start_date.as_deref(), query.end_date.as_deref());
+        let mut writer = ExportEncoder::for_query(&query, calendar.timezone, default_tag_columns)?;
+        
+        // The permit travels with the cursor and is released when the
+        // download ends, however it ends
+        let permit = slots.clone().try_acquire_owned().map_err(|_| {
+            ApiError::ServiceUnavailable("Too many exports in progress, try again shortly".to_string())
+        })?;
+        let mut cursor = UsageRepository::new(self.pool)
+            .open_export_cursor(user_id, start, end, &query.filter, &currency)
+            .await?;
+        
+        // Reading the first batch before responding reports failures such as a
+        // missing exchange rate as an error status instead of a cut-off file
+        let rows = cursor.next_batch(EXPORT_BATCH_SIZE).await?;
+        let mut first = writer.header();
+        first.extend(writer.write_rows(&rows)?);
+        
+        let rest = stream::try_unfold(Some((cursor, writer, permit)), |state| async move {
+            let Some((mut cursor, mut writer, permit)) = state else {
+                return Ok(None);
+            };
+            
+            let rows = cursor.next_batch(EXPORT_BATCH_SIZE).await?;
+            if rows.is_empty() {
+                cursor.close().await?;
+                return Ok(Some((Bytes::from(writer.finish()?), None)));
+            }
+            
+            let chunk = writer.write_rows(&rows)?;
+            Ok(Some((Bytes::from(chunk), Some((cursor, writer, permit)))))
+        });
+        
+        let body = stream::once(future::ready(Ok(Bytes::from(first))))
+            .chain(rest)
+            .inspect_err(move |e| tracing::error!("Usage export for user {} failed: {}", user_id, e))
+            .boxed();
+        
+        Ok(UsageExport {
+            content_type: query.format.content_type(),
+            filename: format!(
+                "usage-{}-to-{}.{}",
+                calendar.date_of(start),
+                calendar.date_of(end),
+                query.format.extension()
+            ),
+            body,
+        })
+    }
+    
+    /// Rejects status and cost filters whose minimum exceeds their maximum.
+    pub(crate) fn check_filter_bounds(
+        status_min: Option<i32>,
+        status_max: Option<i32>,
+        min_cost: Option<Money>,
+        max_cost: Option<Money>,
+    ) -> Result<(), ApiError> {
+        if let (Some(min), Some(max)) = (status_min, status_max) {
+            if min > max {
+                return Err(ApiError::ValidationError(
+                    "status_min cannot be greater than status_max".to_string(),
+                ));
+            }
+        }
+        if let (Some(min), Some(max)) = (min_cost, max_cost) {
+            if min > max {
+                return Err(ApiError::ValidationError(
+                    "min_cost cannot be greater than max_cost".to_string(),
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// The requested currency, or the user's reporting currency.
+    async fn reporting_currency(
+        &self,
+        user_id: Uuid,
+        requested: Option<&str>,
     ) -> Result<String, ApiError> {
-        let usage = self.get_usage(user_id, start_date, end_date, None).await?;
+        match requested {
+            Some(currency) => {
+                let currency = currency.to_ascii_uppercase();
+                validate_currency(&currency)
+                    .map_err(|e| ApiError::ValidationError(e.to_string()))?;
+                Ok(currency)
+            }
+            None => UserRepository::new(self.pool).reporting_currency(user_id).await,
+        }
+    }
+    
+    /// The user's calendar with any requested timezone or week start applied.
+    async fn calendar(
+        &self,
+        user_id: Uuid,
+        timezone: Option<&str>,
+        week_start: Option<&str>,
+    ) -> Result<Calendar, ApiError> {
+        UserRepository::new(self.pool)
+            .calendar(user_id)
+            .await?
+            .with_overrides(timezone, week_start)
+            .map_err(ApiError::ValidationError)
+    }
+    
+    /// Parses an RFC 3339 range, defaulting to the last seven days.
+    fn date_range(start_date: Option<&str>, end_date: Option<&str>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let start = start_date
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc::now() - Duration::days(7));
         
-        let json = serde_json::to_string_pretty(&usage)
-            .map_err(|e| ApiError::Internal(e.to_string()))?;
+        let end = end_date
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(Utc::now);
         
-        Ok(json)
+        (start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::SecondsFormat;
+    use crate::db::testing::{create_api_key, create_user, TestDatabase};
+
+    fn limits() -> IngestionConfig {
+        IngestionConfig {
+            max_batch_size: 3,
+            import_chunk_size: 2,
+            max_clock_skew_secs: 300,
+            max_lateness_secs: 7 * 24 * 60 * 60,
+        }
+    }
+
+    fn item(api_key_id: Uuid) -> serde_json::Value {
+        serde_json::json!({
+            "api_key_id": api_key_id,
+            "model_name": "test-model",
+            "input_tokens": 1000,
+            "output_tokens": 500,
+            "timestamp": Utc::now() - Duration::minutes(5),
+        })
+    }
+
+    fn body(text: String) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Unpin {
+        stream::iter(vec![Ok(Bytes::from(text))])
+    }
+
+    fn aggregate_query(uri: &str) -> UsageAggregateQuery {
+        let uri: axum::http::Uri = uri.parse().unwrap();
+        axum::extract::Query::<UsageAggregateQuery>::try_from_uri(&uri).unwrap().0
+    }
+
+    fn histogram_query(uri: &str) -> LatencyHistogramQuery {
+        let uri: axum::http::Uri = uri.parse().unwrap();
+        axum::extract::Query::<LatencyHistogramQuery>::try_from_uri(&uri).unwrap().0
+    }
+
+    /// Noon UTC `days` ago, clear of any day boundary.
+    fn days_ago(days: i64) -> DateTime<Utc> {
+        (Utc::now() - Duration::days(days))
+            .date_naive()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_utc()
+    }
+
+    async fn record_all(pool: &PgPool, user_id: Uuid, events: Vec<serde_json::Value>) {
+        let (ws_tx, _) = broadcast::channel(64);
+        let service = UsageService::new(pool, &ws_tx);
+        for event in events {
+            let request = serde_json::from_value::<CreateUsageRequest>(event).unwrap();
+            service.record_usage(user_id, request, &limits()).await.unwrap();
+        }
+    }
+
+    async fn usage_rows(pool: &PgPool, user_id: Uuid) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM api_usage WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn event_time_defaults_to_now_without_a_timestamp() {
+        let before = Utc::now();
+
+        let resolved = UsageService::event_time(None, &limits(), true).unwrap();
+
+        assert!(resolved >= before && resolved <= Utc::now());
+    }
+
+    #[test]
+    fn event_time_allows_clock_skew_but_not_beyond_it() {
+        let now = Utc::now();
+        let skewed = now + Duration::seconds(240);
+        let future = now + Duration::seconds(360);
+
+        assert_eq!(UsageService::event_time(Some(skewed), &limits(), true), Ok(skewed));
+        let err = UsageService::event_time(Some(future), &limits(), false).unwrap_err();
+        assert!(err.contains("is in the future (maximum clock skew is 300s)"), "{}", err);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn event_time_enforces_lateness_only_for_live_ingestion() {
+        let now = Utc::now();
+        let late = now - Duration::days(6);
+        let too_late = now - Duration::days(8);
+
+        assert_eq!(UsageService::event_time(Some(late), &limits(), true), Ok(late));
+        let err = UsageService::event_time(Some(too_late), &limits(), true).unwrap_err();
+        assert!(err.contains("older than the maximum lateness of 604800s"), "{}", err);
+        assert_eq!(UsageService::event_time(Some(too_late), &limits(), false), Ok(too_late));
+    }
+
+    #[tokio::test]
+    async fn batch_must_hold_one_to_max_batch_size_items() {
+        // Both are refused before the database is touched
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let (ws_tx, _) = broadcast::channel(1);
+        let service = UsageService::new(&pool, &ws_tx);
+        let items = vec![item(Uuid::new_v4()); 4];
+
+        let oversized = service.record_batch(Uuid::new_v4(), items, &limits()).await;
+        let empty = service.record_batch(Uuid::new_v4(), Vec::new(), &limits()).await;
+
+        assert!(matches!(oversized, Err(ApiError::ValidationError(e)) if e == "Batch contains 4 items, the maximum is 3"));
+        assert!(matches!(empty, Err(ApiError::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn batch_records_valid_items_and_reports_the_rest_by_index() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let (ws_tx, _) = broadcast::channel(16);
+        let mut missing_tokens = item(api_key_id);
+        missing_tokens["input_tokens"] = serde_json::json!(-1);
+        let items = vec![item(api_key_id), missing_tokens, item(Uuid::new_v4())];
+
+        let report = UsageService::new(&db.pool, &ws_tx)
+            .record_batch(user_id, items, &limits())
+            .await
+            .unwrap();
+        let stored = usage_rows(&db.pool, user_id).await;
+        db.drop().await;
+
+        assert_eq!((report.accepted, report.rejected), (1, 2));
+        let outcomes: Vec<(usize, bool)> = report.results.iter().map(|r| (r.index, r.success)).collect();
+        assert_eq!(outcomes, vec![(0, true), (1, false), (2, false)]);
+        assert_eq!(report.results[2].error.as_deref(), Some("API key not found"));
+        assert_eq!(stored, 1);
+    }
+
+    #[tokio::test]
+    async fn replayed_external_id_returns_the_first_row_and_counts_once() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let (ws_tx, mut ws_rx) = broadcast::channel(16);
+        let service = UsageService::new(&db.pool, &ws_tx);
+        let mut event = item(api_key_id);
+        event["external_id"] = serde_json::json!("req-1");
+        let request = || serde_json::from_value::<CreateUsageRequest>(event.clone()).unwrap();
+
+        let first = service.record_usage(user_id, request(), &limits()).await.unwrap();
+        let replay = service.record_usage(user_id, request(), &limits()).await.unwrap();
+        let batch = service
+            .record_batch(user_id, vec![event.clone(), event.clone(), item(api_key_id)], &limits())
+            .await
+            .unwrap();
+        let stored = usage_rows(&db.pool, user_id).await;
+        db.drop().await;
+
+        assert!(!first.deduplicated);
+        assert!(replay.deduplicated);
+        assert_eq!(replay.usage.id, first.usage.id);
+        assert_eq!((batch.accepted, batch.deduplicated), (3, 2));
+        let replayed_ids: Vec<i64> = batch.results[..2]
+            .iter()
+            .map(|r| r.data.as_ref().unwrap().usage.id)
+            .collect();
+        assert_eq!(replayed_ids, vec![first.usage.id; 2]);
+        assert_eq!(stored, 2);
+
+        // One update for the first single event and one for the fresh batch item
+        let mut updates = Vec::new();
+        while let Ok(WsMessage::UsageUpdate { tokens, .. }) = ws_rx.try_recv() {
+            updates.push(tokens);
+        }
+        assert_eq!(updates, vec![1500, 1500]);
+    }
+
+    #[tokio::test]
+    async fn import_writes_every_chunk_and_reports_bad_lines_by_number() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let (ws_tx, _) = broadcast::channel(16);
+        let mut untimed = item(api_key_id);
+        untimed.as_object_mut().unwrap().remove("timestamp");
+        let lines = [
+            item(api_key_id).to_string(),
+            "{not json".to_string(),
+            String::new(),
+            untimed.to_string(),
+            item(api_key_id).to_string(),
+            item(api_key_id).to_string(),
+        ];
+
+        let summary = UsageService::new(&db.pool, &ws_tx)
+            .import_ndjson(user_id, body(lines.join("\n")), &limits())
+            .await
+            .unwrap();
+        let stored = usage_rows(&db.pool, user_id).await;
+        db.drop().await;
+
+        assert_eq!((summary.total_lines, summary.accepted, summary.rejected), (5, 3, 2));
+        let bad_lines: Vec<usize> = summary.errors.iter().map(|e| e.line).collect();
+        assert_eq!(bad_lines, vec![2, 4]);
+        assert_eq!(summary.errors[1].error, "timestamp is required for backfilled usage");
+        assert_eq!(stored, 3);
+    }
+
+    #[tokio::test]
+    async fn import_stops_at_an_over_long_line() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let (ws_tx, _) = broadcast::channel(16);
+        let text = format!("{}\n{}\n", item(api_key_id), "x".repeat(MAX_IMPORT_LINE_BYTES + 1));
+
+        let result = UsageService::new(&db.pool, &ws_tx)
+            .import_ndjson(user_id, body(text), &limits())
+            .await;
+        db.drop().await;
+
+        assert!(matches!(
+            result,
+            Err(ApiError::ValidationError(e)) if e == "Line 2 exceeds the maximum length of 65536 bytes"
+        ));
+    }
+
+    #[tokio::test]
+    async fn aggregate_limits_groups_per_time_bucket_by_cost() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let event = |model: &str, tokens: i32, days: i64| {
+            let mut event = item(api_key_id);
+            event["model_name"] = serde_json::json!(model);
+            event["input_tokens"] = serde_json::json!(tokens);
+            event["timestamp"] = serde_json::json!(days_ago(days));
+            event
+        };
+        record_all(&db.pool, user_id, vec![
+            event("small", 1000, 3),
+            event("large", 9000, 3),
+            event("small", 9000, 2),
+            event("large", 1000, 2),
+        ])
+        .await;
+        let (ws_tx, _) = broadcast::channel(1);
+
+        let aggregation = UsageService::new(&db.pool, &ws_tx)
+            .aggregate_usage(
+                user_id,
+                aggregate_query("/usage/aggregate?group_by=day,model_name&limit=1"),
+                &UntaggedTags::Null,
+            )
+            .await
+            .unwrap();
+        db.drop().await;
+
+        // The limit keeps the costliest model of each day, not of the range
+        let groups: Vec<(&str, Money)> = aggregation
+            .groups
+            .iter()
+            .map(|g| (g.group["model_name"].as_str().unwrap(), g.cost))
+            .collect();
+        assert_eq!(groups, vec![("large", "0.0095".parse().unwrap()), ("small", "0.0095".parse().unwrap())]);
+        assert!(aggregation.groups[0].group["day"].as_str() < aggregation.groups[1].group["day"].as_str());
+    }
+
+    #[tokio::test]
+    async fn aggregate_groups_by_several_bound_keys_and_applies_the_usage_filter() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let event = |team: &str, region: &str, status: i32| {
+            let mut event = item(api_key_id);
+            event["tags"] = serde_json::json!({ "team": team });
+            event["metadata"] = serde_json::json!({ "region": region });
+            event["status_code"] = serde_json::json!(status);
+            event
+        };
+        let mut untagged = item(api_key_id);
+        untagged["input_tokens"] = serde_json::json!(500);
+        untagged["status_code"] = serde_json::json!(200);
+        untagged["metadata"] = serde_json::json!({ "region": "eu" });
+        record_all(&db.pool, user_id, vec![
+            event("search", "eu", 200),
+            event("search", "eu", 200),
+            event("search", "us", 200),
+            event("ads", "eu", 500),
+            untagged,
+        ])
+        .await;
+        let (ws_tx, _) = broadcast::channel(1);
+        let service = UsageService::new(&db.pool, &ws_tx);
+        let aggregate = |uri: &str, untagged: UntaggedTags| {
+            let query = aggregate_query(uri);
+            let service = &service;
+            async move {
+                let groups = service.aggregate_usage(user_id, query, &untagged).await.unwrap().groups;
+                groups
+                    .iter()
+                    .map(|g| (serde_json::Value::Object(g.group.clone()), g.requests))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // Each bound key is its own parameter, so only a positional GROUP BY
+        // matches the selected expressions
+        let by_team_and_region = aggregate(
+            "/usage/aggregate?group_by=tag.team,metadata.region&status_max=399",
+            UntaggedTags::Label("none".to_string()),
+        )
+        .await;
+        let excluded = aggregate("/usage/aggregate?group_by=tag.team", UntaggedTags::Exclude).await;
+        let costly = aggregate("/usage/aggregate?group_by=tag.team&min_cost=1", UntaggedTags::Null).await;
+        db.drop().await;
+
+        assert_eq!(by_team_and_region, vec![
+            (serde_json::json!({ "tag.team": "search", "metadata.region": "eu" }), 2),
+            (serde_json::json!({ "tag.team": "search", "metadata.region": "us" }), 1),
+            (serde_json::json!({ "tag.team": "none", "metadata.region": "eu" }), 1),
+        ]);
+        assert_eq!(excluded, vec![
+            (serde_json::json!({ "tag.team": "search" }), 3),
+            (serde_json::json!({ "tag.team": "ads" }), 1),
+        ]);
+        assert!(costly.is_empty());
+    }
+
+    #[tokio::test]
+    async fn latency_histogram_counts_a_call_at_a_bound_in_the_bucket_above_it() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let mut events: Vec<serde_json::Value> = [0, 99, 100, 499, 500, 30000]
+            .into_iter()
+            .map(|ms| {
+                let mut event = item(api_key_id);
+                event["response_time_ms"] = serde_json::json!(ms);
+                event
+            })
+            .collect();
+        events.push(item(api_key_id));
+        record_all(&db.pool, user_id, events).await;
+        let (ws_tx, _) = broadcast::channel(1);
+
+        let histogram = UsageService::new(&db.pool, &ws_tx)
+            .latency_histogram(user_id, histogram_query("/usage/latency-histogram?buckets=100,500"))
+            .await
+            .unwrap();
+        db.drop().await;
+
+        // The call without a response time is left out
+        assert_eq!(histogram.groups.len(), 1);
+        assert_eq!(histogram.groups[0].requests, 6);
+        let counts: Vec<(i32, Option<i32>, i64)> = histogram.groups[0]
+            .buckets
+            .iter()
+            .map(|b| (b.from_ms, b.to_ms, b.count))
+            .collect();
+        assert_eq!(counts, vec![(0, Some(100), 2), (100, Some(500), 2), (500, None, 2)]);
+    }
+
+    #[tokio::test]
+    async fn latency_of_an_empty_range_has_no_groups_or_percentiles() {
+        let Some(db) = TestDatabase::create().await else { return };
+        let user_id = create_user(&db.pool).await;
+        let api_key_id = create_api_key(&db.pool, user_id).await;
+        let mut event = item(api_key_id);
+        event["response_time_ms"] = serde_json::json!(250);
+        event["timestamp"] = serde_json::json!(days_ago(3));
+        record_all(&db.pool, user_id, vec![event]).await;
+        let (ws_tx, _) = broadcast::channel(1);
+        let start = Utc::now() - Duration::days(1);
+        let uri = format!(
+            "/usage/latency-histogram?start_date={}",
+            start.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+
+        let histogram = UsageService::new(&db.pool, &ws_tx)
+            .latency_histogram(user_id, histogram_query(&uri))
+            .await
+            .unwrap();
+        let stats = UsageRepository::new(&db.pool)
+            .get_latency_stats(user_id, start, Some(Utc::now()), None, None)
+            .await
+            .unwrap();
+        db.drop().await;
+
+        assert!(histogram.groups.is_empty());
+        assert_eq!(histogram.bounds, DEFAULT_LATENCY_BUCKETS.to_vec());
+        assert_eq!((stats.p50_ms, stats.p99_ms, stats.max_ms), (None, None, None));
+    }
+}
@@ -0,0 +1,108 @@
+use base64::Engine;
+
+use crate::errors::ApiError;
+
+/// Encrypts a provider API key or destination secret for storage with
+/// AES-256-GCM under `API_KEY_ENCRYPTION_KEY`, as base64 of nonce and ciphertext.
+pub fn encrypt_api_key(key: &str) -> Result<String, ApiError> {
+    use aes_gcm::{
+        aead::{Aead, KeyInit},
+        Aes256Gcm, Nonce,
+    };
+    
+    let encryption_key = std::env::var("API_KEY_ENCRYPTION_KEY")
+        .unwrap_or_else(|_| {
+            tracing::warn!("Using default encryption key - NOT SECURE FOR PRODUCTION");
+            "your-32-byte-encryption-key-here!".to_string()
+        });
+    
+    let mut key_bytes = [0u8; 32];
+    let enc_key_bytes = encryption_key.as_bytes();
+    let len = enc_key_bytes.len().min(32);
+    key_bytes[..len].copy_from_slice(&enc_key_bytes[..len]);
+    
+    let cipher = Aes256Gcm::new(&key_bytes.into());
+    
+    let mut nonce_bytes = [0u8; 12];
+    getrandom::getrandom(&mut nonce_bytes)
+        .map_err(|e| ApiError::InternalServerError(format!("Failed to generate nonce: {}", e)))?;
+    let nonce = Nonce::from(nonce_bytes);
+    
+    let ciphertext = cipher
+        .encrypt(&nonce, key.as_bytes())
+        .map_err(|e| ApiError::InternalServerError(format!("Encryption failed: {}", e)))?;
+    
+    let mut combined = nonce_bytes.to_vec();
+    combined.extend_from_slice(&ciphertext);
+    
+    Ok(base64::engine::general_purpose::STANDARD.encode(&combined))
+}
+
+/// Reverses [`encrypt_api_key`].
+pub fn decrypt_api_key(encrypted: &str) -> Result<String, ApiError> {
+    use aes_gcm::{
+        aead::{Aead, KeyInit},
+        Aes256Gcm, Nonce,
+    };
+    
+    let encryption_key = std::env::var("API_KEY_ENCRYPTION_KEY")
+        .unwrap_or_else(|_| "your-32-byte-encryption-key-here!".to_string());
+    
+    let mut key_bytes = [0u8; 32];
+    let enc_key_bytes = encryption_key.as_bytes();
+    let len = enc_key_bytes.len().min(32);
+    key_bytes[..len].copy_from_slice(&enc_key_bytes[..len]);
+    
+    let cipher = Aes256Gcm::new(&key_bytes.into());
+    
+    let combined = base64::engine::general_purpose::STANDARD
+        .decode(encrypted)
+        .map_err(|e| ApiError::InternalServerError(format!("Base64 decode failed: {}", e)))?;
+    
+    if combined.len() < 12 {
+        return Err(ApiError::InternalServerError("Invalid encrypted data".to_string()));
+    }
+    
+    let (nonce_bytes, ciphertext) = combined.split_at(12);
+    let nonce_bytes: [u8; 12] = nonce_bytes
+        .try_into()
+        .map_err(|_| ApiError::InternalServerError("Invalid nonce".to_string()))?;
+    let nonce = Nonce::from(nonce_bytes);
+    
+    let plaintext = cipher
+        .decrypt(&nonce, ciphertext)
+        .map_err(|e| ApiError::InternalServerError(format!("Decryption failed: {}", e)))?;
+    
+    String::from_utf8(plaintext)
+        .map_err(|e| ApiError::InternalServerError(format!("Invalid UTF-8: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_key_decrypts_to_the_original() {
+        let encrypted = encrypt_api_key("sk-test-123").unwrap();
+
+        assert_ne!(encrypted, "sk-test-123");
+        assert_eq!(decrypt_api_key(&encrypted).unwrap(), "sk-test-123");
+    }
+
+    #[test]
+    fn each_encryption_uses_a_fresh_nonce() {
+        assert_ne!(encrypt_api_key("sk-test-123").unwrap(), encrypt_api_key("sk-test-123").unwrap());
+    }
+
+    #[test]
+    fn tampered_or_truncated_data_is_rejected() {
+        let encrypted = encrypt_api_key("sk-test-123").unwrap();
+        let mut bytes = base64::engine::general_purpose::STANDARD.decode(&encrypted).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        assert!(decrypt_api_key(&base64::engine::general_purpose::STANDARD.encode(&bytes)).is_err());
+        assert!(decrypt_api_key("AAAA").is_err());
+        assert!(decrypt_api_key("not base64!").is_err());
+    }
+}
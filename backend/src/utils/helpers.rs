@@ -1,6 +1,16 @@
 
-pub fn format_currency(value: f64) -> String {
-    format!("${:.2}", value)
+use crate::models::money::{round_for_display, Money};
+
+pub fn format_currency(value: Money, currency: &str) -> String {
+    let value = round_for_display(value);
+    
+    match currency {
+        "USD" => format!("${:.2}", value),
+        "EUR" => format!("€{:.2}", value),
+        "GBP" => format!("£{:.2}", value),
+        "JPY" => format!("¥{:.0}", value),
+        _ => format!("{} {:.2}", currency, value),
+    }
 }
 
 pub fn format_tokens(tokens: i64) -> String {
@@ -12,13 +22,3 @@ pub fn format_tokens(tokens: i64) -> String {
         tokens.to_string()
     }
 }
-
-pub fn parse_period_to_days(period: &str) -> i64 {
-    match period {
-        "24h" | "1d" => 1,
-        "7d" => 7,
-        "30d" => 30,
-        "90d" => 90,
-        _ => 7,
-    }
-}
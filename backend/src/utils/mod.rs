@@ -1,3 +1,5 @@
+pub mod crypto;
 pub mod helpers;
+pub mod period;
 
 pub use helpers::*;
\ No newline at end of file
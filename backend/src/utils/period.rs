@@ -0,0 +1,462 @@
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+
+use crate::models::calendar::Calendar;
+
+/// Longest period that may be requested, in days.
+pub const MAX_PERIOD_DAYS: i64 = 3660;
+
+/// A reporting period as requested, resolved against a calendar and the
+/// current time with [`Period::resolve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// A window of the given length ending now, e.g. `7d`, `24h` or `P1M`.
+    Rolling { months: u32, duration: Duration },
+    /// A calendar-aligned period such as `mtd` or `last_quarter`.
+    Calendar(CalendarPeriod),
+    /// An explicit `start/end` range.
+    Range { start: RangeBound, end: RangeBound },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarPeriod {
+    Today,
+    Yesterday,
+    WeekToDate,
+    LastWeek,
+    MonthToDate,
+    LastMonth,
+    QuarterToDate,
+    LastQuarter,
+    YearToDate,
+    LastYear,
+}
+
+/// One end of an explicit range. A bare date is a whole local day: from its
+/// midnight as a start, up to the next midnight as an end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeBound {
+    Instant(DateTime<Utc>),
+    Date(NaiveDate),
+}
+
+/// A resolved period: from `start` up to `end`, exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeriodRange {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Length of the calendar unit a calendar period steps by.
+enum CalendarStep {
+    Days(i64),
+    Months(u32),
+}
+
+impl CalendarPeriod {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "today" => Some(Self::Today),
+            "yesterday" => Some(Self::Yesterday),
+            "wtd" => Some(Self::WeekToDate),
+            "last_week" => Some(Self::LastWeek),
+            "mtd" => Some(Self::MonthToDate),
+            "last_month" => Some(Self::LastMonth),
+            "qtd" => Some(Self::QuarterToDate),
+            "last_quarter" => Some(Self::LastQuarter),
+            "ytd" => Some(Self::YearToDate),
+            "last_year" => Some(Self::LastYear),
+            _ => None,
+        }
+    }
+
+    fn step(&self) -> CalendarStep {
+        match self {
+            Self::Today | Self::Yesterday => CalendarStep::Days(1),
+            Self::WeekToDate | Self::LastWeek => CalendarStep::Days(7),
+            Self::MonthToDate | Self::LastMonth => CalendarStep::Months(1),
+            Self::QuarterToDate | Self::LastQuarter => CalendarStep::Months(3),
+            Self::YearToDate | Self::LastYear => CalendarStep::Months(12),
+        }
+    }
+
+    /// Whether the period runs up to now rather than covering a whole unit.
+    fn is_to_date(&self) -> bool {
+        matches!(
+            self,
+            Self::Today | Self::WeekToDate | Self::MonthToDate | Self::QuarterToDate | Self::YearToDate
+        )
+    }
+
+    /// First local day of the unit containing `today`.
+    fn unit_start(&self, calendar: &Calendar, today: NaiveDate) -> Option<NaiveDate> {
+        match self.step() {
+            CalendarStep::Days(1) => Some(today),
+            CalendarStep::Days(_) => Some(calendar.week_start_of(today)),
+            CalendarStep::Months(months) => {
+                let month0 = today.month0() - today.month0() % months;
+                NaiveDate::from_ymd_opt(today.year(), month0 + 1, 1)
+            }
+        }
+    }
+}
+
+impl Period {
+    /// Parses a period written as a duration (`24h`, `7d`, `2w` or ISO 8601
+    /// such as `P1M` or `PT12H`), a calendar period (`today`, `yesterday`,
+    /// `wtd`, `last_week`, `mtd`, `last_month`, `qtd`, `last_quarter`, `ytd`,
+    /// `last_year`) or a `start/end` range of RFC 3339 times or dates.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let invalid = || {
+            format!(
+                "Invalid period '{}'; expected a duration such as 7d or P1M, a calendar period \
+                 (today, yesterday, wtd, last_week, mtd, last_month, qtd, last_quarter, ytd, \
+                 last_year) or a range such as 2026-01-01/2026-03-31",
+                value
+            )
+        };
+
+        if let Some(period) = CalendarPeriod::parse(&value.to_ascii_lowercase()) {
+            return Ok(Self::Calendar(period));
+        }
+
+        if let Some((start, end)) = value.split_once('/') {
+            let start = parse_range_bound(start).ok_or_else(invalid)?;
+            let end = parse_range_bound(end).ok_or_else(invalid)?;
+            return Ok(Self::Range { start, end });
+        }
+
+        let (months, duration) = parse_short_duration(value)
+            .or_else(|| parse_iso_duration(&value.to_ascii_uppercase()))
+            .ok_or_else(invalid)?;
+        if months == 0 && duration <= Duration::zero() {
+            return Err(format!("Invalid period '{}'; the duration must not be zero", value));
+        }
+
+        Ok(Self::Rolling { months, duration })
+    }
+
+    /// The range this period covers at `now`, with days in `calendar`.
+    pub fn resolve(&self, calendar: &Calendar, now: DateTime<Utc>) -> Result<PeriodRange, String> {
+        let range = match self {
+            Self::Rolling { months, duration } => PeriodRange {
+                start: step_back(now, *months, *duration)?,
+                end: now,
+            },
+            Self::Calendar(period) => {
+                let today = calendar.date_of(now);
+                let current = period
+                    .unit_start(calendar, today)
+                    .ok_or_else(|| "Period is out of range".to_string())?;
+                if period.is_to_date() {
+                    PeriodRange { start: calendar.start_of(current), end: now }
+                } else {
+                    let previous = step_dates_back(current, period.step())?;
+                    PeriodRange { start: calendar.start_of(previous), end: calendar.start_of(current) }
+                }
+            }
+            Self::Range { start, end } => {
+                let start = match start {
+                    RangeBound::Instant(at) => *at,
+                    RangeBound::Date(date) => calendar.start_of(*date),
+                };
+                let end = match end {
+                    RangeBound::Instant(at) => *at,
+                    RangeBound::Date(date) => calendar.start_of(
+                        date.succ_opt().ok_or_else(|| "Period is out of range".to_string())?,
+                    ),
+                };
+                PeriodRange { start, end }
+            }
+        };
+
+        if range.start >= range.end {
+            return Err("Period must end after it starts".to_string());
+        }
+        if range.end - range.start > Duration::days(MAX_PERIOD_DAYS) {
+            return Err(format!("Period cannot be longer than {} days", MAX_PERIOD_DAYS));
+        }
+
+        Ok(range)
+    }
+
+    /// The equivalent period just before `range`, the resolution of this
+    /// period: the preceding window of a rolling period or range, the
+    /// preceding unit of a calendar period, cut to the same elapsed time for
+    /// a to-date period (month-to-date on the 17th compares with the 1st to
+    /// the 17th of the previous month).
+    pub fn previous(&self, range: &PeriodRange, calendar: &Calendar) -> Result<PeriodRange, String> {
+        let start = match self {
+            Self::Rolling { months, duration } => step_back(range.start, *months, *duration)?,
+            Self::Range { .. } => range.start - (range.end - range.start),
+            Self::Calendar(period) => {
+                let date = step_dates_back(calendar.date_of(range.start), period.step())?;
+                let start = calendar.start_of(date);
+                if period.is_to_date() {
+                    let end = (start + (range.end - range.start)).min(range.start);
+                    return Ok(PeriodRange { start, end });
+                }
+                start
+            }
+        };
+
+        Ok(PeriodRange { start, end: range.start })
+    }
+}
+
+fn step_back(at: DateTime<Utc>, months: u32, duration: Duration) -> Result<DateTime<Utc>, String> {
+    at.checked_sub_months(Months::new(months))
+        .and_then(|at| at.checked_sub_signed(duration))
+        .ok_or_else(|| "Period is out of range".to_string())
+}
+
+fn step_dates_back(date: NaiveDate, step: CalendarStep) -> Result<NaiveDate, String> {
+    match step {
+        CalendarStep::Days(days) => date.checked_sub_signed(Duration::days(days)),
+        CalendarStep::Months(months) => date.checked_sub_months(Months::new(months)),
+    }
+    .ok_or_else(|| "Period is out of range".to_string())
+}
+
+fn parse_range_bound(value: &str) -> Option<RangeBound> {
+    let value = value.trim();
+
+    DateTime::parse_from_rfc3339(value)
+        .map(|at| RangeBound::Instant(at.with_timezone(&Utc)))
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().map(RangeBound::Date))
+}
+
+/// `<n>h`, `<n>d` or `<n>w`, as the dashboard has always sent.
+fn parse_short_duration(value: &str) -> Option<(u32, Duration)> {
+    let unit = value.chars().last()?;
+    let count = i64::from(value[..value.len() - unit.len_utf8()].parse::<u32>().ok()?);
+    if count > MAX_PERIOD_DAYS * 24 {
+        return None;
+    }
+
+    match unit {
+        'h' => Some((0, Duration::hours(count))),
+        'd' => Some((0, Duration::days(count))),
+        'w' => Some((0, Duration::weeks(count))),
+        _ => None,
+    }
+}
+
+/// An ISO 8601 duration such as `P1Y2M`, `P2W` or `P1DT12H`. Years and
+/// months are kept as calendar months; everything else is exact.
+fn parse_iso_duration(value: &str) -> Option<(u32, Duration)> {
+    let rest = value.strip_prefix('P')?;
+    let (date_part, time_part) = match rest.split_once('T') {
+        Some((date_part, time_part)) if !time_part.is_empty() => (date_part, Some(time_part)),
+        Some(_) => return None,
+        None => (rest, None),
+    };
+    if date_part.is_empty() && time_part.is_none() {
+        return None;
+    }
+
+    let mut months: u32 = 0;
+    let mut duration = Duration::zero();
+
+    for (count, unit) in duration_components(date_part, "YMWD")? {
+        match unit {
+            'Y' => months = months.checked_add(u32::try_from(count).ok()?.checked_mul(12)?)?,
+            'M' => months = months.checked_add(u32::try_from(count).ok()?)?,
+            'W' => duration += Duration::try_weeks(count)?,
+            _ => duration += Duration::try_days(count)?,
+        }
+    }
+    for (count, unit) in duration_components(time_part.unwrap_or_default(), "HMS")? {
+        match unit {
+            'H' => duration += Duration::try_hours(count)?,
+            'M' => duration += Duration::try_minutes(count)?,
+            _ => duration += Duration::try_seconds(count)?,
+        }
+    }
+
+    Some((months, duration))
+}
+
+/// Splits `1Y2M` into `(1, 'Y'), (2, 'M')`, requiring units to appear in
+/// the order of `units`, each at most once.
+fn duration_components(value: &str, units: &str) -> Option<Vec<(i64, char)>> {
+    let mut components = Vec::new();
+    let mut digits = String::new();
+    let mut next_unit = 0;
+
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let position = units[next_unit..].find(c)? + next_unit;
+        if digits.is_empty() || digits.len() > 9 {
+            return None;
+        }
+        components.push((digits.parse().ok()?, c));
+        digits.clear();
+        next_unit = position + 1;
+    }
+
+    digits.is_empty().then_some(components)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn calendar(timezone: &str) -> Calendar {
+        Calendar::parse(timezone, "monday").unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+
+    fn range(start: DateTime<Utc>, end: DateTime<Utc>) -> PeriodRange {
+        PeriodRange { start, end }
+    }
+
+    fn resolve(period: &str, timezone: &str, now: DateTime<Utc>) -> Result<PeriodRange, String> {
+        Period::parse(period).unwrap().resolve(&calendar(timezone), now)
+    }
+
+    fn previous(period: &str, now: DateTime<Utc>) -> PeriodRange {
+        let period = Period::parse(period).unwrap();
+        let current = period.resolve(&calendar("UTC"), now).unwrap();
+        period.previous(&current, &calendar("UTC")).unwrap()
+    }
+
+    #[test]
+    fn short_and_iso_durations_parse_as_rolling_periods() {
+        let rolling = |months, duration| Ok(Period::Rolling { months, duration });
+
+        assert_eq!(Period::parse("24h"), rolling(0, Duration::hours(24)));
+        assert_eq!(Period::parse(" 7d "), rolling(0, Duration::days(7)));
+        assert_eq!(Period::parse("2w"), rolling(0, Duration::weeks(2)));
+        assert_eq!(Period::parse("P1Y2M"), rolling(14, Duration::zero()));
+        assert_eq!(Period::parse("p1dt12h"), rolling(0, Duration::hours(36)));
+        assert_eq!(Period::parse("PT90M"), rolling(0, Duration::minutes(90)));
+    }
+
+    #[test]
+    fn calendar_periods_and_ranges_parse() {
+        assert_eq!(Period::parse("MTD"), Ok(Period::Calendar(CalendarPeriod::MonthToDate)));
+        assert_eq!(Period::parse("last_quarter"), Ok(Period::Calendar(CalendarPeriod::LastQuarter)));
+        assert_eq!(
+            Period::parse("2026-01-01/2026-03-31T12:00:00+02:00"),
+            Ok(Period::Range {
+                start: RangeBound::Date(date(2026, 1, 1)),
+                end: RangeBound::Instant(utc(2026, 3, 31, 10, 0)),
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_periods_are_rejected() {
+        for value in ["", "7", "7x", "d", "-7d", "87841h", "P", "PT", "P1D2Y", "P1M1M", "P1H", "last-month"] {
+            let err = Period::parse(value).unwrap_err();
+            assert!(err.starts_with("Invalid period"), "{}: {}", value, err);
+        }
+        assert!(Period::parse("2026-02-30/2026-03-01").is_err());
+        assert!(Period::parse("2026-01-01/").is_err());
+        assert_eq!(
+            Period::parse("0d"),
+            Err("Invalid period '0d'; the duration must not be zero".to_string())
+        );
+        assert!(Period::parse("PT0S").is_err());
+    }
+
+    #[test]
+    fn calendar_periods_resolve_across_month_and_year_boundaries() {
+        let new_year = utc(2026, 1, 10, 9, 30);
+
+        assert_eq!(
+            resolve("mtd", "UTC", utc(2026, 3, 17, 10, 0)),
+            Ok(range(utc(2026, 3, 1, 0, 0), utc(2026, 3, 17, 10, 0)))
+        );
+        assert_eq!(
+            resolve("last_month", "UTC", new_year),
+            Ok(range(utc(2025, 12, 1, 0, 0), utc(2026, 1, 1, 0, 0)))
+        );
+        assert_eq!(
+            resolve("last_quarter", "UTC", new_year),
+            Ok(range(utc(2025, 10, 1, 0, 0), utc(2026, 1, 1, 0, 0)))
+        );
+        assert_eq!(
+            resolve("last_year", "UTC", new_year),
+            Ok(range(utc(2025, 1, 1, 0, 0), utc(2026, 1, 1, 0, 0)))
+        );
+        // 2026 begins in Tokyo at 15:00 UTC on New Year's Eve
+        assert_eq!(
+            resolve("ytd", "Asia/Tokyo", utc(2025, 12, 31, 16, 0)),
+            Ok(range(utc(2025, 12, 31, 15, 0), utc(2025, 12, 31, 16, 0)))
+        );
+    }
+
+    #[test]
+    fn rolling_periods_and_ranges_resolve_across_month_and_year_boundaries() {
+        // A month back from March 31st is the last day of February
+        assert_eq!(
+            resolve("P1M", "UTC", utc(2026, 3, 31, 12, 0)),
+            Ok(range(utc(2026, 2, 28, 12, 0), utc(2026, 3, 31, 12, 0)))
+        );
+        assert_eq!(
+            resolve("7d", "UTC", utc(2026, 1, 3, 0, 0)),
+            Ok(range(utc(2025, 12, 27, 0, 0), utc(2026, 1, 3, 0, 0)))
+        );
+        // A date as the end covers that whole local day
+        assert_eq!(
+            resolve("2025-12-31/2026-01-01", "America/New_York", utc(2026, 3, 1, 0, 0)),
+            Ok(range(utc(2025, 12, 31, 5, 0), utc(2026, 1, 2, 5, 0)))
+        );
+    }
+
+    #[test]
+    fn backwards_or_overlong_periods_do_not_resolve() {
+        let now = utc(2026, 3, 1, 0, 0);
+
+        assert_eq!(
+            resolve("2026-02-01/2026-01-01", "UTC", now),
+            Err("Period must end after it starts".to_string())
+        );
+        assert_eq!(resolve("P11Y", "UTC", now), Err("Period cannot be longer than 3660 days".to_string()));
+    }
+
+    #[test]
+    fn previous_period_is_the_preceding_window_or_unit() {
+        let now = utc(2026, 1, 10, 9, 30);
+
+        assert_eq!(previous("7d", now), range(utc(2025, 12, 27, 9, 30), utc(2026, 1, 3, 9, 30)));
+        assert_eq!(previous("last_month", now), range(utc(2025, 11, 1, 0, 0), utc(2025, 12, 1, 0, 0)));
+        assert_eq!(previous("last_quarter", now), range(utc(2025, 7, 1, 0, 0), utc(2025, 10, 1, 0, 0)));
+        assert_eq!(
+            previous("2026-01-01/2026-01-03", now),
+            range(utc(2025, 12, 29, 0, 0), utc(2026, 1, 1, 0, 0))
+        );
+    }
+
+    #[test]
+    fn previous_to_date_period_covers_the_same_elapsed_time() {
+        assert_eq!(
+            previous("mtd", utc(2026, 3, 17, 10, 0)),
+            range(utc(2026, 2, 1, 0, 0), utc(2026, 2, 17, 10, 0))
+        );
+        assert_eq!(
+            previous("ytd", utc(2026, 1, 10, 9, 30)),
+            range(utc(2025, 1, 1, 0, 0), utc(2025, 1, 10, 9, 30))
+        );
+        // ... but never runs into the current period
+        assert_eq!(
+            previous("mtd", utc(2026, 3, 31, 12, 0)),
+            range(utc(2026, 2, 1, 0, 0), utc(2026, 3, 1, 0, 0))
+        );
+    }
+}
@@ -9,14 +9,15 @@ use futures_util::{sink::SinkExt, stream::StreamExt};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::AppState;
+use crate::{models::money::Money, AppState};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum WsMessage {
     UsageUpdate {
         user_id: Uuid,
-        cost: f64,
+        cost: Money,
+        currency: String,
         tokens: i32,
         timestamp: String,
     },
@@ -27,9 +28,10 @@ pub enum WsMessage {
     },
     PredictionUpdate {
         user_id: Uuid,
-        daily_cost: f64,
-        weekly_cost: f64,
-        monthly_cost: f64,
+        daily_cost: Money,
+        weekly_cost: Money,
+        monthly_cost: Money,
+        currency: String,
     },
 }
 
@@ -122,7 +122,7 @@ export default function Dashboard() {
             <div className="grid grid-cols-1 gap-6 sm:grid-cols-2 lg:grid-cols-4 mb-8">
               <StatsCard
                 title="Total Cost"
-                value={formatCurrency(stats?.total_cost || 0)}
+                value={formatCurrency(stats?.total_cost || 0, stats?.currency)}
                 icon={DollarSign}
                 color="blue"
                 trend={stats?.cost_trend}
@@ -206,7 +206,7 @@ export default function Dashboard() {
                           {formatNumber(item.total_tokens)}
                         </td>
                         <td className="px-6 py-4 whitespace-nowrap text-sm text-gray-900">
-                          {formatCurrency(item.cost)}
+                          {formatCurrency(item.cost, item.currency)}
                         </td>
                         <td className="px-6 py-4 whitespace-nowrap">
                           <span className={`px-2 inline-flex text-xs leading-5 font-semibold rounded-full ${
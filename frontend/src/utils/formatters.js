@@ -1,9 +1,10 @@
-export function formatCurrency(value) {
-  return value?.toLocaleString('en-US', {
+// Amounts arrive from the API as decimal strings
+export function formatCurrency(value, currency = 'USD') {
+  return Number(value ?? 0).toLocaleString('en-US', {
     style: 'currency',
-    currency: 'USD',
+    currency,
     maximumFractionDigits: 2,
-  }) ?? '$0.00';
+  });
 }
 
 export function formatNumber(value) {